@@ -2,7 +2,285 @@ extern crate proc_macro;
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
 use quote::{format_ident, quote, ToTokens, TokenStreamExt};
-use syn::{parse_macro_input, punctuated::Punctuated, ItemStruct, Lit, MetaNameValue, Token};
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::{
+    parenthesized, parse_macro_input, punctuated::Punctuated, DeriveInput, Ident, ItemStruct,
+    LitStr, Path, Token,
+};
+
+// one `name = "value"` argument or the `options(...)` schema
+enum FdwArg {
+    NameValue(Ident, LitStr),
+    Options(Vec<OptionDef>, Vec<Path>),
+}
+
+impl Parse for FdwArg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.call(Ident::parse_any)?;
+        if input.peek(Token![=]) {
+            input.parse::<Token![=]>()?;
+            return Ok(FdwArg::NameValue(name, input.parse()?));
+        }
+        if name != "options" {
+            return Err(syn::Error::new(name.span(), "expected `name = \"value\"`"));
+        }
+
+        // options(server(...), table(...), column(...), user_mapping(...), include(...))
+        let content;
+        parenthesized!(content in input);
+        let mut defs = Vec::new();
+        let mut includes = Vec::new();
+        let levels: Punctuated<LevelDef, Token![,]> = content.parse_terminated(LevelDef::parse)?;
+        for level in levels {
+            defs.extend(level.options);
+            includes.extend(level.includes);
+        }
+        Ok(FdwArg::Options(defs, includes))
+    }
+}
+
+struct LevelDef {
+    options: Vec<OptionDef>,
+    includes: Vec<Path>,
+}
+
+impl Parse for LevelDef {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let level = input.call(Ident::parse_any)?;
+
+        // include(path::TO_SPECS, ...), option specs shared by several FDWs
+        if level == "include" {
+            let content;
+            parenthesized!(content in input);
+            let paths: Punctuated<Path, Token![,]> = content.parse_terminated(Path::parse)?;
+            return Ok(LevelDef {
+                options: Vec::new(),
+                includes: paths.into_iter().collect(),
+            });
+        }
+
+        let level_variant = match level.to_string().as_str() {
+            "server" => format_ident!("Server"),
+            "table" => format_ident!("Table"),
+            "column" => format_ident!("Column"),
+            "user_mapping" => format_ident!("UserMapping"),
+            _ => {
+                return Err(syn::Error::new(
+                    level.span(),
+                    "option level must be one of `server`, `table`, `column`, `user_mapping` or `include`",
+                ))
+            }
+        };
+
+        let content;
+        parenthesized!(content in input);
+        let opts: Punctuated<OptionDef, Token![,]> = content.parse_terminated(OptionDef::parse)?;
+        let options = opts
+            .into_iter()
+            .map(|mut opt| {
+                opt.level = level_variant.clone();
+                opt
+            })
+            .collect();
+        Ok(LevelDef {
+            options,
+            includes: Vec::new(),
+        })
+    }
+}
+
+// an option definition, e.g. `limit(type = "usize", default = "100")`
+struct OptionDef {
+    name: Ident,
+    level: Ident,
+    ty: String,
+    default: Option<LitStr>,
+    required: bool,
+    secret: bool,
+    superuser: bool,
+}
+
+impl Parse for OptionDef {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.call(Ident::parse_any)?;
+        let mut def = OptionDef {
+            name,
+            level: format_ident!("Server"),
+            ty: "string".to_owned(),
+            default: None,
+            required: false,
+            secret: false,
+            superuser: false,
+        };
+        if !input.peek(syn::token::Paren) {
+            return Ok(def);
+        }
+
+        let content;
+        parenthesized!(content in input);
+        while !content.is_empty() {
+            let attr = content.call(Ident::parse_any)?;
+            match attr.to_string().as_str() {
+                "required" => def.required = true,
+                "secret" => def.secret = true,
+                "superuser" => def.superuser = true,
+                "type" => {
+                    content.parse::<Token![=]>()?;
+                    let ty: LitStr = content.parse()?;
+                    if option_type(&ty.value()).is_none() {
+                        return Err(syn::Error::new(
+                            ty.span(),
+                            "option type must be one of `string`, `bool`, `i32`, `i64`, `usize` or `f64`",
+                        ));
+                    }
+                    def.ty = ty.value();
+                }
+                "default" => {
+                    content.parse::<Token![=]>()?;
+                    def.default = Some(content.parse()?);
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        attr.span(),
+                        "expected `type`, `default`, `required`, `secret` or `superuser`",
+                    ))
+                }
+            }
+            if !content.is_empty() {
+                content.parse::<Token![,]>()?;
+            }
+        }
+        if def.secret && def.ty != "string" {
+            return Err(syn::Error::new(
+                def.name.span(),
+                "secret option must be a string",
+            ));
+        }
+        Ok(def)
+    }
+}
+
+// map option type name to OptionType variant and Rust type
+fn option_type(ty: &str) -> Option<(TokenStream2, TokenStream2)> {
+    let ret = match ty {
+        "string" => (quote! { String }, quote! { String }),
+        "bool" => (quote! { Bool }, quote! { bool }),
+        "i32" => (quote! { I32 }, quote! { i32 }),
+        "i64" => (quote! { I64 }, quote! { i64 }),
+        "usize" => (quote! { Usize }, quote! { usize }),
+        "f64" => (quote! { F64 }, quote! { f64 }),
+        _ => return None,
+    };
+    Some(ret)
+}
+
+// generate option specs, typed option structs and their parsers
+fn option_items(item: &ItemStruct, defs: &[OptionDef]) -> TokenStream2 {
+    let ident = &item.ident;
+    let vis = &item.vis;
+
+    let specs = defs.iter().map(|def| {
+        let name = def.name.unraw().to_string();
+        let level = &def.level;
+        let (ty, _) = option_type(&def.ty).unwrap();
+        let default = match &def.default {
+            Some(default) => quote! { Some(#default) },
+            None => quote! { None },
+        };
+        let required = def.required;
+        let secret = def.secret;
+        let superuser = def.superuser;
+        quote! {
+            supabase_wrappers::options::OptionSpec {
+                name: #name,
+                level: supabase_wrappers::options::OptionLevel::#level,
+                ty: supabase_wrappers::options::OptionType::#ty,
+                default: #default,
+                required: #required,
+                secret: #secret,
+                superuser: #superuser,
+            }
+        }
+    });
+
+    // user mapping options are merged over server options, so they are
+    // parsed in the server options struct
+    let mut structs = TokenStream2::new();
+    for (suffix, levels) in [
+        ("ServerOptions", &["Server", "UserMapping"][..]),
+        ("TableOptions", &["Table"][..]),
+        ("ColumnOptions", &["Column"][..]),
+    ] {
+        let fields: Vec<(usize, &OptionDef)> = defs
+            .iter()
+            .enumerate()
+            .filter(|(_, def)| levels.contains(&def.level.to_string().as_str()))
+            .collect();
+        if fields.is_empty() {
+            continue;
+        }
+
+        let struct_ident = format_ident!("{}{}", ident, suffix);
+        let doc = format!(" Typed {} of [`{}`]", suffix, ident);
+        let field_decls = fields.iter().map(|(_, def)| {
+            let name = &def.name;
+            let (_, ty) = option_type(&def.ty).unwrap();
+            if def.required || def.default.is_some() {
+                quote! { pub #name: #ty }
+            } else {
+                quote! { pub #name: Option<#ty> }
+            }
+        });
+        let field_values = fields.iter().map(|(idx, def)| {
+            let name = &def.name;
+            if def.required || def.default.is_some() {
+                quote! {
+                    #name: supabase_wrappers::options::require_typed_option(
+                        &#ident::OPTION_SPECS[#idx],
+                        options,
+                    )?
+                }
+            } else {
+                quote! {
+                    #name: supabase_wrappers::options::get_option(
+                        &#ident::OPTION_SPECS[#idx],
+                        options,
+                    )?
+                }
+            }
+        });
+
+        structs.append_all(quote! {
+            #[doc = #doc]
+            #[derive(Debug, Clone)]
+            #vis struct #struct_ident {
+                #(#field_decls,)*
+            }
+
+            impl supabase_wrappers::options::FromOptions for #struct_ident {
+                fn from_options(
+                    options: &std::collections::HashMap<String, String>,
+                ) -> Result<Self, supabase_wrappers::options::OptionsError> {
+                    Ok(Self {
+                        #(#field_values,)*
+                    })
+                }
+            }
+        });
+    }
+
+    quote! {
+        impl #ident {
+            /// Options accepted by this foreign data wrapper
+            #vis const OPTION_SPECS: &'static [supabase_wrappers::options::OptionSpec] = &[
+                #(#specs,)*
+            ];
+        }
+
+        #structs
+    }
+}
 
 /// Create necessary handler, validator and meta functions for foreign data wrapper
 ///
@@ -36,22 +314,86 @@ use syn::{parse_macro_input, punctuated::Punctuated, ItemStruct, Lit, MetaNameVa
 ///
 /// select * from hello_world_fdw_meta();
 /// ```
+///
+/// # Options
+///
+/// The options accepted by the FDW can be declared with `options(...)`, grouped
+/// by the object they are specified on: `server`, `user_mapping`, `table` and
+/// `column`. Each option can have a `type` (`string` by default, or `bool`,
+/// `i32`, `i64`, `usize`, `f64`), a `default` value, and be `required`,
+/// `secret` or `superuser`. A secret option `xxx` can also be given as a secret
+/// reference like `env:MY_API_KEY` in option `xxx_secret`, or a Vault secret id
+/// in option `xxx_id` if `xxx` doesn't end with `_id`. A `superuser` option can
+/// only be set by superuser.
+///
+/// ```rust,ignore
+/// #[wrappers_fdw(
+///     version = "0.1.0",
+///     author = "Supabase",
+///     website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/helloworld_fdw",
+///     error_type = "HelloWorldFdwError",
+///     options(
+///         server(api_url(default = "https://api.example.com"), api_key(required, secret)),
+///         table(object(required), limit(type = "usize")),
+///     )
+/// )]
+/// pub struct HelloWorldFdw;
+/// ```
+///
+/// With options declared, the generated validator rejects unknown options,
+/// values which cannot be parsed as the option type and missing required
+/// options, before calling the FDW's own validator. It also generates:
+///
+/// - `HelloWorldFdw::OPTION_SPECS`, the option specs
+/// - `HelloWorldFdwServerOptions`, typed server and user mapping options
+/// - `HelloWorldFdwTableOptions`, typed foreign table options
+/// - `HelloWorldFdwColumnOptions`, typed column options
+///
+/// Server options cannot be specified in user mapping. When the FDW instance
+/// is created, only the declared `user_mapping` options are taken from the
+/// user mapping of current user and merged over the server options, all the
+/// other user mapping options are ignored.
+///
+/// The option structs implement `FromOptions`, so they can be parsed from the
+/// options passed to `new()` and `begin_scan()`. Optional fields without default
+/// value are `Option`s.
+///
+/// ```rust,ignore
+/// let opts = HelloWorldFdwTableOptions::from_options(options)?;
+/// let limit: Option<usize> = opts.limit;
+/// ```
+///
+/// Option specs shared by several FDWs can be accepted with `include(...)`,
+/// which takes paths to `&[OptionSpec]` constants. The included options are
+/// validated, but not added to the typed option structs.
+///
+/// ```rust,ignore
+/// options(server(api_key(secret)), include(crate::http::HTTP_OPTION_SPECS))
+/// ```
 #[proc_macro_attribute]
 pub fn wrappers_fdw(attr: TokenStream, item: TokenStream) -> TokenStream {
     let mut metas = TokenStream2::new();
-    let meta_attrs: Punctuated<MetaNameValue, Token![,]> =
+    let fdw_args: Punctuated<FdwArg, Token![,]> =
         parse_macro_input!(attr with Punctuated::parse_terminated);
     let mut error_type: Option<String> = None;
-    for attr in meta_attrs {
-        let name = format!("{}", attr.path.segments.first().unwrap().ident);
-        if let Lit::Str(val) = attr.lit {
-            let value = val.value();
-            if name == "version" || name == "author" || name == "website" {
-                metas.append_all(quote! {
-                    meta.insert(#name.to_owned(), #value.to_owned());
-                });
-            } else if name == "error_type" {
-                error_type = Some(value);
+    let mut option_defs: Option<Vec<OptionDef>> = None;
+    let mut option_includes: Vec<Path> = Vec::new();
+    for arg in fdw_args {
+        match arg {
+            FdwArg::NameValue(name, val) => {
+                let name = name.to_string();
+                let value = val.value();
+                if name == "version" || name == "author" || name == "website" {
+                    metas.append_all(quote! {
+                        meta.insert(#name.to_owned(), #value.to_owned());
+                    });
+                } else if name == "error_type" {
+                    error_type = Some(value);
+                }
+            }
+            FdwArg::Options(defs, includes) => {
+                option_defs = Some(defs);
+                option_includes = includes;
             }
         }
     }
@@ -67,7 +409,7 @@ pub fn wrappers_fdw(attr: TokenStream, item: TokenStream) -> TokenStream {
 
     let item: ItemStruct = parse_macro_input!(item as ItemStruct);
     let item_tokens = item.to_token_stream();
-    let ident = item.ident;
+    let ident = item.ident.clone();
     let ident_str = ident.to_string();
     let ident_snake = to_snake_case(ident_str.as_str());
 
@@ -76,9 +418,38 @@ pub fn wrappers_fdw(attr: TokenStream, item: TokenStream) -> TokenStream {
     let fn_validator_ident = format_ident!("{}_validator", ident_snake);
     let fn_meta_ident = format_ident!("{}_meta", ident_snake);
 
+    // with options schema, the option specs are registered so user mapping
+    // options can be told from server options, and options are validated
+    // before the fdw's own validator
+    let (options_tokens, register_specs, validate_options) = match &option_defs {
+        Some(defs) => (
+            option_items(&item, defs),
+            quote! {
+                let specs: Vec<OptionSpec> = #ident::OPTION_SPECS
+                    .iter()
+                    #(.chain(#option_includes.iter()))*
+                    .copied()
+                    .collect();
+                register_option_specs(std::any::type_name::<#ident>(), &specs);
+            },
+            quote! {
+                validate_options(&specs, &options, catalog)
+                    .map_err(|e| <OptionsError as Into<ErrorReport>>::into(e))
+                    .report();
+            },
+        ),
+        None => (
+            TokenStream2::new(),
+            TokenStream2::new(),
+            TokenStream2::new(),
+        ),
+    };
+
     let quoted = quote! {
         #item_tokens
 
+        #options_tokens
+
         mod #module_ident {
             use super::#ident;
             use std::collections::HashMap;
@@ -88,14 +459,18 @@ pub fn wrappers_fdw(attr: TokenStream, item: TokenStream) -> TokenStream {
 
             #[pg_extern(create_or_replace)]
             fn #fn_ident() -> supabase_wrappers::FdwRoutine {
+                #register_specs
                 #ident::fdw_routine()
             }
 
             #[pg_extern(create_or_replace)]
             fn #fn_validator_ident(options: Vec<Option<String>>, catalog: Option<pg_sys::Oid>) {
+                #register_specs
+                #validate_options
                 #ident::validator(options, catalog)
                     .map_err(|e| <super::#error_type_ident as Into<ErrorReport>>::into(e))
                     .report();
+                #ident::defer_describe_check(catalog);
             }
 
             #[pg_extern(create_or_replace)]
@@ -135,3 +510,50 @@ fn to_snake_case(s: &str) -> String {
     }
     acc.to_lowercase()
 }
+
+/// Derive `FromRow` for a struct which implements `serde::Deserialize`
+///
+/// The struct fields are deserialized from the row cells with the same column
+/// names, see `supabase_wrappers::json::FromRow`.
+#[proc_macro_derive(FromRow)]
+pub fn derive_from_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let quoted = quote! {
+        impl #impl_generics supabase_wrappers::json::FromRow for #ident #ty_generics #where_clause {
+            fn from_row(
+                row: &supabase_wrappers::interface::Row,
+            ) -> Result<Self, supabase_wrappers::json::JsonError> {
+                supabase_wrappers::json::from_row(row)
+            }
+        }
+    };
+
+    quoted.into()
+}
+
+/// Derive `IntoRow` for a struct which implements `serde::Serialize`
+///
+/// The struct fields are serialized to the cells of target columns with the
+/// same names, see `supabase_wrappers::json::IntoRow`.
+#[proc_macro_derive(IntoRow)]
+pub fn derive_into_row(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let quoted = quote! {
+        impl #impl_generics supabase_wrappers::json::IntoRow for #ident #ty_generics #where_clause {
+            fn into_row(
+                self,
+                columns: &[supabase_wrappers::interface::Column],
+            ) -> Result<supabase_wrappers::interface::Row, supabase_wrappers::json::JsonError> {
+                supabase_wrappers::json::into_row(self, columns)
+            }
+        }
+    };
+
+    quoted.into()
+}
@@ -0,0 +1,241 @@
+//! Adapter to drive an [`AsyncForeignDataWrapper`] as a [`ForeignDataWrapper`]
+//!
+
+use futures::StreamExt;
+use pgrx::pg_sys::panic::{ErrorReport, ErrorReportable};
+use pgrx::pg_sys::Oid;
+use pgrx::AllocatedByRust;
+use std::collections::HashMap;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use crate::error::FdwError;
+use crate::interface::{
+    AsyncForeignDataWrapper, Cell, Column, ForeignDataWrapper, Limit, Qual, RemoteMetrics, Row,
+    RowStream, ServerInfo, Sort,
+};
+use crate::options::OptionSpec;
+use crate::utils::{self, block_on_shared_interruptible, report_warning};
+use crate::FdwRoutine;
+
+// the cancel hook is called while the query cancel is pending, so its wait
+// cannot be interrupted and is bounded by this timeout instead
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(5);
+
+// scan parameters saved for restarting the scan
+struct ScanParams {
+    quals: Vec<Qual>,
+    columns: Vec<Column>,
+    sorts: Vec<Sort>,
+    limit: Option<Limit>,
+    options: HashMap<String, String>,
+}
+
+pub(crate) struct AsyncFdw<E: Into<ErrorReport> + 'static, W: AsyncForeignDataWrapper<E>> {
+    inner: W,
+    stream: Option<RowStream<E>>,
+    params: Option<ScanParams>,
+    _phantom: PhantomData<E>,
+}
+
+impl<E: Into<ErrorReport> + 'static, W: AsyncForeignDataWrapper<E>> AsyncFdw<E, W> {
+    // run a future on the shared runtime, raise an error if the runtime
+    // cannot be created
+    fn block_on<F: Future>(future: F) -> F::Output {
+        utils::block_on(future).map_err(ErrorReport::from).report()
+    }
+
+    // call the cancel hook, give up waiting for it after the cancel timeout
+    fn cancel_inner(&mut self) -> Result<(), E> {
+        match Self::block_on(tokio::time::timeout(CANCEL_TIMEOUT, self.inner.cancel())) {
+            Ok(result) => result,
+            Err(_) => {
+                report_warning("cancel remote request timed out");
+                Ok(())
+            }
+        }
+    }
+
+    // unwrap the result of an interruptible wait, if the query is cancelled
+    // call the cancel hook and raise the cancel error, other errors such as
+    // failing to create the runtime are raised too
+    fn check_cancel<T>(&mut self, result: Result<T, FdwError>) -> T {
+        if matches!(result, Err(FdwError::Cancelled)) {
+            utils::cancel_and_check_interrupts(self.cancel_inner());
+        }
+        Self::check_interrupts(result)
+    }
+
+    // unwrap the result of an interruptible wait which has no remote work
+    // to cancel, if the query is cancelled let Postgres raise the cancel
+    // error, other errors are raised too
+    fn check_interrupts<T>(result: Result<T, FdwError>) -> T {
+        match result {
+            Ok(output) => output,
+            Err(err) => {
+                if matches!(err, FdwError::Cancelled) {
+                    pgrx::pg_sys::check_for_interrupts!();
+                }
+
+                // interrupts are held off, raise the error directly
+                let _ = Err::<T, ErrorReport>(err.into()).report();
+                unreachable!("error is raised")
+            }
+        }
+    }
+}
+
+impl<E: Into<ErrorReport> + 'static, W: AsyncForeignDataWrapper<E>> ForeignDataWrapper<E>
+    for AsyncFdw<E, W>
+{
+    fn new(options: &HashMap<String, String>) -> Result<Self, E> {
+        Ok(Self {
+            inner: W::new(options)?,
+            stream: None,
+            params: None,
+            _phantom: PhantomData,
+        })
+    }
+
+    fn get_rel_size(
+        &mut self,
+        quals: &[Qual],
+        columns: &[Column],
+        sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+    ) -> Result<(i64, i32), E> {
+        self.inner
+            .get_rel_size(quals, columns, sorts, limit, options)
+    }
+
+    fn begin_scan(
+        &mut self,
+        quals: &[Qual],
+        columns: &[Column],
+        sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+    ) -> Result<(), E> {
+        self.stream = Some(
+            self.inner
+                .begin_scan(quals, columns, sorts, limit, options)?,
+        );
+        self.params = Some(ScanParams {
+            quals: quals.to_vec(),
+            columns: columns.to_vec(),
+            sorts: sorts.to_vec(),
+            limit: limit.clone(),
+            options: options.clone(),
+        });
+        Ok(())
+    }
+
+    fn iter_scan(&mut self, row: &mut Row) -> Result<Option<()>, E> {
+        if let Some(ref mut stream) = self.stream {
+            let next = block_on_shared_interruptible(stream.next());
+            match self.check_cancel(next) {
+                Some(Ok(src_row)) => return Ok(Some(row.replace_with(src_row))),
+                Some(Err(err)) => return Err(err),
+                None => {
+                    self.stream.take();
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn re_scan(&mut self) -> Result<(), E> {
+        // drop the old stream and start a new one from the beginning
+        self.stream.take();
+        if let Some(p) = &self.params {
+            self.stream = Some(
+                self.inner
+                    .begin_scan(&p.quals, &p.columns, &p.sorts, &p.limit, &p.options)?,
+            );
+        }
+        Ok(())
+    }
+
+    fn end_scan(&mut self) -> Result<(), E> {
+        self.stream.take();
+        self.params.take();
+        self.inner.end_scan()
+    }
+
+    fn begin_modify(&mut self, options: &HashMap<String, String>) -> Result<(), E> {
+        let result = block_on_shared_interruptible(self.inner.begin_modify(options));
+        self.check_cancel(result)
+    }
+
+    fn insert(&mut self, row: &Row) -> Result<(), E> {
+        let result = block_on_shared_interruptible(self.inner.insert(row));
+        self.check_cancel(result)
+    }
+
+    fn update(&mut self, rowid: &Cell, new_row: &Row) -> Result<(), E> {
+        let result = block_on_shared_interruptible(self.inner.update(rowid, new_row));
+        self.check_cancel(result)
+    }
+
+    fn delete(&mut self, rowid: &Cell) -> Result<(), E> {
+        let result = block_on_shared_interruptible(self.inner.delete(rowid));
+        self.check_cancel(result)
+    }
+
+    fn end_modify(&mut self) -> Result<(), E> {
+        let result = block_on_shared_interruptible(self.inner.end_modify());
+        self.check_cancel(result)
+    }
+
+    fn cancel(&mut self) -> Result<(), E> {
+        self.cancel_inner()
+    }
+
+    fn explain(&self, verbose: bool) -> Vec<(String, String)> {
+        self.inner.explain(verbose)
+    }
+
+    fn remote_metrics(&self) -> Option<RemoteMetrics> {
+        self.inner.remote_metrics()
+    }
+
+    fn describe_columns(
+        options: &HashMap<String, String>,
+        table_options: &HashMap<String, String>,
+    ) -> Result<Option<Vec<Column>>, E> {
+        Self::check_interrupts(block_on_shared_interruptible(W::describe_columns(
+            options,
+            table_options,
+        )))
+    }
+
+    fn remote_query(&mut self, sql: &str) -> Result<Option<Vec<Row>>, E> {
+        let result = block_on_shared_interruptible(self.inner.remote_query(sql));
+        self.check_cancel(result)
+    }
+
+    fn remote_exec(&mut self, sql: &str) -> Result<Option<()>, E> {
+        let result = block_on_shared_interruptible(self.inner.remote_exec(sql));
+        self.check_cancel(result)
+    }
+
+    fn check_connection(&self) -> Result<Option<ServerInfo>, E> {
+        // the connection check is a read-only request, dropping it is
+        // enough to stop it on query cancel
+        Self::check_interrupts(block_on_shared_interruptible(self.inner.check_connection()))
+    }
+
+    fn option_specs() -> Vec<OptionSpec> {
+        W::option_specs()
+    }
+
+    fn fdw_routine_hook(routine: &mut FdwRoutine<AllocatedByRust>) {
+        W::fdw_routine_hook(routine)
+    }
+
+    fn validator(options: Vec<Option<String>>, catalog: Option<Oid>) -> Result<(), E> {
+        W::validator(options, catalog)
+    }
+}
@@ -0,0 +1,211 @@
+//! Scan result cache
+//!
+//! When a foreign table has the `cache_ttl` option, the rows returned by a
+//! foreign scan are kept in memory of current backend for `cache_ttl` seconds,
+//! and the same scan on that table is served from the cache without calling
+//! the FDW. A scan is the same if it has the same pushed down quals, target
+//! columns, sorts and limit, and is run by the same user, as the user mapping
+//! may be different between users.
+//!
+//! The scan is only cached if it is fully iterated and returns no more than
+//! `cache_max_rows` rows. The cache entries of a foreign table are dropped
+//! in all backends when the foreign table is altered or modified, or when
+//! [`invalidate_cache`] is called.
+
+use pgrx::pg_guard;
+use pgrx::pg_sys::panic::{ErrorReport, ErrorReportable};
+use pgrx::pg_sys::{self, Datum, Oid};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::interface::{Column, Limit, Qual, Row, Sort};
+use crate::options::{framework_option_spec, get_option};
+
+// default max number of rows can be cached for a scan
+const DEFAULT_CACHE_MAX_ROWS: usize = 10000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    ftable_id: Oid,
+    user_id: Oid,
+    scan: String,
+}
+
+struct CacheEntry {
+    rows: Rc<Vec<Row>>,
+    expires_at: Instant,
+}
+
+thread_local! {
+    static RESULT_CACHE: RefCell<HashMap<CacheKey, CacheEntry>> = RefCell::new(HashMap::new());
+    static INVAL_CALLBACK_REGISTERED: Cell<bool> = Cell::new(false);
+}
+
+// drop cache entries when the foreign table's relcache entry is invalidated,
+// invalid relid means all relations are invalidated
+#[pg_guard]
+extern "C" fn cache_inval_callback(_arg: Datum, relid: Oid) {
+    RESULT_CACHE.with(|cache| {
+        cache
+            .borrow_mut()
+            .retain(|key, _| relid != pg_sys::InvalidOid && key.ftable_id != relid)
+    });
+}
+
+fn register_inval_callback() {
+    INVAL_CALLBACK_REGISTERED.with(|registered| {
+        if !registered.get() {
+            unsafe {
+                pg_sys::CacheRegisterRelcacheCallback(
+                    Some(cache_inval_callback),
+                    Datum::from(0usize),
+                );
+            }
+            registered.set(true);
+        }
+    });
+}
+
+/// Invalidate the scan result cache
+///
+/// Drop the cached scan results of a foreign table, or all foreign tables if
+/// `ftable_id` is `None`. The invalidation is sent to all backends when the
+/// current transaction commits.
+pub fn invalidate_cache(ftable_id: Option<Oid>) {
+    unsafe {
+        match ftable_id {
+            Some(ftable_id) => pg_sys::CacheInvalidateRelcacheByRelid(ftable_id),
+            None => pg_sys::CacheInvalidateRelcacheAll(),
+        }
+    }
+}
+
+// result cache state of a foreign scan
+pub(crate) enum ScanCache {
+    // cache is not enabled, or the scan result cannot be cached
+    Off,
+
+    // the scan result is not cached yet, collecting the rows returned by FDW
+    Fill {
+        key: CacheKey,
+        ttl: Duration,
+        max_rows: usize,
+        rows: Vec<Row>,
+    },
+
+    // serving the scan result from cache
+    Hit {
+        rows: Rc<Vec<Row>>,
+        pos: usize,
+    },
+}
+
+impl ScanCache {
+    // look up the cache for a scan, `Off` is returned if the foreign table
+    // doesn't have the `cache_ttl` option
+    pub(crate) fn begin(
+        ftable_id: Oid,
+        quals: &[Qual],
+        tgts: &[Column],
+        sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+    ) -> Self {
+        let ttl = get_option::<i64>(framework_option_spec("cache_ttl"), options)
+            .map_err(ErrorReport::from)
+            .report();
+        let ttl = match ttl {
+            Some(ttl) if ttl > 0 => Duration::from_secs(ttl as u64),
+            _ => return ScanCache::Off,
+        };
+        let max_rows = get_option::<usize>(framework_option_spec("cache_max_rows"), options)
+            .map_err(ErrorReport::from)
+            .report()
+            .unwrap_or(DEFAULT_CACHE_MAX_ROWS);
+
+        register_inval_callback();
+
+        let key = CacheKey {
+            ftable_id,
+            user_id: unsafe { pg_sys::GetUserId() },
+            scan: format!("{:?} {:?} {:?} {:?}", quals, tgts, sorts, limit),
+        };
+        let now = Instant::now();
+        let cached = RESULT_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            cache.retain(|_, entry| entry.expires_at > now);
+            cache.get(&key).map(|entry| entry.rows.clone())
+        });
+        match cached {
+            Some(rows) => ScanCache::Hit { rows, pos: 0 },
+            None => ScanCache::Fill {
+                key,
+                ttl,
+                max_rows,
+                rows: Vec::new(),
+            },
+        }
+    }
+
+    #[inline]
+    pub(crate) fn is_enabled(&self) -> bool {
+        !matches!(self, ScanCache::Off)
+    }
+
+    #[inline]
+    pub(crate) fn is_hit(&self) -> bool {
+        matches!(self, ScanCache::Hit { .. })
+    }
+
+    // get next cached row, returns `None` when all rows are returned
+    pub(crate) fn next_row(&mut self, row: &mut Row) -> Option<()> {
+        if let ScanCache::Hit { rows, pos } = self {
+            let cached = rows.get(*pos)?;
+            row.replace_with(cached.clone());
+            *pos += 1;
+            return Some(());
+        }
+        None
+    }
+
+    // collect a row returned by FDW, stop caching if there are too many rows
+    pub(crate) fn add_row(&mut self, row: &Row) {
+        if let ScanCache::Fill { max_rows, rows, .. } = self {
+            if rows.len() >= *max_rows {
+                *self = ScanCache::Off;
+            } else {
+                rows.push(row.clone());
+            }
+        }
+    }
+
+    // save the collected rows when the scan is fully iterated, and then serve
+    // the re-scans from them
+    pub(crate) fn finish(&mut self) {
+        if let ScanCache::Fill { key, ttl, rows, .. } = std::mem::replace(self, ScanCache::Off) {
+            let rows = Rc::new(rows);
+            let pos = rows.len();
+            RESULT_CACHE.with(|cache| {
+                cache.borrow_mut().insert(
+                    key,
+                    CacheEntry {
+                        rows: rows.clone(),
+                        expires_at: Instant::now() + ttl,
+                    },
+                )
+            });
+            *self = ScanCache::Hit { rows, pos };
+        }
+    }
+
+    // restart from the first row
+    pub(crate) fn rewind(&mut self) {
+        match self {
+            ScanCache::Fill { rows, .. } => rows.clear(),
+            ScanCache::Hit { pos, .. } => *pos = 0,
+            ScanCache::Off => {}
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Check foreign table columns against the remote table when it is created
+//!
+//! A FDW can describe the remote table's columns by implementing
+//! [`describe_columns`](crate::interface::ForeignDataWrapper::describe_columns).
+//! When a foreign table is created, the validator generated by
+//! [`wrappers_fdw`](crate::wrappers_fdw) macro saves a pending check, which is
+//! then run by a utility hook right after the `CREATE FOREIGN TABLE` statement
+//! is executed, when the foreign table and its server are both available.
+//!
+//! The utility hook is installed by [`init`](crate::init), which should be
+//! called in the extension's `_PG_init()`. If the remote columns cannot be
+//! described, a warning is raised and the check is skipped, so the foreign
+//! table can still be created when the remote server is unavailable.
+
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::prelude::*;
+use pgrx::{pg_sys, IntoDatum};
+use std::cell::Cell;
+use std::ffi::c_char;
+
+use crate::instance;
+use crate::interface::{Column, ForeignDataWrapper, FOREIGN_TABLE_RELATION_ID};
+use crate::utils::{self, report_error, report_warning};
+
+thread_local! {
+    // check for the foreign table being created, saved by the validator
+    static PENDING_CHECK: Cell<Option<unsafe fn(pg_sys::Oid)>> = Cell::new(None);
+}
+
+static mut PREV_PROCESS_UTILITY_HOOK: pg_sys::ProcessUtility_hook_type = None;
+
+// install the utility hook, must be called only once in `_PG_init()`
+pub(crate) unsafe fn install_hook() {
+    PREV_PROCESS_UTILITY_HOOK = pg_sys::ProcessUtility_hook;
+    pg_sys::ProcessUtility_hook = Some(process_utility_hook);
+}
+
+// save a pending column check if a foreign table is being created
+pub(crate) fn defer_check<E: Into<ErrorReport>, W: ForeignDataWrapper<E>>(
+    catalog: Option<pg_sys::Oid>,
+) {
+    if catalog == Some(FOREIGN_TABLE_RELATION_ID) {
+        PENDING_CHECK.with(|check| check.set(Some(check_columns::<E, W>)));
+    }
+}
+
+// get local columns of a foreign table, dropped columns are excluded
+fn get_table_columns(ftable_id: pg_sys::Oid) -> Vec<Column> {
+    Spi::connect(|client| {
+        let tup_table = client.select(
+            "select attname::text, attnum, atttypid from pg_catalog.pg_attribute
+             where attrelid = $1 and attnum > 0 and not attisdropped
+             order by attnum",
+            None,
+            Some(vec![(PgBuiltInOids::OIDOID.oid(), ftable_id.into_datum())]),
+        )?;
+        let mut columns = Vec::new();
+        for row in tup_table {
+            columns.push(Column {
+                name: row.get_by_name::<String, _>("attname")?.unwrap_or_default(),
+                num: row.get_by_name::<i16, _>("attnum")?.unwrap_or_default() as usize,
+                type_oid: row
+                    .get_by_name::<pg_sys::Oid, _>("atttypid")?
+                    .unwrap_or(pg_sys::InvalidOid),
+            });
+        }
+        Ok::<_, pgrx::spi::Error>(columns)
+    })
+    .unwrap_or_default()
+}
+
+unsafe fn check_columns<E: Into<ErrorReport>, W: ForeignDataWrapper<E>>(ftable_id: pg_sys::Oid) {
+    let ftable = pg_sys::GetForeignTable(ftable_id);
+    let server_id = (*ftable).serverid;
+    let fserver = pg_sys::GetForeignServer(server_id);
+
+    let user_mapping = instance::get_user_mapping(server_id);
+    let table_opts = utils::options_to_hashmap((*ftable).options);
+
+    let remote_cols = instance::with_server_options::<E, W, _>(fserver, user_mapping, |opts| {
+        W::describe_columns(opts, &table_opts)
+    });
+    let remote_cols = match remote_cols {
+        Ok(Some(cols)) => cols,
+        Ok(None) => return,
+        Err(err) => {
+            // remote failure should not stop the foreign table being created
+            let report: ErrorReport = err.into();
+            report_warning(&format!(
+                "cannot check columns against remote table: {}",
+                report.message()
+            ));
+            return;
+        }
+    };
+
+    for col in get_table_columns(ftable_id) {
+        let remote_col = match remote_cols.iter().find(|c| c.name == col.name) {
+            Some(remote_col) => remote_col,
+            None => {
+                report_error(
+                    PgSqlErrorCode::ERRCODE_FDW_COLUMN_NAME_NOT_FOUND,
+                    &format!("column \"{}\" does not exist in remote table", col.name),
+                );
+                return;
+            }
+        };
+        if remote_col.type_oid == pg_sys::InvalidOid {
+            report_error(
+                PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+                &format!(
+                    "column \"{}\" data type in remote table is not supported",
+                    col.name
+                ),
+            );
+            return;
+        }
+        if !utils::is_type_compatible(remote_col.type_oid, col.type_oid) {
+            report_error(
+                PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+                &format!(
+                    "column \"{}\" is of type {} but remote column is mapped to type {}",
+                    col.name,
+                    utils::type_name(col.type_oid),
+                    utils::type_name(remote_col.type_oid),
+                ),
+            );
+            return;
+        }
+    }
+}
+
+// run the pending check after a foreign table is created
+unsafe fn run_pending_check(pstmt: *mut pg_sys::PlannedStmt) {
+    let check = PENDING_CHECK.with(|check| check.take());
+    let check = match check {
+        Some(check) => check,
+        None => return,
+    };
+
+    let stmt = (*pstmt).utilityStmt;
+    if stmt.is_null() || (*stmt).type_ != pg_sys::NodeTag_T_CreateForeignTableStmt {
+        return;
+    }
+    let stmt = stmt as *mut pg_sys::CreateForeignTableStmt;
+    let ftable_id = pg_sys::RangeVarGetRelidExtended(
+        (*stmt).base.relation,
+        pg_sys::NoLock as _,
+        0,
+        None,
+        std::ptr::null_mut(),
+    );
+    check(ftable_id);
+}
+
+#[cfg(any(feature = "pg14", feature = "pg15"))]
+#[pg_guard]
+unsafe extern "C" fn process_utility_hook(
+    pstmt: *mut pg_sys::PlannedStmt,
+    query_string: *const c_char,
+    read_only_tree: bool,
+    context: pg_sys::ProcessUtilityContext,
+    params: pg_sys::ParamListInfo,
+    query_env: *mut pg_sys::QueryEnvironment,
+    dest: *mut pg_sys::DestReceiver,
+    qc: *mut pg_sys::QueryCompletion,
+) {
+    // discard the check left by a failed statement
+    PENDING_CHECK.with(|check| check.take());
+
+    match PREV_PROCESS_UTILITY_HOOK {
+        Some(prev_hook) => prev_hook(
+            pstmt,
+            query_string,
+            read_only_tree,
+            context,
+            params,
+            query_env,
+            dest,
+            qc,
+        ),
+        None => pg_sys::standard_ProcessUtility(
+            pstmt,
+            query_string,
+            read_only_tree,
+            context,
+            params,
+            query_env,
+            dest,
+            qc,
+        ),
+    }
+
+    run_pending_check(pstmt);
+}
+
+#[cfg(feature = "pg13")]
+#[pg_guard]
+unsafe extern "C" fn process_utility_hook(
+    pstmt: *mut pg_sys::PlannedStmt,
+    query_string: *const c_char,
+    context: pg_sys::ProcessUtilityContext,
+    params: pg_sys::ParamListInfo,
+    query_env: *mut pg_sys::QueryEnvironment,
+    dest: *mut pg_sys::DestReceiver,
+    qc: *mut pg_sys::QueryCompletion,
+) {
+    // discard the check left by a failed statement
+    PENDING_CHECK.with(|check| check.take());
+
+    match PREV_PROCESS_UTILITY_HOOK {
+        Some(prev_hook) => prev_hook(pstmt, query_string, context, params, query_env, dest, qc),
+        None => pg_sys::standard_ProcessUtility(
+            pstmt,
+            query_string,
+            context,
+            params,
+            query_env,
+            dest,
+            qc,
+        ),
+    }
+
+    run_pending_check(pstmt);
+}
+
+#[cfg(any(feature = "pg11", feature = "pg12"))]
+#[pg_guard]
+unsafe extern "C" fn process_utility_hook(
+    pstmt: *mut pg_sys::PlannedStmt,
+    query_string: *const c_char,
+    context: pg_sys::ProcessUtilityContext,
+    params: pg_sys::ParamListInfo,
+    query_env: *mut pg_sys::QueryEnvironment,
+    dest: *mut pg_sys::DestReceiver,
+    completion_tag: *mut c_char,
+) {
+    // discard the check left by a failed statement
+    PENDING_CHECK.with(|check| check.take());
+
+    match PREV_PROCESS_UTILITY_HOOK {
+        Some(prev_hook) => prev_hook(
+            pstmt,
+            query_string,
+            context,
+            params,
+            query_env,
+            dest,
+            completion_tag,
+        ),
+        None => pg_sys::standard_ProcessUtility(
+            pstmt,
+            query_string,
+            context,
+            params,
+            query_env,
+            dest,
+            completion_tag,
+        ),
+    }
+
+    run_pending_check(pstmt);
+}
@@ -0,0 +1,239 @@
+//! Structured errors for foreign data wrappers
+//!
+//! [`FdwError`] classifies the common failures when talking to a remote
+//! source, each kind is reported with its own SQLSTATE so that clients can
+//! handle them differently, for example retry on rate limiting. An error can
+//! also carry a detail and a hint, which are shown in the `DETAIL` and `HINT`
+//! fields of the Postgres error report.
+//!
+//! A FDW can wrap it in its own error type and return it from the callback
+//! functions, instead of calling [`report_error`](crate::utils::report_error)
+//! in the middle of a function.
+//!
+//! ```rust,no_run
+//! use pgrx::pg_sys::panic::ErrorReport;
+//! use supabase_wrappers::prelude::*;
+//! use thiserror::Error;
+//!
+//! #[derive(Error, Debug)]
+//! enum HelloWorldFdwError {
+//!     #[error("{0}")]
+//!     FdwError(#[from] FdwError),
+//! }
+//!
+//! impl From<HelloWorldFdwError> for ErrorReport {
+//!     fn from(value: HelloWorldFdwError) -> Self {
+//!         match value {
+//!             HelloWorldFdwError::FdwError(e) => e.into(),
+//!         }
+//!     }
+//! }
+//!
+//! fn fetch(status: u16) -> Result<(), HelloWorldFdwError> {
+//!     if status == 401 {
+//!         return Err(FdwError::auth_failed("request failed: unauthorized")
+//!             .with_hint("Check the api_key option of the foreign server.")
+//!             .into());
+//!     }
+//!     Ok(())
+//! }
+//! ```
+
+use crate::json::JsonError;
+use crate::options::OptionsError;
+use crate::utils::CreateRuntimeError;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::prelude::*;
+use std::fmt;
+use thiserror::Error;
+
+/// Message, detail and hint of an error
+#[derive(Debug, Clone, Default)]
+pub struct ErrorInfo {
+    pub message: String,
+    pub detail: Option<String>,
+    pub hint: Option<String>,
+}
+
+impl ErrorInfo {
+    fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+}
+
+impl fmt::Display for ErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FdwError {
+    /// Remote source rejected the credentials
+    #[error("{0}")]
+    AuthFailed(ErrorInfo),
+
+    /// Remote object is not found
+    #[error("{0}")]
+    NotFound(ErrorInfo),
+
+    /// Request is throttled by remote source
+    #[error("{0}")]
+    RateLimited(ErrorInfo),
+
+    /// Cannot connect to remote source
+    #[error("{0}")]
+    ConnectionFailed(ErrorInfo),
+
+    /// Remote source failed to execute a query or request
+    #[error("{0}")]
+    RemoteQuery(ErrorInfo),
+
+    /// Remote value cannot be converted to or from a Postgres value
+    #[error("{0}")]
+    TypeConversion(ErrorInfo),
+
+    /// Remote call is aborted because the query is being cancelled, see
+    /// [`block_on_interruptible`](crate::utils::block_on_interruptible)
+    #[error("canceling remote request due to query cancel")]
+    Cancelled,
+
+    #[error("{0}")]
+    OptionsError(#[from] OptionsError),
+
+    #[error("{0}")]
+    JsonError(#[from] JsonError),
+
+    #[error("{0}")]
+    CreateRuntimeError(#[from] CreateRuntimeError),
+}
+
+impl FdwError {
+    pub fn auth_failed(message: impl Into<String>) -> Self {
+        Self::AuthFailed(ErrorInfo::new(message))
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::NotFound(ErrorInfo::new(message))
+    }
+
+    pub fn rate_limited(message: impl Into<String>) -> Self {
+        Self::RateLimited(ErrorInfo::new(message))
+    }
+
+    pub fn connection_failed(message: impl Into<String>) -> Self {
+        Self::ConnectionFailed(ErrorInfo::new(message))
+    }
+
+    pub fn remote_query(message: impl Into<String>) -> Self {
+        Self::RemoteQuery(ErrorInfo::new(message))
+    }
+
+    pub fn type_conversion(message: impl Into<String>) -> Self {
+        Self::TypeConversion(ErrorInfo::new(message))
+    }
+
+    /// Create an error from a failed HTTP response status code
+    ///
+    /// 401 and 403 are authentication failures, 404 is not found and 429 is
+    /// rate limited, all the others are remote query errors.
+    pub fn from_http_status(status: u16, message: impl Into<String>) -> Self {
+        match status {
+            401 | 403 => Self::auth_failed(message),
+            404 => Self::not_found(message),
+            429 => Self::rate_limited(message)
+                .with_hint("Retry later or lower the request rate to the remote server."),
+            _ => Self::remote_query(message),
+        }
+    }
+
+    fn info_mut(&mut self) -> Option<&mut ErrorInfo> {
+        match self {
+            Self::AuthFailed(info)
+            | Self::NotFound(info)
+            | Self::RateLimited(info)
+            | Self::ConnectionFailed(info)
+            | Self::RemoteQuery(info)
+            | Self::TypeConversion(info) => Some(info),
+            _ => None,
+        }
+    }
+
+    /// Set the error detail, it has no effect on wrapped errors
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        if let Some(info) = self.info_mut() {
+            info.detail = Some(detail.into());
+        }
+        self
+    }
+
+    /// Set the error hint, it has no effect on wrapped errors
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        if let Some(info) = self.info_mut() {
+            info.hint = Some(hint.into());
+        }
+        self
+    }
+
+    /// Short name of the error kind, for example `auth_failed`, which can be
+    /// used to count errors by kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::AuthFailed(_) => "auth_failed",
+            Self::NotFound(_) => "not_found",
+            Self::RateLimited(_) => "rate_limited",
+            Self::ConnectionFailed(_) => "connection_failed",
+            Self::RemoteQuery(_) => "remote_query",
+            Self::TypeConversion(_) => "type_conversion",
+            Self::Cancelled => "cancelled",
+            Self::OptionsError(_) => "options",
+            Self::JsonError(_) => "json",
+            Self::CreateRuntimeError(_) => "runtime",
+        }
+    }
+
+    /// SQLSTATE of the error
+    pub fn sqlstate(&self) -> PgSqlErrorCode {
+        match self {
+            Self::AuthFailed(_) => PgSqlErrorCode::ERRCODE_INVALID_AUTHORIZATION_SPECIFICATION,
+            Self::NotFound(_) => PgSqlErrorCode::ERRCODE_FDW_TABLE_NOT_FOUND,
+            Self::RateLimited(_) => PgSqlErrorCode::ERRCODE_CONFIGURATION_LIMIT_EXCEEDED,
+            Self::ConnectionFailed(_) => PgSqlErrorCode::ERRCODE_FDW_UNABLE_TO_ESTABLISH_CONNECTION,
+            Self::RemoteQuery(_) => PgSqlErrorCode::ERRCODE_FDW_ERROR,
+            Self::TypeConversion(_) => PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+            Self::Cancelled => PgSqlErrorCode::ERRCODE_QUERY_CANCELED,
+            Self::OptionsError(e) => e.sqlstate(),
+            Self::JsonError(_) => PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+            Self::CreateRuntimeError(_) => PgSqlErrorCode::ERRCODE_FDW_ERROR,
+        }
+    }
+}
+
+impl From<FdwError> for ErrorReport {
+    fn from(value: FdwError) -> Self {
+        let code = value.sqlstate();
+        let info = match value {
+            FdwError::AuthFailed(info)
+            | FdwError::NotFound(info)
+            | FdwError::RateLimited(info)
+            | FdwError::ConnectionFailed(info)
+            | FdwError::RemoteQuery(info)
+            | FdwError::TypeConversion(info) => info,
+            FdwError::Cancelled => ErrorInfo::new(FdwError::Cancelled.to_string()),
+            FdwError::OptionsError(e) => return e.into(),
+            FdwError::JsonError(e) => return e.into(),
+            FdwError::CreateRuntimeError(e) => return e.into(),
+        };
+        let mut report = ErrorReport::new(code, info.message, "");
+        if let Some(detail) = info.detail {
+            report = report.set_detail(detail);
+        }
+        if let Some(hint) = info.hint {
+            report = report.set_hint(hint);
+        }
+        report
+    }
+}
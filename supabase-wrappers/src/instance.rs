@@ -1,16 +1,324 @@
+//! Per-backend cache of foreign data wrapper instances
+//!
+//! Creating a FDW instance can be expensive, for example it may create an
+//! async runtime, a HTTP client or a database connection pool. To avoid paying
+//! that cost on every statement, instances are checked out from a backend-local
+//! pool when a scan or modify starts and checked back in when it ends.
+//! Instances are keyed by foreign server and user mapping, and the pool
+//! entries are removed through syscache callbacks whenever those catalog
+//! entries are changed.
+//!
+//! An instance is only checked back in when its scan or modify ends normally.
+//! If the statement is aborted by an error, the instance may be left with a
+//! running remote query or a half-read result, so it is dropped instead.
+
 use crate::prelude::*;
 use pgrx::pg_sys::panic::{ErrorReport, ErrorReportable};
+use pgrx::pg_sys::Datum;
 use pgrx::prelude::*;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::ops::{Deref, DerefMut};
+use std::os::raw::c_int;
+
+use super::{options, secret, utils};
+
+// cache key, a foreign server and the user mapping resolved for current user,
+// the user mapping oid is invalid if there is no user mapping defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct InstanceKey {
+    server_id: pg_sys::Oid,
+    umid: pg_sys::Oid,
+}
+
+// a type-erased boxed FDW instance
+//
+// The instance type is not stored in the cache key because a foreign server
+// always belongs to one foreign data wrapper, thus one handler and one
+// instance type.
+struct ErasedInstance {
+    ptr: *mut c_void,
+    drop_fn: unsafe fn(*mut c_void),
+}
+
+impl ErasedInstance {
+    fn new<W>(instance: Box<W>) -> Self {
+        unsafe fn drop_boxed<W>(ptr: *mut c_void) {
+            drop(Box::from_raw(ptr as *mut W));
+        }
+
+        Self {
+            ptr: Box::into_raw(instance) as _,
+            drop_fn: drop_boxed::<W>,
+        }
+    }
+
+    // the caller must make sure `W` is the type this instance was created with
+    unsafe fn into_inner<W>(self) -> Box<W> {
+        let ptr = self.ptr as *mut W;
+        std::mem::forget(self);
+        Box::from_raw(ptr)
+    }
+}
+
+impl Drop for ErasedInstance {
+    fn drop(&mut self) {
+        unsafe { (self.drop_fn)(self.ptr) }
+    }
+}
+
+struct CacheEntry {
+    // syscache hash values, used to match invalidation messages
+    server_hash: u32,
+    um_hash: u32,
+
+    // cache generation when this entry is created, instances checked out
+    // from an invalidated entry are not returned to the new one
+    generation: u64,
+
+    // idle instances ready to be reused
+    idle: Vec<ErasedInstance>,
+}
+
+#[derive(Default)]
+struct InstanceCache {
+    callbacks_registered: bool,
+
+    // bumped on each invalidation
+    generation: u64,
+
+    entries: HashMap<InstanceKey, CacheEntry>,
+}
+
+impl InstanceCache {
+    fn check_in(&mut self, key: &InstanceKey, generation: u64, instance: ErasedInstance) {
+        if let Some(entry) = self.entries.get_mut(key) {
+            if entry.generation == generation {
+                entry.idle.push(instance);
+            }
+        }
+    }
+}
+
+thread_local! {
+    static INSTANCE_CACHE: RefCell<InstanceCache> = RefCell::new(InstanceCache::default());
+}
+
+/// A FDW instance checked out from the instance cache
+///
+/// It can be used as the instance itself. It is returned to the cache when
+/// released, or dropped without being reused if it is not released.
+pub(super) struct CachedInstance<W> {
+    key: InstanceKey,
+    generation: u64,
+    instance: Option<Box<W>>,
+}
+
+impl<W> Deref for CachedInstance<W> {
+    type Target = W;
+
+    fn deref(&self) -> &Self::Target {
+        self.instance.as_ref().unwrap()
+    }
+}
+
+impl<W> DerefMut for CachedInstance<W> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.instance.as_mut().unwrap()
+    }
+}
+
+impl<W> CachedInstance<W> {
+    pub(super) fn server_id(&self) -> pg_sys::Oid {
+        self.key.server_id
+    }
 
-use super::utils;
+    // whether the instance is still checked out, it cannot be used after it is
+    // released
+    pub(super) fn is_checked_out(&self) -> bool {
+        self.instance.is_some()
+    }
+
+    // return the instance to the cache, so it can be reused by other scans or
+    // modifies, this is only called when a scan or modify ends normally
+    pub(super) fn release(&mut self) {
+        if let Some(instance) = self.instance.take() {
+            let _ = INSTANCE_CACHE.try_with(|cache| {
+                if let Ok(mut cache) = cache.try_borrow_mut() {
+                    cache.check_in(&self.key, self.generation, ErasedInstance::new(instance));
+                }
+            });
+        }
+    }
+}
+
+// drop cached instances when foreign server, user mapping or foreign data
+// wrapper is changed
+#[pg_guard]
+extern "C" fn invalidate_callback(_arg: Datum, cacheid: c_int, hashvalue: u32) {
+    let cacheid = cacheid as u32;
+    INSTANCE_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        cache.generation += 1;
+        cache.entries.retain(|_, entry| {
+            if cacheid == pg_sys::SysCacheIdentifier_FOREIGNSERVEROID {
+                hashvalue != 0 && entry.server_hash != hashvalue
+            } else if cacheid == pg_sys::SysCacheIdentifier_USERMAPPINGOID {
+                hashvalue != 0 && entry.um_hash != hashvalue
+            } else {
+                false
+            }
+        });
+    });
+}
+
+unsafe fn register_callbacks(cache: &mut InstanceCache) {
+    if cache.callbacks_registered {
+        return;
+    }
+    for cacheid in [
+        pg_sys::SysCacheIdentifier_FOREIGNDATAWRAPPEROID,
+        pg_sys::SysCacheIdentifier_FOREIGNSERVEROID,
+        pg_sys::SysCacheIdentifier_USERMAPPINGOID,
+    ] {
+        pg_sys::CacheRegisterSyscacheCallback(
+            cacheid.try_into().unwrap(),
+            Some(invalidate_callback),
+            Datum::from(0usize),
+        );
+    }
+    cache.callbacks_registered = true;
+}
 
-// create a fdw instance
+// get user mapping for current user, or PUBLIC user mapping if it is not defined
+pub(super) unsafe fn get_user_mapping(server_id: pg_sys::Oid) -> Option<*mut pg_sys::UserMapping> {
+    let user_id = pg_sys::GetUserId();
+    let exists = |uid: pg_sys::Oid| {
+        pg_sys::SearchSysCacheExists(
+            pg_sys::SysCacheIdentifier_USERMAPPINGUSERSERVER
+                .try_into()
+                .unwrap(),
+            Datum::from(uid),
+            Datum::from(server_id),
+            Datum::from(0usize),
+            Datum::from(0usize),
+        )
+    };
+    if exists(user_id) || exists(pg_sys::InvalidOid) {
+        Some(pg_sys::GetUserMapping(user_id, server_id))
+    } else {
+        None
+    }
+}
+
+// get options of a foreign server and the roles who set them, the options
+// declared on user mapping level by the FDW are taken from the user mapping
+// and take precedence
+unsafe fn get_server_options<E: Into<ErrorReport>, W: ForeignDataWrapper<E>>(
+    fserver: *mut pg_sys::ForeignServer,
+    user_mapping: Option<*mut pg_sys::UserMapping>,
+) -> (HashMap<String, String>, HashMap<String, pg_sys::Oid>) {
+    let mut opts = utils::options_to_hashmap((*fserver).options);
+    let mut owners: HashMap<String, pg_sys::Oid> = opts
+        .keys()
+        .map(|name| (name.to_owned(), (*fserver).owner))
+        .collect();
+    if let Some(um) = user_mapping {
+        let specs = W::option_specs();
+        for (name, value) in utils::options_to_hashmap((*um).options) {
+            if options::is_user_mapping_option(&specs, &name) {
+                owners.insert(name.clone(), (*um).userid);
+                opts.insert(name, value);
+            }
+        }
+    }
+    (opts, owners)
+}
+
+// run a function with server options, secrets in the options are resolved with
+// the privilege of the server owner, or the user of user mapping for the
+// options taken from it
+pub(super) unsafe fn with_server_options<E: Into<ErrorReport>, W: ForeignDataWrapper<E>, R>(
+    fserver: *mut pg_sys::ForeignServer,
+    user_mapping: Option<*mut pg_sys::UserMapping>,
+    f: impl FnOnce(&HashMap<String, String>) -> R,
+) -> R {
+    let (opts, owners) = get_server_options::<E, W>(fserver, user_mapping);
+    secret::with_option_owners(owners, || f(&opts))
+}
+
+// get a fdw instance of a foreign server from cache, or create a new one if
+// there is no idle one
 pub(super) unsafe fn create_fdw_instance<E: Into<ErrorReport>, W: ForeignDataWrapper<E>>(
-    ftable_id: pg_sys::Oid,
-) -> W {
-    let ftable = pg_sys::GetForeignTable(ftable_id);
-    let fserver = pg_sys::GetForeignServer((*ftable).serverid);
-    let fserver_opts = utils::options_to_hashmap((*fserver).options);
-    let wrapper = W::new(&fserver_opts);
-    wrapper.map_err(|e| e.into()).report()
+    server_id: pg_sys::Oid,
+) -> CachedInstance<W> {
+    let fserver = pg_sys::GetForeignServer(server_id);
+    let user_mapping = get_user_mapping(server_id);
+
+    let key = InstanceKey {
+        server_id,
+        umid: user_mapping
+            .map(|um| (*um).umid)
+            .unwrap_or(pg_sys::InvalidOid),
+    };
+
+    let (generation, idle) = INSTANCE_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        register_callbacks(&mut cache);
+        let generation = cache.generation;
+        let entry = cache.entries.entry(key).or_insert_with(|| CacheEntry {
+            server_hash: pg_sys::GetSysCacheHashValue(
+                pg_sys::SysCacheIdentifier_FOREIGNSERVEROID
+                    .try_into()
+                    .unwrap(),
+                Datum::from(key.server_id),
+                Datum::from(0usize),
+                Datum::from(0usize),
+                Datum::from(0usize),
+            ),
+            um_hash: pg_sys::GetSysCacheHashValue(
+                pg_sys::SysCacheIdentifier_USERMAPPINGOID
+                    .try_into()
+                    .unwrap(),
+                Datum::from(key.umid),
+                Datum::from(0usize),
+                Datum::from(0usize),
+                Datum::from(0usize),
+            ),
+            generation,
+            idle: Vec::new(),
+        });
+        (entry.generation, entry.idle.pop())
+    });
+
+    let instance = match idle {
+        Some(idle) => idle.into_inner::<W>(),
+        None => {
+            let wrapper = with_server_options::<E, W, _>(fserver, user_mapping, W::new);
+            Box::new(wrapper.map_err(|e| e.into()).report())
+        }
+    };
+
+    CachedInstance {
+        key,
+        generation,
+        instance: Some(instance),
+    }
+}
+
+/// Number of idle instances of a foreign server in the instance cache
+///
+/// Instances are cached per backend, so only the instances created by current
+/// backend are counted.
+pub fn idle_instances(server_id: pg_sys::Oid) -> usize {
+    INSTANCE_CACHE.with(|cache| {
+        cache
+            .borrow()
+            .entries
+            .iter()
+            .filter(|(key, _)| key.server_id == server_id)
+            .map(|(_, entry)| entry.idle.len())
+            .sum()
+    })
 }
@@ -1,7 +1,10 @@
 //! Provides interface types and trait to develop Postgres foreign data wrapper
 //!
 
+use crate::options::OptionSpec;
 use crate::FdwRoutine;
+use futures::future::LocalBoxFuture;
+use futures::stream::LocalBoxStream;
 use pgrx::pg_sys::panic::ErrorReport;
 use pgrx::prelude::{Date, Timestamp};
 use pgrx::{
@@ -15,11 +18,14 @@ use std::fmt;
 use std::iter::Zip;
 use std::mem;
 use std::slice::Iter;
+use std::time::{Duration, Instant};
 
 // fdw system catalog oids
 // https://doxygen.postgresql.org/pg__foreign__data__wrapper_8h.html
 // https://doxygen.postgresql.org/pg__foreign__server_8h.html
 // https://doxygen.postgresql.org/pg__foreign__table_8h.html
+// https://doxygen.postgresql.org/pg__user__mapping_8h.html
+// https://doxygen.postgresql.org/pg__attribute_8h.html
 
 /// Constant can be used in [validator](ForeignDataWrapper::validator)
 pub const FOREIGN_DATA_WRAPPER_RELATION_ID: Oid = unsafe { Oid::from_u32_unchecked(2328) };
@@ -30,6 +36,12 @@ pub const FOREIGN_SERVER_RELATION_ID: Oid = unsafe { Oid::from_u32_unchecked(141
 /// Constant can be used in [validator](ForeignDataWrapper::validator)
 pub const FOREIGN_TABLE_RELATION_ID: Oid = unsafe { Oid::from_u32_unchecked(3118) };
 
+/// Constant can be used in [validator](ForeignDataWrapper::validator)
+pub const USER_MAPPING_RELATION_ID: Oid = unsafe { Oid::from_u32_unchecked(1418) };
+
+/// Constant can be used in [validator](ForeignDataWrapper::validator), for column options
+pub const ATTRIBUTE_RELATION_ID: Oid = unsafe { Oid::from_u32_unchecked(1249) };
+
 /// A data cell in a data row
 #[derive(Debug)]
 pub enum Cell {
@@ -138,6 +150,38 @@ impl IntoDatum for Cell {
     }
 }
 
+impl Cell {
+    /// Returns type OID of the datum this cell is converted to
+    pub fn datum_type_oid(&self) -> Oid {
+        match self {
+            Cell::Bool(_) => pg_sys::BOOLOID,
+            Cell::I8(_) => pg_sys::CHAROID,
+            Cell::I16(_) => pg_sys::INT2OID,
+            Cell::F32(_) => pg_sys::FLOAT4OID,
+            Cell::I32(_) => pg_sys::INT4OID,
+            Cell::F64(_) => pg_sys::FLOAT8OID,
+            Cell::I64(_) => pg_sys::INT8OID,
+            Cell::Numeric(_) => pg_sys::NUMERICOID,
+            Cell::String(_) => pg_sys::TEXTOID,
+            Cell::Date(_) => pg_sys::DATEOID,
+            Cell::Timestamp(_) => pg_sys::TIMESTAMPOID,
+            Cell::Json(_) => pg_sys::JSONBOID,
+        }
+    }
+
+    // widen an integer or float cell to the larger type of a target column,
+    // other cells are returned as is
+    pub(crate) fn widen_to(self, tgt_type: Oid) -> Self {
+        match self {
+            Cell::I16(v) if tgt_type == pg_sys::INT4OID => Cell::I32(v as i32),
+            Cell::I16(v) if tgt_type == pg_sys::INT8OID => Cell::I64(v as i64),
+            Cell::I32(v) if tgt_type == pg_sys::INT8OID => Cell::I64(v as i64),
+            Cell::F32(v) if tgt_type == pg_sys::FLOAT8OID => Cell::F64(v as f64),
+            cell => cell,
+        }
+    }
+}
+
 impl FromDatum for Cell {
     unsafe fn from_polymorphic_datum(datum: Datum, is_null: bool, typoid: Oid) -> Option<Self>
     where
@@ -266,14 +310,31 @@ pub enum Value {
     Array(Vec<Cell>),
 }
 
+/// Kind of a query parameter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    /// parameter of a prepared statement, e.g. `$1`, its value doesn't change
+    /// during a scan
+    Extern,
+
+    /// parameter set by the executor, e.g. a column of the outer relation in
+    /// a nested loop join or a correlated subquery, its value can change
+    /// on each re-scan
+    Exec,
+}
+
 /// Query parameter
 #[derive(Debug, Clone)]
 pub struct Param {
-    /// 1-based parameter id
+    /// parameter id, 1-based for [`ParamKind::Extern`] and 0-based for
+    /// [`ParamKind::Exec`]
     pub id: usize,
 
     /// parameter type OID
     pub type_oid: Oid,
+
+    /// parameter kind
+    pub kind: ParamKind,
 }
 
 /// Query restrictions, a.k.a conditions in `WHERE` clause
@@ -424,17 +485,29 @@ impl Sort {
 ///
 /// ```sql
 /// limit 42;
-/// -- Limit { count: 42, offset: 0 }
+/// -- Limit { count: 42, offset: 0, count_param: None, offset_param: None }
 /// ```
 ///
 /// ```sql
 /// limit 42 offset 7;
-/// -- Limit { count: 42, offset: 7 }
+/// -- Limit { count: 42, offset: 7, count_param: None, offset_param: None }
 /// ```
+///
+/// ```sql
+/// limit $1 offset $2;
+/// -- Limit { count: 42, offset: 7, count_param: Some(Param { id: 1, .. }), offset_param: Some(Param { id: 2, .. }) }
+/// ```
+///
+/// When count or offset is a query parameter, its value is assigned before
+/// [`begin_scan`](ForeignDataWrapper::begin_scan) is called. If the count
+/// parameter value is `NULL`, which means no limit, the limit is not passed
+/// to the FDW.
 #[derive(Debug, Clone, Default)]
 pub struct Limit {
     pub count: i64,
     pub offset: i64,
+    pub count_param: Option<Param>,
+    pub offset_param: Option<Param>,
 }
 
 impl Limit {
@@ -443,6 +516,59 @@ impl Limit {
     }
 }
 
+/// Remote request metrics of a foreign scan, shown by `EXPLAIN ANALYZE`
+///
+/// ## Examples
+///
+/// ```rust,no_run
+/// # use supabase_wrappers::prelude::*;
+/// # use std::time::Instant;
+/// let mut metrics = RemoteMetrics::default();
+///
+/// let started = Instant::now();
+/// // ...make a remote request and fetch one page of 1024 bytes
+/// metrics.record_request(started, 1024);
+/// metrics.record_page();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct RemoteMetrics {
+    /// number of remote requests made
+    pub requests: u64,
+
+    /// total bytes received from remote
+    pub bytes_received: u64,
+
+    /// number of result pages fetched
+    pub pages: u64,
+
+    /// total time spent waiting for remote responses
+    pub latency: Duration,
+}
+
+impl RemoteMetrics {
+    /// Record a finished remote request which started at `started` and
+    /// received `bytes` bytes
+    pub fn record_request(&mut self, started: Instant, bytes: usize) {
+        self.requests += 1;
+        self.bytes_received += bytes as u64;
+        self.latency += started.elapsed();
+    }
+
+    /// Record a fetched result page
+    pub fn record_page(&mut self) {
+        self.pages += 1;
+    }
+}
+
+/// Information of a remote server, returned by a successful connection check
+///
+/// See [`ForeignDataWrapper::check_connection`] for more details.
+#[derive(Debug, Clone, Default)]
+pub struct ServerInfo {
+    /// version of the remote server or API, if it is known
+    pub version: Option<String>,
+}
+
 /// The Foreign Data Wrapper trait
 ///
 /// This is the main interface for your foreign data wrapper. Required functions
@@ -468,11 +594,19 @@ pub trait ForeignDataWrapper<E: Into<ErrorReport>> {
     /// );
     /// ```
     ///
-    /// `options` passed here will be a hashmap { 'foo' -> 'bar' }.
+    /// `options` passed here will be a hashmap { 'foo' -> 'bar' }. If a user
+    /// mapping is defined for the current user (or `PUBLIC`), its options are
+    /// also included and take precedence over the server options.
     ///
     /// You can do any initalization in this function, like saving connection
     /// info or API url in an variable, but don't do heavy works like database
     /// connection or API call.
+    ///
+    /// The created instance is cached per backend, keyed by foreign server and
+    /// user mapping, and will be reused by later scans and modifications, so
+    /// any per-scan state should be reset in [`begin_scan`](Self::begin_scan)
+    /// or [`begin_modify`](Self::begin_modify). The cache is invalidated when
+    /// the server or user mapping is altered.
     fn new(options: &HashMap<String, String>) -> Result<Self, E>
     where
         Self: Sized;
@@ -521,6 +655,14 @@ pub trait ForeignDataWrapper<E: Into<ErrorReport>> {
 
     /// Called when restart the scan from the beginning.
     ///
+    /// The FDW should return the same rows again, for example by rewinding its
+    /// buffered result or by sending the remote request again. It is called
+    /// when the foreign table is re-scanned with the same quals, e.g. as the
+    /// inner relation of a nested loop join. If the quals have parameters
+    /// whose values are changed, e.g. a correlated subquery, the framework
+    /// calls [`end_scan`](Self::end_scan) and then [`begin_scan`](Self::begin_scan)
+    /// with the new values instead.
+    ///
     /// [See more details](https://www.postgresql.org/docs/current/fdw-callbacks.html#FDW-CALLBACKS-SCAN).
     fn re_scan(&mut self) -> Result<(), E> {
         Ok(())
@@ -592,6 +734,123 @@ pub trait ForeignDataWrapper<E: Into<ErrorReport>> {
         Ok(())
     }
 
+    /// Called when a callback is interrupted by query cancel or statement timeout
+    ///
+    /// If a callback function returns an error while the query is being
+    /// cancelled, for example the [`FdwError::Cancelled`](crate::error::FdwError::Cancelled)
+    /// error returned by [`block_on_interruptible`](crate::utils::block_on_interruptible),
+    /// this function is called before Postgres raises the cancel error. The
+    /// local future has already been dropped at this point, so this is the
+    /// place to stop the work still running on remote side, such as a query
+    /// job.
+    ///
+    /// Note the query cancel is still pending when this function is called,
+    /// so use a plain `block_on()` with a short timeout to make remote calls
+    /// here.
+    fn cancel(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
+    /// Return the FDW-specific details shown by `EXPLAIN`
+    ///
+    /// - `verbose` - true if `EXPLAIN (VERBOSE)` is used
+    ///
+    /// Each returned pair is shown as a labelled property of the foreign
+    /// scan, for example the deparsed remote query, the endpoint to be
+    /// requested and the pushed down parts of the query.
+    ///
+    /// For a plain `EXPLAIN`, [`begin_scan`](Self::begin_scan) is not called,
+    /// so the details should be prepared in [`get_rel_size`](Self::get_rel_size)
+    /// which is called with the same parameters on the same instance during
+    /// planning.
+    fn explain(&self, _verbose: bool) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// Return remote request metrics of current scan, shown by `EXPLAIN ANALYZE`
+    ///
+    /// The metrics should be reset in [`begin_scan`](Self::begin_scan), as the
+    /// instance is reused by later scans. Return `None` if no metrics are
+    /// collected, this is the default.
+    fn remote_metrics(&self) -> Option<RemoteMetrics> {
+        None
+    }
+
+    /// Describe the remote table's columns
+    ///
+    /// - `options` - the options defined when `CREATE SERVER` and `CREATE USER MAPPING`
+    /// - `table_options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// Called right after a foreign table is created, every column of the
+    /// foreign table must exist in the returned columns and its type must be
+    /// compatible with the returned column type, otherwise an error is raised
+    /// and the creation is rolled back. The returned column type should be the
+    /// type of [`Cell`] this FDW produces for that remote column, or
+    /// `InvalidOid` if the remote column type is not supported.
+    ///
+    /// Return `None` if the remote columns cannot be described, then the check
+    /// is skipped. This is the default. If an error is returned, it is reported
+    /// as a warning and the check is skipped as well.
+    fn describe_columns(
+        _options: &HashMap<String, String>,
+        _table_options: &HashMap<String, String>,
+    ) -> Result<Option<Vec<Column>>, E>
+    where
+        Self: Sized,
+    {
+        Ok(None)
+    }
+
+    /// Run a query on the remote source and return its result rows
+    ///
+    /// - `sql` - the query in the remote source's own language
+    ///
+    /// Called by [`server::remote_query`](crate::server::remote_query) on an
+    /// instance of the foreign server, without any foreign table. Each
+    /// returned row should contain all columns of the query result.
+    ///
+    /// Return `None` if remote query is not supported, this is the default.
+    fn remote_query(&mut self, _sql: &str) -> Result<Option<Vec<Row>>, E> {
+        Ok(None)
+    }
+
+    /// Run a statement on the remote source without returning any rows
+    ///
+    /// - `sql` - the statement in the remote source's own language
+    ///
+    /// Called by [`server::remote_exec`](crate::server::remote_exec) on an
+    /// instance of the foreign server, without any foreign table.
+    ///
+    /// Return `None` if remote statement is not supported, this is the default.
+    fn remote_exec(&mut self, _sql: &str) -> Result<Option<()>, E> {
+        Ok(None)
+    }
+
+    /// Check the connection to the remote server
+    ///
+    /// Called by [`server::check_server`](crate::server::check_server) on an
+    /// instance of the foreign server, without any foreign table. It should
+    /// make a cheap authenticated request to the remote server, such as getting
+    /// its version or the current account, so that both the connectivity and
+    /// the credentials are checked. Return an error if the request failed.
+    ///
+    /// Return `None` if connection check is not supported, this is the default.
+    fn check_connection(&self) -> Result<Option<ServerInfo>, E> {
+        Ok(None)
+    }
+
+    /// Check the foreign table columns using [`describe_columns`](Self::describe_columns)
+    /// when it is created
+    ///
+    /// Not to be used directly, called by the validator generated by
+    /// [`wrappers_fdw`](crate::wrappers_fdw) macro.
+    fn defer_describe_check(catalog: Option<Oid>)
+    where
+        Self: Sized,
+    {
+        crate::describe::defer_check::<E, Self>(catalog)
+    }
+
     /// Returns a FdwRoutine for the FDW
     ///
     /// Not to be used directly, use [`wrappers_fdw`](crate::wrappers_fdw) macro instead.
@@ -600,7 +859,11 @@ pub trait ForeignDataWrapper<E: Into<ErrorReport>> {
         Self: Sized,
     {
         unsafe {
-            use crate::{modify, scan};
+            use crate::{modify, scan, server};
+
+            // make the server functions of this FDW type reachable by server name
+            server::register::<E, Self>();
+
             let mut fdw_routine =
                 FdwRoutine::<AllocatedByRust>::alloc_node(pg_sys::NodeTag_T_FdwRoutine);
 
@@ -630,6 +893,19 @@ pub trait ForeignDataWrapper<E: Into<ErrorReport>> {
         }
     }
 
+    /// Option specs declared in the options schema of this FDW
+    ///
+    /// Not to be used directly, the specs are registered by the handler and
+    /// validator generated by [`wrappers_fdw`](crate::wrappers_fdw) macro. Only
+    /// the user mapping options declared in the schema are merged over the
+    /// server options passed to [`new`](Self::new).
+    fn option_specs() -> Vec<OptionSpec>
+    where
+        Self: Sized,
+    {
+        crate::options::registered_option_specs(std::any::type_name::<Self>())
+    }
+
     /// Additional FwdRoutine setup, called by default `Self::fdw_routine()`
     /// after completing its initialization.
     fn fdw_routine_hook(_routine: &mut FdwRoutine<AllocatedByRust>) {}
@@ -692,3 +968,245 @@ pub trait ForeignDataWrapper<E: Into<ErrorReport>> {
         Ok(())
     }
 }
+
+/// A stream of rows returned by [`AsyncForeignDataWrapper::begin_scan`]
+pub type RowStream<E> = LocalBoxStream<'static, Result<Row, E>>;
+
+/// A future returned by [`AsyncForeignDataWrapper`] modify callbacks
+pub type ModifyFuture<'a, E> = LocalBoxFuture<'a, Result<(), E>>;
+
+/// The async variant of Foreign Data Wrapper trait
+///
+/// Instead of blocking on every remote call and buffering the results, an
+/// async FDW returns a [`Stream`](futures::Stream) of rows from `begin_scan`,
+/// which will be polled lazily by the framework on one shared per-backend
+/// async runtime whenever Postgres asks for the next row. Thus the FDW doesn't
+/// need to create and manage its own runtime. Required functions are listed
+/// below, all the others are optional.
+///
+/// 1. new
+/// 2. begin_scan
+///
+/// The FDW implements this trait can use [`wrappers_fdw`](crate::wrappers_fdw)
+/// macro in the same way as [`ForeignDataWrapper`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use futures::stream::{self, StreamExt};
+/// use pgrx::pg_sys::panic::ErrorReport;
+/// use pgrx::PgSqlErrorCode;
+/// use std::collections::HashMap;
+/// use supabase_wrappers::prelude::*;
+///
+/// struct HelloWorldFdw;
+///
+/// enum HelloWorldFdwError {}
+///
+/// impl From<HelloWorldFdwError> for ErrorReport {
+///     fn from(_value: HelloWorldFdwError) -> Self {
+///         ErrorReport::new(PgSqlErrorCode::ERRCODE_FDW_ERROR, "", "")
+///     }
+/// }
+///
+/// impl AsyncForeignDataWrapper<HelloWorldFdwError> for HelloWorldFdw {
+///     fn new(_options: &HashMap<String, String>) -> Result<Self, HelloWorldFdwError> {
+///         Ok(Self)
+///     }
+///
+///     fn begin_scan(
+///         &mut self,
+///         _quals: &[Qual],
+///         columns: &[Column],
+///         _sorts: &[Sort],
+///         _limit: &Option<Limit>,
+///         _options: &HashMap<String, String>,
+///     ) -> Result<RowStream<HelloWorldFdwError>, HelloWorldFdwError> {
+///         let columns = columns.to_vec();
+///         let rows = stream::iter(0..3).map(move |i| {
+///             let mut row = Row::new();
+///             for col in &columns {
+///                 row.push(&col.name, Some(Cell::I64(i)));
+///             }
+///             Ok(row)
+///         });
+///         Ok(rows.boxed_local())
+///     }
+/// }
+/// ```
+pub trait AsyncForeignDataWrapper<E: Into<ErrorReport> + 'static> {
+    /// Create a FDW instance
+    ///
+    /// See [`ForeignDataWrapper::new`] for more details.
+    fn new(options: &HashMap<String, String>) -> Result<Self, E>
+    where
+        Self: Sized;
+
+    /// Obtain relation size estimates for a foreign table
+    ///
+    /// See [`ForeignDataWrapper::get_rel_size`] for more details.
+    fn get_rel_size(
+        &mut self,
+        _quals: &[Qual],
+        _columns: &[Column],
+        _sorts: &[Sort],
+        _limit: &Option<Limit>,
+        _options: &HashMap<String, String>,
+    ) -> Result<(i64, i32), E> {
+        Ok((0, 0))
+    }
+
+    /// Called when begin executing a foreign scan, returns a stream of rows
+    ///
+    /// - `quals` - `WHERE` clause pushed down
+    /// - `columns` - target columns to be queried
+    /// - `sorts` - `ORDER BY` clause pushed down
+    /// - `limit` - `LIMIT` clause pushed down
+    /// - `options` - the options defined when `CREATE FOREIGN TABLE`
+    ///
+    /// The returned stream must own all its data, for example a cloned HTTP
+    /// client, as it is kept and polled after this function returns. Each row
+    /// in the stream must contain cells for the target columns. The stream is
+    /// dropped when the scan ends, and this function will be called again
+    /// when the scan is restarted.
+    fn begin_scan(
+        &mut self,
+        quals: &[Qual],
+        columns: &[Column],
+        sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+    ) -> Result<RowStream<E>, E>;
+
+    /// Called when end the scan, after the row stream is dropped
+    fn end_scan(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
+    /// Called when begin executing a foreign table modification operation.
+    ///
+    /// See [`ForeignDataWrapper::begin_modify`] for more details.
+    fn begin_modify(&mut self, _options: &HashMap<String, String>) -> ModifyFuture<'_, E> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Called when insert one row into the foreign table
+    fn insert<'a>(&'a mut self, _row: &'a Row) -> ModifyFuture<'a, E> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Called when update one row into the foreign table
+    fn update<'a>(&'a mut self, _rowid: &'a Cell, _new_row: &'a Row) -> ModifyFuture<'a, E> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Called when delete one row into the foreign table
+    fn delete<'a>(&'a mut self, _rowid: &'a Cell) -> ModifyFuture<'a, E> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Called when end the table update
+    fn end_modify(&mut self) -> ModifyFuture<'_, E> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Called when a callback is interrupted by query cancel or statement timeout
+    ///
+    /// See [`ForeignDataWrapper::cancel`] for more details.
+    fn cancel(&mut self) -> ModifyFuture<'_, E> {
+        Box::pin(async { Ok(()) })
+    }
+
+    /// Return the FDW-specific details shown by `EXPLAIN`
+    ///
+    /// See [`ForeignDataWrapper::explain`] for more details.
+    fn explain(&self, _verbose: bool) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// Return remote request metrics of current scan, shown by `EXPLAIN ANALYZE`
+    ///
+    /// See [`ForeignDataWrapper::remote_metrics`] for more details.
+    fn remote_metrics(&self) -> Option<RemoteMetrics> {
+        None
+    }
+
+    /// Describe the remote table's columns
+    ///
+    /// See [`ForeignDataWrapper::describe_columns`] for more details.
+    fn describe_columns<'a>(
+        _options: &'a HashMap<String, String>,
+        _table_options: &'a HashMap<String, String>,
+    ) -> LocalBoxFuture<'a, Result<Option<Vec<Column>>, E>>
+    where
+        Self: Sized,
+    {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Run a query on the remote source and return its result rows
+    ///
+    /// See [`ForeignDataWrapper::remote_query`] for more details.
+    fn remote_query<'a>(
+        &'a mut self,
+        _sql: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Vec<Row>>, E>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Run a statement on the remote source without returning any rows
+    ///
+    /// See [`ForeignDataWrapper::remote_exec`] for more details.
+    fn remote_exec<'a>(&'a mut self, _sql: &'a str) -> LocalBoxFuture<'a, Result<Option<()>, E>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Check the connection to the remote server
+    ///
+    /// See [`ForeignDataWrapper::check_connection`] for more details.
+    fn check_connection(&self) -> LocalBoxFuture<'_, Result<Option<ServerInfo>, E>> {
+        Box::pin(async { Ok(None) })
+    }
+
+    /// Check the foreign table columns when it is created
+    ///
+    /// Not to be used directly, called by the validator generated by
+    /// [`wrappers_fdw`](crate::wrappers_fdw) macro.
+    fn defer_describe_check(catalog: Option<Oid>)
+    where
+        Self: Sized,
+    {
+        crate::describe::defer_check::<E, crate::async_fdw::AsyncFdw<E, Self>>(catalog)
+    }
+
+    /// Returns a FdwRoutine for the FDW
+    ///
+    /// Not to be used directly, use [`wrappers_fdw`](crate::wrappers_fdw) macro instead.
+    fn fdw_routine() -> FdwRoutine
+    where
+        Self: Sized,
+    {
+        crate::async_fdw::AsyncFdw::<E, Self>::fdw_routine()
+    }
+
+    /// Option specs declared in the options schema of this FDW
+    ///
+    /// See [`ForeignDataWrapper::option_specs`] for more details.
+    fn option_specs() -> Vec<OptionSpec>
+    where
+        Self: Sized,
+    {
+        crate::options::registered_option_specs(std::any::type_name::<Self>())
+    }
+
+    /// Additional FwdRoutine setup, called by default `Self::fdw_routine()`
+    /// after completing its initialization.
+    fn fdw_routine_hook(_routine: &mut FdwRoutine<AllocatedByRust>) {}
+
+    /// Validator function for validating options
+    ///
+    /// See [`ForeignDataWrapper::validator`] for more details.
+    fn validator(_options: Vec<Option<String>>, _catalog: Option<Oid>) -> Result<(), E> {
+        Ok(())
+    }
+}
@@ -0,0 +1,398 @@
+//! Conversion between rows and JSON values or Rust structs
+//!
+//! Most of the remote sources return JSON, this module provides a common way
+//! to convert a JSON object to a [`Row`] according to the target columns' types,
+//! and to convert a [`Row`] back to JSON for insert or update.
+//!
+//! It also provides [`FromRow`] and [`IntoRow`] traits, which can be derived for
+//! a struct implements `serde::Deserialize` and `serde::Serialize`, so a FDW can
+//! declare a struct for each remote object.
+//!
+//! ```rust,no_run
+//! use serde::{Deserialize, Serialize};
+//! use supabase_wrappers::prelude::*;
+//!
+//! #[derive(Serialize, Deserialize, FromRow, IntoRow)]
+//! struct Customer {
+//!     id: String,
+//!     email: Option<String>,
+//!     created: i64,
+//! }
+//!
+//! # fn run(row: &Row, columns: &[Column]) -> Result<(), JsonError> {
+//! let customer = Customer::from_row(row)?;
+//! let row = customer.into_row(columns)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! A [`JsonPath`] can be used to locate the records or fields in a JSON
+//! response, for FDWs whose remote object layout is configured by options.
+
+use crate::interface::{Cell, Column, Row};
+use pgrx::datum::datetime_support::to_timestamp;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::prelude::*;
+use pgrx::{pg_sys, AnyNumeric, IntoDatum, JsonB};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Number, Value as JsonValue};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum JsonError {
+    #[error(
+        "column '{column}' data type not match, cannot convert {value} to type oid {type_oid}"
+    )]
+    TypeMismatch {
+        column: String,
+        type_oid: pg_sys::Oid,
+        value: String,
+    },
+
+    #[error("column '{column}' data type oid '{type_oid}' is not supported")]
+    UnsupportedType {
+        column: String,
+        type_oid: pg_sys::Oid,
+    },
+
+    #[error("expect a JSON object to convert to row, but got {0}")]
+    NotAnObject(String),
+
+    #[error("invalid JSON path '{0}'")]
+    InvalidPath(String),
+
+    #[error("serde_json error: {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+}
+
+impl From<JsonError> for ErrorReport {
+    fn from(value: JsonError) -> Self {
+        let error_message = format!("{value}");
+        ErrorReport::new(
+            PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+            error_message,
+            "",
+        )
+    }
+}
+
+impl Cell {
+    /// Convert a JSON value to a cell of the specified column's type
+    ///
+    /// JSON `null` is converted to `None`. Numbers are range checked for integer
+    /// types, strings are parsed for date, timestamp and numeric types, numeric
+    /// is parsed from the number's text, and a number is treated as a Unix epoch
+    /// for timestamp type. Any value can be
+    /// converted to `jsonb`, and non-string values are converted to their JSON
+    /// text for `text` type.
+    pub fn from_json(value: &JsonValue, col: &Column) -> Result<Option<Cell>, JsonError> {
+        if value.is_null() {
+            return Ok(None);
+        }
+
+        let mismatch = || JsonError::TypeMismatch {
+            column: col.name.clone(),
+            type_oid: col.type_oid,
+            value: value.to_string(),
+        };
+        let as_int = || {
+            value
+                .as_i64()
+                .or_else(|| value.as_str().and_then(|s| s.parse::<i64>().ok()))
+                .ok_or_else(mismatch)
+        };
+        let as_float = || {
+            value
+                .as_f64()
+                .or_else(|| value.as_str().and_then(|s| s.parse::<f64>().ok()))
+                .ok_or_else(mismatch)
+        };
+
+        let cell = match col.type_oid {
+            pg_sys::BOOLOID => Cell::Bool(value.as_bool().ok_or_else(mismatch)?),
+            pg_sys::CHAROID => Cell::I8(i8::try_from(as_int()?).map_err(|_| mismatch())?),
+            pg_sys::INT2OID => Cell::I16(i16::try_from(as_int()?).map_err(|_| mismatch())?),
+            pg_sys::INT4OID => Cell::I32(i32::try_from(as_int()?).map_err(|_| mismatch())?),
+            pg_sys::INT8OID => Cell::I64(as_int()?),
+            pg_sys::FLOAT4OID => Cell::F32(as_float()? as f32),
+            pg_sys::FLOAT8OID => Cell::F64(as_float()?),
+            pg_sys::NUMERICOID => {
+                // parse the number's text to keep its precision
+                let text = match value {
+                    JsonValue::Number(n) => n.to_string(),
+                    JsonValue::String(s) => s.to_owned(),
+                    _ => return Err(mismatch()),
+                };
+                Cell::Numeric(AnyNumeric::from_str(&text).map_err(|_| mismatch())?)
+            }
+            pg_sys::TEXTOID => match value {
+                JsonValue::String(s) => Cell::String(s.to_owned()),
+                _ => Cell::String(value.to_string()),
+            },
+            pg_sys::DATEOID => Cell::Date(
+                value
+                    .as_str()
+                    .and_then(|s| Date::from_str(s).ok())
+                    .ok_or_else(mismatch)?,
+            ),
+            pg_sys::TIMESTAMPOID => match value {
+                JsonValue::Number(n) => {
+                    let epoch = n.as_f64().ok_or_else(mismatch)?;
+                    Cell::Timestamp(to_timestamp(epoch).to_utc())
+                }
+                JsonValue::String(s) => {
+                    Cell::Timestamp(Timestamp::from_str(s).map_err(|_| mismatch())?)
+                }
+                _ => return Err(mismatch()),
+            },
+            pg_sys::JSONBOID => Cell::Json(JsonB(value.clone())),
+            _ => {
+                return Err(JsonError::UnsupportedType {
+                    column: col.name.clone(),
+                    type_oid: col.type_oid,
+                })
+            }
+        };
+        Ok(Some(cell))
+    }
+
+    /// Convert this cell to a JSON value
+    ///
+    /// Date and timestamp are converted to strings in Postgres output format,
+    /// a float which is not finite is converted to `null`.
+    pub fn to_json(&self) -> JsonValue {
+        match self {
+            Cell::Bool(v) => JsonValue::Bool(*v),
+            Cell::I8(v) => JsonValue::from(*v),
+            Cell::I16(v) => JsonValue::from(*v),
+            Cell::I32(v) => JsonValue::from(*v),
+            Cell::I64(v) => JsonValue::from(*v),
+            Cell::F32(v) => Number::from_f64(*v as f64)
+                .map(JsonValue::Number)
+                .unwrap_or(JsonValue::Null),
+            Cell::F64(v) => Number::from_f64(*v)
+                .map(JsonValue::Number)
+                .unwrap_or(JsonValue::Null),
+            Cell::Numeric(v) => {
+                let s = v.to_string();
+                s.parse::<Number>()
+                    .map(JsonValue::Number)
+                    .unwrap_or(JsonValue::String(s))
+            }
+            Cell::String(v) => JsonValue::String(v.clone()),
+            Cell::Date(_) | Cell::Timestamp(_) => {
+                JsonValue::String(self.to_string().trim_matches('\'').to_owned())
+            }
+            Cell::Json(v) => v.0.clone(),
+        }
+    }
+}
+
+impl Row {
+    /// Convert a JSON object to a row with the target columns
+    ///
+    /// Each column's value is taken from the object's property of the same
+    /// name and converted by column's type, see [`Cell::from_json`]. Missing
+    /// property is converted to `None`.
+    pub fn from_json(value: &JsonValue, columns: &[Column]) -> Result<Row, JsonError> {
+        let obj = value
+            .as_object()
+            .ok_or_else(|| JsonError::NotAnObject(value.to_string()))?;
+        let mut row = Row::new();
+        for col in columns {
+            let cell = match obj.get(&col.name) {
+                Some(v) => Cell::from_json(v, col)?,
+                None => None,
+            };
+            row.push(&col.name, cell);
+        }
+        Ok(row)
+    }
+
+    /// Convert this row to a JSON object keyed by column names
+    pub fn to_json(&self) -> JsonValue {
+        let map: Map<String, JsonValue> = self
+            .iter()
+            .map(|(col, cell)| {
+                let value = cell
+                    .as_ref()
+                    .map(|cell| cell.to_json())
+                    .unwrap_or(JsonValue::Null);
+                (col.to_owned(), value)
+            })
+            .collect();
+        JsonValue::Object(map)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+    Wildcard,
+}
+
+/// A simple JSONPath to select values in a JSON document
+///
+/// Only a subset of JSONPath is supported: the root `$`, child by name like
+/// `.foo` or `['foo']`, array element by index like `[0]`, and wildcard `.*`
+/// or `[*]` for all elements or properties. The leading `$` can be omitted,
+/// so `data.items` is same as `$.data.items`.
+///
+/// ```rust,no_run
+/// # use supabase_wrappers::prelude::*;
+/// # fn run(value: &serde_json::Value) -> Result<(), JsonError> {
+/// let path: JsonPath = "$.data.items[*].name".parse()?;
+/// let names = path.select(value);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct JsonPath {
+    segments: Vec<PathSegment>,
+}
+
+impl FromStr for JsonPath {
+    type Err = JsonError;
+
+    fn from_str(path: &str) -> Result<Self, Self::Err> {
+        let invalid = || JsonError::InvalidPath(path.to_owned());
+        let mut segments = Vec::new();
+        let s = path.trim();
+        let mut rest = s.strip_prefix('$').unwrap_or(s);
+        if !rest.is_empty() && !rest.starts_with(['.', '[']) {
+            // path without leading `$`, e.g. `data.items`
+            rest = s;
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            segments.push(PathSegment::Key(rest[..end].to_owned()));
+            rest = &rest[end..];
+        }
+
+        while !rest.is_empty() {
+            if let Some(r) = rest.strip_prefix('.') {
+                let end = r.find(['.', '[']).unwrap_or(r.len());
+                let key = &r[..end];
+                if key.is_empty() {
+                    return Err(invalid());
+                }
+                segments.push(if key == "*" {
+                    PathSegment::Wildcard
+                } else {
+                    PathSegment::Key(key.to_owned())
+                });
+                rest = &r[end..];
+            } else if let Some(r) = rest.strip_prefix('[') {
+                let end = r.find(']').ok_or_else(invalid)?;
+                let inner = r[..end].trim();
+                let quoted = inner
+                    .strip_prefix('\'')
+                    .and_then(|k| k.strip_suffix('\''))
+                    .or_else(|| inner.strip_prefix('"').and_then(|k| k.strip_suffix('"')));
+                segments.push(match quoted {
+                    Some(key) => PathSegment::Key(key.to_owned()),
+                    None if inner == "*" => PathSegment::Wildcard,
+                    None => PathSegment::Index(inner.parse().map_err(|_| invalid())?),
+                });
+                rest = &r[end + 1..];
+            } else {
+                return Err(invalid());
+            }
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl JsonPath {
+    /// Select all values matching this path, a path without wildcard
+    /// matches at most one value
+    pub fn select<'a>(&self, value: &'a JsonValue) -> Vec<&'a JsonValue> {
+        let mut values = vec![value];
+        for seg in &self.segments {
+            values = values
+                .into_iter()
+                .flat_map(|v| -> Vec<&JsonValue> {
+                    match (seg, v) {
+                        (PathSegment::Key(key), JsonValue::Object(obj)) => {
+                            obj.get(key).into_iter().collect()
+                        }
+                        (PathSegment::Index(idx), JsonValue::Array(arr)) => {
+                            arr.get(*idx).into_iter().collect()
+                        }
+                        (PathSegment::Wildcard, JsonValue::Array(arr)) => arr.iter().collect(),
+                        (PathSegment::Wildcard, JsonValue::Object(obj)) => obj.values().collect(),
+                        _ => Vec::new(),
+                    }
+                })
+                .collect();
+        }
+        values
+    }
+
+    /// Get the first value matching this path
+    pub fn get<'a>(&self, value: &'a JsonValue) -> Option<&'a JsonValue> {
+        self.select(value).into_iter().next()
+    }
+}
+
+/// Types can be created from a [`Row`]
+///
+/// It can be derived by `#[derive(FromRow)]` for a struct implements
+/// `serde::Deserialize`, the struct fields are matched with row's column names.
+/// A timestamp cell is deserialized as seconds since Unix epoch, so it can be
+/// deserialized to an integer field, and a date cell is deserialized as a string.
+pub trait FromRow: Sized {
+    fn from_row(row: &Row) -> Result<Self, JsonError>;
+}
+
+/// Types can be converted to a [`Row`]
+///
+/// It can be derived by `#[derive(IntoRow)]` for a struct implements
+/// `serde::Serialize`, the struct fields are converted to the target columns
+/// with the same names.
+pub trait IntoRow {
+    fn into_row(self, columns: &[Column]) -> Result<Row, JsonError>;
+}
+
+// seconds between Unix epoch and Postgres epoch 2000-01-01
+const POSTGRES_EPOCH_SECS: i64 = 946_684_800;
+
+// convert a timestamp to seconds since Unix epoch, it is an integer if there
+// is no fractional second
+fn timestamp_to_epoch(ts: Timestamp) -> JsonValue {
+    // timestamp datum is microseconds since Postgres epoch
+    let micros = ts
+        .into_datum()
+        .map(|d| d.value() as i64)
+        .unwrap_or_default();
+    if micros % 1_000_000 == 0 {
+        JsonValue::from(micros / 1_000_000 + POSTGRES_EPOCH_SECS)
+    } else {
+        JsonValue::from(micros as f64 / 1_000_000.0 + POSTGRES_EPOCH_SECS as f64)
+    }
+}
+
+/// Deserialize a value from a row, used by `#[derive(FromRow)]`
+pub fn from_row<T: DeserializeOwned>(row: &Row) -> Result<T, JsonError> {
+    // timestamps are converted to Unix epoch, the same as they are converted
+    // from numbers in `Cell::from_json`, all the other cells are as to_json
+    let map: Map<String, JsonValue> = row
+        .iter()
+        .map(|(col, cell)| {
+            let value = match cell {
+                Some(Cell::Timestamp(ts)) => timestamp_to_epoch(*ts),
+                Some(cell) => cell.to_json(),
+                None => JsonValue::Null,
+            };
+            (col.to_owned(), value)
+        })
+        .collect();
+    Ok(serde_json::from_value(JsonValue::Object(map))?)
+}
+
+/// Serialize a value to a row, used by `#[derive(IntoRow)]`
+pub fn into_row<T: Serialize>(value: T, columns: &[Column]) -> Result<Row, JsonError> {
+    Row::from_json(&serde_json::to_value(value)?, columns)
+}
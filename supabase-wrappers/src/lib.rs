@@ -54,6 +54,8 @@
 //!   - [update()](`interface::ForeignDataWrapper#method.update`)
 //!   - [delete()](`interface::ForeignDataWrapper#method.delete`)
 //!   - [end_modify()](`interface::ForeignDataWrapper#method.end_modify`)
+//! - Foreign table creation
+//!   - [describe_columns()](`interface::ForeignDataWrapper#method.describe_columns`)
 //!
 //! To give different functionalities to your FDW, you can choose different callback functions to implement. The required ones are `begin_scan`, `iter_scan` and `end_scan`, all the others are optional. See [Postgres FDW document](https://www.postgresql.org/docs/current/fdw-callbacks.html) for more details about FDW development.
 //!
@@ -254,6 +256,73 @@
 //! (1 row)
 //! ```
 //!
+//! ### Async FDW
+//!
+//! If the remote source is accessed with async clients, you can implement
+//! [`AsyncForeignDataWrapper`](interface::AsyncForeignDataWrapper) instead. Its
+//! `begin_scan` returns a stream of rows, which is polled lazily by the framework
+//! on a runtime shared by all FDWs in the same backend, so there is no need to
+//! create and hold a runtime in each FDW instance. Use [`block_on`](utils::block_on)
+//! if you need to run a future outside the scan and modify callbacks.
+//!
+//! ### Query Cancellation
+//!
+//! Use [`block_on_interruptible`](utils::block_on_interruptible) to make remote
+//! calls, so that query cancel and `statement_timeout` can abort the call while
+//! waiting for it. The framework then calls [`cancel`](interface::ForeignDataWrapper::cancel),
+//! which can be implemented to stop the query on remote side. Async FDWs are
+//! interruptible by default, and waiting for their `cancel` is bounded by a
+//! timeout.
+//!
+//! ### Result Cache
+//!
+//! Any FDW can cache its scan results in memory of each backend by setting the
+//! `cache_ttl` option, in seconds, on a foreign table. Only the scans returning
+//! no more than `cache_max_rows` rows (default 10000) are cached. See the
+//! [`cache`] module for details.
+//!
+//! ```sql
+//! alter foreign table hello options (add cache_ttl '60');
+//! ```
+//!
+//! ### Pagination
+//!
+//! FDWs scanning a paginated REST API can use a [`Paginator`](paginator::Paginator)
+//! to fetch the pages lazily in `iter_scan` instead of buffering the whole
+//! result set in `begin_scan`. It stops fetching when the pushed down limit is
+//! reached. See the [`paginator`] module for details.
+//!
+//! ### Remote Query
+//!
+//! FDWs backed by a query engine can implement [`remote_query`](interface::ForeignDataWrapper::remote_query)
+//! and [`remote_exec`](interface::ForeignDataWrapper::remote_exec) to run ad-hoc
+//! queries on a foreign server without defining foreign tables. They are called
+//! by the functions in the [`server`] module with the foreign server name.
+//!
+//! ### Connection Check
+//!
+//! Implement [`check_connection`](interface::ForeignDataWrapper::check_connection)
+//! to make a cheap authenticated request to the remote server, it is called by
+//! [`server::check_server`] to tell whether a foreign server is healthy without
+//! running a real query.
+//!
+//! ### Column Check
+//!
+//! Implement [`describe_columns`](interface::ForeignDataWrapper::describe_columns)
+//! to check the foreign table columns against the remote table when it is
+//! created. The check is run by a utility hook, which is installed by [`init`]
+//! in the extension's `_PG_init()`. For example,
+//!
+//! ```rust,no_run
+//! use pgrx::prelude::*;
+//!
+//! #[pg_guard]
+//! #[allow(non_snake_case)]
+//! pub extern "C" fn _PG_init() {
+//!     supabase_wrappers::init();
+//! }
+//! ```
+//!
 //! ### Pro Tips
 //!
 //! You can use `EXPLAIN` to check what have been pushed down. For example,
@@ -271,10 +340,16 @@
 //!                Wrappers: quals = [Qual { field: "id", operator: "=", value: Cell(I32(1)), use_or: false, param: None }]
 //!                Wrappers: tgts = [Column { name: "id", num: 1, type_oid: 20 }, Column { name: "col", num: 2, type_oid: 25 }]
 //!                Wrappers: sorts = [Sort { field: "col", field_no: 2, reversed: false, nulls_first: false, collate: None }]
-//!                Wrappers: limit = Some(Limit { count: 1, offset: 0 })
+//!                Wrappers: limit = Some(Limit { count: 1, offset: 0, count_param: None, offset_param: None })
 //! (9 rows)
 //! ```
 //!
+//! Implement [`explain`](interface::ForeignDataWrapper::explain) to show more
+//! details in `EXPLAIN`, such as the remote query text. Implement
+//! [`remote_metrics`](interface::ForeignDataWrapper::remote_metrics) to show
+//! remote request count, bytes received, pages fetched and remote latency in
+//! `EXPLAIN ANALYZE`.
+//!
 //! ### More FDW Examples
 //!
 //! See more FDW examples which interact with RDBMS or RESTful API.
@@ -286,21 +361,41 @@
 //! - [Airtable](https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/airtable_fdw): A FDW for [Airtable](https://airtable.com/) API which supports data read only.
 //! - [S3](https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/s3_fdw): A FDW for [AWS S3](https://aws.amazon.com/s3/) which supports data read only.
 //! - [Logflare](https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/logflare_fdw): A FDW for [Logflare](https://logflare.app/) which supports data read only.
+//! - [REST](https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/rest_fdw): A generic FDW for REST APIs returning JSON which supports data read only.
+//! - [GraphQL](https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/graphql_fdw): A generic FDW for GraphQL APIs which supports data read only.
+//! - [Wasm](https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/wasm_fdw): A FDW host running wrappers packaged as [WebAssembly](https://webassembly.org/) components which supports data read and modify.
 
+pub mod cache;
+pub mod error;
 pub mod interface;
+pub mod json;
+pub mod options;
+pub mod paginator;
+pub mod secret;
+pub mod server;
 pub mod utils;
 
 /// The prelude includes all necessary imports to make Wrappers work
 pub mod prelude {
+    pub use crate::cache::invalidate_cache;
+    pub use crate::error::{ErrorInfo, FdwError};
+    pub use crate::instance::idle_instances;
     pub use crate::interface::*;
+    pub use crate::json::{FromRow, IntoRow, JsonError, JsonPath};
+    pub use crate::options::*;
+    pub use crate::paginator::{NextPage, Page, PageRequest, Paginator};
+    pub use crate::secret::*;
     pub use crate::utils::*;
     pub use crate::wrappers_fdw;
+    pub use crate::{FromRow, IntoRow};
     pub use ::tokio::runtime::Runtime;
 }
 
 use pgrx::prelude::*;
 use pgrx::AllocatedByPostgres;
 
+mod async_fdw;
+mod describe;
 mod instance;
 mod limit;
 mod memctx;
@@ -310,7 +405,15 @@ mod qual;
 mod scan;
 mod sort;
 
+/// Initialize the framework, must be called in the extension's `_PG_init()`
+///
+/// It installs the utility hook which checks foreign table columns on creation,
+/// see [`describe_columns`](interface::ForeignDataWrapper::describe_columns).
+pub fn init() {
+    unsafe { describe::install_hook() };
+}
+
 /// PgBox'ed `FdwRoutine`, used in [`fdw_routine`](interface::ForeignDataWrapper::fdw_routine)
 pub type FdwRoutine<A = AllocatedByPostgres> = PgBox<pg_sys::FdwRoutine, A>;
 
-pub use supabase_wrappers_macros::wrappers_fdw;
+pub use supabase_wrappers_macros::{wrappers_fdw, FromRow, IntoRow};
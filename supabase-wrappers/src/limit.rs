@@ -1,6 +1,30 @@
-use crate::interface::Limit;
+use crate::interface::{Limit, Param, ParamKind};
 use pgrx::{is_a, pg_sys, FromDatum};
 
+// extract a non-NULL constant or a parameter value from a LIMIT or OFFSET
+// expression, the parameter value will be assigned from execution state
+unsafe fn extract_limit_value(node: *mut pg_sys::Node) -> Option<(i64, Option<Param>)> {
+    if is_a(node, pg_sys::NodeTag_T_Const) {
+        let node = node as *mut pg_sys::Const;
+        i64::from_polymorphic_datum((*node).constvalue, (*node).constisnull, (*node).consttype)
+            .map(|value| (value, None))
+    } else if is_a(node, pg_sys::NodeTag_T_Param) {
+        let node = node as *mut pg_sys::Param;
+        let param = Param {
+            id: (*node).paramid as _,
+            type_oid: (*node).paramtype,
+            kind: if (*node).paramkind == pg_sys::ParamKind_PARAM_EXEC {
+                ParamKind::Exec
+            } else {
+                ParamKind::Extern
+            },
+        };
+        Some((0, Some(param)))
+    } else {
+        None
+    }
+}
+
 // extract limit
 pub(crate) unsafe fn extract_limit(
     root: *mut pg_sys::PlannerInfo,
@@ -14,33 +38,25 @@ pub(crate) unsafe fn extract_limit(
         return None;
     }
 
-    // only push down constant LIMITs that are not NULL
-    let limit_count = (*parse).limitCount as *mut pg_sys::Const;
-    if limit_count.is_null() || !is_a(limit_count as *mut pg_sys::Node, pg_sys::NodeTag_T_Const) {
+    // only push down constant LIMITs that are not NULL, or parameter LIMITs
+    let limit_count = (*parse).limitCount;
+    if limit_count.is_null() {
         return None;
     }
+    let (count, count_param) = extract_limit_value(limit_count)?;
 
-    let mut limit = Limit::default();
-
-    if let Some(count) = i64::from_polymorphic_datum(
-        (*limit_count).constvalue,
-        (*limit_count).constisnull,
-        (*limit_count).consttype,
-    ) {
-        limit.count = count;
-    } else {
-        return None;
-    }
+    let mut limit = Limit {
+        count,
+        count_param,
+        ..Default::default()
+    };
 
-    // only consider OFFSETS that are non-NULL constants
-    let limit_offset = (*parse).limitOffset as *mut pg_sys::Const;
-    if !limit_offset.is_null() && is_a(limit_offset as *mut pg_sys::Node, pg_sys::NodeTag_T_Const) {
-        if let Some(offset) = i64::from_polymorphic_datum(
-            (*limit_offset).constvalue,
-            (*limit_offset).constisnull,
-            (*limit_offset).consttype,
-        ) {
+    // only consider OFFSETS that are non-NULL constants or parameters
+    let limit_offset = (*parse).limitOffset;
+    if !limit_offset.is_null() {
+        if let Some((offset, offset_param)) = extract_limit_value(limit_offset) {
             limit.offset = offset;
+            limit.offset_param = offset_param;
         }
     }
 
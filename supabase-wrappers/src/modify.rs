@@ -7,6 +7,7 @@ use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::os::raw::c_int;
 use std::ptr;
+use std::rc::Rc;
 
 use crate::prelude::*;
 
@@ -17,8 +18,11 @@ use super::utils;
 
 // Fdw private state for modify
 struct FdwModifyState<E: Into<ErrorReport>, W: ForeignDataWrapper<E>> {
-    // foreign data wrapper instance
-    instance: W,
+    // foreign data wrapper instance, checked out from the instance cache
+    instance: instance::CachedInstance<W>,
+
+    // foreign table and server names
+    table_name: Rc<utils::ForeignTableName>,
 
     // row id attribute number and type id
     rowid_name: String,
@@ -36,8 +40,13 @@ struct FdwModifyState<E: Into<ErrorReport>, W: ForeignDataWrapper<E>> {
 
 impl<E: Into<ErrorReport>, W: ForeignDataWrapper<E>> FdwModifyState<E, W> {
     unsafe fn new(foreigntableid: Oid, tmp_ctx: PgMemoryContexts) -> Self {
+        let table_name = Rc::new(utils::ForeignTableName::from_oid(foreigntableid));
+        utils::set_current_foreign_table(&table_name);
         Self {
-            instance: instance::create_fdw_instance(foreigntableid),
+            instance: instance::create_fdw_instance(
+                (*pg_sys::GetForeignTable(foreigntableid)).serverid,
+            ),
+            table_name,
             rowid_name: String::default(),
             rowid_attno: 0,
             rowid_typid: Oid::INVALID,
@@ -48,22 +57,31 @@ impl<E: Into<ErrorReport>, W: ForeignDataWrapper<E>> FdwModifyState<E, W> {
     }
 
     fn begin_modify(&mut self) -> Result<(), E> {
-        self.instance.begin_modify(&self.opts)
+        utils::set_current_foreign_table(&self.table_name);
+        let result = self.instance.begin_modify(&self.opts);
+        utils::check_cancel(&mut *self.instance, result)
     }
 
     fn insert(&mut self, row: &Row) -> Result<(), E> {
-        self.instance.insert(row)
+        utils::set_current_foreign_table(&self.table_name);
+        let result = self.instance.insert(row);
+        utils::check_cancel(&mut *self.instance, result)
     }
 
     fn update(&mut self, rowid: &Cell, new_row: &Row) -> Result<(), E> {
-        self.instance.update(rowid, new_row)
+        utils::set_current_foreign_table(&self.table_name);
+        let result = self.instance.update(rowid, new_row);
+        utils::check_cancel(&mut *self.instance, result)
     }
 
     fn delete(&mut self, rowid: &Cell) -> Result<(), E> {
-        self.instance.delete(rowid)
+        utils::set_current_foreign_table(&self.table_name);
+        let result = self.instance.delete(rowid);
+        utils::check_cancel(&mut *self.instance, result)
     }
 
     fn end_modify(&mut self) -> Result<(), E> {
+        utils::set_current_foreign_table(&self.table_name);
         self.instance.end_modify()
     }
 }
@@ -202,6 +220,11 @@ pub(super) extern "C" fn begin_foreign_modify<E: Into<ErrorReport>, W: ForeignDa
         let mut state = FdwModifyState::<E, W>::deserialize_from_list(fdw_private as _);
         assert!(!state.is_null());
 
+        // the instance is released when previous execution of the plan ended
+        if !state.instance.is_checked_out() {
+            state.instance = instance::create_fdw_instance(state.instance.server_id());
+        }
+
         // search for rowid attribute number
         let subplan = (*polyfill::outer_plan_state(&mut (*mtstate).ps)).plan;
         let rowid_name_c = PgMemoryContexts::CurrentMemoryContext.pstrdup(&state.rowid_name);
@@ -312,7 +335,19 @@ pub(super) extern "C" fn end_foreign_modify<E: Into<ErrorReport>, W: ForeignData
         let fdw_state = (*rinfo).ri_FdwState as *mut FdwModifyState<E, W>;
         if !fdw_state.is_null() {
             let mut state = PgBox::<FdwModifyState<E, W>>::from_pg(fdw_state);
-            state.end_modify().map_err(|e| e.into()).report();
+            let result = state.end_modify();
+
+            // return the instance to cache for reuse, it is dropped with the
+            // state if the modify is not ended cleanly
+            if result.is_ok() {
+                state.instance.release();
+            }
+
+            // cached scan results of the modified table are stale now
+            if state.opts.contains_key("cache_ttl") {
+                invalidate_cache(Some((*(*rinfo).ri_RelationDesc).rd_id));
+            }
+            result.map_err(|e| e.into()).report();
         }
     }
 }
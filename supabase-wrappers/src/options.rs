@@ -0,0 +1,430 @@
+//! Declarative foreign data wrapper options
+//!
+//! The options accepted by a FDW can be declared in the `options(...)` argument
+//! of the [`wrappers_fdw`](crate::wrappers_fdw) macro. From that schema the macro
+//! generates the option specs, the typed option structs for each level and a
+//! validator which rejects unknown options, missing required options and values
+//! of wrong type when the server, user mapping, foreign table or column is
+//! created or altered.
+//!
+//! This module contains the runtime part used by the generated code.
+
+use crate::interface::{
+    ATTRIBUTE_RELATION_ID, FOREIGN_SERVER_RELATION_ID, FOREIGN_TABLE_RELATION_ID,
+    USER_MAPPING_RELATION_ID,
+};
+use crate::secret::{
+    get_secret_option, option_owner_is_superuser, validate_secret_ref, vault_id_option_name,
+    SecretError,
+};
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::pg_sys::{self, Oid};
+use pgrx::prelude::PgSqlErrorCode;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// The object an option is defined on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionLevel {
+    /// `CREATE SERVER` options
+    Server,
+    /// `CREATE FOREIGN TABLE` options
+    Table,
+    /// column options in `CREATE FOREIGN TABLE`
+    Column,
+    /// `CREATE USER MAPPING` options, they are merged over server options
+    UserMapping,
+}
+
+impl OptionLevel {
+    fn name(&self) -> &'static str {
+        match self {
+            OptionLevel::Server => "server",
+            OptionLevel::Table => "foreign table",
+            OptionLevel::Column => "column",
+            OptionLevel::UserMapping => "user mapping",
+        }
+    }
+
+    // option level can be specified on the catalog, server options are not
+    // allowed in user mapping, otherwise a user could redirect the server's
+    // credentials to another endpoint
+    fn from_catalog(catalog: Oid) -> Option<OptionLevel> {
+        if catalog == FOREIGN_SERVER_RELATION_ID {
+            Some(OptionLevel::Server)
+        } else if catalog == USER_MAPPING_RELATION_ID {
+            Some(OptionLevel::UserMapping)
+        } else if catalog == FOREIGN_TABLE_RELATION_ID {
+            Some(OptionLevel::Table)
+        } else if catalog == ATTRIBUTE_RELATION_ID {
+            Some(OptionLevel::Column)
+        } else {
+            None
+        }
+    }
+}
+
+/// Value type of an option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionType {
+    String,
+    Bool,
+    I32,
+    I64,
+    Usize,
+    F64,
+}
+
+impl OptionType {
+    fn name(&self) -> &'static str {
+        match self {
+            OptionType::String => "string",
+            OptionType::Bool => "bool",
+            OptionType::I32 => "i32",
+            OptionType::I64 => "i64",
+            OptionType::Usize => "usize",
+            OptionType::F64 => "f64",
+        }
+    }
+
+    fn is_valid(&self, value: &str) -> bool {
+        match self {
+            OptionType::String => true,
+            OptionType::Bool => value.parse::<bool>().is_ok(),
+            OptionType::I32 => value.parse::<i32>().is_ok(),
+            OptionType::I64 => value.parse::<i64>().is_ok(),
+            OptionType::Usize => value.parse::<usize>().is_ok(),
+            OptionType::F64 => value.parse::<f64>().is_ok(),
+        }
+    }
+}
+
+/// Specification of an option, generated by the `wrappers_fdw` macro
+#[derive(Debug, Clone, Copy)]
+pub struct OptionSpec {
+    /// option name
+    pub name: &'static str,
+
+    /// the object this option is defined on
+    pub level: OptionLevel,
+
+    /// value type
+    pub ty: OptionType,
+
+    /// default value used when the option is not specified
+    pub default: Option<&'static str>,
+
+    /// whether the option must be specified
+    pub required: bool,
+
+    /// whether the option is a secret, a secret option `xxx` can also be
+    /// specified as a secret reference in option `xxx_secret` or a Vault
+    /// secret id in option `xxx_id` if `xxx` doesn't end with `_id`, see
+    /// [`secret`](crate::secret)
+    pub secret: bool,
+
+    /// whether the option can only be set by superuser, such as an option
+    /// accessing files on the server, it is checked when the option is set
+    /// and when it is used
+    pub superuser: bool,
+}
+
+impl OptionSpec {
+    // other option names this secret option can be specified in
+    fn secret_names(&self) -> Vec<String> {
+        if self.secret {
+            std::iter::once(format!("{}_secret", self.name))
+                .chain(vault_id_option_name(self.name))
+                .collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        self.name == name || self.secret_names().iter().any(|n| n == name)
+    }
+}
+
+/// Foreign table options handled by the framework itself, they are accepted
+/// by all FDWs declaring an options schema
+pub const FRAMEWORK_OPTION_SPECS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "startup_cost",
+        level: OptionLevel::Table,
+        ty: OptionType::F64,
+        default: None,
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "cache_ttl",
+        level: OptionLevel::Table,
+        ty: OptionType::I64,
+        default: None,
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "cache_max_rows",
+        level: OptionLevel::Table,
+        ty: OptionType::Usize,
+        default: None,
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+];
+
+// get a framework option spec by name
+pub(crate) fn framework_option_spec(name: &str) -> &'static OptionSpec {
+    FRAMEWORK_OPTION_SPECS
+        .iter()
+        .find(|spec| spec.name == name)
+        .unwrap()
+}
+
+#[derive(Error, Debug)]
+pub enum OptionsError {
+    #[error("invalid option \"{name}\" for {level}")]
+    UnknownOption {
+        name: String,
+        level: &'static str,
+        hint: Option<String>,
+    },
+
+    #[error("required option \"{0}\" is not specified")]
+    MissingOption(&'static str),
+
+    #[error("invalid value \"{value}\" for option \"{name}\", expected a {ty}")]
+    InvalidValue {
+        name: &'static str,
+        value: String,
+        ty: &'static str,
+    },
+
+    #[error("only superuser can set option \"{0}\"")]
+    SuperuserOnly(&'static str),
+
+    #[error("{0}")]
+    SecretError(#[from] SecretError),
+}
+
+impl OptionsError {
+    /// SQLSTATE of the error
+    pub fn sqlstate(&self) -> PgSqlErrorCode {
+        match self {
+            OptionsError::UnknownOption { .. } => PgSqlErrorCode::ERRCODE_FDW_INVALID_OPTION_NAME,
+            OptionsError::MissingOption(_) => PgSqlErrorCode::ERRCODE_FDW_OPTION_NAME_NOT_FOUND,
+            OptionsError::InvalidValue { .. } => {
+                PgSqlErrorCode::ERRCODE_FDW_INVALID_ATTRIBUTE_VALUE
+            }
+            OptionsError::SuperuserOnly(_) => PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            OptionsError::SecretError(e) => e.sqlstate(),
+        }
+    }
+}
+
+impl From<OptionsError> for ErrorReport {
+    fn from(value: OptionsError) -> Self {
+        let error_message = format!("{value}");
+        let report = ErrorReport::new(value.sqlstate(), error_message, "");
+        match value {
+            OptionsError::UnknownOption {
+                hint: Some(hint), ..
+            } => report.set_hint(hint),
+            _ => report,
+        }
+    }
+}
+
+/// Typed options which can be parsed from an options map
+///
+/// It is implemented by the option structs generated by the `wrappers_fdw` macro.
+pub trait FromOptions: Sized {
+    fn from_options(options: &HashMap<String, String>) -> Result<Self, OptionsError>;
+}
+
+// edit distance between two strings, used to suggest misspelled options
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != *cb);
+            curr[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
+}
+
+fn unknown_option_hint(specs: &[OptionSpec], level: OptionLevel, name: &str) -> Option<String> {
+    // the option exists but is specified on a wrong object
+    if let Some(spec) = specs.iter().find(|spec| spec.matches(name)) {
+        return Some(format!(
+            "Option \"{}\" should be specified on {}.",
+            name,
+            spec.level.name()
+        ));
+    }
+
+    // the option is probably misspelled
+    specs
+        .iter()
+        .filter(|spec| spec.level == level)
+        .flat_map(|spec| std::iter::once(spec.name.to_owned()).chain(spec.secret_names()))
+        .map(|candidate| (edit_distance(name, &candidate), candidate))
+        .filter(|(dist, _)| *dist <= 2)
+        .min_by_key(|(dist, _)| *dist)
+        .map(|(_, candidate)| format!("Perhaps you meant option \"{}\".", candidate))
+}
+
+/// Validate options against option specs
+///
+/// This is called by the validator generated by the `wrappers_fdw` macro, the
+/// `options` and `catalog` are the arguments passed to the validator. It
+/// reports options not defined for the catalog, values which cannot be parsed
+/// as the option type, and required options which are not specified. The
+/// options in [`FRAMEWORK_OPTION_SPECS`] are accepted in addition to `specs`.
+pub fn validate_options(
+    specs: &[OptionSpec],
+    options: &[Option<String>],
+    catalog: Option<Oid>,
+) -> Result<(), OptionsError> {
+    let level = match catalog.and_then(OptionLevel::from_catalog) {
+        Some(level) => level,
+        None => return Ok(()),
+    };
+    let specs: Vec<OptionSpec> = specs
+        .iter()
+        .chain(FRAMEWORK_OPTION_SPECS)
+        .copied()
+        .collect();
+    let level_specs: Vec<&OptionSpec> = specs.iter().filter(|spec| spec.level == level).collect();
+
+    let mut names = Vec::new();
+    for opt in options.iter().flatten() {
+        let (name, value) = opt.split_once('=').unwrap_or((opt.as_str(), ""));
+        let spec = level_specs
+            .iter()
+            .find(|spec| spec.matches(name))
+            .ok_or_else(|| OptionsError::UnknownOption {
+                name: name.to_owned(),
+                level: level.name(),
+                hint: unknown_option_hint(&specs, level, name),
+            })?;
+
+        if spec.superuser && !unsafe { pg_sys::superuser() } {
+            return Err(OptionsError::SuperuserOnly(spec.name));
+        }
+
+        // secret reference must be usable by current user, and vault secret
+        // id is always a string
+        if name == format!("{}_secret", spec.name) {
+            validate_secret_ref(value)?;
+        } else if spec.name == name && !spec.ty.is_valid(value) {
+            return Err(OptionsError::InvalidValue {
+                name: spec.name,
+                value: value.to_owned(),
+                ty: spec.ty.name(),
+            });
+        }
+        names.push(name);
+    }
+
+    for spec in level_specs
+        .iter()
+        .filter(|spec| spec.required && spec.default.is_none())
+    {
+        if !names.iter().any(|name| spec.matches(name)) {
+            return Err(OptionsError::MissingOption(spec.name));
+        }
+    }
+
+    Ok(())
+}
+
+thread_local! {
+    // option specs of FDW types keyed by type name, registered by the handler
+    // and validator generated by the `wrappers_fdw` macro
+    static REGISTERED_SPECS: RefCell<HashMap<&'static str, Vec<OptionSpec>>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Register the option specs of a FDW type
+///
+/// Not to be used directly, called by the handler and validator generated by
+/// the [`wrappers_fdw`](crate::wrappers_fdw) macro.
+pub fn register_option_specs(fdw_type: &'static str, specs: &[OptionSpec]) {
+    REGISTERED_SPECS.with(|registered| {
+        registered.borrow_mut().insert(fdw_type, specs.to_vec());
+    });
+}
+
+// option specs registered for a FDW type, empty if it has no options schema
+pub(crate) fn registered_option_specs(fdw_type: &str) -> Vec<OptionSpec> {
+    REGISTERED_SPECS.with(|registered| {
+        registered
+            .borrow()
+            .get(fdw_type)
+            .cloned()
+            .unwrap_or_default()
+    })
+}
+
+// whether an option can be taken from user mapping, only the options declared
+// on user mapping level are merged over server options and all the others are
+// ignored
+pub(crate) fn is_user_mapping_option(specs: &[OptionSpec], name: &str) -> bool {
+    specs
+        .iter()
+        .any(|spec| spec.level == OptionLevel::UserMapping && spec.matches(name))
+}
+
+/// Get an option value and parse it as type `T`
+///
+/// The value is taken from `options`, the secret in `<name>_secret` or `<name>_id`
+/// option if this is a secret option, or the default value. Returns `None` if none
+/// of them exists. A superuser-only option is rejected if the role who set it is
+/// no longer superuser.
+pub fn get_option<T: FromStr>(
+    spec: &OptionSpec,
+    options: &HashMap<String, String>,
+) -> Result<Option<T>, OptionsError> {
+    if spec.superuser && options.contains_key(spec.name) && !option_owner_is_superuser(spec.name) {
+        return Err(OptionsError::SuperuserOnly(spec.name));
+    }
+
+    let value = if spec.secret {
+        get_secret_option(spec.name, options)?
+    } else {
+        options.get(spec.name).map(|s| s.to_owned())
+    };
+    let value = value.or_else(|| spec.default.map(|s| s.to_owned()));
+
+    value
+        .map(|value| {
+            value.parse::<T>().map_err(|_| OptionsError::InvalidValue {
+                name: spec.name,
+                value,
+                ty: spec.ty.name(),
+            })
+        })
+        .transpose()
+}
+
+/// Get a required option value and parse it as type `T`
+///
+/// Same as [`get_option`] but returns an error if the option is not specified.
+pub fn require_typed_option<T: FromStr>(
+    spec: &OptionSpec,
+    options: &HashMap<String, String>,
+) -> Result<T, OptionsError> {
+    get_option(spec, options)?.ok_or(OptionsError::MissingOption(spec.name))
+}
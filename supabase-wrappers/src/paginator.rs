@@ -0,0 +1,226 @@
+//! Pagination helper for FDWs scanning a paginated remote API
+//!
+//! A [`Paginator`] fetches the pages lazily: the next page is only requested
+//! when all rows of current page have been returned by `iter_scan`, and no
+//! more pages are requested once the pushed down [`Limit`] is reached. The
+//! FDW provides a function to fetch a page, which returns the page rows and
+//! how to request the next page as a [`NextPage`], so the common pagination
+//! styles are covered:
+//!
+//! - cursor, e.g. Stripe's `starting_after` and `has_more`
+//! - page token, e.g. Firebase's `nextPageToken` or BigQuery's `pageToken`
+//! - offset and limit, see [`Page::with_offset`]
+//! - URL in the `Link` header, see [`NextPage::from_link_header`]
+//!
+//! For example,
+//!
+//! ```rust,no_run
+//! # use supabase_wrappers::prelude::*;
+//! # fn fetch(next: Option<&NextPage>, size: Option<i64>) -> Result<(Vec<Row>, Option<String>), FdwError> { todo!() }
+//! struct MyFdw {
+//!     pager: Paginator,
+//! }
+//!
+//! impl MyFdw {
+//!     fn begin_scan(&mut self, limit: &Option<Limit>) {
+//!         self.pager = Paginator::new().with_page_size(100).with_limit(limit);
+//!     }
+//!
+//!     fn iter_scan(&mut self, row: &mut Row) -> Result<Option<()>, FdwError> {
+//!         self.pager.next_row(row, |req| {
+//!             let (rows, token) = fetch(req.next, req.page_size)?;
+//!             Ok(Page::new(rows, token.map(NextPage::Token)))
+//!         })
+//!     }
+//! }
+//! ```
+
+use std::collections::VecDeque;
+
+use crate::interface::{Limit, Row};
+
+/// How to request the next page
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NextPage {
+    /// cursor of the last row, e.g. Stripe's `starting_after`
+    Cursor(String),
+
+    /// opaque token returned by the remote API, e.g. Firebase's `nextPageToken`
+    Token(String),
+
+    /// offset of the first row of next page
+    Offset(i64),
+
+    /// URL of next page, e.g. from the `Link` header
+    Url(String),
+}
+
+impl NextPage {
+    /// Get next page URL from a `Link` header value, e.g.
+    /// `<https://api.example.com/items?page=2>; rel="next"`
+    pub fn from_link_header(value: &str) -> Option<Self> {
+        value.split(',').find_map(|link| {
+            let mut parts = link.split(';');
+            let url = parts
+                .next()?
+                .trim()
+                .strip_prefix('<')?
+                .strip_suffix('>')?
+                .to_owned();
+            parts
+                .any(|param| matches!(param.trim(), "rel=\"next\"" | "rel=next"))
+                .then_some(NextPage::Url(url))
+        })
+    }
+}
+
+/// A page of rows fetched from remote
+#[derive(Debug, Default)]
+pub struct Page {
+    pub rows: Vec<Row>,
+
+    /// how to request the next page, `None` if this is the last page
+    pub next: Option<NextPage>,
+}
+
+impl Page {
+    pub fn new(rows: Vec<Row>, next: Option<NextPage>) -> Self {
+        Self { rows, next }
+    }
+
+    /// Create a page fetched by offset and limit, it is the last page if it
+    /// has less rows than `page_size`
+    pub fn with_offset(rows: Vec<Row>, offset: i64, page_size: i64) -> Self {
+        let next = (rows.len() as i64 >= page_size)
+            .then_some(NextPage::Offset(offset + rows.len() as i64));
+        Self { rows, next }
+    }
+}
+
+/// Request of a page, passed to the page fetching function
+#[derive(Debug, Clone)]
+pub struct PageRequest<'a> {
+    /// `None` for the first page
+    pub next: Option<&'a NextPage>,
+
+    /// number of rows to fetch, it is the page size set by
+    /// [`Paginator::with_page_size`] but no more than the rows remaining
+    /// to reach the limit
+    pub page_size: Option<i64>,
+}
+
+#[derive(Debug, Default)]
+enum PageState {
+    #[default]
+    First,
+    Next(NextPage),
+    Done,
+}
+
+/// Lazy page fetcher of a foreign scan
+#[derive(Debug, Default)]
+pub struct Paginator {
+    page_size: Option<i64>,
+    max_rows: Option<i64>,
+    state: PageState,
+    rows: VecDeque<Row>,
+    returned: i64,
+    pages: usize,
+    fetched: usize,
+}
+
+impl Paginator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set number of rows to fetch per page
+    pub fn with_page_size(mut self, page_size: i64) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Stop fetching once the rows required by the limit, including the
+    /// offset rows, are returned
+    pub fn with_limit(self, limit: &Option<Limit>) -> Self {
+        match limit {
+            Some(limit) => self.with_max_rows(limit.offset + limit.count),
+            None => self,
+        }
+    }
+
+    /// Stop fetching once `max_rows` rows are returned
+    pub fn with_max_rows(mut self, max_rows: i64) -> Self {
+        self.max_rows = Some(self.max_rows.map_or(max_rows, |n| n.min(max_rows)));
+        self
+    }
+
+    /// Add a page fetched outside of the paginator, e.g. the first page
+    /// fetched in `begin_scan`. An empty page is treated as the last page.
+    pub fn add_page(&mut self, page: Page) {
+        self.pages += 1;
+        self.fetched += page.rows.len();
+        self.state = match page.next {
+            Some(next) if !page.rows.is_empty() => PageState::Next(next),
+            _ => PageState::Done,
+        };
+        self.rows.extend(page.rows);
+    }
+
+    /// Get the next row, the next page is fetched by `fetch` when all rows of
+    /// current page have been returned. Returns `None` when there are no more
+    /// rows or the limit is reached.
+    pub fn next_row<E, F>(&mut self, row: &mut Row, mut fetch: F) -> Result<Option<()>, E>
+    where
+        F: FnMut(PageRequest) -> Result<Page, E>,
+    {
+        loop {
+            let remaining = self.max_rows.map(|max_rows| max_rows - self.returned);
+            if remaining.map_or(false, |n| n <= 0) {
+                return Ok(None);
+            }
+
+            if let Some(src_row) = self.rows.pop_front() {
+                self.returned += 1;
+                row.replace_with(src_row);
+                return Ok(Some(()));
+            }
+
+            let next = match std::mem::take(&mut self.state) {
+                PageState::First => None,
+                PageState::Next(next) => Some(next),
+                PageState::Done => {
+                    self.state = PageState::Done;
+                    return Ok(None);
+                }
+            };
+            self.state = PageState::Done;
+            let page_size = match (self.page_size, remaining) {
+                (Some(page_size), Some(remaining)) => Some(page_size.min(remaining)),
+                (page_size, remaining) => page_size.or(remaining),
+            };
+            let page = fetch(PageRequest {
+                next: next.as_ref(),
+                page_size,
+            })?;
+            self.add_page(page);
+        }
+    }
+
+    /// Restart from the first page, the pages will be fetched again
+    pub fn reset(&mut self) {
+        self.state = PageState::First;
+        self.rows.clear();
+        self.returned = 0;
+    }
+
+    /// Number of pages fetched
+    pub fn pages(&self) -> usize {
+        self.pages
+    }
+
+    /// Number of rows fetched
+    pub fn rows_fetched(&self) -> usize {
+        self.fetched
+    }
+}
@@ -4,7 +4,7 @@ use pgrx::{is_a, list::PgList, pg_sys, pg_sys::Datum, FromDatum, PgBuiltInOids,
 use std::ffi::CStr;
 use std::os::raw::c_int;
 
-use crate::interface::Param;
+use crate::interface::{Param, ParamKind};
 
 // create array of Cell from constant datum array
 pub(crate) unsafe fn form_array_from_datum(
@@ -135,6 +135,11 @@ pub(crate) unsafe fn extract_from_op_expr(
                 let param = Param {
                     id: (*right).paramid as _,
                     type_oid: (*right).paramtype,
+                    kind: if (*right).paramkind == pg_sys::ParamKind_PARAM_EXEC {
+                        ParamKind::Exec
+                    } else {
+                        ParamKind::Extern
+                    },
                 };
                 (Some(Cell::I64(0)), Some(param))
             } else {
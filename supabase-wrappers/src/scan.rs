@@ -9,9 +9,11 @@ use std::marker::PhantomData;
 use pgrx::pg_sys::panic::{ErrorReport, ErrorReportable};
 use std::os::raw::c_int;
 use std::ptr;
+use std::rc::Rc;
 
+use crate::cache::ScanCache;
 use crate::instance;
-use crate::interface::{Cell, Column, Limit, Qual, Row, Sort, Value};
+use crate::interface::{Cell, Column, Limit, Param, ParamKind, Qual, Row, Sort, Value};
 use crate::limit::*;
 use crate::memctx;
 use crate::polyfill;
@@ -22,8 +24,12 @@ use crate::utils::{self, report_error, SerdeList};
 
 // Fdw private state for scan
 struct FdwState<E: Into<ErrorReport>, W: ForeignDataWrapper<E>> {
-    // foreign data wrapper instance
-    instance: W,
+    // foreign data wrapper instance, checked out from the instance cache
+    instance: instance::CachedInstance<W>,
+
+    // foreign table oid, and foreign table and server names
+    ftable_id: Oid,
+    table_name: Rc<utils::ForeignTableName>,
 
     // query conditions
     quals: Vec<Qual>,
@@ -37,9 +43,22 @@ struct FdwState<E: Into<ErrorReport>, W: ForeignDataWrapper<E>> {
     // limit
     limit: Option<Limit>,
 
+    // limit with count or offset parameters, kept to assign the parameter
+    // values to `limit` again on re-scan
+    param_limit: Option<Limit>,
+
     // foreign table options
     opts: HashMap<String, String>,
 
+    // scan result cache, and whether the scan is served from cache, it is
+    // `None` if the cache is not enabled for this foreign table
+    cache: ScanCache,
+    cache_hit: Option<bool>,
+
+    // whether beginning the FDW scan is deferred until the first row is
+    // fetched, because its executor parameter values are not set yet
+    scan_deferred: bool,
+
     // temporary memory context per foreign table, created under Wrappers root
     // memory context
     tmp_ctx: PgMemoryContexts,
@@ -53,13 +72,23 @@ struct FdwState<E: Into<ErrorReport>, W: ForeignDataWrapper<E>> {
 
 impl<E: Into<ErrorReport>, W: ForeignDataWrapper<E>> FdwState<E, W> {
     unsafe fn new(foreigntableid: Oid, tmp_ctx: PgMemoryContexts) -> Self {
+        let table_name = Rc::new(utils::ForeignTableName::from_oid(foreigntableid));
+        utils::set_current_foreign_table(&table_name);
         Self {
-            instance: instance::create_fdw_instance(foreigntableid),
+            instance: instance::create_fdw_instance(
+                (*pg_sys::GetForeignTable(foreigntableid)).serverid,
+            ),
+            ftable_id: foreigntableid,
+            table_name,
             quals: Vec::new(),
             tgts: Vec::new(),
             sorts: Vec::new(),
             limit: None,
+            param_limit: None,
             opts: HashMap::new(),
+            cache: ScanCache::Off,
+            cache_hit: None,
+            scan_deferred: false,
             tmp_ctx,
             values: Vec::new(),
             nulls: Vec::new(),
@@ -70,38 +99,94 @@ impl<E: Into<ErrorReport>, W: ForeignDataWrapper<E>> FdwState<E, W> {
 
     #[inline]
     fn get_rel_size(&mut self) -> Result<(i64, i32), E> {
-        self.instance.get_rel_size(
+        utils::set_current_foreign_table(&self.table_name);
+        let result = self.instance.get_rel_size(
             &self.quals,
             &self.tgts,
             &self.sorts,
             &self.limit,
             &self.opts,
-        )
+        );
+        utils::check_cancel(&mut *self.instance, result)
     }
 
     #[inline]
     fn begin_scan(&mut self) -> Result<(), E> {
-        self.instance.begin_scan(
+        utils::set_current_foreign_table(&self.table_name);
+        self.cache = ScanCache::begin(
+            self.ftable_id,
             &self.quals,
             &self.tgts,
             &self.sorts,
             &self.limit,
             &self.opts,
-        )
+        );
+        self.cache_hit = self.cache.is_enabled().then_some(self.cache.is_hit());
+        if self.cache.is_hit() {
+            return Ok(());
+        }
+        let result = self.instance.begin_scan(
+            &self.quals,
+            &self.tgts,
+            &self.sorts,
+            &self.limit,
+            &self.opts,
+        );
+        utils::check_cancel(&mut *self.instance, result)
     }
 
     #[inline]
     fn iter_scan(&mut self) -> Result<Option<()>, E> {
-        self.instance.iter_scan(&mut self.row)
+        if self.cache.is_hit() {
+            return Ok(self.cache.next_row(&mut self.row));
+        }
+        utils::set_current_foreign_table(&self.table_name);
+        let result = self.instance.iter_scan(&mut self.row);
+        let result = utils::check_cancel(&mut *self.instance, result)?;
+        match result {
+            Some(_) => self.cache.add_row(&self.row),
+            None => self.cache.finish(),
+        }
+        Ok(result)
+    }
+
+    // parameters used by quals and limit
+    fn params(&self) -> impl Iterator<Item = &Param> {
+        let limit = self.param_limit.as_ref().or(self.limit.as_ref());
+        self.quals.iter().filter_map(|q| q.param.as_ref()).chain(
+            limit
+                .into_iter()
+                .flat_map(|l| l.count_param.iter().chain(l.offset_param.iter())),
+        )
+    }
+
+    // check if any qual or limit has an executor parameter
+    #[inline]
+    fn has_exec_params(&self) -> bool {
+        self.params().any(|p| p.kind == ParamKind::Exec)
     }
 
     #[inline]
     fn re_scan(&mut self) -> Result<(), E> {
-        self.instance.re_scan()
+        if self.scan_deferred {
+            return Ok(());
+        }
+        self.cache.rewind();
+        if self.cache.is_hit() {
+            return Ok(());
+        }
+        utils::set_current_foreign_table(&self.table_name);
+        let result = self.instance.re_scan();
+        utils::check_cancel(&mut *self.instance, result)
     }
 
     #[inline]
     fn end_scan(&mut self) -> Result<(), E> {
+        // the FDW scan is not begun if it is deferred or served from cache
+        if self.scan_deferred || self.cache_hit == Some(true) {
+            return Ok(());
+        }
+        utils::set_current_foreign_table(&self.table_name);
         self.instance.end_scan()
     }
 }
@@ -259,33 +344,146 @@ pub(super) extern "C" fn explain_foreign_scan<E: Into<ErrorReport>, W: ForeignDa
 
         let value = ctx.pstrdup(&format!("limit = {:?}", state.limit));
         pg_sys::ExplainPropertyText(label, value, es);
+
+        // FDW-specific details, such as the remote query
+        for (key, value) in state.instance.explain((*es).verbose) {
+            let label = ctx.pstrdup(&key);
+            let value = ctx.pstrdup(&value);
+            pg_sys::ExplainPropertyText(label, value, es);
+        }
+
+        // remote metrics are only available when the scan is executed
+        if (*es).analyze {
+            if let Some(hit) = state.cache_hit {
+                let value = if hit { "hit" } else { "miss" };
+                pg_sys::ExplainPropertyText(ctx.pstrdup("Result Cache"), ctx.pstrdup(value), es);
+            }
+            if let Some(metrics) = state.instance.remote_metrics() {
+                let bytes_unit = ctx.pstrdup("bytes");
+                let ms_unit = ctx.pstrdup("ms");
+                pg_sys::ExplainPropertyInteger(
+                    ctx.pstrdup("Remote Requests"),
+                    ptr::null(),
+                    metrics.requests as i64,
+                    es,
+                );
+                pg_sys::ExplainPropertyInteger(
+                    ctx.pstrdup("Remote Bytes Received"),
+                    bytes_unit,
+                    metrics.bytes_received as i64,
+                    es,
+                );
+                pg_sys::ExplainPropertyInteger(
+                    ctx.pstrdup("Remote Pages Fetched"),
+                    ptr::null(),
+                    metrics.pages as i64,
+                    es,
+                );
+                pg_sys::ExplainPropertyFloat(
+                    ctx.pstrdup("Remote Latency"),
+                    ms_unit,
+                    metrics.latency.as_secs_f64() * 1000.0,
+                    3,
+                    es,
+                );
+            }
+        }
     }
 }
 
-// extract paramter value and assign it to qual in scan state
-unsafe fn assign_paramenter_value<E: Into<ErrorReport>, W: ForeignDataWrapper<E>>(
+// get parameter value from execution state, returns its datum, whether it
+// is null and its type
+unsafe fn get_param_value(
     node: *mut pg_sys::ForeignScanState,
-    state: &mut FdwState<E, W>,
-) {
-    // get parameter list in execution state
+    param: &Param,
+) -> Option<(Datum, bool, Oid)> {
     let estate = (*node).ss.ps.state;
-    let plist_info = (*estate).es_param_list_info;
-    if plist_info.is_null() {
-        return;
+    match param.kind {
+        ParamKind::Extern => {
+            let plist_info = (*estate).es_param_list_info;
+            if plist_info.is_null() || param.id < 1 || param.id > (*plist_info).numParams as usize {
+                return None;
+            }
+
+            // parameter values can be fetched lazily, e.g. in PL/pgSQL
+            let prm = match (*plist_info).paramFetch {
+                Some(param_fetch) => {
+                    let mut workspace: pg_sys::ParamExternData = std::mem::zeroed();
+                    *param_fetch(plist_info, param.id as _, false, &mut workspace)
+                }
+                None => {
+                    let plist = (*plist_info)
+                        .params
+                        .as_slice((*plist_info).numParams as usize);
+                    plist[param.id - 1]
+                }
+            };
+            Some((prm.value, prm.isnull, prm.ptype))
+        }
+        ParamKind::Exec => {
+            // evaluate the initplan if the parameter is computed by it
+            let prm = (*estate).es_param_exec_vals.add(param.id);
+            if !(*prm).execPlan.is_null() {
+                pg_sys::ExecSetParamPlan((*prm).execPlan as _, (*node).ss.ps.ps_ExprContext);
+            }
+            Some(((*prm).value, (*prm).isnull, param.type_oid))
+        }
     }
-    let params_cnt = (*plist_info).numParams as usize;
-    let plist = (*plist_info).params.as_slice(params_cnt);
-    assert!(state.quals.iter().filter(|q| q.param.is_some()).count() <= params_cnt);
+}
 
+// extract paramter value and assign it to qual and limit in scan state
+unsafe fn assign_paramenter_value<E: Into<ErrorReport>, W: ForeignDataWrapper<E>>(
+    node: *mut pg_sys::ForeignScanState,
+    state: &mut FdwState<E, W>,
+) {
     // assign parameter value to qual
     for qual in &mut state.quals.iter_mut() {
         if let Some(param) = &qual.param {
-            let p: pg_sys::ParamExternData = plist[param.id - 1];
-            if let Some(value) = Cell::from_polymorphic_datum(p.value, p.isnull, p.ptype) {
+            let value = get_param_value(node, param).and_then(|(value, isnull, typoid)| {
+                Cell::from_polymorphic_datum(value, isnull, typoid)
+            });
+            if let Some(value) = value {
                 qual.value = Value::Cell(value);
             }
         }
     }
+
+    // assign parameter value to limit, NULL count means no limit
+    if state.param_limit.is_none() {
+        if let Some(limit) = &state.limit {
+            if limit.count_param.is_some() || limit.offset_param.is_some() {
+                state.param_limit = Some(limit.clone());
+            }
+        }
+    }
+    if let Some(param_limit) = &state.param_limit {
+        let get_value = |param: &Option<Param>, default: i64| match param {
+            Some(param) => get_param_value(node, param).and_then(|(value, isnull, typoid)| {
+                i64::from_polymorphic_datum(value, isnull, typoid)
+            }),
+            None => Some(default),
+        };
+        state.limit = get_value(&param_limit.count_param, param_limit.count).map(|count| Limit {
+            count,
+            offset: get_value(&param_limit.offset_param, param_limit.offset).unwrap_or(0),
+            ..param_limit.clone()
+        });
+    }
+}
+
+// check if any executor parameter used by quals or limit has changed since
+// last scan
+unsafe fn exec_params_changed<E: Into<ErrorReport>, W: ForeignDataWrapper<E>>(
+    node: *mut pg_sys::ForeignScanState,
+    state: &FdwState<E, W>,
+) -> bool {
+    let chg_param = (*node).ss.ps.chgParam;
+    if chg_param.is_null() {
+        return false;
+    }
+    state
+        .params()
+        .any(|p| p.kind == ParamKind::Exec && pg_sys::bms_is_member(p.id as _, chg_param))
 }
 
 #[pg_guard]
@@ -300,12 +498,27 @@ pub(super) extern "C" fn begin_foreign_scan<E: Into<ErrorReport>, W: ForeignData
         let mut state = FdwState::<E, W>::deserialize_from_list((*plan).fdw_private as _);
         assert!(!state.is_null());
 
-        // assign parameter values to qual
-        assign_paramenter_value(node, &mut state);
+        // the instance is released when previous execution of the plan ended
+        if !state.instance.is_checked_out() {
+            state.instance = instance::create_fdw_instance(state.instance.server_id());
+        }
+
+        // executor parameter values are set after the scan node is
+        // initialized, e.g. by the outer relation of a nested loop, so the
+        // scan is begun when the first row is fetched
+        let deferred = state.has_exec_params();
+        if !deferred {
+            // assign parameter values to qual
+            assign_paramenter_value(node, &mut state);
+        }
 
         // begin scan if it is not EXPLAIN statement
         if eflags & pg_sys::EXEC_FLAG_EXPLAIN_ONLY as c_int <= 0 {
-            state.begin_scan().map_err(|e| e.into()).report();
+            if deferred {
+                state.scan_deferred = true;
+            } else {
+                state.begin_scan().map_err(|e| e.into()).report();
+            }
 
             let rel = scan_state.ss_currentRelation;
             let tup_desc = (*rel).rd_att;
@@ -335,22 +548,48 @@ pub(super) extern "C" fn iterate_foreign_scan<E: Into<ErrorReport>, W: ForeignDa
         let slot = (*node).ss.ss_ScanTupleSlot;
         polyfill::exec_clear_tuple(slot);
 
+        if state.scan_deferred {
+            assign_paramenter_value(node, &mut state);
+            state.scan_deferred = false;
+            state.begin_scan().map_err(|e| e.into()).report();
+        }
+
         state.row.clear();
         if state.iter_scan().map_err(|e| e.into()).report().is_some() {
             if state.row.cols.len() != state.tgts.len() {
                 report_error(
                     PgSqlErrorCode::ERRCODE_FDW_INVALID_COLUMN_NUMBER,
-                    "target column number not match",
+                    &format!(
+                        "target column number not match, expected {} but got {}",
+                        state.tgts.len(),
+                        state.row.cols.len()
+                    ),
                 );
                 return slot;
             }
 
             for i in 0..state.row.cells.len() {
-                let att_idx = state.tgts[i].num - 1;
+                let tgt = &state.tgts[i];
+                let att_idx = tgt.num - 1;
+                if let Some(cell) = &state.row.cells[i] {
+                    let cell_type = cell.datum_type_oid();
+                    if !utils::is_type_compatible(cell_type, tgt.type_oid) {
+                        report_error(
+                            PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
+                            &format!(
+                                "column \"{}\" is of type {} but foreign data wrapper returned a value of type {}",
+                                tgt.name,
+                                utils::type_name(tgt.type_oid),
+                                utils::type_name(cell_type),
+                            ),
+                        );
+                        return slot;
+                    }
+                }
                 let cell = state.row.cells.get_unchecked_mut(i);
                 match cell.take() {
                     Some(cell) => {
-                        state.values[att_idx] = cell.into_datum().unwrap();
+                        state.values[att_idx] = cell.widen_to(tgt.type_oid).into_datum().unwrap();
                         state.nulls[att_idx] = false;
                     }
                     None => state.nulls[att_idx] = true,
@@ -375,7 +614,15 @@ pub(super) extern "C" fn re_scan_foreign_scan<E: Into<ErrorReport>, W: ForeignDa
         let fdw_state = (*node).fdw_state as *mut FdwState<E, W>;
         if !fdw_state.is_null() {
             let mut state = PgBox::<FdwState<E, W>>::from_pg(fdw_state);
-            state.re_scan().map_err(|e| e.into()).report();
+
+            // restart the scan with new parameter values if they have changed,
+            // otherwise restart from the beginning of current scan
+            if exec_params_changed(node, &state) {
+                state.end_scan().map_err(|e| e.into()).report();
+                state.scan_deferred = true;
+            } else {
+                state.re_scan().map_err(|e| e.into()).report();
+            }
         }
     }
 }
@@ -392,6 +639,13 @@ pub(super) extern "C" fn end_foreign_scan<E: Into<ErrorReport>, W: ForeignDataWr
         }
 
         let mut state = PgBox::<FdwState<E, W>>::from_pg(fdw_state);
-        state.end_scan().map_err(|e| e.into()).report();
+        let result = state.end_scan();
+
+        // return the instance to cache for reuse, it is dropped with the state
+        // if the scan is not ended cleanly
+        if result.is_ok() {
+            state.instance.release();
+        }
+        result.map_err(|e| e.into()).report();
     }
 }
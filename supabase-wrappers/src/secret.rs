@@ -0,0 +1,348 @@
+//! Secret providers for credentials in foreign data wrapper options
+//!
+//! Instead of storing a credential in plain text in a server or user mapping
+//! option `xxx`, it can be given as a reference in option `xxx_secret`, in the
+//! form of `<provider>:<reference>`. The built-in providers are:
+//!
+//! - `vault:<secret id>` - decrypted secret from [Vault](https://github.com/supabase/vault)
+//! - `env:<variable name>` - environment variable of the Postgres server process
+//! - `file:<path>` - content of a file on the Postgres server, trailing newlines are removed
+//! - `guc:<setting name>` - value of a Postgres configuration parameter, e.g. a custom
+//!   setting like `my_app.stripe_key` set in `postgresql.conf` or with `ALTER ROLE ... SET`
+//!
+//! The `env` and `file` providers expose the server environment and file system,
+//! so they can only be used in options set by a superuser. This is checked when
+//! the options are validated, and again when the secret is resolved, against
+//! the owner of the foreign server or the user of the user mapping the option
+//! is defined on.
+//!
+//! Other providers can be added by [`register_secret_provider`]. For backward
+//! compatibility, a Vault secret id can still be specified in option `xxx_id`,
+//! unless the option name already ends with `_id`, such as `aws_access_key_id`.
+//!
+//! For example,
+//!
+//! ```sql
+//! create server my_stripe_server
+//!   foreign data wrapper stripe_wrapper
+//!   options (
+//!     api_key_secret 'env:STRIPE_API_KEY'
+//!   );
+//! ```
+
+use crate::utils::report_error;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::prelude::*;
+use pgrx::{pg_sys, IntoDatum};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::rc::Rc;
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum SecretError {
+    #[error("invalid secret reference \"{0}\", expected \"<provider>:<reference>\"")]
+    InvalidReference(String),
+
+    #[error("unknown secret provider \"{0}\"")]
+    UnknownProvider(String),
+
+    #[error("secret \"{0}\" is not found")]
+    NotFound(String),
+
+    #[error("permission denied to use secret provider \"{0}\", must be superuser")]
+    PermissionDenied(String),
+
+    #[error("read secret \"{0}\" failed: {1}")]
+    ReadFailed(String, String),
+}
+
+impl SecretError {
+    /// SQLSTATE of the error
+    pub fn sqlstate(&self) -> PgSqlErrorCode {
+        match self {
+            SecretError::InvalidReference(_) | SecretError::UnknownProvider(_) => {
+                PgSqlErrorCode::ERRCODE_FDW_INVALID_ATTRIBUTE_VALUE
+            }
+            SecretError::PermissionDenied(_) => PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            _ => PgSqlErrorCode::ERRCODE_FDW_ERROR,
+        }
+    }
+}
+
+impl From<SecretError> for ErrorReport {
+    fn from(value: SecretError) -> Self {
+        let error_message = format!("{value}");
+        ErrorReport::new(value.sqlstate(), error_message, "")
+    }
+}
+
+/// A source of secrets
+pub trait SecretProvider {
+    /// Get the secret by its reference, the part after `<provider>:`
+    fn get_secret(&self, reference: &str) -> Result<String, SecretError>;
+
+    /// Whether only superuser can use this provider in options
+    fn superuser_only(&self) -> bool {
+        false
+    }
+}
+
+/// Secrets stored in [Vault](https://github.com/supabase/vault), referenced by secret id
+pub struct VaultProvider;
+
+impl SecretProvider for VaultProvider {
+    fn get_secret(&self, reference: &str) -> Result<String, SecretError> {
+        let sid = Uuid::try_parse(reference)
+            .map_err(|err| SecretError::ReadFailed(reference.to_owned(), err.to_string()))?;
+        Spi::get_one_with_args::<String>(
+            "select decrypted_secret from vault.decrypted_secrets where key_id = $1",
+            vec![(
+                PgBuiltInOids::UUIDOID.oid(),
+                pgrx::Uuid::from_bytes(sid.into_bytes()).into_datum(),
+            )],
+        )
+        .map_err(|err| SecretError::ReadFailed(reference.to_owned(), err.to_string()))?
+        .ok_or_else(|| SecretError::NotFound(reference.to_owned()))
+    }
+}
+
+/// Secrets in environment variables of the Postgres server process
+pub struct EnvProvider;
+
+impl SecretProvider for EnvProvider {
+    fn get_secret(&self, reference: &str) -> Result<String, SecretError> {
+        std::env::var(reference).map_err(|_| SecretError::NotFound(reference.to_owned()))
+    }
+
+    fn superuser_only(&self) -> bool {
+        true
+    }
+}
+
+/// Secrets in files on the Postgres server
+pub struct FileProvider;
+
+impl SecretProvider for FileProvider {
+    fn get_secret(&self, reference: &str) -> Result<String, SecretError> {
+        std::fs::read_to_string(reference)
+            .map(|s| s.trim_end_matches(&['\r', '\n'][..]).to_owned())
+            .map_err(|err| SecretError::ReadFailed(reference.to_owned(), err.to_string()))
+    }
+
+    fn superuser_only(&self) -> bool {
+        true
+    }
+}
+
+/// Secrets in Postgres configuration parameters
+pub struct GucProvider;
+
+impl SecretProvider for GucProvider {
+    fn get_secret(&self, reference: &str) -> Result<String, SecretError> {
+        let name = CString::new(reference)
+            .map_err(|_| SecretError::InvalidReference(reference.to_owned()))?;
+        // privilege of reading the setting is checked by Postgres
+        let value =
+            unsafe { pg_sys::GetConfigOptionByName(name.as_ptr(), std::ptr::null_mut(), true) };
+        if value.is_null() {
+            return Err(SecretError::NotFound(reference.to_owned()));
+        }
+        let secret = unsafe { CStr::from_ptr(value) }
+            .to_string_lossy()
+            .into_owned();
+        unsafe { pg_sys::pfree(value as _) };
+        if secret.is_empty() {
+            return Err(SecretError::NotFound(reference.to_owned()));
+        }
+        Ok(secret)
+    }
+}
+
+thread_local! {
+    static PROVIDERS: RefCell<HashMap<String, Rc<dyn SecretProvider>>> = RefCell::new(HashMap::new());
+}
+
+/// Register a secret provider
+///
+/// The provider can then be used in `*_secret` options as `<name>:<reference>`.
+/// Registration is per backend, so it should be done in `_PG_init()`. A built-in
+/// provider with the same name will be replaced.
+pub fn register_secret_provider<P: SecretProvider + 'static>(name: &str, provider: P) {
+    PROVIDERS.with(|providers| {
+        providers
+            .borrow_mut()
+            .insert(name.to_owned(), Rc::new(provider));
+    });
+}
+
+fn get_provider(name: &str) -> Result<Rc<dyn SecretProvider>, SecretError> {
+    if let Some(provider) = PROVIDERS.with(|providers| providers.borrow().get(name).cloned()) {
+        return Ok(provider);
+    }
+    let provider: Rc<dyn SecretProvider> = match name {
+        "vault" => Rc::new(VaultProvider),
+        "env" => Rc::new(EnvProvider),
+        "file" => Rc::new(FileProvider),
+        "guc" => Rc::new(GucProvider),
+        _ => return Err(SecretError::UnknownProvider(name.to_owned())),
+    };
+    Ok(provider)
+}
+
+fn parse_reference(secret_ref: &str) -> Result<(&str, &str), SecretError> {
+    secret_ref
+        .split_once(':')
+        .filter(|(name, reference)| !name.is_empty() && !reference.is_empty())
+        .ok_or_else(|| SecretError::InvalidReference(secret_ref.to_owned()))
+}
+
+/// Get a secret by reference in the form of `<provider>:<reference>`
+pub fn get_secret(secret_ref: &str) -> Result<String, SecretError> {
+    let (name, reference) = parse_reference(secret_ref)?;
+    get_provider(name)?.get_secret(reference)
+}
+
+thread_local! {
+    // roles who set the options being resolved, keyed by option name
+    static OPTION_OWNERS: RefCell<Option<HashMap<String, pg_sys::Oid>>> = RefCell::new(None);
+}
+
+// restore previous option owners when dropped
+struct OptionOwnersGuard(Option<HashMap<String, pg_sys::Oid>>);
+
+impl Drop for OptionOwnersGuard {
+    fn drop(&mut self) {
+        let prev = self.0.take();
+        let _ = OPTION_OWNERS.try_with(|owners| *owners.borrow_mut() = prev);
+    }
+}
+
+// run a function with the roles who set the options, which are used to check
+// the privilege of superuser-only providers when the secrets are resolved
+pub(crate) fn with_option_owners<R>(
+    owners: HashMap<String, pg_sys::Oid>,
+    f: impl FnOnce() -> R,
+) -> R {
+    let _guard = OptionOwnersGuard(OPTION_OWNERS.with(|o| o.replace(Some(owners))));
+    f()
+}
+
+// check if the role who set an option is superuser, it is current user if the
+// option owner is unknown
+pub(crate) fn option_owner_is_superuser(opt_name: &str) -> bool {
+    let owner = OPTION_OWNERS.with(|owners| {
+        owners
+            .borrow()
+            .as_ref()
+            .and_then(|owners| owners.get(opt_name).copied())
+    });
+    match owner {
+        Some(owner) => unsafe { pg_sys::superuser_arg(owner) },
+        None => unsafe { pg_sys::superuser() },
+    }
+}
+
+// check the role who set an option is allowed to use the provider in it
+fn check_option_owner(opt_name: &str, provider_name: &str) -> Result<(), SecretError> {
+    if !option_owner_is_superuser(opt_name) {
+        return Err(SecretError::PermissionDenied(provider_name.to_owned()));
+    }
+    Ok(())
+}
+
+/// Check a secret reference when it is specified in options
+///
+/// It is used in validator, the provider must exist and current user must be
+/// allowed to use it.
+pub fn validate_secret_ref(secret_ref: &str) -> Result<(), SecretError> {
+    let (name, _) = parse_reference(secret_ref)?;
+    let provider = get_provider(name)?;
+    if provider.superuser_only() && !unsafe { pg_sys::superuser() } {
+        return Err(SecretError::PermissionDenied(name.to_owned()));
+    }
+    Ok(())
+}
+
+// name of the option holding a Vault secret id for a secret option, options
+// already ending with `_id` have no such option, e.g. there is no
+// `aws_access_key_id_id`
+pub(crate) fn vault_id_option_name(opt_name: &str) -> Option<String> {
+    if opt_name.ends_with("_id") {
+        None
+    } else {
+        Some(format!("{}_id", opt_name))
+    }
+}
+
+/// Get a secret option value
+///
+/// A secret option `xxx` can be specified in one of below options, checked in order:
+///
+/// 1. `xxx` - the plain text value
+/// 2. `xxx_secret` - a secret reference like `env:MY_API_KEY`
+/// 3. `xxx_id` - a Vault secret id, not applicable if `xxx` ends with `_id`
+///
+/// Returns `None` if none of them is specified.
+pub fn get_secret_option(
+    opt_name: &str,
+    options: &HashMap<String, String>,
+) -> Result<Option<String>, SecretError> {
+    if let Some(value) = options.get(opt_name) {
+        return Ok(Some(value.to_owned()));
+    }
+    let secret_name = format!("{}_secret", opt_name);
+    if let Some(secret_ref) = options.get(&secret_name) {
+        // options can be altered after validation, e.g. by a role which is
+        // no longer superuser, so superuser-only providers are checked again
+        let (name, reference) = parse_reference(secret_ref)?;
+        let provider = get_provider(name)?;
+        if provider.superuser_only() {
+            check_option_owner(&secret_name, name)?;
+        }
+        return provider.get_secret(reference).map(Some);
+    }
+    if let Some(secret_id) = vault_id_option_name(opt_name).and_then(|name| options.get(&name)) {
+        return VaultProvider.get_secret(secret_id).map(Some);
+    }
+    Ok(None)
+}
+
+/// Get required secret option value
+///
+/// Same as [`get_secret_option`], but report an error if the option is not
+/// specified or the secret cannot be read.
+///
+/// For example,
+///
+/// ```rust,no_run
+/// # use supabase_wrappers::prelude::require_secret_option;
+/// # use std::collections::HashMap;
+/// # let options = &HashMap::new();
+/// let api_key = require_secret_option("api_key", options);
+/// ```
+pub fn require_secret_option(opt_name: &str, options: &HashMap<String, String>) -> Option<String> {
+    match get_secret_option(opt_name, options) {
+        Ok(Some(value)) => Some(value),
+        Ok(None) => {
+            let msg = match vault_id_option_name(opt_name) {
+                Some(id_name) => format!(
+                    "required option \"{0}\", \"{0}_secret\" or \"{1}\" is not specified",
+                    opt_name, id_name
+                ),
+                None => format!(
+                    "required option \"{0}\" or \"{0}_secret\" is not specified",
+                    opt_name
+                ),
+            };
+            report_error(PgSqlErrorCode::ERRCODE_FDW_OPTION_NAME_NOT_FOUND, &msg);
+            None
+        }
+        Err(err) => {
+            report_error(PgSqlErrorCode::ERRCODE_FDW_ERROR, &err.to_string());
+            None
+        }
+    }
+}
@@ -0,0 +1,288 @@
+//! Run remote queries on a foreign server without foreign tables
+//!
+//! Some FDWs can run ad-hoc queries on their remote source, see
+//! [`remote_query`](crate::interface::ForeignDataWrapper::remote_query) and
+//! [`remote_exec`](crate::interface::ForeignDataWrapper::remote_exec), or check
+//! the connection to it, see
+//! [`check_connection`](crate::interface::ForeignDataWrapper::check_connection).
+//! The functions here find the FDW of a foreign server by its name, and run the
+//! query on an instance of that server. The instance is checked out from the
+//! same instance cache used by foreign table scans, so the server's connection
+//! and credentials are reused. The instance is returned to the cache only if
+//! the call succeeds.
+//!
+//! The FDW type of a foreign server is only known by its handler function, so
+//! the handler is called to create the `FdwRoutine`, which registers the
+//! server functions of its FDW type in current backend.
+
+use pgrx::pg_sys::panic::{ErrorReport, ErrorReportable};
+use pgrx::prelude::*;
+use std::cell::Cell;
+use std::ffi::CString;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::error::ErrorInfo;
+use crate::instance;
+use crate::interface::{ForeignDataWrapper, Row, ServerInfo};
+use crate::utils::{self, report_error, ForeignTableName};
+
+// server functions of a FDW type
+#[derive(Clone, Copy)]
+struct ServerFns {
+    remote_query: unsafe fn(pg_sys::Oid, &str) -> Option<Vec<Row>>,
+    remote_exec: unsafe fn(pg_sys::Oid, &str) -> Option<()>,
+    check_connection: unsafe fn(pg_sys::Oid) -> (Option<ServerInfo>, Duration),
+}
+
+thread_local! {
+    // server functions of the FDW whose routine is created most recently
+    static REGISTERED_FNS: Cell<Option<ServerFns>> = Cell::new(None);
+}
+
+// register server functions of a FDW type, called when its routine is created
+pub(crate) fn register<E: Into<ErrorReport>, W: ForeignDataWrapper<E>>() {
+    REGISTERED_FNS.with(|fns| {
+        fns.set(Some(ServerFns {
+            remote_query: server_remote_query::<E, W>,
+            remote_exec: server_remote_exec::<E, W>,
+            check_connection: server_check_connection::<E, W>,
+        }))
+    });
+}
+
+unsafe fn server_remote_query<E: Into<ErrorReport>, W: ForeignDataWrapper<E>>(
+    server_id: pg_sys::Oid,
+    sql: &str,
+) -> Option<Vec<Row>> {
+    let mut instance = instance::create_fdw_instance::<E, W>(server_id);
+    let result = instance.remote_query(sql);
+    let result = utils::check_cancel(&mut *instance, result);
+    if result.is_ok() {
+        instance.release();
+    }
+    result.map_err(|e| e.into()).report()
+}
+
+unsafe fn server_remote_exec<E: Into<ErrorReport>, W: ForeignDataWrapper<E>>(
+    server_id: pg_sys::Oid,
+    sql: &str,
+) -> Option<()> {
+    let mut instance = instance::create_fdw_instance::<E, W>(server_id);
+    let result = instance.remote_exec(sql);
+    let result = utils::check_cancel(&mut *instance, result);
+    if result.is_ok() {
+        instance.release();
+    }
+    result.map_err(|e| e.into()).report()
+}
+
+// returns the server info and the time spent on the check, the time of
+// creating the instance is not included
+unsafe fn server_check_connection<E: Into<ErrorReport>, W: ForeignDataWrapper<E>>(
+    server_id: pg_sys::Oid,
+) -> (Option<ServerInfo>, Duration) {
+    let mut instance = instance::create_fdw_instance::<E, W>(server_id);
+    let started = Instant::now();
+    let result = instance.check_connection();
+    let result = utils::check_cancel(&mut *instance, result);
+    let latency = started.elapsed();
+    if result.is_ok() {
+        instance.release();
+    }
+    (result.map_err(|e| e.into()).report(), latency)
+}
+
+// find a foreign server by name and the server functions of its FDW, an error
+// is raised if the server doesn't exist, current user has no usage privilege
+// on it, or its FDW is not built by Wrappers
+unsafe fn find_server(server_name: &str) -> (pg_sys::Oid, ServerFns) {
+    let name = CString::new(server_name).unwrap_or_default();
+    let fserver = pg_sys::GetForeignServerByName(name.as_ptr(), false);
+    let server_id = (*fserver).serverid;
+
+    // same privilege is required as creating a foreign table on the server
+    let has_usage = Spi::get_one_with_args::<bool>(
+        "select has_server_privilege($1, 'USAGE')",
+        vec![(PgBuiltInOids::OIDOID.oid(), server_id.into_datum())],
+    )
+    .ok()
+    .flatten()
+    .unwrap_or(false);
+    if !has_usage {
+        report_error(
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            &format!("permission denied for foreign server {}", server_name),
+        );
+    }
+
+    REGISTERED_FNS.with(|fns| fns.set(None));
+    pg_sys::GetFdwRoutineByServerId(server_id);
+    let fns = REGISTERED_FNS.with(|fns| fns.take());
+
+    // statistics and errors are attributed to the server only
+    utils::set_current_foreign_table(&Rc::new(ForeignTableName {
+        server: server_name.to_owned(),
+        table: String::default(),
+    }));
+
+    match fns {
+        Some(fns) => (server_id, fns),
+        None => {
+            report_error(
+                PgSqlErrorCode::ERRCODE_FDW_ERROR,
+                &format!(
+                    "foreign server \"{}\" is not using a Wrappers foreign data wrapper",
+                    server_name
+                ),
+            );
+            unreachable!()
+        }
+    }
+}
+
+// a remote query or statement is sent to the remote source as is, and can read
+// or change anything the server's credentials allow, so it requires the current
+// user is the server owner, or has its own user mapping on the server, besides
+// the usage privilege
+unsafe fn check_remote_privilege(server_id: pg_sys::Oid, server_name: &str, feature: &str) {
+    let allowed = Spi::get_one_with_args::<bool>(
+        "select pg_has_role(s.srvowner, 'USAGE')
+                or exists (
+                  select 1 from pg_user_mappings m
+                  where m.srvid = s.oid and m.usename = current_user
+                )
+         from pg_foreign_server s
+         where s.oid = $1",
+        vec![(PgBuiltInOids::OIDOID.oid(), server_id.into_datum())],
+    )
+    .ok()
+    .flatten()
+    .unwrap_or(false);
+    if !allowed {
+        report_error(
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            &format!(
+                "must be owner of foreign server {} or have a user mapping on it to run {}",
+                server_name, feature
+            ),
+        );
+    }
+}
+
+fn report_not_supported(server_name: &str, feature: &str) {
+    report_error(
+        PgSqlErrorCode::ERRCODE_FEATURE_NOT_SUPPORTED,
+        &format!(
+            "{} is not supported by the foreign data wrapper of server \"{}\"",
+            feature, server_name
+        ),
+    );
+}
+
+/// Run a query on a foreign server and return its result rows
+///
+/// The query is run by [`remote_query`](crate::interface::ForeignDataWrapper::remote_query)
+/// of the server's FDW. An error is raised if the server doesn't exist, current
+/// user has no `USAGE` privilege on it, is neither its owner nor has a user
+/// mapping on it, or its FDW doesn't support remote query.
+pub fn remote_query(server_name: &str, sql: &str) -> Vec<Row> {
+    unsafe {
+        let (server_id, fns) = find_server(server_name);
+        check_remote_privilege(server_id, server_name, "remote query");
+        (fns.remote_query)(server_id, sql).unwrap_or_else(|| {
+            report_not_supported(server_name, "remote query");
+            Vec::new()
+        })
+    }
+}
+
+/// Run a statement on a foreign server without returning any rows
+///
+/// The statement is run by [`remote_exec`](crate::interface::ForeignDataWrapper::remote_exec)
+/// of the server's FDW. An error is raised if the server doesn't exist, current
+/// user has no `USAGE` privilege on it, is neither its owner nor has a user
+/// mapping on it, or its FDW doesn't support remote statement.
+pub fn remote_exec(server_name: &str, sql: &str) {
+    unsafe {
+        let (server_id, fns) = find_server(server_name);
+        check_remote_privilege(server_id, server_name, "remote statement");
+        if (fns.remote_exec)(server_id, sql).is_none() {
+            report_not_supported(server_name, "remote statement");
+        }
+    }
+}
+
+/// Status of a foreign server connection check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionStatus {
+    /// the remote server is reachable and the credentials are accepted
+    Ok,
+
+    /// creating the FDW instance or connecting to the remote server failed
+    Failed,
+
+    /// the FDW instance is created, but its FDW doesn't support connection check
+    Unchecked,
+}
+
+impl ConnectionStatus {
+    /// Status name, which is `ok`, `failed` or `unchecked`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Failed => "failed",
+            Self::Unchecked => "unchecked",
+        }
+    }
+}
+
+/// Result of a foreign server connection check, see [`check_server`]
+#[derive(Debug, Clone)]
+pub struct ServerCheck {
+    /// status of the check
+    pub status: ConnectionStatus,
+
+    /// time spent on the connection check, `None` if the check is not made
+    pub latency: Option<Duration>,
+
+    /// version of the remote server or API, if it is known
+    pub version: Option<String>,
+
+    /// the error if the check is failed
+    pub error: Option<ErrorInfo>,
+}
+
+/// Check the connection to a foreign server
+///
+/// An instance of the server is created and its
+/// [`check_connection`](crate::interface::ForeignDataWrapper::check_connection)
+/// is called. Errors of creating the instance, such as a missing secret, and
+/// errors of the check are returned in the result instead of being raised, so
+/// a broken server can be told from a healthy one. An error is still raised if
+/// the server doesn't exist, or current user has no `USAGE` privilege on it.
+pub fn check_server(server_name: &str) -> ServerCheck {
+    unsafe {
+        let (server_id, fns) = find_server(server_name);
+        match utils::run_in_subtransaction(|| (fns.check_connection)(server_id)) {
+            Ok((Some(info), latency)) => ServerCheck {
+                status: ConnectionStatus::Ok,
+                latency: Some(latency),
+                version: info.version,
+                error: None,
+            },
+            Ok((None, _)) => ServerCheck {
+                status: ConnectionStatus::Unchecked,
+                latency: None,
+                version: None,
+                error: None,
+            },
+            Err(error) => ServerCheck {
+                status: ConnectionStatus::Failed,
+                latency: None,
+                version: None,
+                error: Some(error),
+            },
+        }
+    }
+}
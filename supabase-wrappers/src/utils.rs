@@ -1,19 +1,23 @@
 //! Helper functions for working with Wrappers
 //!
 
-use crate::interface::{Cell, Column, Row};
-use pgrx::pg_sys::panic::ErrorReport;
-use pgrx::prelude::PgBuiltInOids;
-use pgrx::spi::Spi;
+use crate::error::{ErrorInfo, FdwError};
+use crate::interface::{Cell, Column, ForeignDataWrapper, Row};
+use crate::secret::{SecretError, SecretProvider, VaultProvider};
+use pgrx::pg_sys::panic::{CaughtError, ErrorReport};
 use pgrx::IntoDatum;
 use pgrx::*;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::ffi::CStr;
+use std::future::Future;
 use std::num::NonZeroUsize;
+use std::panic::AssertUnwindSafe;
 use std::ptr;
+use std::rc::Rc;
+use std::time::Duration;
 use thiserror::Error;
 use tokio::runtime::{Builder, Runtime};
-use uuid::Uuid;
 
 /// Log debug message to Postgres log.
 ///
@@ -155,6 +159,139 @@ pub fn create_async_runtime() -> Result<Runtime, CreateRuntimeError> {
     Ok(Builder::new_current_thread().enable_all().build()?)
 }
 
+thread_local! {
+    // shared async runtime for current backend, created when it is first used
+    static RUNTIME: RefCell<Option<Rc<Runtime>>> = RefCell::new(None);
+}
+
+// get the shared async runtime, create it if it is not created yet
+fn shared_runtime() -> Result<Rc<Runtime>, CreateRuntimeError> {
+    RUNTIME.with(|runtime| {
+        let mut runtime = runtime.borrow_mut();
+        if let Some(rt) = runtime.as_ref() {
+            return Ok(rt.clone());
+        }
+        let rt = Rc::new(create_async_runtime()?);
+        *runtime = Some(rt.clone());
+        Ok(rt)
+    })
+}
+
+/// Run a future to completion on the shared async runtime
+///
+/// Each Postgres backend has one shared current-thread runtime, which is used
+/// to drive [`AsyncForeignDataWrapper`](crate::interface::AsyncForeignDataWrapper)
+/// and can also be used by any FDW, so it doesn't need to create and keep its
+/// own runtime.
+///
+/// The runtime is created when it is first used, an error is returned if it
+/// cannot be created. Note this function cannot be nested, that is, it cannot
+/// be called inside a future which is running by this function.
+///
+/// For example,
+///
+/// ```rust,no_run
+/// # use supabase_wrappers::prelude::*;
+/// # fn main() -> Result<(), CreateRuntimeError> {
+/// # async fn fetch() -> Result<String, ()> { Ok(String::new()) }
+/// match block_on(fetch())? {
+///     Ok(body) => { }
+///     Err(err) => { }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn block_on<F: Future>(future: F) -> Result<F::Output, CreateRuntimeError> {
+    Ok(shared_runtime()?.block_on(future))
+}
+
+// how often to check for interrupts while waiting for a future
+const INTERRUPT_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Check if current query is being cancelled
+///
+/// Returns `true` if there is a pending query cancel request, which is sent by
+/// `pg_cancel_backend()`, Ctrl-C in psql or `statement_timeout`, or a pending
+/// backend termination request.
+pub fn interrupt_pending() -> bool {
+    unsafe {
+        ptr::read_volatile(ptr::addr_of!(pg_sys::QueryCancelPending)) as i32 != 0
+            || ptr::read_volatile(ptr::addr_of!(pg_sys::ProcDiePending)) as i32 != 0
+    }
+}
+
+/// Run a future to completion on a runtime, abort it if the query is cancelled
+///
+/// Postgres cannot process query cancel or `statement_timeout` while a
+/// backend is blocked in a remote call. This function checks for pending
+/// interrupts periodically while waiting, and drops the future and returns
+/// [`FdwError::Cancelled`] as soon as the query is cancelled.
+///
+/// The FDW should return the error from its callback function, the framework
+/// will then call [`ForeignDataWrapper::cancel`] to stop the remote work and
+/// let Postgres raise the cancel error.
+///
+/// For example,
+///
+/// ```rust,no_run
+/// # use supabase_wrappers::prelude::*;
+/// # fn main() -> Result<(), FdwError> {
+/// # async fn fetch() -> Result<String, ()> { Ok(String::new()) }
+/// let rt = create_async_runtime()?;
+/// match block_on_interruptible(&rt, fetch())? {
+///     Ok(body) => { }
+///     Err(err) => { }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn block_on_interruptible<F: Future>(rt: &Runtime, future: F) -> Result<F::Output, FdwError> {
+    rt.block_on(async {
+        tokio::pin!(future);
+        let mut interval = tokio::time::interval(INTERRUPT_CHECK_INTERVAL);
+        loop {
+            tokio::select! {
+                output = &mut future => return Ok(output),
+                _ = interval.tick() => {
+                    if interrupt_pending() {
+                        return Err(FdwError::Cancelled);
+                    }
+                }
+            }
+        }
+    })
+}
+
+// run a future on the shared runtime, abort it if the query is cancelled
+pub(crate) fn block_on_shared_interruptible<F: Future>(future: F) -> Result<F::Output, FdwError> {
+    block_on_interruptible(&shared_runtime()?, future)
+}
+
+// call the FDW cancel hook if a callback is failed because the query is being
+// cancelled, then let Postgres process the interrupt and raise the cancel error
+pub(crate) fn check_cancel<E: Into<ErrorReport>, W: ForeignDataWrapper<E>, T>(
+    fdw: &mut W,
+    result: Result<T, E>,
+) -> Result<T, E> {
+    if result.is_err() && interrupt_pending() {
+        cancel_and_check_interrupts(fdw.cancel());
+    }
+    result
+}
+
+// report the cancel hook failure as a warning, so it doesn't hide the cancel
+// error, then let Postgres process the pending interrupt
+pub(crate) fn cancel_and_check_interrupts<E: Into<ErrorReport>>(cancel_result: Result<(), E>) {
+    if let Err(err) = cancel_result {
+        let report: ErrorReport = err.into();
+        report_warning(&format!(
+            "cancel remote request failed: {}",
+            report.message()
+        ));
+    }
+    pg_sys::check_for_interrupts!();
+}
+
 /// Get required option value from the `options` map
 ///
 /// Get the required option's value from `options` map, return None and report
@@ -205,27 +342,12 @@ pub fn require_option_or(
 ///
 /// Get decrypted secret as string from Vault. Vault is an extension for storing
 /// encrypted secrets, [see more details](https://github.com/supabase/vault).
+///
+/// See [`secret`](crate::secret) module for other secret providers.
 pub fn get_vault_secret(secret_id: &str) -> Option<String> {
-    match Uuid::try_parse(secret_id) {
-        Ok(sid) => {
-            let sid = sid.into_bytes();
-            match Spi::get_one_with_args::<String>(
-                "select decrypted_secret from vault.decrypted_secrets where key_id = $1",
-                vec![(
-                    PgBuiltInOids::UUIDOID.oid(),
-                    pgrx::Uuid::from_bytes(sid).into_datum(),
-                )],
-            ) {
-                Ok(sid) => sid,
-                Err(err) => {
-                    report_error(
-                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                        &format!("invalid secret id \"{}\": {}", secret_id, err),
-                    );
-                    None
-                }
-            }
-        }
+    match VaultProvider.get_secret(secret_id) {
+        Ok(secret) => Some(secret),
+        Err(SecretError::NotFound(_)) => None,
         Err(err) => {
             report_error(
                 PgSqlErrorCode::ERRCODE_FDW_ERROR,
@@ -344,6 +466,102 @@ pub fn check_options_contain(opt_list: &[Option<String>], tgt: &str) {
     }
 }
 
+/// Check if a value of type `src_type` can be stored in a column of type `tgt_type`
+///
+/// Besides the same type, a text value can be stored in `varchar` and `bpchar`
+/// columns, a timestamp value, which is in UTC, can be stored in `timestamptz`
+/// column, and an integer or float value can be widened to a larger type of
+/// the same kind, for example, an `integer` value can be stored in `bigint`
+/// column.
+///
+/// For example,
+///
+/// ```rust,no_run
+/// # use supabase_wrappers::prelude::*;
+/// # use pgrx::pg_sys;
+/// let cell = Cell::String("foo".to_owned());
+/// assert!(is_type_compatible(cell.datum_type_oid(), pg_sys::VARCHAROID));
+/// ```
+pub fn is_type_compatible(src_type: pg_sys::Oid, tgt_type: pg_sys::Oid) -> bool {
+    src_type == tgt_type
+        || (src_type == pg_sys::TEXTOID
+            && (tgt_type == pg_sys::VARCHAROID || tgt_type == pg_sys::BPCHAROID))
+        || (src_type == pg_sys::TIMESTAMPOID && tgt_type == pg_sys::TIMESTAMPTZOID)
+        || (src_type == pg_sys::INT2OID
+            && (tgt_type == pg_sys::INT4OID || tgt_type == pg_sys::INT8OID))
+        || (src_type == pg_sys::INT4OID && tgt_type == pg_sys::INT8OID)
+        || (src_type == pg_sys::FLOAT4OID && tgt_type == pg_sys::FLOAT8OID)
+}
+
+// get type name for error messages
+pub(super) fn type_name(type_oid: pg_sys::Oid) -> String {
+    unsafe {
+        let name = pg_sys::format_type_be(type_oid);
+        let ret = CStr::from_ptr(name).to_string_lossy().into_owned();
+        pg_sys::pfree(name as _);
+        ret
+    }
+}
+
+/// Names of a foreign table and its foreign server
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ForeignTableName {
+    /// foreign server name
+    pub server: String,
+
+    /// schema qualified foreign table name
+    pub table: String,
+}
+
+impl ForeignTableName {
+    pub(super) unsafe fn from_oid(ftable_id: pg_sys::Oid) -> Self {
+        let ftable = pg_sys::GetForeignTable(ftable_id);
+        let fserver = pg_sys::GetForeignServer((*ftable).serverid);
+        let to_string = |s: *const std::os::raw::c_char| {
+            if s.is_null() {
+                String::default()
+            } else {
+                CStr::from_ptr(s).to_string_lossy().into_owned()
+            }
+        };
+        let nsp = to_string(pg_sys::get_namespace_name(pg_sys::get_rel_namespace(
+            ftable_id,
+        )));
+        let rel = to_string(pg_sys::get_rel_name(ftable_id));
+        Self {
+            server: to_string((*fserver).servername),
+            table: format!("{}.{}", nsp, rel),
+        }
+    }
+}
+
+thread_local! {
+    // the foreign table whose FDW callback is called most recently
+    static CURRENT_FOREIGN_TABLE: RefCell<Option<Rc<ForeignTableName>>> = RefCell::new(None);
+}
+
+/// Get the foreign table being scanned or modified
+///
+/// The framework sets it before calling each scan and modify callback of a
+/// FDW, including [`new`](crate::interface::ForeignDataWrapper::new), so it
+/// can be used to break down statistics by foreign table. It is kept after
+/// the callback returns, until another foreign table is accessed, so errors
+/// returned by the callback can be attributed to the same table.
+///
+/// Returns `None` if no foreign table is accessed yet in current backend.
+pub fn current_foreign_table() -> Option<Rc<ForeignTableName>> {
+    CURRENT_FOREIGN_TABLE.with(|t| t.borrow().clone())
+}
+
+pub(super) fn set_current_foreign_table(name: &Rc<ForeignTableName>) {
+    CURRENT_FOREIGN_TABLE.with(|t| {
+        let mut t = t.borrow_mut();
+        if !matches!(&*t, Some(current) if Rc::ptr_eq(current, name)) {
+            *t = Some(name.clone());
+        }
+    });
+}
+
 // trait for "serialize" and "deserialize" state from specified memory context,
 // so that it is safe to be carried between the planning and the execution
 pub(super) trait SerdeList {
@@ -386,3 +604,57 @@ pub(super) trait SerdeList {
         PgBox::<Self>::from_pg(ptr as _)
     }
 }
+
+/// Run a function in a subtransaction and return its error instead of aborting
+/// current transaction
+///
+/// The subtransaction is rolled back if the function raised an error, which is
+/// then returned as [`ErrorInfo`]. Query cancel and statement timeout errors
+/// are not caught but raised again, so the statement is still stopped.
+///
+/// For example,
+///
+/// ```rust,no_run
+/// # use supabase_wrappers::prelude::*;
+/// # use pgrx::prelude::*;
+/// let result = unsafe { run_in_subtransaction(|| Spi::run("select 1/0")) };
+/// assert!(result.is_err());
+/// ```
+pub unsafe fn run_in_subtransaction<T, F: FnOnce() -> T>(f: F) -> Result<T, ErrorInfo> {
+    let old_ctx = pg_sys::CurrentMemoryContext;
+    let old_owner = pg_sys::CurrentResourceOwner;
+    pg_sys::BeginInternalSubTransaction(std::ptr::null());
+    pg_sys::MemoryContextSwitchTo(old_ctx);
+
+    let f = AssertUnwindSafe(f);
+    PgTryBuilder::new(move || {
+        let result = f();
+        pg_sys::ReleaseCurrentSubTransaction();
+        pg_sys::MemoryContextSwitchTo(old_ctx);
+        pg_sys::CurrentResourceOwner = old_owner;
+        Ok(result)
+    })
+    .catch_others(|err| {
+        pg_sys::MemoryContextSwitchTo(old_ctx);
+        pg_sys::RollbackAndReleaseCurrentSubTransaction();
+        pg_sys::MemoryContextSwitchTo(old_ctx);
+        pg_sys::CurrentResourceOwner = old_owner;
+        match err {
+            CaughtError::PostgresError(ref report)
+                if report.sql_error_code() == PgSqlErrorCode::ERRCODE_QUERY_CANCELED =>
+            {
+                err.rethrow()
+            }
+            CaughtError::PostgresError(report)
+            | CaughtError::ErrorReport(report)
+            | CaughtError::RustPanic {
+                ereport: report, ..
+            } => Err(ErrorInfo {
+                message: report.message().to_owned(),
+                detail: report.detail().map(|s| s.to_owned()),
+                hint: report.hint().map(|s| s.to_owned()),
+            }),
+        }
+    })
+    .execute()
+}
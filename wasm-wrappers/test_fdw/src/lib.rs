@@ -0,0 +1,174 @@
+//! A Wasm wrapper used by the Wasm FDW tests, it goes through all the routines
+//! of the `wrappers` world.
+//!
+//! The wrapper returns `rows` rows set by the server option, the row `id` is
+//! from 1 and `name` is the `prefix` table option followed by the id. The
+//! `spin` table option makes the scan never end and `alloc_mb` allocates memory
+//! when the scan starts, they are used to test the host limits.
+
+wit_bindgen::generate!({
+    path: "../../wrappers/src/fdw/wasm_fdw/wit",
+    world: "wrappers",
+    exports: { "supabase:wrappers/routines": TestFdw },
+});
+
+use exports::supabase::wrappers::routines::Guest;
+use std::cell::RefCell;
+use supabase::wrappers::{types::*, utils};
+
+#[derive(Default)]
+struct State {
+    rows: i64,
+    prefix: String,
+    spin: bool,
+    columns: Vec<Column>,
+    start: i64,
+    end: i64,
+    next: i64,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::new(State::default());
+}
+
+fn get_option<'a>(options: &'a Options, name: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+// get the id of a row or a rowid cell, it must be an existing row for update
+// and delete
+fn row_id(cell: Option<&Cell>) -> Result<i64, FdwError> {
+    match cell {
+        Some(Cell::I64(id)) => Ok(*id),
+        _ => Err("row id must be a bigint".to_owned()),
+    }
+}
+
+fn check_exists(id: i64) -> Result<(), FdwError> {
+    STATE.with(|state| {
+        if (1..=state.borrow().rows).contains(&id) {
+            Ok(())
+        } else {
+            Err(format!("row {} is not found", id))
+        }
+    })
+}
+
+struct TestFdw;
+
+impl Guest for TestFdw {
+    fn init(options: Options) -> Result<(), FdwError> {
+        let rows = get_option(&options, "rows")
+            .ok_or("option 'rows' is required")?
+            .parse::<i64>()
+            .map_err(|_| "option 'rows' must be an integer")?;
+        STATE.with(|state| state.borrow_mut().rows = rows);
+        Ok(())
+    }
+
+    fn begin_scan(
+        _quals: Vec<Qual>,
+        columns: Vec<Column>,
+        _sorts: Vec<Sort>,
+        limit: Option<Limit>,
+        options: Options,
+    ) -> Result<(), FdwError> {
+        if let Some(size) = get_option(&options, "alloc_mb") {
+            let size = size.parse::<usize>().map_err(|_| "invalid alloc_mb")?;
+            let mut buf: Vec<u8> = Vec::new();
+            buf.try_reserve_exact(size << 20)
+                .map_err(|_| format!("cannot allocate {} MB", size))?;
+        }
+
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state.prefix = get_option(&options, "prefix")
+                .unwrap_or_default()
+                .to_owned();
+            state.spin = get_option(&options, "spin").is_some();
+            state.columns = columns;
+            state.start = limit.as_ref().map_or(0, |limit| limit.offset);
+            state.end = match limit {
+                Some(limit) => state.rows.min(limit.offset + limit.count),
+                None => state.rows,
+            };
+            state.next = state.start;
+        });
+        Ok(())
+    }
+
+    fn iter_scan() -> Result<Option<Row>, FdwError> {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            while state.spin {
+                std::hint::spin_loop();
+            }
+            if state.next >= state.end {
+                return Ok(None);
+            }
+            state.next += 1;
+            let id = state.next;
+
+            let mut row = Row {
+                cols: Vec::new(),
+                cells: Vec::new(),
+            };
+            for col in &state.columns {
+                let cell = match col.name.as_str() {
+                    "id" => Some(Cell::I64(id)),
+                    "name" => Some(Cell::String(format!("{}{}", state.prefix, id))),
+                    "attrs" => Some(Cell::Json(format!("{{\"id\": {}}}", id))),
+                    _ => None,
+                };
+                row.cols.push(col.name.clone());
+                row.cells.push(cell);
+            }
+            Ok(Some(row))
+        })
+    }
+
+    fn re_scan() -> Result<(), FdwError> {
+        STATE.with(|state| {
+            let mut state = state.borrow_mut();
+            state.next = state.start;
+        });
+        Ok(())
+    }
+
+    fn end_scan() -> Result<(), FdwError> {
+        STATE.with(|state| state.borrow_mut().columns.clear());
+        Ok(())
+    }
+
+    fn begin_modify(options: Options) -> Result<(), FdwError> {
+        if get_option(&options, "rowid_column") != Some("id") {
+            return Err("option 'rowid_column' must be 'id'".to_owned());
+        }
+        Ok(())
+    }
+
+    fn insert(row: Row) -> Result<(), FdwError> {
+        let pos = row.cols.iter().position(|col| col == "id");
+        let id = row_id(pos.and_then(|pos| row.cells[pos].as_ref()))?;
+        if check_exists(id).is_ok() {
+            return Err(format!("row {} already exists", id));
+        }
+        Ok(())
+    }
+
+    fn update(rowid: Cell, _new_row: Row) -> Result<(), FdwError> {
+        check_exists(row_id(Some(&rowid))?)
+    }
+
+    fn delete(rowid: Cell) -> Result<(), FdwError> {
+        check_exists(row_id(Some(&rowid))?)
+    }
+
+    fn end_modify() -> Result<(), FdwError> {
+        utils::report_info("modify ended");
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+from http.server import BaseHTTPRequestHandler, HTTPServer
+import json
+from graphql import build_schema, graphql_sync
+
+hostName = "0.0.0.0"
+serverPort = 8088
+
+schema = build_schema("""
+    type Address {
+      city: String!
+    }
+
+    type User {
+      id: Int!
+      name: String!
+      status: String!
+      createdAt: String!
+      address: Address!
+    }
+
+    type UserEdge {
+      cursor: String!
+      node: User!
+    }
+
+    type PageInfo {
+      hasNextPage: Boolean!
+      endCursor: String
+    }
+
+    type UserConnection {
+      nodes: [User!]!
+      edges: [UserEdge!]!
+      pageInfo: PageInfo!
+    }
+
+    type Query {
+      users(first: Int, after: String, status: [String!]): UserConnection!
+    }
+""")
+
+users = [
+    {'id': 1, 'name': 'Alice', 'status': 'active', 'createdAt': '2023-07-01T01:02:03Z', 'address': {'city': 'Auckland'}},
+    {'id': 2, 'name': 'Bob', 'status': 'inactive', 'createdAt': '2023-07-02T01:02:03Z', 'address': {'city': 'Berlin'}},
+    {'id': 3, 'name': 'Carol', 'status': 'active', 'createdAt': '2023-07-03T01:02:03Z', 'address': {'city': 'Chicago'}},
+    {'id': 4, 'name': 'Dave', 'status': 'active', 'createdAt': '2023-07-04T01:02:03Z', 'address': {'city': 'Dublin'}},
+    {'id': 5, 'name': 'Eve', 'status': 'inactive', 'createdAt': '2023-07-05T01:02:03Z', 'address': {'city': 'Edinburgh'}},
+]
+
+
+# Relay-style connection of users, the cursor is user id
+def resolve_users(info, first=None, after=None, status=None):
+    matched = [u for u in users if status is None or u['status'] in status]
+    if after is not None:
+        matched = [u for u in matched if u['id'] > int(after)]
+    page = matched if first is None else matched[:first]
+    return {
+        'nodes': page,
+        'edges': [{'cursor': str(u['id']), 'node': u} for u in page],
+        'pageInfo': {
+            'hasNextPage': len(page) < len(matched),
+            'endCursor': str(page[-1]['id']) if page else None,
+        },
+    }
+
+
+class GraphQLMockServer(BaseHTTPRequestHandler):
+    def do_POST(self):
+        if self.path != '/graphql':
+            self.send_response(404)
+            self.end_headers()
+            return
+
+        if self.headers.get('Authorization') != 'Bearer test_token':
+            self.send_response(401)
+            self.end_headers()
+            return
+
+        length = int(self.headers.get('Content-Length', 0))
+        req = json.loads(self.rfile.read(length))
+        result = graphql_sync(
+            schema,
+            req['query'],
+            root_value={'users': resolve_users},
+            variable_values=req.get('variables'),
+        )
+        resp = {'data': result.data}
+        if result.errors:
+            resp['errors'] = [{'message': e.message} for e in result.errors]
+
+        self.send_response(200)
+        self.send_header("Content-type", "application/json")
+        self.end_headers()
+        self.wfile.write(bytes(json.dumps(resp), "utf-8"))
+
+
+if __name__ == "__main__":
+    webServer = HTTPServer((hostName, serverPort), GraphQLMockServer)
+    print("GraphQL Mock Server started at http://%s:%s" % (hostName, serverPort))
+
+    try:
+        webServer.serve_forever()
+    except KeyboardInterrupt:
+        pass
+
+    webServer.server_close()
+    print("Server stopped.")
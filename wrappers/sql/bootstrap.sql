@@ -3,22 +3,73 @@
 DROP TABLE IF EXISTS wrappers_fdw_stats;
 
 CREATE TABLE wrappers_fdw_stats (
-  fdw_name          text NOT NULL PRIMARY KEY,
+  fdw_name          text NOT NULL,
+  server_name       text NOT NULL DEFAULT '',
+  table_name        text NOT NULL DEFAULT '',
   create_times      bigint NULL,
   rows_in           bigint NULL,
   rows_out          bigint NULL,
   bytes_in          bigint NULL,
   bytes_out         bigint NULL,
+  requests          bigint NULL,
+  errors            jsonb NULL,
+  latency_sum_ms    double precision NULL,
+  latency_buckets   bigint[] NULL,
   metadata          jsonb NULL,
   created_at        timestamptz NOT NULL DEFAULT timezone('utc'::text, now()),
-  updated_at        timestamptz NOT NULL DEFAULT timezone('utc'::text, now())
+  updated_at        timestamptz NOT NULL DEFAULT timezone('utc'::text, now()),
+  PRIMARY KEY (fdw_name, server_name, table_name)
 );
 
 COMMENT ON TABLE wrappers_fdw_stats IS 'Wrappers Foreign Data Wrapper statistics';
+COMMENT ON COLUMN wrappers_fdw_stats.server_name IS 'Foreign server name, empty if not collected for a foreign table';
+COMMENT ON COLUMN wrappers_fdw_stats.table_name IS 'Schema qualified foreign table name, empty if not collected for a foreign table';
 COMMENT ON COLUMN wrappers_fdw_stats.create_times IS 'Total number of times the FDW instacne has been created';
 COMMENT ON COLUMN wrappers_fdw_stats.rows_in IS 'Total rows input from origin';
 COMMENT ON COLUMN wrappers_fdw_stats.rows_out IS 'Total rows output to Postgres';
 COMMENT ON COLUMN wrappers_fdw_stats.bytes_in IS 'Total bytes input from origin';
 COMMENT ON COLUMN wrappers_fdw_stats.bytes_out IS 'Total bytes output to Postgres';
+COMMENT ON COLUMN wrappers_fdw_stats.requests IS 'Total requests made to origin';
+COMMENT ON COLUMN wrappers_fdw_stats.errors IS 'Total errors by error kind';
+COMMENT ON COLUMN wrappers_fdw_stats.latency_sum_ms IS 'Total latency of requests made to origin, in milliseconds';
+COMMENT ON COLUMN wrappers_fdw_stats.latency_buckets IS 'Request latency histogram, bounded by 5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000 and 10000 milliseconds';
 COMMENT ON COLUMN wrappers_fdw_stats.metadata IS 'Metadata specific for the FDW';
 
+DROP TABLE IF EXISTS wrappers_sync_state;
+
+CREATE TABLE wrappers_sync_state (
+  foreign_table     text NOT NULL,
+  target_table      text NOT NULL,
+  cursor_column     text NOT NULL,
+  last_value        text NULL,
+  last_synced_at    timestamptz NULL,
+  created_at        timestamptz NOT NULL DEFAULT timezone('utc'::text, now()),
+  PRIMARY KEY (foreign_table, target_table)
+);
+
+COMMENT ON TABLE wrappers_sync_state IS 'Wrappers foreign table sync high-water marks';
+COMMENT ON COLUMN wrappers_sync_state.foreign_table IS 'Schema qualified source foreign table name';
+COMMENT ON COLUMN wrappers_sync_state.target_table IS 'Schema qualified target local table name';
+COMMENT ON COLUMN wrappers_sync_state.cursor_column IS 'Column used to find new rows since last sync';
+COMMENT ON COLUMN wrappers_sync_state.last_value IS 'Max cursor column value synced, in text';
+COMMENT ON COLUMN wrappers_sync_state.last_synced_at IS 'Time of last successful sync';
+
+DROP TABLE IF EXISTS wrappers_sync_history;
+
+CREATE TABLE wrappers_sync_history (
+  id                bigserial PRIMARY KEY,
+  foreign_table     text NOT NULL,
+  target_table      text NOT NULL,
+  from_value        text NULL,
+  to_value          text NULL,
+  rows_synced       bigint NOT NULL DEFAULT 0,
+  error             text NULL,
+  started_at        timestamptz NOT NULL,
+  finished_at       timestamptz NOT NULL DEFAULT timezone('utc'::text, now())
+);
+
+COMMENT ON TABLE wrappers_sync_history IS 'Wrappers foreign table sync history';
+COMMENT ON COLUMN wrappers_sync_history.from_value IS 'Cursor column value the sync started after';
+COMMENT ON COLUMN wrappers_sync_history.to_value IS 'Max cursor column value synced';
+COMMENT ON COLUMN wrappers_sync_history.rows_synced IS 'Number of rows inserted or updated in target table';
+COMMENT ON COLUMN wrappers_sync_history.error IS 'Error message if the sync failed';
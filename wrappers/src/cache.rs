@@ -0,0 +1,50 @@
+use pgrx::prelude::*;
+use supabase_wrappers::prelude::{invalidate_cache, report_error};
+
+#[pg_extern]
+fn wrappers_cache_invalidate(table_name: default!(Option<&str>, "NULL")) {
+    // invalidating all relations affects every backend, so only superuser
+    // can do it
+    if table_name.is_none() && !unsafe { pg_sys::superuser() } {
+        report_error(
+            PgSqlErrorCode::ERRCODE_INSUFFICIENT_PRIVILEGE,
+            "only superuser can invalidate result cache of all foreign tables",
+        );
+        return;
+    }
+
+    let ftable_id = table_name.map(|table_name| {
+        Spi::get_one_with_args::<pg_sys::Oid>(
+            "select $1::regclass::oid",
+            vec![(PgBuiltInOids::TEXTOID.oid(), table_name.into_datum())],
+        )
+        .unwrap()
+        .unwrap()
+    });
+    invalidate_cache(ftable_id);
+}
+
+#[pg_extern]
+fn wrappers_cache_invalidate_server(server_name: &str) -> i64 {
+    let ftable_ids = Spi::connect(|client| {
+        client
+            .select(
+                "select ft.ftrelid
+                 from pg_foreign_table ft
+                 join pg_foreign_server s on s.oid = ft.ftserver
+                 where s.srvname = $1",
+                None,
+                Some(vec![(
+                    PgBuiltInOids::TEXTOID.oid(),
+                    server_name.into_datum(),
+                )]),
+            )
+            .unwrap()
+            .filter_map(|row| row.get_by_name::<pg_sys::Oid, _>("ftrelid").unwrap())
+            .collect::<Vec<_>>()
+    });
+    for ftable_id in &ftable_ids {
+        invalidate_cache(Some(*ftable_id));
+    }
+    ftable_ids.len() as i64
+}
@@ -1,11 +1,12 @@
+use crate::http::{self, HttpOptions};
 use crate::stats;
-use pgrx::pg_sys;
+use futures::future::LocalBoxFuture;
+use futures::stream::{self, StreamExt, TryStreamExt};
 use pgrx::pg_sys::panic::ErrorReport;
-use pgrx::prelude::PgSqlErrorCode;
 use reqwest::{self, header};
-use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use reqwest_middleware::ClientWithMiddleware;
 use std::collections::HashMap;
+use std::time::Instant;
 use url::Url;
 
 use supabase_wrappers::prelude::*;
@@ -13,33 +14,34 @@ use thiserror::Error;
 
 use super::result::AirtableResponse;
 
-fn create_client(api_key: &str) -> ClientWithMiddleware {
+fn create_client(api_key: &str, opts: &HttpOptions) -> Result<ClientWithMiddleware, FdwError> {
     let mut headers = header::HeaderMap::new();
     let value = format!("Bearer {}", api_key);
-    let mut auth_value = header::HeaderValue::from_str(&value).unwrap();
+    let mut auth_value =
+        header::HeaderValue::from_str(&value).map_err(|_| OptionsError::InvalidValue {
+            name: "api_key",
+            value: "(not shown)".to_owned(),
+            ty: "header value",
+        })?;
     auth_value.set_sensitive(true);
     headers.insert(header::AUTHORIZATION, auth_value);
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        .build()
-        .unwrap();
-    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
-    ClientBuilder::new(client)
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build()
+    http::create_client(headers, opts)
 }
 
 #[wrappers_fdw(
-    version = "0.1.2",
+    version = "0.1.3",
     author = "Ankur Goyal",
     website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/airtable_fdw",
-    error_type = "AirtableFdwError"
+    error_type = "AirtableFdwError",
+    options(
+        server(api_url(default = "https://api.airtable.com/v0"), api_key(secret)),
+        table(base_id(required), table_id(required), view_id),
+        include(crate::http::HTTP_OPTION_SPECS)
+    )
 )]
 pub(crate) struct AirtableFdw {
-    rt: Runtime,
     client: Option<ClientWithMiddleware>,
     base_url: String,
-    scan_result: Option<Vec<Row>>,
 }
 
 impl AirtableFdw {
@@ -58,7 +60,6 @@ impl AirtableFdw {
 
     #[inline]
     fn set_limit_offset(
-        &self,
         url: &str,
         page_size: Option<usize>,
         offset: Option<&str>,
@@ -75,162 +76,162 @@ impl AirtableFdw {
     }
 
     // convert response body text to rows
-    fn parse_resp(&self, resp_body: &str, columns: &[Column]) -> (Vec<Row>, Option<String>) {
-        let response: AirtableResponse = serde_json::from_str(resp_body).unwrap();
+    fn parse_resp(
+        resp_body: &str,
+        columns: &[Column],
+    ) -> Result<(Vec<Row>, Option<String>), AirtableFdwError> {
+        let response: AirtableResponse = serde_json::from_str(resp_body)?;
         let mut result = Vec::new();
 
         for record in response.records.iter() {
-            result.push(record.to_row(columns));
+            result.push(record.to_row(columns)?);
         }
 
-        (result, response.offset)
+        Ok((result, response.offset))
     }
-}
 
-macro_rules! report_fetch_error {
-    ($url:ident, $err:ident) => {
-        report_error(
-            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-            &format!("fetch {} failed: {}", $url, $err),
-        )
-    };
+    // fetch one page of records, returns the rows and the offset of next page
+    async fn fetch_page(
+        client: &ClientWithMiddleware,
+        url: &str,
+        offset: Option<&str>,
+        columns: &[Column],
+    ) -> Result<Page, AirtableFdwError> {
+        let url = Self::set_limit_offset(url, None, offset)?;
+        let started = Instant::now();
+        let resp = client.get(&url).send().await?.error_for_status()?;
+        stats::record_request(Self::FDW_NAME, started);
+        stats::inc_stats(
+            Self::FDW_NAME,
+            stats::Metric::BytesIn,
+            resp.content_length().unwrap_or(0) as i64,
+        );
+        let body = resp.text().await?;
+        let (rows, offset) = Self::parse_resp(&body, columns)?;
+
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsIn, rows.len() as i64);
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsOut, rows.len() as i64);
+
+        // the offset token is returned until the last page
+        Ok(Page::new(rows, offset.map(NextPage::Token)))
+    }
 }
 
 #[derive(Error, Debug)]
 enum AirtableFdwError {
+    #[error("parse url failed: {0}")]
+    UrlParseError(#[from] url::ParseError),
+
+    #[error("request failed: {0}")]
+    RequestError(#[from] reqwest_middleware::Error),
+
+    #[error("request failed: {0}")]
+    ReqwestError(#[from] reqwest::Error),
+
+    #[error("parse response failed: {0}")]
+    SerdeJsonError(#[from] serde_json::Error),
+
+    #[error("{0}")]
+    OptionsError(#[from] OptionsError),
+
     #[error("{0}")]
-    CreateRuntimeError(#[from] CreateRuntimeError),
+    FdwError(#[from] FdwError),
 }
 
 impl From<AirtableFdwError> for ErrorReport {
     fn from(value: AirtableFdwError) -> Self {
-        match value {
-            AirtableFdwError::CreateRuntimeError(e) => e.into(),
-        }
+        let msg = format!("{value}");
+        let err = match value {
+            AirtableFdwError::OptionsError(e) => FdwError::from(e),
+            AirtableFdwError::FdwError(e) => e,
+            AirtableFdwError::RequestError(reqwest_middleware::Error::Reqwest(e))
+            | AirtableFdwError::ReqwestError(e) => match e.status() {
+                Some(status) => FdwError::from_http_status(status.as_u16(), msg),
+                None => FdwError::connection_failed(msg),
+            },
+            AirtableFdwError::SerdeJsonError(_) => FdwError::type_conversion(msg),
+            _ => FdwError::remote_query(msg),
+        };
+        stats::record_error(AirtableFdw::FDW_NAME, err.kind());
+        err.into()
     }
 }
 
 // TODO Add support for INSERT, UPDATE, DELETE
-impl ForeignDataWrapper<AirtableFdwError> for AirtableFdw {
+impl AsyncForeignDataWrapper<AirtableFdwError> for AirtableFdw {
     fn new(options: &HashMap<String, String>) -> Result<Self, AirtableFdwError> {
-        let base_url = options
-            .get("api_url")
-            .map(|t| t.to_owned())
-            .unwrap_or_else(|| "https://api.airtable.com/v0".to_string());
-
-        let client = match options.get("api_key") {
-            Some(api_key) => Some(create_client(api_key)),
-            None => require_option("api_key_id", options)
-                .and_then(|key_id| get_vault_secret(&key_id))
-                .map(|api_key| create_client(&api_key)),
-        };
+        let opts = AirtableFdwServerOptions::from_options(options)?;
+        let base_url = opts.api_url;
+        let api_key = opts.api_key.ok_or(OptionsError::MissingOption("api_key"))?;
+        let mut http_opts = HttpOptions::from_options(options)?;
+
+        // Airtable API is limited to 5 requests per second per base
+        // ref: https://airtable.com/developers/web/api/rate-limits
+        http_opts.requests_per_second.get_or_insert(5.0);
+        let client = Some(create_client(&api_key, &http_opts)?);
 
         stats::inc_stats(Self::FDW_NAME, stats::Metric::CreateTimes, 1);
 
-        Ok(Self {
-            rt: create_async_runtime()?,
-            client,
-            base_url,
-            scan_result: None,
-        })
+        Ok(Self { client, base_url })
     }
 
     fn begin_scan(
         &mut self,
         _quals: &[Qual], // TODO: Propagate filters
         columns: &[Column],
-        _sorts: &[Sort],        // TODO: Propagate sort
-        _limit: &Option<Limit>, // TODO: maxRecords
+        _sorts: &[Sort], // TODO: Propagate sort
+        limit: &Option<Limit>,
         options: &HashMap<String, String>,
-    ) -> Result<(), AirtableFdwError> {
-        let url = if let Some(url) = require_option("base_id", options).and_then(|base_id| {
-            require_option("table_id", options)
-                .map(|table_id| self.build_url(&base_id, &table_id, options.get("view_id")))
-        }) {
-            url
-        } else {
-            return Ok(());
+    ) -> Result<RowStream<AirtableFdwError>, AirtableFdwError> {
+        let opts = AirtableFdwTableOptions::from_options(options)?;
+        let url = self.build_url(&opts.base_id, &opts.table_id, opts.view_id.as_ref());
+        let client = match &self.client {
+            Some(client) => client.clone(),
+            None => return Ok(stream::empty().boxed_local()),
         };
-
-        let mut rows = Vec::new();
-        if let Some(client) = &self.client {
-            let mut offset: Option<String> = None;
-
-            loop {
-                // Fetch all of the rows upfront. Arguably, this could be done in batches (and invoked each
-                // time iter_scan() runs out of rows) to pipeline the I/O, but we'd have to manage more
-                // state so starting with the simpler solution.
-                let url = match self.set_limit_offset(&url, None, offset.as_deref()) {
-                    Ok(url) => url,
-                    Err(err) => {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("internal error: {}", err),
-                        );
-                        return Ok(());
-                    }
+        let columns = columns.to_vec();
+
+        // pages are fetched lazily, the next page is only requested when all
+        // rows of current page have been consumed, the state is `None` after
+        // the last page
+        let pages = stream::try_unfold(Some(None::<NextPage>), move |next| {
+            let client = client.clone();
+            let url = url.clone();
+            let columns = columns.clone();
+            async move {
+                let next = match next {
+                    Some(next) => next,
+                    None => return Ok(None),
                 };
-
-                match self.rt.block_on(client.get(&url).send()) {
-                    Ok(resp) => match resp.error_for_status() {
-                        Ok(resp) => {
-                            stats::inc_stats(
-                                Self::FDW_NAME,
-                                stats::Metric::BytesIn,
-                                resp.content_length().unwrap_or(0) as i64,
-                            );
-                            let body = self.rt.block_on(resp.text()).unwrap();
-                            let (new_rows, new_offset) = self.parse_resp(&body, columns);
-                            rows.extend(new_rows.into_iter());
-
-                            if let Some(new_offset) = new_offset {
-                                offset = Some(new_offset);
-                            } else {
-                                break;
-                            }
-                        }
-                        Err(err) => report_fetch_error!(url, err),
-                    },
-                    Err(err) => report_fetch_error!(url, err),
-                }
-            }
-        }
-
-        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsIn, rows.len() as i64);
-        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsOut, rows.len() as i64);
-
-        self.scan_result = Some(rows);
-        Ok(())
-    }
-
-    fn iter_scan(&mut self, row: &mut Row) -> Result<Option<()>, AirtableFdwError> {
-        if let Some(ref mut result) = self.scan_result {
-            if !result.is_empty() {
-                return Ok(result
-                    .drain(0..1)
-                    .last()
-                    .map(|src_row| row.replace_with(src_row)));
+                let offset = match &next {
+                    Some(NextPage::Token(offset)) => Some(offset.as_str()),
+                    _ => None,
+                };
+                let page = Self::fetch_page(&client, &url, offset, &columns).await?;
+                Ok(Some((page.rows, page.next.map(Some))))
             }
-        }
-        Ok(None)
-    }
-
-    fn end_scan(&mut self) -> Result<(), AirtableFdwError> {
-        self.scan_result.take();
-        Ok(())
+        });
+        let rows = pages
+            .map_ok(|rows| stream::iter(rows.into_iter().map(Ok)))
+            .try_flatten();
+
+        // no more pages are fetched once the limit is reached
+        Ok(match limit {
+            Some(limit) => rows
+                .take((limit.offset + limit.count) as usize)
+                .boxed_local(),
+            None => rows.boxed_local(),
+        })
     }
 
-    fn validator(
-        options: Vec<Option<String>>,
-        catalog: Option<pg_sys::Oid>,
-    ) -> Result<(), AirtableFdwError> {
-        if let Some(oid) = catalog {
-            if oid == FOREIGN_TABLE_RELATION_ID {
-                check_options_contain(&options, "base_id");
-                check_options_contain(&options, "table_id");
+    fn check_connection(&self) -> LocalBoxFuture<'_, Result<Option<ServerInfo>, AirtableFdwError>> {
+        Box::pin(async move {
+            if let Some(ref client) = self.client {
+                // the user info endpoint is cheap and needs a valid api key
+                let url = format!("{}/meta/whoami", &self.base_url);
+                client.get(&url).send().await?.error_for_status()?;
             }
-        }
-
-        Ok(())
+            Ok(Some(ServerInfo::default()))
+        })
     }
 }
@@ -1,12 +1,9 @@
-use pgrx::pg_sys;
-use pgrx::prelude::PgSqlErrorCode;
 use serde::de::{MapAccess, Visitor};
 use serde::{Deserialize, Deserializer};
-use serde_json::{value::Number, Value};
+use serde_json::Value;
 use std::collections::HashMap;
 use std::fmt;
 use std::marker::PhantomData;
-use std::str::FromStr;
 use supabase_wrappers::prelude::*;
 
 #[derive(Deserialize, Debug)]
@@ -84,74 +81,22 @@ impl<'de> Deserialize<'de> for AirtableFields {
 }
 
 impl AirtableRecord {
-    pub fn to_row(&self, columns: &[Column]) -> Row {
+    pub fn to_row(&self, columns: &[Column]) -> Result<Row, FdwError> {
         let mut row = Row::new();
 
-        macro_rules! col_to_cell {
-            ($col:ident, $src_type:ident, $conv:expr) => {{
-                self.fields.0.get(&$col.name).and_then(|val| {
-                    if let Value::$src_type(v) = val {
-                        $conv(v)
-                    } else {
-                        panic!("column '{}' data type not match", $col.name)
-                    }
-                })
-            }};
-        }
-
         for col in columns.iter() {
             if col.name == "id" {
                 row.push("id", Some(Cell::String(self.id.clone())));
                 continue;
             }
 
-            let cell = match col.type_oid {
-                pg_sys::BOOLOID => col_to_cell!(col, Bool, |v: &bool| Some(Cell::Bool(*v))),
-                pg_sys::CHAROID => col_to_cell!(col, Number, |v: &Number| {
-                    v.as_i64().map(|n| Cell::I8(n as i8))
-                }),
-                pg_sys::INT2OID => col_to_cell!(col, Number, |v: &Number| {
-                    v.as_i64().map(|n| Cell::I16(n as i16))
-                }),
-                pg_sys::FLOAT4OID => col_to_cell!(col, Number, |v: &Number| {
-                    v.as_f64().map(|n| Cell::F32(n as f32))
-                }),
-                pg_sys::INT4OID => col_to_cell!(col, Number, |v: &Number| {
-                    v.as_i64().map(|n| Cell::I32(n as i32))
-                }),
-                pg_sys::FLOAT8OID => {
-                    col_to_cell!(col, Number, |v: &Number| { v.as_f64().map(Cell::F64) })
-                }
-                pg_sys::INT8OID => {
-                    col_to_cell!(col, Number, |v: &Number| { v.as_i64().map(Cell::I64) })
-                }
-                pg_sys::NUMERICOID => col_to_cell!(col, Number, |v: &Number| {
-                    v.as_f64()
-                        .map(|n| Cell::Numeric(pgrx::AnyNumeric::try_from(n).unwrap()))
-                }),
-                pg_sys::TEXTOID => {
-                    col_to_cell!(col, String, |v: &String| { Some(Cell::String(v.clone())) })
-                }
-                pg_sys::DATEOID => col_to_cell!(col, String, |v: &String| {
-                    pgrx::Date::from_str(v.as_str()).ok().map(Cell::Date)
-                }),
-                pg_sys::TIMESTAMPOID => col_to_cell!(col, String, |v: &String| {
-                    pgrx::Timestamp::from_str(v.as_str())
-                        .ok()
-                        .map(Cell::Timestamp)
-                }),
-                _ => {
-                    report_error(
-                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                        &format!("column '{}' data type not supported", col.name),
-                    );
-                    None
-                }
+            let cell = match self.fields.0.get(&col.name) {
+                Some(v) => Cell::from_json(v, col)?,
+                None => None,
             };
-
             row.push(&col.name, cell);
         }
 
-        row
+        Ok(row)
     }
 }
@@ -79,6 +79,19 @@ mod tests {
                 .filter_map(|r| r.get_by_name::<&str, _>("field2").unwrap())
                 .collect::<Vec<_>>();
             assert_eq!(results, vec!["three"]);
+
+            // a re-scan starts a new record stream from the first page
+            let results = c
+                .select(
+                    "SELECT v.n, (SELECT count(*) FROM airtable_view WHERE v.n > 0)
+                     FROM (VALUES (1), (2)) v(n) ORDER BY v.n",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get::<i64>(2).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![1, 1]);
         });
     }
 }
@@ -2,6 +2,7 @@ use crate::stats;
 use futures::executor;
 use gcp_bigquery_client::{
     client_builder::ClientBuilder,
+    error::BQError,
     model::{
         field_type::FieldType, get_query_results_parameters::GetQueryResultsParameters,
         query_request::QueryRequest, query_response::QueryResponse, query_response::ResultSet,
@@ -10,85 +11,159 @@ use gcp_bigquery_client::{
     },
     Client,
 };
-use pgrx::pg_sys::panic::ErrorReport;
-use pgrx::prelude::PgSqlErrorCode;
+use pgrx::pg_sys::{self, panic::ErrorReport};
 use pgrx::prelude::{AnyNumeric, Date, Timestamp};
 use serde_json::json;
 use std::collections::HashMap;
 use std::str::FromStr;
+use std::time::{Duration, Instant};
 
 use supabase_wrappers::prelude::*;
 use thiserror::Error;
 
 macro_rules! field_type_error {
     ($field:ident, $err:ident) => {{
-        report_error(
-            PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
-            &format!("get field {} failed: {}", &$field.name, $err),
-        );
-        None
+        FdwError::type_conversion(format!("get field {} failed: {}", &$field.name, $err))
     }};
 }
 
 // convert BigQuery field to Cell
-fn field_to_cell(rs: &ResultSet, field: &TableFieldSchema) -> Option<Cell> {
-    match field.r#type {
+fn field_to_cell(rs: &ResultSet, field: &TableFieldSchema) -> Result<Option<Cell>, FdwError> {
+    let cell = match field.r#type {
         FieldType::Boolean => rs
             .get_bool_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(Cell::Bool),
         FieldType::Int64 | FieldType::Integer => rs
             .get_i64_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(Cell::I64),
         FieldType::Float64 | FieldType::Float => rs
             .get_f64_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(Cell::F64),
         FieldType::Numeric => rs
             .get_f64_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
-            .map(|v| Cell::Numeric(AnyNumeric::try_from(v).unwrap())),
+            .map_err(|err| field_type_error!(field, err))?
+            .map(|v| {
+                AnyNumeric::try_from(v)
+                    .map(Cell::Numeric)
+                    .map_err(|err| field_type_error!(field, err))
+            })
+            .transpose()?,
         FieldType::String => rs
             .get_string_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(Cell::String),
         FieldType::Date => rs
             .get_string_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(|v| {
-                let dt = Date::from_str(&v).unwrap();
-                Cell::Date(dt)
-            }),
+                Date::from_str(&v)
+                    .map(Cell::Date)
+                    .map_err(|err| field_type_error!(field, err))
+            })
+            .transpose()?,
         FieldType::Datetime => rs
             .get_string_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(|v| {
-                let ts = Timestamp::from_str(&v).unwrap();
-                Cell::Timestamp(ts)
-            }),
+                Timestamp::from_str(&v)
+                    .map(Cell::Timestamp)
+                    .map_err(|err| field_type_error!(field, err))
+            })
+            .transpose()?,
         FieldType::Timestamp => rs
             .get_f64_by_name(&field.name)
-            .unwrap_or_else(|err| field_type_error!(field, err))
+            .map_err(|err| field_type_error!(field, err))?
             .map(|v| {
                 let ts = pgrx::to_timestamp(v);
                 Cell::Timestamp(ts.to_utc())
             }),
         _ => {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                &format!("field type {:?} not supported", field.r#type),
-            );
-            None
+            return Err(FdwError::type_conversion(format!(
+                "field type {:?} not supported",
+                field.r#type
+            )));
+        }
+    };
+    Ok(cell)
+}
+
+// convert BigQuery client error to FdwError
+fn bq_error(err: BQError, context: &str) -> FdwError {
+    let msg = format!("{}: {}", context, err);
+    match err {
+        BQError::ResponseError { error } => {
+            FdwError::from_http_status(error.error.code as u16, msg)
         }
+        BQError::AuthError(_) | BQError::YupAuthError(_) | BQError::NoToken => {
+            FdwError::auth_failed(msg)
+        }
+        BQError::RequestError(_) => FdwError::connection_failed(msg),
+        _ => FdwError::remote_query(msg),
+    }
+}
+
+// request to cancel a running query job, it doesn't wait for the job to stop
+fn cancel_job(
+    rt: &Runtime,
+    client: &Client,
+    project_id: &str,
+    job_id: &str,
+    location: &str,
+) -> Result<(), FdwError> {
+    rt.block_on(tokio::time::timeout(
+        BigQueryFdw::CANCEL_TIMEOUT,
+        client.job().cancel_job(project_id, job_id, Some(location)),
+    ))
+    .map_err(|_| FdwError::remote_query("cancel job timed out"))?
+    .map_err(|err| bq_error(err, "cancel job failed"))?;
+    Ok(())
+}
+
+// approximate size of a query response received, as the raw response body is
+// not exposed by the client library
+fn response_size(resp: &QueryResponse) -> usize {
+    serde_json::to_vec(resp).map(|v| v.len()).unwrap_or(0)
+}
+
+// get id and location of the job which produced a query response
+fn response_job(resp: &QueryResponse) -> Option<(String, Option<String>)> {
+    resp.job_reference
+        .as_ref()
+        .and_then(|r| r.job_id.clone().map(|job_id| (job_id, r.location.clone())))
+}
+
+// get the type of cell converted from BigQuery field by field_to_cell()
+fn field_type_oid(field_type: &FieldType) -> pg_sys::Oid {
+    match field_type {
+        FieldType::Boolean => pg_sys::BOOLOID,
+        FieldType::Int64 | FieldType::Integer => pg_sys::INT8OID,
+        FieldType::Float64 | FieldType::Float => pg_sys::FLOAT8OID,
+        FieldType::Numeric => pg_sys::NUMERICOID,
+        FieldType::String => pg_sys::TEXTOID,
+        FieldType::Date => pg_sys::DATEOID,
+        FieldType::Datetime | FieldType::Timestamp => pg_sys::TIMESTAMPOID,
+        _ => pg_sys::InvalidOid,
     }
 }
 
 #[wrappers_fdw(
-    version = "0.1.4",
+    version = "0.1.5",
     author = "Supabase",
     website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/bigquery_fdw",
-    error_type = "BigQueryFdwError"
+    error_type = "BigQueryFdwError",
+    options(
+        server(
+            project_id(required),
+            dataset_id(required),
+            api_endpoint,
+            mock_auth(type = "bool"),
+            sa_key(secret),
+        ),
+        table(table(required), rowid_column, location, timeout(type = "i32"))
+    )
 )]
 pub(crate) struct BigQueryFdw {
     rt: Runtime,
@@ -98,13 +173,157 @@ pub(crate) struct BigQueryFdw {
     table: String,
     rowid_col: String,
     tgt_cols: Vec<Column>,
-    scan_result: Option<ResultSet>,
+    pager: Paginator,
     auth_mock: Option<GoogleAuthMock>,
+
+    // id and location of the query job being waited for
+    job: Option<(String, String)>,
+
+    // id and location of the completed query job whose result is being
+    // fetched page by page
+    result_job: Option<(String, Option<String>)>,
+
+    // remote query and metrics for EXPLAIN
+    remote_sql: Option<String>,
+    location: String,
+    metrics: RemoteMetrics,
 }
 
 impl BigQueryFdw {
     const FDW_NAME: &str = "BigQueryFdw";
 
+    // maximum time of each request waiting for a query job to complete, in
+    // milliseconds, the job is polled until it completes or times out
+    const POLL_TIMEOUT_MS: i32 = 1_000;
+
+    // maximum time to wait for cancelling a query job
+    const CANCEL_TIMEOUT: Duration = Duration::from_secs(5);
+
+    // default query timeout, in milliseconds
+    const DEFAULT_TIMEOUT_MS: i32 = 30_000;
+
+    // run a query job and poll it until it completes or times out, the job is
+    // saved so it can be cancelled if the query is cancelled while waiting.
+    // The job location is detected by BigQuery if it is not specified.
+    fn run_query(
+        &mut self,
+        sql: String,
+        location: Option<String>,
+        timeout: i32,
+    ) -> Result<ResultSet, FdwError> {
+        let client = self
+            .client
+            .as_ref()
+            .ok_or_else(|| FdwError::connection_failed("BigQuery client is not created"))?;
+
+        let mut req = QueryRequest::new(sql);
+        req.location = location.clone();
+        req.timeout_ms = Some(timeout.min(Self::POLL_TIMEOUT_MS));
+
+        self.job = None;
+        let started = Instant::now();
+        let mut rs = block_on_interruptible(&self.rt, client.job().query(&self.project_id, req))?
+            .map_err(|err| bq_error(err, "query failed"))?;
+        self.metrics
+            .record_request(started, response_size(rs.query_response()));
+        stats::record_request(Self::FDW_NAME, started);
+
+        while rs.query_response().job_complete == Some(false) {
+            let (job_id, job_location) = response_job(rs.query_response()).unwrap_or_default();
+            let job_location = location.clone().or(job_location).unwrap_or_default();
+            if started.elapsed() >= Duration::from_millis(timeout as u64) {
+                let _ = cancel_job(&self.rt, client, &self.project_id, &job_id, &job_location);
+                return Err(
+                    FdwError::remote_query(format!("query timeout {}ms expired", timeout))
+                        .with_hint("Increase the timeout option of the foreign table."),
+                );
+            }
+            self.job = Some((job_id.clone(), job_location.clone()));
+
+            let polled = Instant::now();
+            let resp = block_on_interruptible(
+                &self.rt,
+                client.job().get_query_results(
+                    &self.project_id,
+                    &job_id,
+                    GetQueryResultsParameters {
+                        location: Some(job_location),
+                        timeout_ms: Some(Self::POLL_TIMEOUT_MS),
+                        ..Default::default()
+                    },
+                ),
+            )?
+            .map_err(|err| bq_error(err, "fetch query result failed"))?;
+            rs = ResultSet::new(QueryResponse::from(resp));
+            self.metrics
+                .record_request(polled, response_size(rs.query_response()));
+            stats::record_request(Self::FDW_NAME, polled);
+        }
+        self.job = None;
+        self.metrics.record_page();
+
+        Ok(rs)
+    }
+
+    // convert rows of a result set page to a page
+    fn rs_to_page(&self, rs: &mut ResultSet) -> Result<Page, FdwError> {
+        let fields = rs
+            .query_response()
+            .schema
+            .as_ref()
+            .and_then(|schema| schema.fields.clone())
+            .unwrap_or_default();
+        let mut rows = Vec::new();
+        while rs.next_row() {
+            let mut row = Row::new();
+            for tgt_col in &self.tgt_cols {
+                if let Some(field) = fields.iter().find(|&f| f.name == tgt_col.name) {
+                    let cell = field_to_cell(rs, field)?;
+                    row.push(&field.name, cell);
+                }
+            }
+            rows.push(row);
+        }
+        let next = rs.query_response().page_token.clone().map(NextPage::Token);
+        Ok(Page::new(rows, next))
+    }
+
+    // fetch next page of the query job result by page token, or the first page
+    // if the scan is restarted, the completed job's result is read again
+    // instead of running the query again
+    fn fetch_page(&mut self, req: PageRequest) -> Result<Page, FdwError> {
+        let (client, (job_id, location), page_token) =
+            match (&self.client, &self.result_job, req.next) {
+                (Some(client), Some(job), Some(NextPage::Token(token))) => {
+                    (client, job, Some(token.clone()))
+                }
+                (Some(client), Some(job), None) => (client, job, None),
+                _ => return Ok(Page::default()),
+            };
+
+        let started = Instant::now();
+        let resp = block_on_interruptible(
+            &self.rt,
+            client.job().get_query_results(
+                &self.project_id,
+                job_id,
+                GetQueryResultsParameters {
+                    location: location.clone(),
+                    page_token,
+                    ..Default::default()
+                },
+            ),
+        )?
+        .map_err(|err| bq_error(err, "fetch query result failed"))?;
+
+        let mut rs = ResultSet::new(QueryResponse::from(resp));
+        self.metrics
+            .record_request(started, response_size(rs.query_response()));
+        stats::record_request(Self::FDW_NAME, started);
+        self.metrics.record_page();
+        self.rs_to_page(&mut rs)
+    }
+
     fn deparse(
         &self,
         quals: &[Qual],
@@ -165,101 +384,77 @@ impl BigQueryFdw {
 enum BigQueryFdwError {
     #[error("{0}")]
     CreateRuntimeError(#[from] CreateRuntimeError),
+
+    #[error("{0}")]
+    OptionsError(#[from] OptionsError),
+
+    #[error("{0}")]
+    FdwError(#[from] FdwError),
 }
 
 impl From<BigQueryFdwError> for ErrorReport {
     fn from(value: BigQueryFdwError) -> Self {
-        match value {
-            BigQueryFdwError::CreateRuntimeError(e) => e.into(),
-        }
+        let err = match value {
+            BigQueryFdwError::CreateRuntimeError(e) => FdwError::from(e),
+            BigQueryFdwError::OptionsError(e) => FdwError::from(e),
+            BigQueryFdwError::FdwError(e) => e,
+        };
+        stats::record_error(BigQueryFdw::FDW_NAME, err.kind());
+        err.into()
     }
 }
 
 impl ForeignDataWrapper<BigQueryFdwError> for BigQueryFdw {
     fn new(options: &HashMap<String, String>) -> Result<Self, BigQueryFdwError> {
+        let opts = BigQueryFdwServerOptions::from_options(options)?;
         let mut ret = BigQueryFdw {
             rt: create_async_runtime()?,
             client: None,
-            project_id: "".to_string(),
-            dataset_id: "".to_string(),
+            project_id: opts.project_id,
+            dataset_id: opts.dataset_id,
             table: "".to_string(),
             rowid_col: "".to_string(),
             tgt_cols: Vec::new(),
-            scan_result: None,
+            pager: Paginator::default(),
             auth_mock: None,
+            job: None,
+            result_job: None,
+            remote_sql: None,
+            location: String::default(),
+            metrics: RemoteMetrics::default(),
         };
 
-        let project_id = require_option("project_id", options);
-        let dataset_id = require_option("dataset_id", options);
-
-        if project_id.is_none() || dataset_id.is_none() {
-            return Ok(ret);
-        }
-        ret.project_id = project_id.unwrap();
-        ret.dataset_id = dataset_id.unwrap();
-
-        // Is authentication mocked
-        let mock_auth: bool = options
-            .get("mock_auth")
-            .map(|t| t.to_owned())
-            .unwrap_or_else(|| "false".to_string())
-            == *"true";
-
-        let api_endpoint = options
-            .get("api_endpoint")
-            .map(|t| t.to_owned())
+        let api_endpoint = opts
+            .api_endpoint
             .unwrap_or_else(|| "https://bigquery.googleapis.com/bigquery/v2".to_string());
 
-        let sa_key_json = match mock_auth {
-            true => {
-                // Key file is not required if we're mocking auth
-                let auth_mock = executor::block_on(GoogleAuthMock::start());
-                executor::block_on(auth_mock.mock_token(1));
-                let auth_mock_uri = auth_mock.uri();
-                let dummy_auth_config = dummy_configuration(&auth_mock_uri);
-                ret.auth_mock = Some(auth_mock);
-                serde_json::to_string_pretty(&dummy_auth_config).unwrap()
-            }
-            false => match options.get("sa_key") {
-                Some(sa_key) => sa_key.to_owned(),
-                None => {
-                    let sa_key_id = match require_option("sa_key_id", options) {
-                        Some(sa_key_id) => sa_key_id,
-                        None => return Ok(ret),
-                    };
-                    match get_vault_secret(&sa_key_id) {
-                        Some(sa_key) => sa_key,
-                        None => return Ok(ret),
-                    }
-                }
-            },
+        // Is authentication mocked
+        let sa_key_json = if opts.mock_auth.unwrap_or(false) {
+            // Key file is not required if we're mocking auth
+            let auth_mock = executor::block_on(GoogleAuthMock::start());
+            executor::block_on(auth_mock.mock_token(1));
+            let auth_mock_uri = auth_mock.uri();
+            let dummy_auth_config = dummy_configuration(&auth_mock_uri);
+            ret.auth_mock = Some(auth_mock);
+            serde_json::to_string_pretty(&dummy_auth_config).unwrap()
+        } else {
+            opts.sa_key.ok_or(OptionsError::MissingOption("sa_key"))?
         };
 
-        let sa_key = match yup_oauth2::parse_service_account_key(sa_key_json.as_bytes()) {
-            Ok(sa_key) => sa_key,
-            Err(err) => {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("parse service account key JSON failed: {}", err),
-                );
-                return Ok(ret);
-            }
-        };
+        let sa_key =
+            yup_oauth2::parse_service_account_key(sa_key_json.as_bytes()).map_err(|err| {
+                FdwError::auth_failed(format!("parse service account key JSON failed: {}", err))
+                    .with_hint("Check the sa_key option is a valid service account key JSON.")
+            })?;
 
-        ret.client = match ret.rt.block_on(
+        let client = block_on_interruptible(
+            &ret.rt,
             ClientBuilder::new()
                 .with_v2_base_url(api_endpoint)
                 .build_from_service_account_key(sa_key, false),
-        ) {
-            Ok(client) => Some(client),
-            Err(err) => {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("create client failed: {}", err),
-                );
-                None
-            }
-        };
+        )?
+        .map_err(|err| bq_error(err, "create client failed"))?;
+        ret.client = Some(client);
 
         stats::inc_stats(Self::FDW_NAME, stats::Metric::CreateTimes, 1);
 
@@ -268,12 +463,17 @@ impl ForeignDataWrapper<BigQueryFdwError> for BigQueryFdw {
 
     fn get_rel_size(
         &mut self,
-        _quals: &[Qual],
-        _columns: &[Column],
-        _sorts: &[Sort],
-        _limit: &Option<Limit>,
-        _options: &HashMap<String, String>,
+        quals: &[Qual],
+        columns: &[Column],
+        sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
     ) -> Result<(i64, i32), BigQueryFdwError> {
+        // prepare the remote query for EXPLAIN
+        let opts = BigQueryFdwTableOptions::from_options(options)?;
+        self.table = opts.table;
+        self.location = opts.location.unwrap_or_else(|| "US".to_string());
+        self.remote_sql = Some(self.deparse(quals, columns, sorts, limit));
         Ok((0, 0))
     }
 
@@ -285,160 +485,83 @@ impl ForeignDataWrapper<BigQueryFdwError> for BigQueryFdw {
         limit: &Option<Limit>,
         options: &HashMap<String, String>,
     ) -> Result<(), BigQueryFdwError> {
-        let table = require_option("table", options);
-        if table.is_none() {
-            return Ok(());
-        }
-        self.table = table.unwrap();
+        let opts = BigQueryFdwTableOptions::from_options(options)?;
+        self.table = opts.table;
         self.tgt_cols = columns.to_vec();
 
-        let location = options
-            .get("location")
-            .map(|t| t.to_owned())
-            .unwrap_or_else(|| "US".to_string());
-
-        let mut timeout: i32 = 30_000;
-        if let Some(timeout_str) = options.get("timeout") {
-            match timeout_str.parse::<i32>() {
-                Ok(t) => timeout = t,
-                Err(_) => report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("invalid timeout value: {}", timeout_str),
-                ),
-            }
-        }
+        let location = opts.location.unwrap_or_else(|| "US".to_string());
+        let timeout = opts.timeout.unwrap_or(Self::DEFAULT_TIMEOUT_MS);
+        self.location = location.clone();
+        self.metrics = RemoteMetrics::default();
 
-        if let Some(client) = &self.client {
+        if self.client.is_some() {
             let sql = self.deparse(quals, columns, sorts, limit);
-            let mut req = QueryRequest::new(sql);
-            req.location = Some(location);
-            req.timeout_ms = Some(timeout);
+            self.remote_sql = Some(sql.clone());
 
             // execute query on BigQuery
-            match self.rt.block_on(client.job().query(&self.project_id, req)) {
-                Ok(rs) => {
-                    let resp = rs.query_response();
-                    if resp.job_complete == Some(false) {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("query timeout {}ms expired", timeout),
-                        );
-                    } else {
-                        stats::inc_stats(
-                            Self::FDW_NAME,
-                            stats::Metric::RowsIn,
-                            resp.total_rows
-                                .as_ref()
-                                .and_then(|v| v.parse::<i64>().ok())
-                                .unwrap_or(0i64),
-                        );
-                        stats::inc_stats(
-                            Self::FDW_NAME,
-                            stats::Metric::RowsOut,
-                            resp.total_rows
-                                .as_ref()
-                                .and_then(|v| v.parse::<i64>().ok())
-                                .unwrap_or(0i64),
-                        );
-                        stats::inc_stats(
-                            Self::FDW_NAME,
-                            stats::Metric::BytesIn,
-                            resp.total_bytes_processed
-                                .as_ref()
-                                .and_then(|v| v.parse::<i64>().ok())
-                                .unwrap_or(0i64),
-                        );
-                        self.scan_result = Some(rs);
-                    }
-                }
-                Err(err) => {
-                    self.scan_result = None;
-                    report_error(
-                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                        &format!("query failed: {}", err),
-                    );
-                }
-            }
+            self.pager = Paginator::default();
+            self.result_job = None;
+            let mut rs = self.run_query(sql, Some(location), timeout)?;
+
+            let resp = rs.query_response();
+
+            stats::inc_stats(
+                Self::FDW_NAME,
+                stats::Metric::RowsIn,
+                resp.total_rows
+                    .as_ref()
+                    .and_then(|v| v.parse::<i64>().ok())
+                    .unwrap_or(0i64),
+            );
+            stats::inc_stats(
+                Self::FDW_NAME,
+                stats::Metric::RowsOut,
+                resp.total_rows
+                    .as_ref()
+                    .and_then(|v| v.parse::<i64>().ok())
+                    .unwrap_or(0i64),
+            );
+            stats::inc_stats(
+                Self::FDW_NAME,
+                stats::Metric::BytesIn,
+                resp.total_bytes_processed
+                    .as_ref()
+                    .and_then(|v| v.parse::<i64>().ok())
+                    .unwrap_or(0i64),
+            );
+            self.result_job = response_job(resp);
+            let page = self.rs_to_page(&mut rs)?;
+            self.pager.add_page(page);
         }
 
         Ok(())
     }
 
     fn iter_scan(&mut self, row: &mut Row) -> Result<Option<()>, BigQueryFdwError> {
-        if let Some(client) = &self.client {
-            if let Some(ref mut rs) = self.scan_result {
-                let mut extract_row = |rs: &mut ResultSet| {
-                    if rs.next_row() {
-                        if let Some(schema) = &rs.query_response().schema {
-                            if let Some(fields) = &schema.fields {
-                                for tgt_col in &self.tgt_cols {
-                                    if let Some(field) =
-                                        fields.iter().find(|&f| f.name == tgt_col.name)
-                                    {
-                                        let cell = field_to_cell(rs, field);
-                                        row.push(&field.name, cell);
-                                    }
-                                }
-                                return true;
-                            }
-                        }
-                    }
-                    false
-                };
-
-                if extract_row(rs) {
-                    return Ok(Some(()));
-                }
+        let mut pager = std::mem::take(&mut self.pager);
+        let result = pager.next_row(row, |req| self.fetch_page(req));
+        self.pager = pager;
+        Ok(result?)
+    }
 
-                // deal with pagination
-                if rs.query_response().page_token.is_some() {
-                    if let Some(job_ref) = &rs.query_response().job_reference {
-                        if let Some(job_id) = &job_ref.job_id {
-                            match self.rt.block_on(client.job().get_query_results(
-                                &self.project_id,
-                                job_id,
-                                GetQueryResultsParameters {
-                                    location: job_ref.location.clone(),
-                                    page_token: rs.query_response().page_token.clone(),
-                                    ..Default::default()
-                                },
-                            )) {
-                                Ok(resp) => {
-                                    // replace result set with data from the new page
-                                    *rs = ResultSet::new(QueryResponse::from(resp));
-                                    if extract_row(rs) {
-                                        return Ok(Some(()));
-                                    }
-                                }
-                                Err(err) => {
-                                    self.scan_result = None;
-                                    report_error(
-                                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                                        &format!("fetch query result failed: {}", err),
-                                    );
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        Ok(None)
+    fn re_scan(&mut self) -> Result<(), BigQueryFdwError> {
+        // fetch the result pages again from the first page
+        self.pager.reset();
+        Ok(())
     }
 
     fn end_scan(&mut self) -> Result<(), BigQueryFdwError> {
-        self.scan_result.take();
+        self.pager = Paginator::default();
+        self.result_job = None;
         Ok(())
     }
 
     fn begin_modify(&mut self, options: &HashMap<String, String>) -> Result<(), BigQueryFdwError> {
-        let table = require_option("table", options);
-        let rowid_col = require_option("rowid_column", options);
-        if table.is_none() || rowid_col.is_none() {
-            return Ok(());
-        }
-        self.table = table.unwrap();
-        self.rowid_col = rowid_col.unwrap();
+        let opts = BigQueryFdwTableOptions::from_options(options)?;
+        self.table = opts.table;
+        self.rowid_col = opts
+            .rowid_column
+            .ok_or(OptionsError::MissingOption("rowid_column"))?;
 
         Ok(())
     }
@@ -467,20 +590,23 @@ impl ForeignDataWrapper<BigQueryFdwError> for BigQueryFdw {
                 }
             }
 
-            insert_request.add_row(None, row_json).unwrap();
+            insert_request
+                .add_row(None, row_json)
+                .map_err(|err| bq_error(err, "insert failed"))?;
 
             // execute insert job on BigQuery
-            if let Err(err) = self.rt.block_on(client.tabledata().insert_all(
-                &self.project_id,
-                &self.dataset_id,
-                &self.table,
-                insert_request,
-            )) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("insert failed: {}", err),
-                );
-            }
+            let started = Instant::now();
+            block_on_interruptible(
+                &self.rt,
+                client.tabledata().insert_all(
+                    &self.project_id,
+                    &self.dataset_id,
+                    &self.table,
+                    insert_request,
+                ),
+            )?
+            .map_err(|err| bq_error(err, "insert failed"))?;
+            stats::record_request(Self::FDW_NAME, started);
         }
 
         Ok(())
@@ -512,12 +638,10 @@ impl ForeignDataWrapper<BigQueryFdwError> for BigQueryFdw {
             let query_job = client.job().query(&self.project_id, QueryRequest::new(sql));
 
             // execute update on BigQuery
-            if let Err(err) = self.rt.block_on(query_job) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("update failed: {}", err),
-                );
-            }
+            let started = Instant::now();
+            block_on_interruptible(&self.rt, query_job)?
+                .map_err(|err| bq_error(err, "update failed"))?;
+            stats::record_request(Self::FDW_NAME, started);
         }
         Ok(())
     }
@@ -532,15 +656,128 @@ impl ForeignDataWrapper<BigQueryFdwError> for BigQueryFdw {
             let query_job = client.job().query(&self.project_id, QueryRequest::new(sql));
 
             // execute delete on BigQuery
-            if let Err(err) = self.rt.block_on(query_job) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("update failed: {}", err),
-                );
-            }
+            let started = Instant::now();
+            block_on_interruptible(&self.rt, query_job)?
+                .map_err(|err| bq_error(err, "delete failed"))?;
+            stats::record_request(Self::FDW_NAME, started);
         }
         Ok(())
     }
+
+    fn remote_query(&mut self, sql: &str) -> Result<Option<Vec<Row>>, BigQueryFdwError> {
+        self.metrics = RemoteMetrics::default();
+        let mut rs = self.run_query(sql.to_owned(), None, Self::DEFAULT_TIMEOUT_MS)?;
+
+        // all columns of the query result are returned
+        self.tgt_cols = rs
+            .query_response()
+            .schema
+            .as_ref()
+            .and_then(|schema| schema.fields.as_ref())
+            .map(|fields| {
+                fields
+                    .iter()
+                    .map(|field| Column {
+                        name: field.name.clone(),
+                        num: 0,
+                        type_oid: field_type_oid(&field.r#type),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        self.result_job = response_job(rs.query_response());
+
+        let mut pager = Paginator::default();
+        pager.add_page(self.rs_to_page(&mut rs)?);
+        let mut rows = Vec::new();
+        let mut row = Row::new();
+        while pager
+            .next_row(&mut row, |req| self.fetch_page(req))?
+            .is_some()
+        {
+            rows.push(std::mem::take(&mut row));
+        }
+        self.result_job = None;
+
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsIn, rows.len() as i64);
+        Ok(Some(rows))
+    }
+
+    fn remote_exec(&mut self, sql: &str) -> Result<Option<()>, BigQueryFdwError> {
+        self.metrics = RemoteMetrics::default();
+        self.run_query(sql.to_owned(), None, Self::DEFAULT_TIMEOUT_MS)?;
+        Ok(Some(()))
+    }
+
+    fn check_connection(&self) -> Result<Option<ServerInfo>, BigQueryFdwError> {
+        // getting the dataset needs both valid credentials and access to it,
+        // BigQuery doesn't expose a server version
+        if let Some(ref client) = self.client {
+            block_on_interruptible(
+                &self.rt,
+                client.dataset().get(&self.project_id, &self.dataset_id),
+            )?
+            .map_err(|err| bq_error(err, "get dataset failed"))?;
+        }
+        Ok(Some(ServerInfo::default()))
+    }
+
+    fn explain(&self, verbose: bool) -> Vec<(String, String)> {
+        let mut ret = Vec::new();
+        if let Some(sql) = &self.remote_sql {
+            ret.push(("Remote SQL".to_string(), sql.clone()));
+        }
+        if verbose {
+            ret.push(("Remote Location".to_string(), self.location.clone()));
+        }
+        ret
+    }
+
+    fn remote_metrics(&self) -> Option<RemoteMetrics> {
+        Some(self.metrics.clone())
+    }
+
+    fn cancel(&mut self) -> Result<(), BigQueryFdwError> {
+        if let (Some(client), Some((job_id, location))) = (&self.client, self.job.take()) {
+            cancel_job(&self.rt, client, &self.project_id, &job_id, &location)?;
+        }
+        Ok(())
+    }
+
+    fn describe_columns(
+        options: &HashMap<String, String>,
+        table_options: &HashMap<String, String>,
+    ) -> Result<Option<Vec<Column>>, BigQueryFdwError> {
+        // subquery result columns cannot be described without running it
+        let table = match table_options.get("table") {
+            Some(table) if !table.starts_with('(') => table,
+            _ => return Ok(None),
+        };
+
+        let fdw = Self::new(options)?;
+        if let Some(client) = &fdw.client {
+            let tbl = block_on_interruptible(
+                &fdw.rt,
+                client
+                    .table()
+                    .get(&fdw.project_id, &fdw.dataset_id, table, None),
+            )?
+            .map_err(|err| bq_error(err, "get table schema failed"))?;
+            let columns = tbl
+                .schema
+                .fields
+                .unwrap_or_default()
+                .iter()
+                .map(|field| Column {
+                    name: field.name.clone(),
+                    num: 0,
+                    type_oid: field_type_oid(&field.r#type),
+                })
+                .collect();
+            return Ok(Some(columns));
+        }
+        Ok(None)
+    }
 }
 
 use auth_mock::GoogleAuthMock;
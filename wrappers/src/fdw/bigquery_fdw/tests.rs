@@ -104,6 +104,33 @@ mod tests {
 
             assert_eq!(results, vec!["0.123", "1234.56789"]);
 
+            // a re-scan reads the query job result again from its first page
+            let results = c
+                .select(
+                    "SELECT v.n, (SELECT count(*) FROM test_table WHERE v.n > 0) AS cnt
+                     FROM (VALUES (1), (2)) v(n) ORDER BY v.n",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<i64, _>("cnt").unwrap())
+                .collect::<Vec<_>>();
+
+            assert_eq!(results, vec![2, 2]);
+
+            let results = c
+                .select(
+                    "EXPLAIN SELECT name FROM test_table WHERE id = 1",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("QUERY PLAN").unwrap())
+                .collect::<Vec<_>>();
+
+            assert!(results.iter().any(|r| r.contains("Remote SQL: select ")
+                && r.ends_with("from `test_project.test_dataset.test_table` where id = 1")));
+
             // DISABLED: error: [FIXME]
             // insert failed: Request error (error: error decoding response body: missing field `status` at line 1 column 436)
 
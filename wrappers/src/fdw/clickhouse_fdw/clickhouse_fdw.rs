@@ -1,90 +1,124 @@
 use crate::stats;
 use chrono::{Date, DateTime, NaiveDate, NaiveDateTime, Utc};
 use chrono_tz::Tz;
-use clickhouse_rs::{types, types::Block, types::SqlType, ClientHandle, Pool};
-use pgrx::pg_sys::panic::ErrorReport;
-use pgrx::{prelude::PgSqlErrorCode, to_timestamp};
+use clickhouse_rs::{types, types::Block, types::Query, types::SqlType, ClientHandle, Pool};
+use pgrx::pg_sys::{self, panic::ErrorReport};
+use pgrx::to_timestamp;
 use regex::{Captures, Regex};
 use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use supabase_wrappers::prelude::*;
 use thiserror::Error;
 
-fn field_to_cell(row: &types::Row<types::Complex>, i: usize) -> Option<Cell> {
-    let sql_type = row.sql_type(i).unwrap();
-    match sql_type {
+// convert a failed field access to error
+fn field_error(i: usize, err: clickhouse_rs::errors::Error) -> FdwError {
+    FdwError::type_conversion(format!("get field {} failed: {}", i, err))
+}
+
+fn field_to_cell(row: &types::Row<types::Complex>, i: usize) -> Result<Option<Cell>, FdwError> {
+    let get_error = |err| field_error(i, err);
+    let sql_type = row.sql_type(i).map_err(get_error)?;
+    let cell = match sql_type {
         SqlType::UInt8 => {
             // Bool is stored as UInt8 in ClickHouse, so we treat it as bool here
-            let value = row.get::<u8, usize>(i).unwrap();
+            let value = row.get::<u8, usize>(i).map_err(get_error)?;
             Some(Cell::Bool(value != 0))
         }
         SqlType::Int16 => {
-            let value = row.get::<i16, usize>(i).unwrap();
+            let value = row.get::<i16, usize>(i).map_err(get_error)?;
             Some(Cell::I16(value))
         }
         SqlType::UInt16 => {
-            let value = row.get::<u16, usize>(i).unwrap();
+            let value = row.get::<u16, usize>(i).map_err(get_error)?;
             Some(Cell::I32(value as i32))
         }
         SqlType::Int32 => {
-            let value = row.get::<i32, usize>(i).unwrap();
+            let value = row.get::<i32, usize>(i).map_err(get_error)?;
             Some(Cell::I32(value))
         }
         SqlType::UInt32 => {
-            let value = row.get::<u32, usize>(i).unwrap();
+            let value = row.get::<u32, usize>(i).map_err(get_error)?;
             Some(Cell::I64(value as i64))
         }
         SqlType::Float32 => {
-            let value = row.get::<f32, usize>(i).unwrap();
+            let value = row.get::<f32, usize>(i).map_err(get_error)?;
             Some(Cell::F32(value))
         }
         SqlType::Float64 => {
-            let value = row.get::<f64, usize>(i).unwrap();
+            let value = row.get::<f64, usize>(i).map_err(get_error)?;
             Some(Cell::F64(value))
         }
         SqlType::UInt64 => {
-            let value = row.get::<u64, usize>(i).unwrap();
+            let value = row.get::<u64, usize>(i).map_err(get_error)?;
             Some(Cell::I64(value as i64))
         }
         SqlType::Int64 => {
-            let value = row.get::<i64, usize>(i).unwrap();
+            let value = row.get::<i64, usize>(i).map_err(get_error)?;
             Some(Cell::I64(value))
         }
         SqlType::String => {
-            let value = row.get::<String, usize>(i).unwrap();
+            let value = row.get::<String, usize>(i).map_err(get_error)?;
             Some(Cell::String(value))
         }
         SqlType::Date => {
-            let value = row.get::<Date<_>, usize>(i).unwrap();
+            let value = row.get::<Date<_>, usize>(i).map_err(get_error)?;
             let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
             let seconds_from_epoch = value.naive_utc().signed_duration_since(epoch).num_seconds();
             let ts = to_timestamp(seconds_from_epoch as f64);
             Some(Cell::Date(pgrx::Date::from(ts)))
         }
         SqlType::DateTime(_) => {
-            let value = row.get::<DateTime<_>, usize>(i).unwrap();
+            let value = row.get::<DateTime<_>, usize>(i).map_err(get_error)?;
             let ts = to_timestamp(value.timestamp() as f64);
             Some(Cell::Timestamp(ts.to_utc()))
         }
         _ => {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
-                &format!("data type {} is not supported", sql_type.to_string()),
-            );
-            None
+            return Err(FdwError::type_conversion(format!(
+                "data type {} is not supported",
+                sql_type.to_string()
+            )));
         }
+    };
+    Ok(cell)
+}
+
+// get the type of cell converted from ClickHouse column type by field_to_cell()
+fn type_name_oid(type_name: &str) -> pg_sys::Oid {
+    match type_name {
+        "UInt8" => pg_sys::BOOLOID,
+        "Int16" => pg_sys::INT2OID,
+        "UInt16" | "Int32" => pg_sys::INT4OID,
+        "UInt32" | "Int64" | "UInt64" => pg_sys::INT8OID,
+        "Float32" => pg_sys::FLOAT4OID,
+        "Float64" => pg_sys::FLOAT8OID,
+        "String" => pg_sys::TEXTOID,
+        "Date" => pg_sys::DATEOID,
+        _ if type_name.starts_with("DateTime") => pg_sys::TIMESTAMPOID,
+        _ => pg_sys::InvalidOid,
     }
 }
 
+// quote a table name, which can be qualified by database name, as identifiers
+fn quote_table_name(table: &str) -> String {
+    table
+        .split('.')
+        .map(|part| format!("`{}`", part.replace('\\', "\\\\").replace('`', "\\`")))
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
 #[wrappers_fdw(
-    version = "0.1.3",
+    version = "0.1.4",
     author = "Supabase",
     website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/clickhouse_fdw",
-    error_type = "ClickHouseFdwError"
+    error_type = "ClickHouseFdwError",
+    options(server(conn_string(secret)), table(table(required), rowid_column))
 )]
 pub(crate) struct ClickHouseFdw {
     rt: Runtime,
     conn_str: String,
+    pool: Option<Pool>,
     client: Option<ClientHandle>,
     table: String,
     rowid_col: String,
@@ -92,23 +126,53 @@ pub(crate) struct ClickHouseFdw {
     scan_blk: Option<Block<types::Complex>>,
     row_idx: usize,
     params: Vec<Qual>,
+
+    // id of the query running on ClickHouse, used to kill the query when it
+    // is cancelled
+    query_id: Option<String>,
+
+    // remote query and metrics for EXPLAIN
+    remote_sql: Option<String>,
+    metrics: RemoteMetrics,
 }
 
 impl ClickHouseFdw {
     const FDW_NAME: &str = "ClickHouseFdw";
 
-    fn create_client(&mut self) {
-        let pool = Pool::new(self.conn_str.as_str());
-        self.client = self.rt.block_on(pool.get_handle()).map_or_else(
-            |err| {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_UNABLE_TO_ESTABLISH_CONNECTION,
-                    &format!("connection failed: {}", err),
-                );
-                None
-            },
-            Some,
-        );
+    // maximum time to wait for killing a cancelled query
+    const CANCEL_TIMEOUT: Duration = Duration::from_secs(5);
+
+    // create a query with a unique id, and save the id for cancellation
+    fn new_query(query_id: &mut Option<String>, sql: &str) -> Query {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let id = format!("wrappers_{}_{}", std::process::id(), nanos);
+        let query = Query::new(sql).id(&id);
+        *query_id = Some(id);
+        query
+    }
+
+    fn create_client(&mut self) -> Result<(), FdwError> {
+        // reuse existing connection if it is still alive, as the instance
+        // can be cached and reused across queries
+        if let Some(ref mut client) = self.client {
+            if block_on_interruptible(&self.rt, client.check_connection())?.is_ok() {
+                return Ok(());
+            }
+        }
+
+        self.client = None;
+        let pool = self
+            .pool
+            .get_or_insert_with(|| Pool::new(self.conn_str.as_str()));
+        let client = block_on_interruptible(&self.rt, pool.get_handle())?.map_err(|err| {
+            FdwError::connection_failed(format!("connection failed: {}", err))
+                .with_hint("Check the conn_string option of the foreign server.")
+        })?;
+        self.client = Some(client);
+        Ok(())
     }
 
     fn deparse(
@@ -117,35 +181,42 @@ impl ClickHouseFdw {
         columns: &[Column],
         sorts: &[Sort],
         limit: &Option<Limit>,
-    ) -> String {
+    ) -> Result<String, FdwError> {
         let table = if self.table.starts_with('(') {
             let re = Regex::new(r"\$\{(\w+)\}").unwrap();
-            re.replace_all(&self.table, |caps: &Captures| {
-                let param = &caps[1];
-                match quals.iter().find(|&q| q.field == param) {
-                    Some(qual) => {
-                        self.params.push(qual.clone());
-                        match &qual.value {
-                            Value::Cell(cell) => cell.to_string(),
-                            Value::Array(_) => {
-                                report_error(
-                                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                                    "invalid query parameter",
-                                );
-                                String::default()
+            let mut param_err = None;
+            let table = re
+                .replace_all(&self.table, |caps: &Captures| {
+                    let param = &caps[1];
+                    match quals.iter().find(|&q| q.field == param) {
+                        Some(qual) => {
+                            self.params.push(qual.clone());
+                            match &qual.value {
+                                Value::Cell(cell) => cell.to_string(),
+                                Value::Array(_) => {
+                                    param_err.get_or_insert_with(|| {
+                                        FdwError::remote_query("invalid query parameter")
+                                    });
+                                    String::default()
+                                }
                             }
                         }
+                        None => {
+                            param_err.get_or_insert_with(|| {
+                                FdwError::remote_query(format!(
+                                    "unmatched query parameter: {}",
+                                    param
+                                ))
+                            });
+                            String::default()
+                        }
                     }
-                    None => {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("unmatched query parameter: {}", param),
-                        );
-                        String::default()
-                    }
-                }
-            })
-            .into_owned()
+                })
+                .into_owned();
+            if let Some(err) = param_err {
+                return Err(err);
+            }
+            table
         } else {
             self.table.clone()
         };
@@ -195,7 +266,7 @@ impl ClickHouseFdw {
             sql.push_str(&format!(" limit {}", real_limit));
         }
 
-        sql
+        Ok(sql)
     }
 }
 
@@ -203,31 +274,39 @@ impl ClickHouseFdw {
 enum ClickHouseFdwError {
     #[error("{0}")]
     CreateRuntimeError(#[from] CreateRuntimeError),
+
+    #[error("{0}")]
+    OptionsError(#[from] OptionsError),
+
+    #[error("{0}")]
+    FdwError(#[from] FdwError),
 }
 
 impl From<ClickHouseFdwError> for ErrorReport {
     fn from(value: ClickHouseFdwError) -> Self {
-        match value {
-            ClickHouseFdwError::CreateRuntimeError(e) => e.into(),
-        }
+        let err = match value {
+            ClickHouseFdwError::CreateRuntimeError(e) => FdwError::from(e),
+            ClickHouseFdwError::OptionsError(e) => FdwError::from(e),
+            ClickHouseFdwError::FdwError(e) => e,
+        };
+        stats::record_error(ClickHouseFdw::FDW_NAME, err.kind());
+        err.into()
     }
 }
 
 impl ForeignDataWrapper<ClickHouseFdwError> for ClickHouseFdw {
     fn new(options: &HashMap<String, String>) -> Result<Self, ClickHouseFdwError> {
         let rt = create_async_runtime()?;
-        let conn_str = match options.get("conn_string") {
-            Some(conn_str) => conn_str.to_owned(),
-            None => require_option("conn_string_id", options)
-                .and_then(|conn_str_id| get_vault_secret(&conn_str_id))
-                .unwrap_or_default(),
-        };
+        let conn_str = ClickHouseFdwServerOptions::from_options(options)?
+            .conn_string
+            .ok_or(OptionsError::MissingOption("conn_string"))?;
 
         stats::inc_stats(Self::FDW_NAME, stats::Metric::CreateTimes, 1);
 
         Ok(Self {
             rt,
             conn_str,
+            pool: None,
             client: None,
             table: String::default(),
             rowid_col: String::default(),
@@ -235,9 +314,28 @@ impl ForeignDataWrapper<ClickHouseFdwError> for ClickHouseFdw {
             scan_blk: None,
             row_idx: 0,
             params: Vec::new(),
+            query_id: None,
+            remote_sql: None,
+            metrics: RemoteMetrics::default(),
         })
     }
 
+    fn get_rel_size(
+        &mut self,
+        quals: &[Qual],
+        columns: &[Column],
+        sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+    ) -> Result<(i64, i32), ClickHouseFdwError> {
+        // prepare the remote query for EXPLAIN, query parameters may not be
+        // known yet during planning
+        self.table = ClickHouseFdwTableOptions::from_options(options)?.table;
+        self.params.clear();
+        self.remote_sql = self.deparse(quals, columns, sorts, limit).ok();
+        Ok((0, 0))
+    }
+
     fn begin_scan(
         &mut self,
         quals: &[Qual],
@@ -246,40 +344,40 @@ impl ForeignDataWrapper<ClickHouseFdwError> for ClickHouseFdw {
         limit: &Option<Limit>,
         options: &HashMap<String, String>,
     ) -> Result<(), ClickHouseFdwError> {
-        self.create_client();
+        self.create_client()?;
 
-        let table = require_option("table", options);
-        if table.is_none() {
-            return Ok(());
-        }
-        self.table = table.unwrap();
+        self.table = ClickHouseFdwTableOptions::from_options(options)?.table;
         self.tgt_cols = columns.to_vec();
         self.row_idx = 0;
+        self.params.clear();
+        self.metrics = RemoteMetrics::default();
 
-        let sql = self.deparse(quals, columns, sorts, limit);
+        let sql = self.deparse(quals, columns, sorts, limit)?;
+        let query = Self::new_query(&mut self.query_id, &sql);
+        self.remote_sql = Some(sql);
 
         if let Some(ref mut client) = self.client {
             // for simplicity purpose, we fetch whole query result to local,
             // may need optimization in the future.
-            match self.rt.block_on(client.query(&sql).fetch_all()) {
-                Ok(block) => {
-                    stats::inc_stats(
-                        Self::FDW_NAME,
-                        stats::Metric::RowsIn,
-                        block.row_count() as i64,
-                    );
-                    stats::inc_stats(
-                        Self::FDW_NAME,
-                        stats::Metric::RowsOut,
-                        block.row_count() as i64,
-                    );
-                    self.scan_blk = Some(block);
-                }
-                Err(err) => report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("query failed: {}", err),
-                ),
-            }
+            let started = Instant::now();
+            let block = block_on_interruptible(&self.rt, client.query(query).fetch_all())?
+                .map_err(|err| FdwError::remote_query(format!("query failed: {}", err)))?;
+
+            // received bytes are not exposed by the native protocol client
+            self.metrics.record_request(started, 0);
+            self.metrics.record_page();
+            stats::record_request(Self::FDW_NAME, started);
+            stats::inc_stats(
+                Self::FDW_NAME,
+                stats::Metric::RowsIn,
+                block.row_count() as i64,
+            );
+            stats::inc_stats(
+                Self::FDW_NAME,
+                stats::Metric::RowsOut,
+                block.row_count() as i64,
+            );
+            self.scan_blk = Some(block);
         }
 
         Ok(())
@@ -303,13 +401,14 @@ impl ForeignDataWrapper<ClickHouseFdwError> for ClickHouseFdw {
                         .iter()
                         .enumerate()
                         .find(|(_, c)| c.name() == tgt_col.name)
-                        .unwrap();
-                    let cell = field_to_cell(&src_row, i);
-                    let col_name = src_row.name(i).unwrap();
-                    if cell.as_ref().is_none() {
-                        return Ok(None);
-                    }
-                    row.push(col_name, cell);
+                        .ok_or_else(|| {
+                            FdwError::remote_query(format!(
+                                "column '{}' is not found in query result",
+                                tgt_col.name
+                            ))
+                        })?;
+                    let cell = field_to_cell(&src_row, i)?;
+                    row.push(&tgt_col.name, cell);
                 }
                 self.row_idx += 1;
                 return Ok(Some(()));
@@ -318,6 +417,11 @@ impl ForeignDataWrapper<ClickHouseFdwError> for ClickHouseFdw {
         Ok(None)
     }
 
+    fn re_scan(&mut self) -> Result<(), ClickHouseFdwError> {
+        self.row_idx = 0;
+        Ok(())
+    }
+
     fn end_scan(&mut self) -> Result<(), ClickHouseFdwError> {
         self.scan_blk.take();
         Ok(())
@@ -327,15 +431,13 @@ impl ForeignDataWrapper<ClickHouseFdwError> for ClickHouseFdw {
         &mut self,
         options: &HashMap<String, String>,
     ) -> Result<(), ClickHouseFdwError> {
-        self.create_client();
+        self.create_client()?;
 
-        let table = require_option("table", options);
-        let rowid_col = require_option("rowid_column", options);
-        if table.is_none() || rowid_col.is_none() {
-            return Ok(());
-        }
-        self.table = table.unwrap();
-        self.rowid_col = rowid_col.unwrap();
+        let opts = ClickHouseFdwTableOptions::from_options(options)?;
+        self.table = opts.table;
+        self.rowid_col = opts
+            .rowid_column
+            .ok_or(OptionsError::MissingOption("rowid_column"))?;
         Ok(())
     }
 
@@ -352,47 +454,47 @@ impl ForeignDataWrapper<ClickHouseFdwError> for ClickHouseFdw {
                         Cell::String(v) => row.push((col_name, types::Value::from(v.as_str()))),
                         Cell::Date(_) => {
                             let s = cell.to_string().replace('\'', "");
-                            if let Ok(tm) = NaiveDate::parse_from_str(&s, "%Y-%m-%d") {
-                                let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
-                                let duration = tm - epoch;
-                                let dt = types::Value::Date(duration.num_days() as u16, Tz::UTC);
-                                row.push((col_name, dt));
-                            } else {
-                                report_error(
-                                    PgSqlErrorCode::ERRCODE_FDW_INVALID_STRING_FORMAT,
-                                    &format!("invalid date format {}", s),
-                                );
-                            }
+                            let tm = NaiveDate::parse_from_str(&s, "%Y-%m-%d").map_err(|_| {
+                                FdwError::type_conversion(format!("invalid date format {}", s))
+                            })?;
+                            let epoch = NaiveDate::from_ymd_opt(1970, 1, 1).unwrap();
+                            let duration = tm - epoch;
+                            let dt = types::Value::Date(duration.num_days() as u16, Tz::UTC);
+                            row.push((col_name, dt));
                         }
                         Cell::Timestamp(_) => {
                             let s = cell.to_string().replace('\'', "");
-                            if let Ok(tm) = NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S") {
-                                let tm: DateTime<Utc> = DateTime::from_utc(tm, Utc);
-                                row.push((col_name, types::Value::from(tm)));
-                            } else {
-                                report_error(
-                                    PgSqlErrorCode::ERRCODE_FDW_INVALID_STRING_FORMAT,
-                                    &format!("invalid timestamp format {}", s),
-                                );
-                            }
+                            let tm = NaiveDateTime::parse_from_str(&s, "%Y-%m-%d %H:%M:%S")
+                                .map_err(|_| {
+                                    FdwError::type_conversion(format!(
+                                        "invalid timestamp format {}",
+                                        s
+                                    ))
+                                })?;
+                            let tm: DateTime<Utc> = DateTime::from_utc(tm, Utc);
+                            row.push((col_name, types::Value::from(tm)));
+                        }
+                        _ => {
+                            return Err(FdwError::type_conversion(format!(
+                                "field type {:?} not supported",
+                                cell
+                            ))
+                            .into())
                         }
-                        _ => report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
-                            &format!("field type {:?} not supported", cell),
-                        ),
                     }
                 }
             }
             let mut block = Block::new();
-            block.push(row).unwrap();
+            block
+                .push(row)
+                .map_err(|err| FdwError::type_conversion(format!("convert row failed: {}", err)))?;
 
             // execute query on ClickHouse
-            if let Err(err) = self.rt.block_on(client.insert(&self.table, block)) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("insert failed: {}", err),
-                );
-            }
+            self.query_id = None;
+            let started = Instant::now();
+            block_on_interruptible(&self.rt, client.insert(&self.table, block))?
+                .map_err(|err| FdwError::remote_query(format!("insert failed: {}", err)))?;
+            stats::record_request(Self::FDW_NAME, started);
         }
         Ok(())
     }
@@ -419,12 +521,11 @@ impl ForeignDataWrapper<ClickHouseFdwError> for ClickHouseFdw {
             );
 
             // execute query on ClickHouse
-            if let Err(err) = self.rt.block_on(client.execute(&sql)) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("update failed: {}", err),
-                );
-            }
+            let query = Self::new_query(&mut self.query_id, &sql);
+            let started = Instant::now();
+            block_on_interruptible(&self.rt, client.execute(query))?
+                .map_err(|err| FdwError::remote_query(format!("update failed: {}", err)))?;
+            stats::record_request(Self::FDW_NAME, started);
         }
         Ok(())
     }
@@ -437,13 +538,151 @@ impl ForeignDataWrapper<ClickHouseFdwError> for ClickHouseFdw {
             );
 
             // execute query on ClickHouse
-            if let Err(err) = self.rt.block_on(client.execute(&sql)) {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("delete failed: {}", err),
-                );
+            let query = Self::new_query(&mut self.query_id, &sql);
+            let started = Instant::now();
+            block_on_interruptible(&self.rt, client.execute(query))?
+                .map_err(|err| FdwError::remote_query(format!("delete failed: {}", err)))?;
+            stats::record_request(Self::FDW_NAME, started);
+        }
+        Ok(())
+    }
+
+    fn remote_query(&mut self, sql: &str) -> Result<Option<Vec<Row>>, ClickHouseFdwError> {
+        self.create_client()?;
+
+        let mut rows = Vec::new();
+        if let Some(ref mut client) = self.client {
+            let query = Self::new_query(&mut self.query_id, sql);
+            let started = Instant::now();
+            let block = block_on_interruptible(&self.rt, client.query(query).fetch_all())?
+                .map_err(|err| FdwError::remote_query(format!("query failed: {}", err)))?;
+            stats::record_request(Self::FDW_NAME, started);
+            stats::inc_stats(
+                Self::FDW_NAME,
+                stats::Metric::RowsIn,
+                block.row_count() as i64,
+            );
+
+            // all columns of the query result are returned
+            for src_row in block.rows() {
+                let mut row = Row::new();
+                for (i, col) in block.columns().iter().enumerate() {
+                    row.push(col.name(), field_to_cell(&src_row, i)?);
+                }
+                rows.push(row);
             }
         }
+        Ok(Some(rows))
+    }
+
+    fn remote_exec(&mut self, sql: &str) -> Result<Option<()>, ClickHouseFdwError> {
+        self.create_client()?;
+
+        if let Some(ref mut client) = self.client {
+            let query = Self::new_query(&mut self.query_id, sql);
+            let started = Instant::now();
+            block_on_interruptible(&self.rt, client.execute(query))?
+                .map_err(|err| FdwError::remote_query(format!("execute failed: {}", err)))?;
+            stats::record_request(Self::FDW_NAME, started);
+        }
+        Ok(Some(()))
+    }
+
+    fn check_connection(&self) -> Result<Option<ServerInfo>, ClickHouseFdwError> {
+        // use a new connection, so a cached one doesn't hide connection errors
+        let pool = Pool::new(self.conn_str.as_str());
+        let check = async {
+            let mut client = pool.get_handle().await.map_err(|err| {
+                FdwError::connection_failed(format!("connection failed: {}", err))
+                    .with_hint("Check the conn_string option of the foreign server.")
+            })?;
+            client
+                .query("select version()")
+                .fetch_all()
+                .await
+                .map_err(|err| FdwError::remote_query(format!("query failed: {}", err)))
+        };
+        let block = block_on_interruptible(&self.rt, check)??;
+        let version = match block.rows().next() {
+            Some(row) => Some(row.get::<String, _>(0).map_err(|err| {
+                FdwError::type_conversion(format!("invalid server version: {}", err))
+            })?),
+            None => None,
+        };
+        Ok(Some(ServerInfo { version }))
+    }
+
+    fn explain(&self, verbose: bool) -> Vec<(String, String)> {
+        let mut ret = Vec::new();
+        if let Some(sql) = &self.remote_sql {
+            ret.push(("Remote SQL".to_string(), sql.clone()));
+        }
+        if verbose && !self.params.is_empty() {
+            let params = self
+                .params
+                .iter()
+                .map(|p| p.field.clone())
+                .collect::<Vec<String>>()
+                .join(", ");
+            ret.push(("Remote Parameters".to_string(), params));
+        }
+        ret
+    }
+
+    fn remote_metrics(&self) -> Option<RemoteMetrics> {
+        Some(self.metrics.clone())
+    }
+
+    fn cancel(&mut self) -> Result<(), ClickHouseFdwError> {
+        // the connection is left in the middle of a query, so it cannot be
+        // reused anymore
+        self.client = None;
+        self.pool = None;
+
+        if let Some(query_id) = self.query_id.take() {
+            // kill the query using a new connection
+            let pool = Pool::new(self.conn_str.as_str());
+            let sql = format!("kill query where query_id = '{}' async", query_id);
+            let kill = async {
+                let mut client = pool.get_handle().await?;
+                client.query(sql.as_str()).fetch_all().await
+            };
+            self.rt
+                .block_on(tokio::time::timeout(Self::CANCEL_TIMEOUT, kill))
+                .map_err(|_| FdwError::remote_query("kill query timed out"))?
+                .map_err(|err| FdwError::remote_query(format!("kill query failed: {}", err)))?;
+        }
         Ok(())
     }
+
+    fn describe_columns(
+        options: &HashMap<String, String>,
+        table_options: &HashMap<String, String>,
+    ) -> Result<Option<Vec<Column>>, ClickHouseFdwError> {
+        // custom sql result columns cannot be described without running it
+        let table = match table_options.get("table") {
+            Some(table) if !table.starts_with('(') => table,
+            _ => return Ok(None),
+        };
+
+        let mut fdw = Self::new(options)?;
+        fdw.create_client()?;
+        if let Some(ref mut client) = fdw.client {
+            let sql = format!("describe table {}", quote_table_name(table));
+            let block = block_on_interruptible(&fdw.rt, client.query(&sql).fetch_all())?
+                .map_err(|err| FdwError::remote_query(format!("describe table failed: {}", err)))?;
+            let mut columns = Vec::new();
+            for row in block.rows() {
+                let name: String = row.get("name").unwrap_or_default();
+                let type_name: String = row.get("type").unwrap_or_default();
+                columns.push(Column {
+                    name,
+                    num: 0,
+                    type_oid: type_name_oid(&type_name),
+                });
+            }
+            return Ok(Some(columns));
+        }
+        Ok(None)
+    }
 }
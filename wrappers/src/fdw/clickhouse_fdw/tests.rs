@@ -154,6 +154,41 @@ mod tests {
                 "test"
             );
 
+            let results = c
+                .select("EXPLAIN SELECT name FROM test_table", None, None)
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("QUERY PLAN").unwrap())
+                .collect::<Vec<_>>();
+            assert!(results
+                .iter()
+                .any(|r| r.contains("Remote SQL: select name from test_table")));
+
+            // a re-scan rewinds the fetched block, and a changed parameter
+            // value is pushed down in a new remote query
+            let results = c
+                .select(
+                    "SELECT v.n, (SELECT count(*) FROM test_table WHERE v.n > 0)
+                     FROM (VALUES (1), (2)) v(n) ORDER BY v.n",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get::<i64>(2).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![4, 4]);
+
+            let results = c
+                .select(
+                    "SELECT v.name, (SELECT count(*) FROM test_table t WHERE t.name = v.name)
+                     FROM (VALUES ('test2'), ('test3'), ('test5')) v(name) ORDER BY v.name",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get::<i64>(2).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![1, 1, 0]);
+
             assert_eq!(
                 c.select(
                     "SELECT name FROM test_table WHERE name = $1",
@@ -232,6 +267,23 @@ mod tests {
                 "test3"
             );
 
+            // parameterized limit and offset are pushed down in generic plan
+            c.update(
+                "SET LOCAL plan_cache_mode = force_generic_plan;
+                 PREPARE names_page(bigint, bigint) AS
+                   SELECT name FROM test_table ORDER BY name LIMIT $1 OFFSET $2",
+                None,
+                None,
+            )
+            .unwrap();
+            let results = c
+                .select("EXECUTE names_page(2, 1)", None, None)
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("name").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec!["test2", "test3"]);
+            c.update("DEALLOCATE names_page", None, None).unwrap();
+
             let remote_value: String = rt
                 .block_on(async {
                     handle
@@ -247,4 +299,189 @@ mod tests {
             assert_eq!(remote_value, "test");
         });
     }
+
+    #[pg_test(error = "column \"id\" is of type text but remote column is mapped to type integer")]
+    fn clickhouse_column_type_mismatch() {
+        Spi::connect(|mut c| {
+            let clickhouse_pool = ch::Pool::new("tcp://default:@localhost:9000/supa");
+
+            let rt = create_async_runtime().expect("failed to create runtime");
+            let mut handle = rt
+                .block_on(async { clickhouse_pool.get_handle().await })
+                .expect("handle");
+
+            rt.block_on(async {
+                handle
+                    .execute("DROP TABLE IF EXISTS supa.test_type_table")
+                    .await?;
+                handle
+                    .execute(
+                        "CREATE TABLE supa.test_type_table (id INT, name TEXT) engine = Memory",
+                    )
+                    .await
+            })
+            .expect("test_type_table in ClickHouse");
+
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER clickhouse_wrapper
+                         HANDLER click_house_fdw_handler VALIDATOR click_house_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"CREATE SERVER my_clickhouse_server
+                         FOREIGN DATA WRAPPER clickhouse_wrapper
+                         OPTIONS (
+                           conn_string 'tcp://default:@localhost:9000/supa'
+                         )"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"
+                  CREATE FOREIGN TABLE test_type_table (
+                    id text,
+                    name text
+                  )
+                  SERVER my_clickhouse_server
+                  OPTIONS (
+                    table 'test_type_table'
+                  )
+             "#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_remote_query() {
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER clickhouse_wrapper
+                         HANDLER click_house_fdw_handler VALIDATOR click_house_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"CREATE SERVER my_clickhouse_server
+                         FOREIGN DATA WRAPPER clickhouse_wrapper
+                         OPTIONS (
+                           conn_string 'tcp://default:@localhost:9000/supa'
+                         )"#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            // statements are run on the server without foreign tables
+            for sql in [
+                "DROP TABLE IF EXISTS supa.test_remote_table",
+                "CREATE TABLE supa.test_remote_table (id Int32, name String) engine = Memory",
+                "INSERT INTO supa.test_remote_table VALUES (1, 'foo'), (2, 'bar')",
+            ] {
+                c.update(
+                    "SELECT wrappers.remote_exec('my_clickhouse_server', $1)",
+                    None,
+                    Some(vec![(PgBuiltInOids::TEXTOID.oid(), sql.into_datum())]),
+                )
+                .unwrap();
+            }
+
+            let results = c
+                .select(
+                    r#"SELECT r->>'name' AS name
+                       FROM wrappers.remote_query(
+                         'my_clickhouse_server',
+                         'SELECT id, name FROM supa.test_remote_table ORDER BY id'
+                       ) r"#,
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("name").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec!["foo", "bar"]);
+
+            // the rows can be converted to typed records
+            let results = c
+                .select(
+                    r#"SELECT t.id
+                       FROM wrappers.remote_query(
+                         'my_clickhouse_server',
+                         'SELECT id, name FROM supa.test_remote_table ORDER BY id'
+                       ) r, jsonb_to_record(r) AS t(id integer, name text)"#,
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<i32, _>("id").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![1, 2]);
+        });
+    }
+
+    #[pg_test]
+    fn clickhouse_check_server() {
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER clickhouse_wrapper
+                         HANDLER click_house_fdw_handler VALIDATOR click_house_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"CREATE SERVER my_clickhouse_server
+                         FOREIGN DATA WRAPPER clickhouse_wrapper
+                         OPTIONS (
+                           conn_string 'tcp://default:@localhost:9000/supa'
+                         )"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"CREATE SERVER my_broken_server
+                         FOREIGN DATA WRAPPER clickhouse_wrapper
+                         OPTIONS (
+                           conn_string 'tcp://default:@localhost:9999/supa?send_retries=0'
+                         )"#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            let (status, version) = c
+                .select(
+                    "SELECT status, version FROM wrappers.check_server('my_clickhouse_server')",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<String, String>()
+                .unwrap();
+            assert_eq!(status.as_deref(), Some("ok"));
+            assert!(version.is_some());
+
+            // the error is returned instead of being raised
+            let (status, error) = c
+                .select(
+                    "SELECT status, error FROM wrappers.check_server('my_broken_server')",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_two::<String, String>()
+                .unwrap();
+            assert_eq!(status.as_deref(), Some("failed"));
+            assert!(error.unwrap().contains("connection failed"));
+        });
+    }
 }
@@ -1,49 +1,39 @@
+use crate::http::{self, HttpOptions};
 use crate::stats;
 use pgrx::pg_sys::panic::ErrorReport;
-use pgrx::{pg_sys, prelude::*, JsonB};
+use pgrx::JsonB;
 use regex::Regex;
-use reqwest::{self, header};
-use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use reqwest::header;
+use reqwest_middleware::ClientWithMiddleware;
 use serde_json::Value as JsonValue;
 use std::collections::HashMap;
-use std::str::FromStr;
+use std::time::Instant;
 use yup_oauth2::AccessToken;
 use yup_oauth2::ServiceAccountAuthenticator;
 
 use supabase_wrappers::prelude::*;
 use thiserror::Error;
 
-macro_rules! report_request_error {
-    ($url:ident, $err:ident) => {
-        report_error(
-            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-            &format!("fetch {} failed: {}", $url, $err),
-        )
-    };
+// convert a failed request to error, the error kind is decided by response status
+fn request_error(url: &str, err: impl Into<reqwest_middleware::Error>) -> FdwError {
+    let err = err.into();
+    let msg = format!("fetch {} failed: {}", url, err);
+    match &err {
+        reqwest_middleware::Error::Reqwest(e) => match e.status() {
+            Some(status) => FdwError::from_http_status(status.as_u16(), msg),
+            None => FdwError::connection_failed(msg),
+        },
+        reqwest_middleware::Error::Middleware(_) => FdwError::remote_query(msg),
+    }
 }
 
-fn get_oauth2_token(sa_key: &str, rt: &Runtime) -> Option<AccessToken> {
-    let creds = match yup_oauth2::parse_service_account_key(sa_key.as_bytes()) {
-        Ok(creds) => creds,
-        Err(err) => {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                &format!("parse service account key JSON failed: {}", err),
-            );
-            return None;
-        }
-    };
-    let sa = match rt.block_on(ServiceAccountAuthenticator::builder(creds).build()) {
-        Ok(sa) => sa,
-        Err(err) => {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                &format!("invalid service account key: {}", err),
-            );
-            return None;
-        }
-    };
+fn get_oauth2_token(sa_key: &str, rt: &Runtime) -> Result<AccessToken, FdwError> {
+    let creds = yup_oauth2::parse_service_account_key(sa_key.as_bytes()).map_err(|err| {
+        FdwError::auth_failed(format!("parse service account key JSON failed: {}", err))
+            .with_hint("Check the sa_key option is a valid service account key JSON.")
+    })?;
+    let sa = block_on_interruptible(rt, ServiceAccountAuthenticator::builder(creds).build())?
+        .map_err(|err| FdwError::auth_failed(format!("invalid service account key: {}", err)))?;
     let scopes = &[
         "https://www.googleapis.com/auth/cloud-platform",
         "https://www.googleapis.com/auth/firebase.database",
@@ -51,24 +41,39 @@ fn get_oauth2_token(sa_key: &str, rt: &Runtime) -> Option<AccessToken> {
         "https://www.googleapis.com/auth/identitytoolkit",
         "https://www.googleapis.com/auth/userinfo.email",
     ];
-    match rt.block_on(sa.token(scopes)) {
-        Ok(token) => Some(token),
-        Err(err) => {
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                &format!("get token failed: {}", err),
-            );
-            None
-        }
+    block_on_interruptible(rt, sa.token(scopes))?
+        .map_err(|err| FdwError::auth_failed(format!("get token failed: {}", err)))
+}
+
+fn create_client(token: &str, opts: &HttpOptions) -> Result<ClientWithMiddleware, FdwError> {
+    let mut headers = header::HeaderMap::new();
+    let value = format!("Bearer {}", token);
+    let mut auth_value = header::HeaderValue::from_str(&value)
+        .map_err(|_| FdwError::auth_failed("get token failed: invalid access token"))?;
+    auth_value.set_sensitive(true);
+    headers.insert(header::AUTHORIZATION, auth_value);
+    http::create_client(headers, opts)
+}
+
+// convert a property value to cell of the target column, Firebase returns
+// user's `createdAt` as a string of Unix epoch in milliseconds
+fn value_to_cell(
+    src_name: &str,
+    value: &JsonValue,
+    tgt_col: &Column,
+) -> Result<Option<Cell>, JsonError> {
+    match (src_name, value.as_str().and_then(|v| v.parse::<i64>().ok())) {
+        ("createdAt", Some(millis)) => Cell::from_json(&JsonValue::from(millis / 1000), tgt_col),
+        _ => Cell::from_json(value, tgt_col),
     }
 }
 
 fn body_to_rows(
     resp: &JsonValue,
     obj_key: &str,
-    normal_cols: Vec<(&str, &str, &str)>,
+    normal_cols: Vec<(&str, &str)>,
     tgt_cols: &[Column],
-) -> Vec<Row> {
+) -> Result<Vec<Row>, JsonError> {
     let mut result = Vec::new();
 
     let objs = match resp
@@ -77,7 +82,7 @@ fn body_to_rows(
         .and_then(|v| v.as_array())
     {
         Some(objs) => objs,
-        None => return result,
+        None => return Ok(result),
     };
 
     for obj in objs {
@@ -85,46 +90,29 @@ fn body_to_rows(
 
         // extract normal columns
         for tgt_col in tgt_cols {
-            if let Some((src_name, col_name, col_type)) =
-                normal_cols.iter().find(|(_, c, _)| c == &tgt_col.name)
+            if let Some((src_name, col_name)) = normal_cols.iter().find(|(_, c)| c == &tgt_col.name)
             {
-                let cell = obj
-                    .as_object()
-                    .and_then(|v| v.get(*src_name))
-                    .and_then(|v| match *col_type {
-                        "bool" => v.as_bool().map(Cell::Bool),
-                        "i64" => v.as_i64().map(Cell::I64),
-                        "string" => v.as_str().map(|a| Cell::String(a.to_owned())),
-                        "timestamp" => v.as_str().map(|a| {
-                            let secs = a.parse::<i64>().unwrap() / 1000;
-                            let ts = to_timestamp(secs as f64);
-                            Cell::Timestamp(ts.to_utc())
-                        }),
-                        "timestamp_iso" => v.as_str().map(|a| {
-                            let ts = Timestamp::from_str(a).unwrap();
-                            Cell::Timestamp(ts)
-                        }),
-                        "json" => Some(Cell::Json(JsonB(v.clone()))),
-                        _ => None,
-                    });
+                let cell = match obj.as_object().and_then(|v| v.get(*src_name)) {
+                    Some(v) => value_to_cell(src_name, v, tgt_col)?,
+                    None => None,
+                };
                 row.push(col_name, cell);
             }
         }
 
         // put all properties into 'attrs' JSON column
         if tgt_cols.iter().any(|c| &c.name == "attrs") {
-            let attrs = serde_json::from_str(&obj.to_string()).unwrap();
-            row.push("attrs", Some(Cell::Json(JsonB(attrs))));
+            row.push("attrs", Some(Cell::Json(JsonB(obj.clone()))));
         }
 
         result.push(row);
     }
 
-    result
+    Ok(result)
 }
 
 // convert response body text to rows
-fn resp_to_rows(obj: &str, resp: &JsonValue, tgt_cols: &[Column]) -> Vec<Row> {
+fn resp_to_rows(obj: &str, resp: &JsonValue, tgt_cols: &[Column]) -> Result<Vec<Row>, FdwError> {
     let mut result = Vec::new();
 
     match obj {
@@ -133,12 +121,12 @@ fn resp_to_rows(obj: &str, resp: &JsonValue, tgt_cols: &[Column]) -> Vec<Row> {
                 resp,
                 "users",
                 vec![
-                    ("localId", "uid", "string"),
-                    ("email", "email", "string"),
-                    ("createdAt", "created_at", "timestamp"),
+                    ("localId", "uid"),
+                    ("email", "email"),
+                    ("createdAt", "created_at"),
                 ],
                 tgt_cols,
-            );
+            )?;
         }
         _ => {
             // match firestore documents
@@ -147,36 +135,47 @@ fn resp_to_rows(obj: &str, resp: &JsonValue, tgt_cols: &[Column]) -> Vec<Row> {
                     resp,
                     "documents",
                     vec![
-                        ("name", "name", "string"),
-                        ("fields", "fields", "json"),
-                        ("createTime", "created_at", "timestamp_iso"),
-                        ("updateTime", "updated_at", "timestamp_iso"),
+                        ("name", "name"),
+                        ("fields", "fields"),
+                        ("createTime", "created_at"),
+                        ("updateTime", "updated_at"),
                     ],
                     tgt_cols,
-                );
+                )?;
             } else {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_TABLE_NOT_FOUND,
-                    &format!("'{}' object is not implemented", obj),
-                );
+                return Err(FdwError::not_found(format!(
+                    "'{}' object is not implemented",
+                    obj
+                )));
             }
         }
     }
 
-    result
+    Ok(result)
 }
 
 #[wrappers_fdw(
-    version = "0.1.2",
+    version = "0.1.3",
     author = "Supabase",
     website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/firebase_fdw",
-    error_type = "FirebaseFdwError"
+    error_type = "FirebaseFdwError",
+    options(
+        server(project_id(required), access_token(secret), sa_key(secret)),
+        table(object(required), base_url, limit(type = "usize", default = "10000")),
+        include(crate::http::HTTP_OPTION_SPECS)
+    )
 )]
 pub(crate) struct FirebaseFdw {
     rt: Runtime,
     project_id: String,
+    sa_key: Option<String>,
+    token: Option<AccessToken>,
+    http_opts: HttpOptions,
     client: Option<ClientWithMiddleware>,
-    scan_result: Option<Vec<Row>>,
+    pager: Paginator,
+    obj: String,
+    tgt_cols: Vec<Column>,
+    scan_opts: HashMap<String, String>,
 }
 
 impl FirebaseFdw {
@@ -191,15 +190,30 @@ impl FirebaseFdw {
     // https://firebase.google.com/docs/reference/admin/node/firebase-admin.auth.baseauth.md#baseauthlistusers
     const PAGE_SIZE: usize = 1000;
 
-    // default maximum row count limit
-    const DEFAULT_ROWS_LIMIT: usize = 10_000;
+    // refresh the access token if it is expired, as this instance can be
+    // cached and reused for longer than the token lifetime
+    fn refresh_token(&mut self) -> Result<(), FdwError> {
+        let expired = self.token.as_ref().map(|t| t.is_expired()).unwrap_or(false);
+        if !expired {
+            return Ok(());
+        }
+        if let Some(sa_key) = &self.sa_key {
+            let token = get_oauth2_token(sa_key, &self.rt)?;
+            self.client = token
+                .token()
+                .map(|token| create_client(token, &self.http_opts))
+                .transpose()?;
+            self.token = Some(token);
+        }
+        Ok(())
+    }
 
     fn build_url(
         &self,
         obj: &str,
-        next_page: &Option<String>,
+        next_page: Option<&String>,
         options: &HashMap<String, String>,
-    ) -> String {
+    ) -> Result<String, FdwError> {
         match obj {
             "auth/users" => {
                 // ref: https://firebase.google.com/docs/reference/admin/node/firebase-admin.auth.baseauth.md#baseauthlistusers
@@ -216,7 +230,7 @@ impl FirebaseFdw {
                 if let Some(next_page_token) = next_page {
                     ret.push_str(&format!("&nextPageToken={}", next_page_token));
                 }
-                ret
+                Ok(ret)
             }
             _ => {
                 // match for firestore documents
@@ -239,26 +253,82 @@ impl FirebaseFdw {
                     if let Some(next_page_token) = next_page {
                         ret.push_str(&format!("&pageToken={}", next_page_token));
                     }
-                    return ret;
+                    return Ok(ret);
                 }
 
-                "".to_string()
+                Err(FdwError::not_found(format!(
+                    "'{}' object is not implemented",
+                    obj
+                )))
             }
         }
     }
 }
 
+impl FirebaseFdw {
+    // fetch a page of the scanned object, the next page is requested by the
+    // page token returned in current page
+    fn fetch_page(&mut self, req: PageRequest) -> Result<Page, FdwError> {
+        let client = match &self.client {
+            Some(client) => client,
+            None => return Ok(Page::default()),
+        };
+        let next_page = match req.next {
+            Some(NextPage::Token(token)) => Some(token),
+            _ => None,
+        };
+        let url = self.build_url(&self.obj, next_page, &self.scan_opts)?;
+
+        let started = Instant::now();
+        let resp = block_on_interruptible(&self.rt, client.get(&url).send())?
+            .map_err(|err| request_error(&url, err))?
+            .error_for_status()
+            .map_err(|err| request_error(&url, err))?;
+        stats::record_request(Self::FDW_NAME, started);
+        stats::inc_stats(
+            Self::FDW_NAME,
+            stats::Metric::BytesIn,
+            resp.content_length().unwrap_or(0) as i64,
+        );
+
+        let body = block_on_interruptible(&self.rt, resp.text())?
+            .map_err(|err| request_error(&url, err))?;
+        let json: JsonValue = serde_json::from_str(&body)
+            .map_err(|err| FdwError::type_conversion(format!("invalid response body: {}", err)))?;
+        let rows = resp_to_rows(&self.obj, &json, &self.tgt_cols)?;
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsIn, rows.len() as i64);
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsOut, rows.len() as i64);
+
+        // get next page token, stop fetching if no more pages
+        let next = json
+            .get("nextPageToken")
+            .and_then(|v| v.as_str())
+            .map(|v| NextPage::Token(v.to_owned()));
+        Ok(Page::new(rows, next))
+    }
+}
+
 #[derive(Error, Debug)]
 enum FirebaseFdwError {
     #[error("{0}")]
     CreateRuntimeError(#[from] CreateRuntimeError),
+
+    #[error("{0}")]
+    OptionsError(#[from] OptionsError),
+
+    #[error("{0}")]
+    FdwError(#[from] FdwError),
 }
 
 impl From<FirebaseFdwError> for ErrorReport {
     fn from(value: FirebaseFdwError) -> Self {
-        match value {
-            FirebaseFdwError::CreateRuntimeError(e) => e.into(),
-        }
+        let err = match value {
+            FirebaseFdwError::CreateRuntimeError(e) => FdwError::from(e),
+            FirebaseFdwError::OptionsError(e) => FdwError::from(e),
+            FirebaseFdwError::FdwError(e) => e,
+        };
+        stats::record_error(FirebaseFdw::FDW_NAME, err.kind());
+        err.into()
     }
 }
 
@@ -267,55 +337,37 @@ impl ForeignDataWrapper<FirebaseFdwError> for FirebaseFdw {
         let mut ret = Self {
             rt: create_async_runtime()?,
             project_id: "".to_string(),
+            sa_key: None,
+            token: None,
+            http_opts: HttpOptions::from_options(options)?,
             client: None,
-            scan_result: None,
+            pager: Paginator::default(),
+            obj: String::default(),
+            tgt_cols: Vec::new(),
+            scan_opts: HashMap::new(),
         };
 
-        ret.project_id = match require_option("project_id", options) {
-            Some(project_id) => project_id,
-            None => return Ok(ret),
-        };
+        let opts = FirebaseFdwServerOptions::from_options(options)?;
+        ret.project_id = opts.project_id;
 
         // get oauth2 access token if it is directly defined in options
-        let token = if let Some(access_token) = options.get("access_token") {
-            access_token.to_owned()
+        let token = if let Some(access_token) = opts.access_token {
+            access_token
         } else {
-            // otherwise, get it from the options or Vault
-            let sa_key = match options.get("sa_key") {
-                Some(sa_key) => sa_key.to_owned(),
-                None => {
-                    let sa_key_id = match require_option("sa_key_id", options) {
-                        Some(sa_key_id) => sa_key_id,
-                        None => return Ok(ret),
-                    };
-                    match get_vault_secret(&sa_key_id) {
-                        Some(sa_key) => sa_key,
-                        None => return Ok(ret),
-                    }
-                }
-            };
-            if let Some(access_token) = get_oauth2_token(&sa_key, &ret.rt) {
-                access_token.token().map(|t| t.to_owned()).unwrap()
-            } else {
-                return Ok(ret);
-            }
+            // otherwise, get it from the service account key in options or Vault
+            let sa_key = opts.sa_key.ok_or(OptionsError::MissingOption("sa_key"))?;
+            let access_token = get_oauth2_token(&sa_key, &ret.rt)?;
+            let token = access_token
+                .token()
+                .map(|t| t.to_owned())
+                .ok_or_else(|| FdwError::auth_failed("no access token is returned"))?;
+            ret.sa_key = Some(sa_key);
+            ret.token = Some(access_token);
+            token
         };
 
         // create client
-        let mut headers = header::HeaderMap::new();
-        let value = format!("Bearer {}", token);
-        let mut auth_value = header::HeaderValue::from_str(&value).unwrap();
-        auth_value.set_sensitive(true);
-        headers.insert(header::AUTHORIZATION, auth_value);
-        let client = reqwest::Client::builder()
-            .default_headers(headers)
-            .build()
-            .unwrap();
-        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
-        let client = ClientBuilder::new(client)
-            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-            .build();
-        ret.client = Some(client);
+        ret.client = Some(create_client(&token, &ret.http_opts)?);
 
         stats::inc_stats(Self::FDW_NAME, stats::Metric::CreateTimes, 1);
 
@@ -327,101 +379,38 @@ impl ForeignDataWrapper<FirebaseFdwError> for FirebaseFdw {
         _quals: &[Qual],
         columns: &[Column],
         _sorts: &[Sort],
-        _limit: &Option<Limit>,
+        limit: &Option<Limit>,
         options: &HashMap<String, String>,
     ) -> Result<(), FirebaseFdwError> {
-        let obj = match require_option("object", options) {
-            Some(obj) => obj,
-            None => return Ok(()),
-        };
-        let row_cnt_limit = options
-            .get("limit")
-            .map(|n| n.parse::<usize>().unwrap())
-            .unwrap_or(Self::DEFAULT_ROWS_LIMIT);
-
-        self.scan_result = None;
-
-        if let Some(client) = &self.client {
-            let mut next_page: Option<String> = None;
-            let mut result = Vec::new();
-
-            loop {
-                let url = self.build_url(&obj, &next_page, options);
-
-                match self.rt.block_on(client.get(&url).send()) {
-                    Ok(resp) => match resp.error_for_status() {
-                        Ok(resp) => {
-                            stats::inc_stats(
-                                Self::FDW_NAME,
-                                stats::Metric::BytesIn,
-                                resp.content_length().unwrap_or(0) as i64,
-                            );
-
-                            let body = self.rt.block_on(resp.text()).unwrap();
-                            let json: JsonValue = serde_json::from_str(&body).unwrap();
-                            let mut rows = resp_to_rows(&obj, &json, columns);
-                            result.append(&mut rows);
-                            if result.len() >= row_cnt_limit {
-                                break;
-                            }
-
-                            // get next page token, stop fetching if no more pages
-                            next_page = json
-                                .get("nextPageToken")
-                                .and_then(|v| v.as_str())
-                                .map(|v| v.to_owned());
-                            if next_page.is_none() {
-                                break;
-                            }
-                        }
-                        Err(err) => {
-                            report_request_error!(url, err);
-                            break;
-                        }
-                    },
-                    Err(err) => {
-                        report_request_error!(url, err);
-                        break;
-                    }
-                }
-            }
-
-            stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsIn, result.len() as i64);
-            stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsOut, result.len() as i64);
+        let opts = FirebaseFdwTableOptions::from_options(options)?;
+        self.obj = opts.object;
+        self.tgt_cols = columns.to_vec();
+        self.scan_opts = options.clone();
+        self.refresh_token()?;
 
-            self.scan_result = Some(result);
-        }
+        // pages are fetched lazily in iter_scan, no more than the limit option
+        self.pager = Paginator::new()
+            .with_limit(limit)
+            .with_max_rows(opts.limit as i64);
 
         Ok(())
     }
 
     fn iter_scan(&mut self, row: &mut Row) -> Result<Option<()>, FirebaseFdwError> {
-        if let Some(ref mut result) = self.scan_result {
-            if !result.is_empty() {
-                return Ok(result
-                    .drain(0..1)
-                    .last()
-                    .map(|src_row| row.replace_with(src_row)));
-            }
-        }
-        Ok(None)
+        let mut pager = std::mem::take(&mut self.pager);
+        let result = pager.next_row(row, |req| self.fetch_page(req));
+        self.pager = pager;
+        Ok(result?)
     }
 
-    fn end_scan(&mut self) -> Result<(), FirebaseFdwError> {
-        self.scan_result.take();
+    fn re_scan(&mut self) -> Result<(), FirebaseFdwError> {
+        // fetch the pages again from the first page
+        self.pager.reset();
         Ok(())
     }
 
-    fn validator(
-        options: Vec<Option<String>>,
-        catalog: Option<pg_sys::Oid>,
-    ) -> Result<(), FirebaseFdwError> {
-        if let Some(oid) = catalog {
-            if oid == FOREIGN_TABLE_RELATION_ID {
-                check_options_contain(&options, "object");
-            }
-        }
-
+    fn end_scan(&mut self) -> Result<(), FirebaseFdwError> {
+        self.pager = Paginator::default();
         Ok(())
     }
 }
@@ -61,6 +61,19 @@ mod tests {
 
             assert_eq!(results, vec!["bo@supabase.io", "copple@supabase.io"]);
 
+            // a re-scan fetches the pages again from the first page
+            let results = c
+                .select(
+                    "SELECT v.n, (SELECT count(*) FROM firebase_users WHERE v.n > 0)
+                     FROM (VALUES (1), (2)) v(n) ORDER BY v.n",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get::<i64>(2).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![2, 2]);
+
             c.update(
                 r#"
                 CREATE FOREIGN TABLE firebase_docs (
@@ -97,4 +110,44 @@ mod tests {
                  serde_json::json!({"id": {"integerValue": "1"}, "name": {"stringValue": "hello"}}))]);
         });
     }
+
+    #[pg_test(error = "invalid value \"abc\" for option \"limit\", expected a usize")]
+    fn firebase_invalid_option() {
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER firebase_wrapper
+                         HANDLER firebase_fdw_handler VALIDATOR firebase_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"CREATE SERVER my_firebase_server
+                         FOREIGN DATA WRAPPER firebase_wrapper
+                         OPTIONS (
+                          project_id 'supa',
+                          access_token 'owner'
+                         )"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"
+                  CREATE FOREIGN TABLE firebase_users (
+                    local_id text,
+                    email text
+                  )
+                 SERVER my_firebase_server
+                 OPTIONS (
+                   object 'auth/users',
+                   limit 'abc'
+                )
+             "#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
 }
@@ -0,0 +1,506 @@
+use crate::http::{self, HttpOptions};
+use crate::stats;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::JsonB;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    StatusCode, Url,
+};
+use reqwest_middleware::ClientWithMiddleware;
+use serde_json::{json, Value as JsonValue};
+use std::collections::{BTreeMap, HashMap};
+use std::time::Instant;
+
+use supabase_wrappers::prelude::*;
+use thiserror::Error;
+
+// convert a failed request to error, the error kind is decided by response status
+fn request_error(err: impl Into<reqwest_middleware::Error>) -> FdwError {
+    let err = err.into();
+    let msg = format!("request failed: {}", err);
+    match &err {
+        reqwest_middleware::Error::Reqwest(e) => match e.status() {
+            Some(status) => FdwError::from_http_status(status.as_u16(), msg),
+            None => FdwError::connection_failed(msg),
+        },
+        reqwest_middleware::Error::Middleware(_) => FdwError::remote_query(msg),
+    }
+}
+
+// parse a mapping option like 'author=author.login, state=states'
+fn parse_mapping(name: &'static str, value: &str) -> Result<Vec<(String, String)>, OptionsError> {
+    value
+        .split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(|item| match item.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() && !value.trim().is_empty() => {
+                Ok((key.trim().to_owned(), value.trim().to_owned()))
+            }
+            _ => Err(OptionsError::InvalidValue {
+                name,
+                value: value.to_owned(),
+                ty: "mapping",
+            }),
+        })
+        .collect()
+}
+
+// a GraphQL selection set, the fields are kept in the order of target columns
+#[derive(Debug, Default)]
+struct Selection(Vec<(String, Selection)>);
+
+impl Selection {
+    fn add(&mut self, fields: &[&str]) {
+        if let Some((first, rest)) = fields.split_first() {
+            let idx = match self.0.iter().position(|(name, _)| name == first) {
+                Some(idx) => idx,
+                None => {
+                    self.0.push((first.to_string(), Selection::default()));
+                    self.0.len() - 1
+                }
+            };
+            self.0[idx].1.add(rest);
+        }
+    }
+
+    fn deparse(&self) -> String {
+        if self.0.is_empty() {
+            // selection set cannot be empty, e.g. for `count(*)`
+            return "__typename".to_string();
+        }
+        self.0
+            .iter()
+            .map(|(name, sub)| {
+                if sub.0.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{} {{ {} }}", name, sub.deparse())
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+#[wrappers_fdw(
+    version = "0.1.0",
+    author = "Supabase",
+    website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/graphql_fdw",
+    error_type = "GraphqlFdwError",
+    options(
+        server(
+            api_url(required),
+            api_key(secret),
+            auth_header(default = "Authorization"),
+            auth_prefix(default = "Bearer"),
+            page_size(type = "i64", default = "100"),
+        ),
+        table(
+            query(required),
+            connection_path(required),
+            column_fields,
+            variables,
+            cursor_variable(default = "after"),
+            page_size_variable(default = "first")
+        ),
+        include(crate::http::HTTP_OPTION_SPECS)
+    )
+)]
+pub(crate) struct GraphqlFdw {
+    rt: Runtime,
+    api_url: Url,
+    client: Option<ClientWithMiddleware>,
+    page_size: i64,
+    query: Option<String>,
+    connection_path: JsonPath,
+    // JSON path of each target column in a node, `None` for the whole node
+    column_paths: Vec<(Column, Option<JsonPath>)>,
+    // pushed down quals as (column, variable, value)
+    variables: Vec<(String, String, JsonValue)>,
+    cursor_variable: String,
+    page_size_variable: String,
+    pager: Paginator,
+    metrics: RemoteMetrics,
+}
+
+impl GraphqlFdw {
+    const FDW_NAME: &str = "GraphqlFdw";
+    const FIELDS_PLACEHOLDER: &str = "{{fields}}";
+
+    // build the query from template, the selection set only has the fields of
+    // target columns
+    fn build_query(
+        template: &str,
+        columns: &[Column],
+        column_fields: &HashMap<String, String>,
+    ) -> Result<(String, Vec<(Column, Option<JsonPath>)>), OptionsError> {
+        if !template.contains(Self::FIELDS_PLACEHOLDER) {
+            return Err(OptionsError::InvalidValue {
+                name: "query",
+                value: template.to_owned(),
+                ty: "query template with {{fields}}",
+            });
+        }
+
+        let mut selection = Selection::default();
+        let mut column_paths = Vec::new();
+        for col in columns {
+            let field = match column_fields.get(&col.name) {
+                Some(field) => field.as_str(),
+                // attrs column is the whole node
+                None if col.name == "attrs" => {
+                    column_paths.push((col.clone(), None));
+                    continue;
+                }
+                None => col.name.as_str(),
+            };
+            let fields: Vec<&str> = field.split('.').map(|f| f.trim()).collect();
+            if fields
+                .iter()
+                .any(|f| f.is_empty() || !f.chars().all(|c| c.is_alphanumeric() || c == '_'))
+            {
+                return Err(OptionsError::InvalidValue {
+                    name: "column_fields",
+                    value: field.to_owned(),
+                    ty: "field path",
+                });
+            }
+            selection.add(&fields);
+            let path = fields
+                .join(".")
+                .parse()
+                .map_err(|_| OptionsError::InvalidValue {
+                    name: "column_fields",
+                    value: field.to_owned(),
+                    ty: "field path",
+                })?;
+            column_paths.push((col.clone(), Some(path)));
+        }
+
+        let query = template.replace(Self::FIELDS_PLACEHOLDER, &selection.deparse());
+        Ok((query, column_paths))
+    }
+
+    // fetch a page of nodes, the next page is requested by the end cursor of
+    // current page
+    fn fetch_page(&mut self, req: PageRequest) -> Result<Page, FdwError> {
+        let (client, query) = match (&self.client, &self.query) {
+            (Some(client), Some(query)) => (client, query),
+            _ => return Ok(Page::default()),
+        };
+
+        // variables are sorted by name so the request body is stable
+        let mut variables = BTreeMap::new();
+        for (_, var, value) in &self.variables {
+            variables.insert(var.clone(), value.clone());
+        }
+        variables.insert(
+            self.page_size_variable.clone(),
+            JsonValue::from(req.page_size.unwrap_or(self.page_size)),
+        );
+        if let Some(NextPage::Cursor(cursor)) = req.next {
+            variables.insert(
+                self.cursor_variable.clone(),
+                JsonValue::from(cursor.clone()),
+            );
+        }
+        let body = json!({
+            "query": query,
+            "variables": JsonValue::Object(variables.into_iter().collect()),
+        });
+
+        // make api call
+        let started = Instant::now();
+        let resp = block_on_interruptible(
+            &self.rt,
+            client.post(self.api_url.clone()).json(&body).send(),
+        )?
+        .map_err(request_error)?;
+        stats::record_request(Self::FDW_NAME, started);
+        stats::inc_stats(
+            Self::FDW_NAME,
+            stats::Metric::BytesIn,
+            resp.content_length().unwrap_or(0) as i64,
+        );
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            // if it is 404 error, we should treat it as an empty
+            // result rather than a request error
+            self.metrics.record_request(started, 0);
+            return Ok(Page::default());
+        }
+
+        let resp = resp.error_for_status().map_err(request_error)?;
+        let body = block_on_interruptible(&self.rt, resp.text())?.map_err(request_error)?;
+        self.metrics.record_request(started, body.len());
+        self.metrics.record_page();
+
+        let body: JsonValue = serde_json::from_str(&body).map_err(JsonError::from)?;
+        if let Some(errors) = body.get("errors").and_then(|v| v.as_array()) {
+            if !errors.is_empty() {
+                let msgs = errors
+                    .iter()
+                    .map(|e| match e.get("message").and_then(|m| m.as_str()) {
+                        Some(msg) => msg.to_owned(),
+                        None => e.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                return Err(FdwError::remote_query(format!(
+                    "GraphQL error: {}",
+                    msgs.join("; ")
+                )));
+            }
+        }
+        let connection = match body
+            .get("data")
+            .and_then(|data| self.connection_path.get(data))
+        {
+            Some(connection) => connection,
+            None => return Ok(Page::default()),
+        };
+
+        // nodes are either in `nodes` or `edges`, a plain list is also accepted
+        let nodes: Vec<&JsonValue> =
+            if let Some(nodes) = connection.get("nodes").and_then(|v| v.as_array()) {
+                nodes.iter().collect()
+            } else if let Some(edges) = connection.get("edges").and_then(|v| v.as_array()) {
+                edges.iter().filter_map(|edge| edge.get("node")).collect()
+            } else if let Some(nodes) = connection.as_array() {
+                nodes.iter().collect()
+            } else {
+                Vec::new()
+            };
+        let rows = nodes
+            .into_iter()
+            .filter(|node| !node.is_null())
+            .map(|node| self.node_to_row(node))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // save stats
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsIn, rows.len() as i64);
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsOut, rows.len() as i64);
+
+        let page_info = connection.get("pageInfo");
+        let has_next = page_info
+            .and_then(|info| info.get("hasNextPage"))
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let next = page_info
+            .and_then(|info| info.get("endCursor"))
+            .and_then(|v| v.as_str())
+            .filter(|_| has_next)
+            .map(|cursor| NextPage::Cursor(cursor.to_owned()));
+        Ok(Page::new(rows, next))
+    }
+
+    fn node_to_row(&self, node: &JsonValue) -> Result<Row, FdwError> {
+        let mut row = Row::new();
+        for (tgt_col, path) in &self.column_paths {
+            let cell = match path {
+                Some(path) => path
+                    .get(node)
+                    .map(|v| Cell::from_json(v, tgt_col))
+                    .transpose()?
+                    .flatten(),
+                None => Some(Cell::Json(JsonB(node.clone()))),
+            };
+
+            // fill the column with pushed down qual value if the node
+            // doesn't have it, so the row can pass local qual check
+            let cell = match cell {
+                Some(cell) => Some(cell),
+                None => self
+                    .variables
+                    .iter()
+                    .find(|(col, _, value)| col == &tgt_col.name && !value.is_array())
+                    .map(|(_, _, value)| Cell::from_json(value, tgt_col))
+                    .transpose()?
+                    .flatten(),
+            };
+            row.push(&tgt_col.name, cell);
+        }
+        Ok(row)
+    }
+}
+
+#[derive(Error, Debug)]
+enum GraphqlFdwError {
+    #[error("{0}")]
+    CreateRuntimeError(#[from] CreateRuntimeError),
+
+    #[error("{0}")]
+    OptionsError(#[from] OptionsError),
+
+    #[error("{0}")]
+    FdwError(#[from] FdwError),
+}
+
+impl From<GraphqlFdwError> for ErrorReport {
+    fn from(value: GraphqlFdwError) -> Self {
+        let err = match value {
+            GraphqlFdwError::CreateRuntimeError(e) => FdwError::from(e),
+            GraphqlFdwError::OptionsError(e) => FdwError::from(e),
+            GraphqlFdwError::FdwError(e) => e,
+        };
+        stats::record_error(GraphqlFdw::FDW_NAME, err.kind());
+        err.into()
+    }
+}
+
+impl ForeignDataWrapper<GraphqlFdwError> for GraphqlFdw {
+    fn new(options: &HashMap<String, String>) -> Result<Self, GraphqlFdwError> {
+        let opts = GraphqlFdwServerOptions::from_options(options)?;
+        let api_url = Url::parse(&opts.api_url).map_err(|_| OptionsError::InvalidValue {
+            name: "api_url",
+            value: opts.api_url.clone(),
+            ty: "URL",
+        })?;
+        if opts.page_size <= 0 {
+            return Err(OptionsError::InvalidValue {
+                name: "page_size",
+                value: opts.page_size.to_string(),
+                ty: "positive integer",
+            }
+            .into());
+        }
+        let http_opts = HttpOptions::from_options(options)?;
+
+        let mut headers = HeaderMap::new();
+        if let Some(api_key) = &opts.api_key {
+            let name = HeaderName::from_bytes(opts.auth_header.as_bytes()).map_err(|_| {
+                OptionsError::InvalidValue {
+                    name: "auth_header",
+                    value: opts.auth_header.clone(),
+                    ty: "header name",
+                }
+            })?;
+            let value = if opts.auth_prefix.is_empty() {
+                api_key.clone()
+            } else {
+                format!("{} {}", opts.auth_prefix, api_key)
+            };
+            let mut value =
+                HeaderValue::from_str(&value).map_err(|_| OptionsError::InvalidValue {
+                    name: "api_key",
+                    value: "(not shown)".to_owned(),
+                    ty: "header value",
+                })?;
+            value.set_sensitive(true);
+            headers.insert(name, value);
+        }
+
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::CreateTimes, 1);
+
+        Ok(GraphqlFdw {
+            rt: create_async_runtime()?,
+            api_url,
+            client: Some(http::create_client(headers, &http_opts)?),
+            page_size: opts.page_size,
+            query: None,
+            connection_path: JsonPath::default(),
+            column_paths: Vec::new(),
+            variables: Vec::new(),
+            cursor_variable: String::default(),
+            page_size_variable: String::default(),
+            pager: Paginator::default(),
+            metrics: RemoteMetrics::default(),
+        })
+    }
+
+    fn begin_scan(
+        &mut self,
+        quals: &[Qual],
+        columns: &[Column],
+        _sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+    ) -> Result<(), GraphqlFdwError> {
+        let opts = GraphqlFdwTableOptions::from_options(options)?;
+        self.connection_path =
+            opts.connection_path
+                .parse()
+                .map_err(|_| OptionsError::InvalidValue {
+                    name: "connection_path",
+                    value: opts.connection_path.clone(),
+                    ty: "JSON path",
+                })?;
+        let column_fields = match &opts.column_fields {
+            Some(mapping) => parse_mapping("column_fields", mapping)?
+                .into_iter()
+                .collect(),
+            None => HashMap::new(),
+        };
+        let (query, column_paths) = Self::build_query(&opts.query, columns, &column_fields)?;
+        self.query = Some(query);
+        self.column_paths = column_paths;
+        self.cursor_variable = opts.cursor_variable;
+        self.page_size_variable = opts.page_size_variable;
+
+        // push down the '=' and 'in' quals on mapped columns as variables
+        let variables = match &opts.variables {
+            Some(mapping) => parse_mapping("variables", mapping)?,
+            None => Vec::new(),
+        };
+        self.variables = quals
+            .iter()
+            .filter(|qual| qual.operator == "=")
+            .filter_map(|qual| {
+                let (_, var) = variables.iter().find(|(col, _)| col == &qual.field)?;
+                let value = match &qual.value {
+                    Value::Cell(cell) if !qual.use_or => cell.to_json(),
+                    Value::Array(cells) if qual.use_or => {
+                        JsonValue::Array(cells.iter().map(|cell| cell.to_json()).collect())
+                    }
+                    _ => return None,
+                };
+                Some((qual.field.clone(), var.clone(), value))
+            })
+            .collect();
+        self.metrics = RemoteMetrics::default();
+
+        // pages are fetched lazily in iter_scan until the limit is reached
+        self.pager = Paginator::new()
+            .with_page_size(self.page_size)
+            .with_limit(limit);
+
+        Ok(())
+    }
+
+    fn explain(&self, verbose: bool) -> Vec<(String, String)> {
+        let mut ret = Vec::new();
+        if let Some(query) = &self.query {
+            ret.push(("Remote Query".to_string(), query.clone()));
+        }
+        if verbose && !self.variables.is_empty() {
+            let vars = self
+                .variables
+                .iter()
+                .map(|(_, var, value)| format!("{}: {}", var, value))
+                .collect::<Vec<_>>();
+            ret.push(("Remote Variables".to_string(), vars.join(", ")));
+        }
+        ret
+    }
+
+    fn remote_metrics(&self) -> Option<RemoteMetrics> {
+        Some(self.metrics.clone())
+    }
+
+    fn iter_scan(&mut self, row: &mut Row) -> Result<Option<()>, GraphqlFdwError> {
+        let mut pager = std::mem::take(&mut self.pager);
+        let result = pager.next_row(row, |req| self.fetch_page(req));
+        self.pager = pager;
+        Ok(result?)
+    }
+
+    fn re_scan(&mut self) -> Result<(), GraphqlFdwError> {
+        // fetch the pages again from the first page
+        self.pager.reset();
+        Ok(())
+    }
+
+    fn end_scan(&mut self) -> Result<(), GraphqlFdwError> {
+        self.pager = Paginator::default();
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    fn graphql_smoketest() {
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER graphql_wrapper
+                         HANDLER graphql_fdw_handler VALIDATOR graphql_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"CREATE SERVER my_graphql_server
+                         FOREIGN DATA WRAPPER graphql_wrapper
+                         OPTIONS (
+                           api_url 'http://localhost:8088/graphql',
+                           api_key 'test_token',
+                           page_size '2'
+                         )"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"
+                CREATE FOREIGN TABLE users (
+                  id bigint,
+                  name text,
+                  status text,
+                  city text,
+                  created_at timestamp,
+                  attrs jsonb
+                )
+                SERVER my_graphql_server
+                OPTIONS (
+                    query 'query($first: Int, $after: String, $status: [String!]) {
+                      users(first: $first, after: $after, status: $status) {
+                        nodes { {{fields}} }
+                        pageInfo { hasNextPage endCursor }
+                      }
+                    }',
+                    connection_path 'users',
+                    column_fields 'city=address.city, created_at=createdAt',
+                    variables 'status=status'
+                  )
+             "#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            // all pages are fetched
+            let results = c
+                .select("SELECT * FROM users ORDER BY id", None, None)
+                .unwrap()
+                .filter_map(|r| {
+                    r.get_by_name::<i64, _>("id")
+                        .unwrap()
+                        .zip(r.get_by_name::<&str, _>("city").unwrap())
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                results,
+                vec![
+                    (1, "Auckland"),
+                    (2, "Berlin"),
+                    (3, "Chicago"),
+                    (4, "Dublin"),
+                    (5, "Edinburgh")
+                ]
+            );
+
+            // quals are pushed down as variables
+            let results = c
+                .select(
+                    "SELECT name FROM users WHERE status = 'active' ORDER BY id",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("name").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec!["Alice", "Carol", "Dave"]);
+
+            let results = c
+                .select(
+                    "SELECT name FROM users WHERE status IN ('active', 'inactive') ORDER BY id",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("name").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec!["Alice", "Bob", "Carol", "Dave", "Eve"]);
+
+            // only the target columns are selected
+            let results = c
+                .select("SELECT attrs FROM users WHERE id = 1", None, None)
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<pgrx::JsonB, _>("attrs").unwrap())
+                .map(|j| j.0.to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![r#"{"id":1}"#]);
+
+            // pagination stops at the limit
+            let results = c
+                .select("SELECT name FROM users LIMIT 3", None, None)
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("name").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec!["Alice", "Bob", "Carol"]);
+        });
+    }
+}
@@ -1,47 +1,43 @@
+use crate::http::{self, HttpOptions};
 use crate::stats;
 use pgrx::pg_sys::panic::ErrorReport;
-use pgrx::{
-    pg_sys,
-    prelude::{AnyNumeric, Date, PgSqlErrorCode, Timestamp},
-};
 use reqwest::{
-    self,
     header::{HeaderMap, HeaderName, HeaderValue},
     StatusCode, Url,
 };
-use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use reqwest_middleware::ClientWithMiddleware;
 use serde_json::value::Value as JsonValue;
 use std::collections::HashMap;
-use std::str::FromStr;
+use std::time::Instant;
 
 use supabase_wrappers::prelude::*;
 use thiserror::Error;
 
-fn create_client(api_key: &str) -> ClientWithMiddleware {
+fn create_client(api_key: &str, opts: &HttpOptions) -> Result<ClientWithMiddleware, FdwError> {
     let mut headers = HeaderMap::new();
     let header_name = HeaderName::from_static("x-api-key");
-    let mut auth_value = HeaderValue::from_str(api_key).unwrap();
+    let mut auth_value =
+        HeaderValue::from_str(api_key).map_err(|_| OptionsError::InvalidValue {
+            name: "api_key",
+            value: "(not shown)".to_owned(),
+            ty: "header value",
+        })?;
     auth_value.set_sensitive(true);
     headers.insert(header_name, auth_value);
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        .build()
-        .unwrap();
-    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
-    ClientBuilder::new(client)
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build()
+    http::create_client(headers, opts)
 }
 
-macro_rules! report_request_error {
-    ($err:ident) => {{
-        report_error(
-            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-            &format!("request failed: {}", $err),
-        );
-        return Ok(());
-    }};
+// convert a failed request to error, the error kind is decided by response status
+fn request_error(err: impl Into<reqwest_middleware::Error>) -> FdwError {
+    let err = err.into();
+    let msg = format!("request failed: {}", err);
+    match &err {
+        reqwest_middleware::Error::Reqwest(e) => match e.status() {
+            Some(status) => FdwError::from_http_status(status.as_u16(), msg),
+            None => FdwError::connection_failed(msg),
+        },
+        reqwest_middleware::Error::Middleware(_) => FdwError::remote_query(msg),
+    }
 }
 
 fn extract_params(quals: &[Qual]) -> Option<Vec<Qual>> {
@@ -58,82 +54,23 @@ fn extract_params(quals: &[Qual]) -> Option<Vec<Qual>> {
     Some(params)
 }
 
-macro_rules! type_mismatch {
-    ($col:ident) => {
-        panic!("column '{}' data type not match", $col.name)
-    };
-}
-
-fn json_value_to_cell(tgt_col: &Column, v: &JsonValue) -> Cell {
-    match tgt_col.type_oid {
-        pg_sys::BOOLOID => Cell::Bool(v.as_bool().unwrap_or_else(|| type_mismatch!(tgt_col))),
-        pg_sys::CHAROID => Cell::I8(
-            v.as_i64()
-                .map(|s| i8::try_from(s).unwrap_or_else(|_| type_mismatch!(tgt_col)))
-                .unwrap_or_else(|| type_mismatch!(tgt_col)),
-        ),
-        pg_sys::INT2OID => Cell::I16(
-            v.as_i64()
-                .map(|s| i16::try_from(s).unwrap_or_else(|_| type_mismatch!(tgt_col)))
-                .unwrap_or_else(|| type_mismatch!(tgt_col)),
-        ),
-        pg_sys::FLOAT4OID => Cell::F32(
-            v.as_f64()
-                .map(|s| s as f32)
-                .unwrap_or_else(|| type_mismatch!(tgt_col)),
-        ),
-        pg_sys::INT4OID => Cell::I32(
-            v.as_i64()
-                .map(|s| i32::try_from(s).unwrap_or_else(|_| type_mismatch!(tgt_col)))
-                .unwrap_or_else(|| type_mismatch!(tgt_col)),
-        ),
-        pg_sys::FLOAT8OID => Cell::F64(v.as_f64().unwrap_or_else(|| type_mismatch!(tgt_col))),
-        pg_sys::INT8OID => Cell::I64(v.as_i64().unwrap_or_else(|| type_mismatch!(tgt_col))),
-        pg_sys::NUMERICOID => Cell::Numeric(
-            v.as_f64()
-                .map(|s| AnyNumeric::try_from(s).unwrap_or_else(|_| type_mismatch!(tgt_col)))
-                .unwrap_or_else(|| type_mismatch!(tgt_col)),
-        ),
-        pg_sys::TEXTOID => Cell::String(
-            v.as_str()
-                .map(|s| s.to_owned())
-                .unwrap_or_else(|| type_mismatch!(tgt_col)),
-        ),
-        pg_sys::DATEOID => Cell::Date(
-            v.as_str()
-                .map(|s| Date::from_str(s).unwrap_or_else(|_| type_mismatch!(tgt_col)))
-                .unwrap_or_else(|| type_mismatch!(tgt_col)),
-        ),
-        pg_sys::TIMESTAMPOID => Cell::Timestamp(
-            v.as_str()
-                .map(|s| Timestamp::from_str(s).unwrap_or_else(|_| type_mismatch!(tgt_col)))
-                .unwrap_or_else(|| type_mismatch!(tgt_col)),
-        ),
-        _ => {
-            // report error and return a dummy cell
-            report_error(
-                PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
-                &format!(
-                    "column '{}' data type oid '{}' is not supported",
-                    tgt_col.name, tgt_col.type_oid
-                ),
-            );
-            Cell::Bool(false)
-        }
-    }
-}
-
 #[wrappers_fdw(
-    version = "0.1.0",
+    version = "0.1.1",
     author = "Supabase",
     website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/logflare_fdw",
-    error_type = "LogflareFdwError"
+    error_type = "LogflareFdwError",
+    options(
+        server(api_url, api_key(secret)),
+        table(endpoint(required)),
+        include(crate::http::HTTP_OPTION_SPECS)
+    )
 )]
 pub(crate) struct LogflareFdw {
     rt: Runtime,
     base_url: Url,
     client: Option<ClientWithMiddleware>,
     scan_result: Option<Vec<Row>>,
+    scan_idx: usize,
     params: Vec<Qual>,
 }
 
@@ -141,18 +78,22 @@ impl LogflareFdw {
     const FDW_NAME: &str = "LogflareFdw";
     const BASE_URL: &str = "https://api.logflare.app/api/endpoints/query/";
 
-    fn build_url(&self, endpoint: &str) -> Option<Url> {
-        let mut url = self.base_url.join(endpoint).unwrap();
+    fn build_url(&self, endpoint: &str) -> Result<Url, FdwError> {
+        let mut url = self.base_url.join(endpoint).map_err(|err| {
+            FdwError::type_conversion(format!(
+                "cannot build request URL from '{}': {}",
+                endpoint, err
+            ))
+        })?;
         for param in &self.params {
             // extract actual param name, e.g. "_param_foo" => "foo"
             let param_name = &param.field[7..];
 
             if param.operator != "=" {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                    &format!("Parameter '{}' only supports '=' operator", param_name),
-                );
-                return None;
+                return Err(FdwError::remote_query(format!(
+                    "Parameter '{}' only supports '=' operator",
+                    param_name
+                )));
             }
             match &param.value {
                 Value::Cell(cell) => {
@@ -165,54 +106,64 @@ impl LogflareFdw {
                     url.query_pairs_mut().append_pair(param_name, &value);
                 }
                 Value::Array(_) => {
-                    report_error(
-                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                        &format!("Parameter '{}' doesn't supports array value", param_name),
-                    );
-                    return None;
+                    return Err(FdwError::remote_query(format!(
+                        "Parameter '{}' doesn't supports array value",
+                        param_name
+                    )));
                 }
             }
         }
-        Some(url)
+        Ok(url)
     }
 
-    fn resp_to_rows(&mut self, body: &JsonValue, tgt_cols: &[Column]) -> Option<Vec<Row>> {
-        body.as_object()
+    fn resp_to_rows(
+        &mut self,
+        body: &JsonValue,
+        tgt_cols: &[Column],
+    ) -> Result<Option<Vec<Row>>, FdwError> {
+        let records = match body
+            .as_object()
             .and_then(|v| v.get("result"))
             .and_then(|v| v.as_array())
-            .map(|arr| {
-                arr.iter()
-                    .map(|record| {
-                        let mut row = Row::new();
-                        if let Some(r) = record.as_object() {
-                            for tgt_col in tgt_cols {
-                                let cell = if tgt_col.name == "_result" {
-                                    // add _result meta cell
-                                    Some(Cell::String(record.to_string()))
-                                } else if tgt_col.name.starts_with("_param_") {
-                                    // add param cell
-                                    self.params.iter().find_map(|p| {
-                                        if p.field == tgt_col.name {
-                                            if let Value::Cell(cell) = &p.value {
-                                                Some(cell.clone())
-                                            } else {
-                                                None
-                                            }
-                                        } else {
-                                            None
-                                        }
-                                    })
+        {
+            Some(records) => records,
+            None => return Ok(None),
+        };
+
+        let mut rows = Vec::new();
+        for record in records {
+            let mut row = Row::new();
+            if let Some(r) = record.as_object() {
+                for tgt_col in tgt_cols {
+                    let cell = if tgt_col.name == "_result" {
+                        // add _result meta cell
+                        Some(Cell::String(record.to_string()))
+                    } else if tgt_col.name.starts_with("_param_") {
+                        // add param cell
+                        self.params.iter().find_map(|p| {
+                            if p.field == tgt_col.name {
+                                if let Value::Cell(cell) = &p.value {
+                                    Some(cell.clone())
                                 } else {
-                                    // add normal cell
-                                    r.get(&tgt_col.name).map(|v| json_value_to_cell(tgt_col, v))
-                                };
-                                row.push(&tgt_col.name, cell);
+                                    None
+                                }
+                            } else {
+                                None
                             }
-                        }
-                        row
-                    })
-                    .collect::<Vec<Row>>()
-            })
+                        })
+                    } else {
+                        // add normal cell
+                        r.get(&tgt_col.name)
+                            .map(|v| Cell::from_json(v, tgt_col))
+                            .transpose()?
+                            .flatten()
+                    };
+                    row.push(&tgt_col.name, cell);
+                }
+            }
+            rows.push(row);
+        }
+        Ok(Some(rows))
     }
 }
 
@@ -220,21 +171,31 @@ impl LogflareFdw {
 enum LogflareFdwError {
     #[error("{0}")]
     CreateRuntimeError(#[from] CreateRuntimeError),
+
+    #[error("{0}")]
+    OptionsError(#[from] OptionsError),
+
+    #[error("{0}")]
+    FdwError(#[from] FdwError),
 }
 
 impl From<LogflareFdwError> for ErrorReport {
     fn from(value: LogflareFdwError) -> Self {
-        match value {
-            LogflareFdwError::CreateRuntimeError(e) => e.into(),
-        }
+        let err = match value {
+            LogflareFdwError::CreateRuntimeError(e) => FdwError::from(e),
+            LogflareFdwError::OptionsError(e) => FdwError::from(e),
+            LogflareFdwError::FdwError(e) => e,
+        };
+        stats::record_error(LogflareFdw::FDW_NAME, err.kind());
+        err.into()
     }
 }
 
 impl ForeignDataWrapper<LogflareFdwError> for LogflareFdw {
     fn new(options: &HashMap<String, String>) -> Result<Self, LogflareFdwError> {
-        let base_url = options
-            .get("api_url")
-            .map(|t| t.to_owned())
+        let opts = LogflareFdwServerOptions::from_options(options)?;
+        let base_url = opts
+            .api_url
             .map(|s| {
                 if s.ends_with('/') {
                     s
@@ -243,20 +204,22 @@ impl ForeignDataWrapper<LogflareFdwError> for LogflareFdw {
                 }
             })
             .unwrap_or_else(|| LogflareFdw::BASE_URL.to_string());
-        let client = match options.get("api_key") {
-            Some(api_key) => Some(create_client(api_key)),
-            None => require_option("api_key_id", options)
-                .and_then(|key_id| get_vault_secret(&key_id))
-                .map(|api_key| create_client(&api_key)),
-        };
+        let base_url = Url::parse(&base_url).map_err(|_| OptionsError::InvalidValue {
+            name: "api_url",
+            value: base_url.clone(),
+            ty: "URL",
+        })?;
+        let api_key = opts.api_key.ok_or(OptionsError::MissingOption("api_key"))?;
+        let http_opts = HttpOptions::from_options(options)?;
 
         stats::inc_stats(Self::FDW_NAME, stats::Metric::CreateTimes, 1);
 
         Ok(LogflareFdw {
             rt: create_async_runtime()?,
-            base_url: Url::parse(&base_url).unwrap(),
-            client,
+            base_url,
+            client: Some(create_client(&api_key, &http_opts)?),
             scan_result: None,
+            scan_idx: 0,
             params: Vec::default(),
         })
     }
@@ -269,11 +232,9 @@ impl ForeignDataWrapper<LogflareFdwError> for LogflareFdw {
         _limit: &Option<Limit>,
         options: &HashMap<String, String>,
     ) -> Result<(), LogflareFdwError> {
-        let endpoint = if let Some(name) = require_option("endpoint", options) {
-            name
-        } else {
-            return Ok(());
-        };
+        let endpoint = LogflareFdwTableOptions::from_options(options)?.endpoint;
+        self.scan_result = None;
+        self.scan_idx = 0;
 
         // extract params
         self.params = if let Some(params) = extract_params(quals) {
@@ -284,82 +245,57 @@ impl ForeignDataWrapper<LogflareFdwError> for LogflareFdw {
 
         if let Some(client) = &self.client {
             // build url
-            let url = self.build_url(&endpoint);
-            if url.is_none() {
-                return Ok(());
-            }
-            let url = url.unwrap();
+            let url = self.build_url(&endpoint)?;
 
             // make api call
-            match self.rt.block_on(client.get(url).send()) {
-                Ok(resp) => {
-                    stats::inc_stats(
-                        Self::FDW_NAME,
-                        stats::Metric::BytesIn,
-                        resp.content_length().unwrap_or(0) as i64,
-                    );
+            let started = Instant::now();
+            let resp =
+                block_on_interruptible(&self.rt, client.get(url).send())?.map_err(request_error)?;
+            stats::record_request(Self::FDW_NAME, started);
+            stats::inc_stats(
+                Self::FDW_NAME,
+                stats::Metric::BytesIn,
+                resp.content_length().unwrap_or(0) as i64,
+            );
 
-                    if resp.status() == StatusCode::NOT_FOUND {
-                        // if it is 404 error, we should treat it as an empty
-                        // result rather than a request error
-                        return Ok(());
-                    }
+            if resp.status() == StatusCode::NOT_FOUND {
+                // if it is 404 error, we should treat it as an empty
+                // result rather than a request error
+                return Ok(());
+            }
 
-                    match resp.error_for_status() {
-                        Ok(resp) => {
-                            let body: JsonValue = self.rt.block_on(resp.json()).unwrap();
-                            let result = self.resp_to_rows(&body, columns);
-                            if let Some(result) = &result {
-                                stats::inc_stats(
-                                    Self::FDW_NAME,
-                                    stats::Metric::RowsIn,
-                                    result.len() as i64,
-                                );
-                                stats::inc_stats(
-                                    Self::FDW_NAME,
-                                    stats::Metric::RowsOut,
-                                    result.len() as i64,
-                                );
-                            }
-                            self.scan_result = result;
-                        }
-                        Err(err) => report_request_error!(err),
-                    }
-                }
-                Err(err) => report_request_error!(err),
+            let resp = resp.error_for_status().map_err(request_error)?;
+            let body: JsonValue =
+                block_on_interruptible(&self.rt, resp.json())?.map_err(request_error)?;
+            let result = self.resp_to_rows(&body, columns)?;
+            if let Some(result) = &result {
+                stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsIn, result.len() as i64);
+                stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsOut, result.len() as i64);
             }
+            self.scan_result = result;
         }
 
         Ok(())
     }
 
     fn iter_scan(&mut self, row: &mut Row) -> Result<Option<()>, LogflareFdwError> {
-        if let Some(ref mut result) = self.scan_result {
-            if !result.is_empty() {
-                return Ok(result
-                    .drain(0..1)
-                    .last()
-                    .map(|src_row| row.replace_with(src_row)));
+        if let Some(ref result) = self.scan_result {
+            if let Some(src_row) = result.get(self.scan_idx) {
+                self.scan_idx += 1;
+                return Ok(Some(row.replace_with(src_row.clone())));
             }
         }
         Ok(None)
     }
 
-    fn end_scan(&mut self) -> Result<(), LogflareFdwError> {
-        self.scan_result.take();
+    fn re_scan(&mut self) -> Result<(), LogflareFdwError> {
+        // the result is kept until end of scan, so just rewind it
+        self.scan_idx = 0;
         Ok(())
     }
 
-    fn validator(
-        options: Vec<Option<String>>,
-        catalog: Option<pg_sys::Oid>,
-    ) -> Result<(), LogflareFdwError> {
-        if let Some(oid) = catalog {
-            if oid == FOREIGN_TABLE_RELATION_ID {
-                check_options_contain(&options, "endpoint");
-            }
-        }
-
+    fn end_scan(&mut self) -> Result<(), LogflareFdwError> {
+        self.scan_result.take();
         Ok(())
     }
 }
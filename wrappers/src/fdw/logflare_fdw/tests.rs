@@ -0,0 +1,76 @@
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    fn logflare_rescan() {
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER logflare_wrapper
+                         HANDLER logflare_fdw_handler VALIDATOR logflare_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            // responses are replayed from the cassette, the API URL is not accessed
+            let cassette = concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/fdw/logflare_fdw/cassettes/logs.json"
+            );
+            c.update(
+                &format!(
+                    r#"CREATE SERVER my_logflare_server
+                         FOREIGN DATA WRAPPER logflare_wrapper
+                         OPTIONS (
+                           api_url 'http://logflare.invalid/api/endpoints/query',
+                           api_key 'test_key',
+                           http_cassette '{}',
+                           http_cassette_mode 'replay'
+                         )"#,
+                    cassette
+                ),
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"
+                CREATE FOREIGN TABLE logflare_logs (
+                  id bigint,
+                  event_message text
+                )
+                SERVER my_logflare_server
+                OPTIONS (
+                    endpoint 'logs'
+                  )
+             "#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            let results = c
+                .select("SELECT * FROM logflare_logs ORDER BY id", None, None)
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("event_message").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec!["foo", "bar", "baz"]);
+
+            // the result is kept for the whole scan, a re-scan rewinds it
+            // and returns all the rows again
+            let results = c
+                .select(
+                    "SELECT v.n, (SELECT count(*) FROM logflare_logs WHERE v.n > 0) AS cnt
+                     FROM (VALUES (1), (2)) v(n) ORDER BY v.n",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<i64, _>("cnt").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![3, 3]);
+        });
+    }
+}
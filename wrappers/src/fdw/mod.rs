@@ -21,3 +21,12 @@ mod s3_fdw;
 
 #[cfg(feature = "logflare_fdw")]
 mod logflare_fdw;
+
+#[cfg(feature = "rest_fdw")]
+mod rest_fdw;
+
+#[cfg(feature = "graphql_fdw")]
+mod graphql_fdw;
+
+#[cfg(feature = "wasm_fdw")]
+mod wasm_fdw;
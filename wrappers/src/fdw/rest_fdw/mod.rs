@@ -0,0 +1,3 @@
+#![allow(clippy::module_inception)]
+mod rest_fdw;
+mod tests;
@@ -0,0 +1,613 @@
+use crate::http::{self, HttpOptions};
+use crate::stats;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::JsonB;
+use reqwest::{
+    header::{self, HeaderMap, HeaderName, HeaderValue},
+    StatusCode, Url,
+};
+use reqwest_middleware::ClientWithMiddleware;
+use serde_json::Value as JsonValue;
+use std::collections::HashMap;
+use std::time::Instant;
+
+use supabase_wrappers::prelude::*;
+use thiserror::Error;
+
+// convert a failed request to error, the error kind is decided by response
+// status, the URL in reqwest error is removed as it may have the API key
+fn request_error(url: &str, err: impl Into<reqwest_middleware::Error>) -> FdwError {
+    match err.into() {
+        reqwest_middleware::Error::Reqwest(e) => {
+            let status = e.status();
+            let msg = format!("request {} failed: {}", url, e.without_url());
+            match status {
+                Some(status) => FdwError::from_http_status(status.as_u16(), msg),
+                None => FdwError::connection_failed(msg),
+            }
+        }
+        reqwest_middleware::Error::Middleware(e) => {
+            FdwError::remote_query(format!("request {} failed: {}", url, e))
+        }
+    }
+}
+
+fn parse_path(name: &'static str, value: &str) -> Result<JsonPath, OptionsError> {
+    value.parse().map_err(|_| OptionsError::InvalidValue {
+        name,
+        value: value.to_owned(),
+        ty: "JSON path",
+    })
+}
+
+// parse a mapping option like 'id=$.id, name=$.user.name'
+fn parse_mapping(name: &'static str, value: &str) -> Result<Vec<(String, String)>, OptionsError> {
+    value
+        .split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(|item| match item.split_once('=') {
+            Some((key, value)) if !key.trim().is_empty() && !value.trim().is_empty() => {
+                Ok((key.trim().to_owned(), value.trim().to_owned()))
+            }
+            _ => Err(OptionsError::InvalidValue {
+                name,
+                value: value.to_owned(),
+                ty: "mapping",
+            }),
+        })
+        .collect()
+}
+
+// convert a qual value to query parameter value
+fn param_value(cell: &Cell) -> String {
+    match cell {
+        Cell::String(s) => s.clone(),
+        Cell::Date(_) | Cell::Timestamp(_) => cell.to_string().trim_matches('\'').to_owned(),
+        _ => cell.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pagination {
+    None,
+    Cursor,
+    Offset,
+    Page,
+    Link,
+}
+
+impl Pagination {
+    fn parse(value: &str) -> Result<Self, OptionsError> {
+        match value {
+            "none" => Ok(Self::None),
+            "cursor" => Ok(Self::Cursor),
+            "offset" => Ok(Self::Offset),
+            "page" => Ok(Self::Page),
+            "link" => Ok(Self::Link),
+            _ => Err(OptionsError::InvalidValue {
+                name: "pagination",
+                value: value.to_owned(),
+                ty: "pagination style",
+            }),
+        }
+    }
+}
+
+#[wrappers_fdw(
+    version = "0.1.0",
+    author = "Supabase",
+    website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/rest_fdw",
+    error_type = "RestFdwError",
+    options(
+        server(
+            base_url(required),
+            auth_scheme(default = "none"),
+            api_key(secret),
+            username,
+            password(secret),
+            auth_header(default = "X-API-Key"),
+            auth_param(default = "api_key"),
+            pagination(default = "none"),
+            page_size(type = "i64"),
+            page_size_param,
+            cursor_param(default = "cursor"),
+            cursor_path,
+            has_more_path,
+            offset_param(default = "offset"),
+            page_param(default = "page"),
+        ),
+        table(
+            path(required),
+            records_path(default = "$"),
+            column_paths,
+            query_params
+        ),
+        include(crate::http::HTTP_OPTION_SPECS)
+    )
+)]
+pub(crate) struct RestFdw {
+    rt: Runtime,
+    base_url: Url,
+    client: Option<ClientWithMiddleware>,
+    basic_auth: Option<(String, Option<String>)>,
+    // query parameter of the API key for `query` auth scheme
+    auth_param: Option<String>,
+    pagination: Pagination,
+    page_size: Option<i64>,
+    page_size_param: Option<String>,
+    cursor_param: String,
+    cursor_path: Option<JsonPath>,
+    has_more_path: Option<JsonPath>,
+    offset_param: String,
+    page_param: String,
+    remote_url: Option<Url>,
+    records_path: JsonPath,
+    column_paths: HashMap<String, JsonPath>,
+    // pushed down quals as (column, query parameter, value)
+    params: Vec<(String, String, Cell)>,
+    tgt_cols: Vec<Column>,
+    pager: Paginator,
+    metrics: RemoteMetrics,
+}
+
+impl RestFdw {
+    const FDW_NAME: &str = "RestFdw";
+
+    fn build_url(&self, path: &str) -> Result<Url, OptionsError> {
+        let mut url = self
+            .base_url
+            .join(path.trim_start_matches('/'))
+            .map_err(|_| OptionsError::InvalidValue {
+                name: "path",
+                value: path.to_owned(),
+                ty: "URL path",
+            })?;
+        for (_, param, cell) in &self.params {
+            url.query_pairs_mut().append_pair(param, &param_value(cell));
+        }
+        Ok(url)
+    }
+
+    // build the url of a page, the first page url is the remote url and the
+    // pagination parameters are added for the next pages
+    fn page_url(&self, base: &Url, req: &PageRequest) -> Result<Url, FdwError> {
+        let mut params = Vec::new();
+        let mut url = match req.next {
+            Some(NextPage::Url(next)) => Url::parse(next)
+                .map_err(|_| FdwError::remote_query(format!("invalid next page URL: {}", next)))?,
+            next => {
+                match (self.pagination, next) {
+                    (Pagination::Cursor, Some(NextPage::Token(cursor))) => {
+                        params.push((self.cursor_param.clone(), cursor.clone()));
+                    }
+                    (Pagination::Offset, Some(NextPage::Offset(offset))) => {
+                        params.push((self.offset_param.clone(), offset.to_string()));
+                    }
+                    (Pagination::Page, Some(NextPage::Offset(offset))) => {
+                        // page number starts from 1
+                        let page_size = self.page_size.unwrap_or(1).max(1);
+                        let page = offset / page_size + 1;
+                        params.push((self.page_param.clone(), page.to_string()));
+                    }
+                    _ => {}
+                }
+                if let (Some(param), Some(page_size)) = (&self.page_size_param, self.req_size(req))
+                {
+                    params.push((param.clone(), page_size.to_string()));
+                }
+                base.clone()
+            }
+        };
+        // the API key is added by the client to outgoing request only, so it
+        // is removed if a next page URL has it
+        if let Some(param) = &self.auth_param {
+            if url.query_pairs().any(|(k, _)| k == param.as_str()) {
+                let pairs: Vec<(String, String)> = url
+                    .query_pairs()
+                    .filter(|(k, _)| k != param.as_str())
+                    .map(|(k, v)| (k.into_owned(), v.into_owned()))
+                    .collect();
+                url.set_query(None);
+                params.splice(0..0, pairs);
+            }
+        }
+        if !params.is_empty() {
+            url.query_pairs_mut().extend_pairs(params);
+        }
+        Ok(url)
+    }
+
+    // URL shown in explain and errors, with the API key value hidden
+    fn display_url(&self, url: &Url) -> String {
+        match &self.auth_param {
+            Some(param) if url.query_pairs().any(|(k, _)| k == param.as_str()) => {
+                let mut url = url.clone();
+                let pairs: Vec<(String, String)> = url
+                    .query_pairs()
+                    .map(|(k, v)| {
+                        let v = if k == param.as_str() { "***".into() } else { v };
+                        (k.into_owned(), v.into_owned())
+                    })
+                    .collect();
+                url.query_pairs_mut().clear().extend_pairs(pairs);
+                url.to_string()
+            }
+            _ => url.to_string(),
+        }
+    }
+
+    // number of rows requested in a page, the page number style needs a fixed
+    // page size to calculate page number
+    fn req_size(&self, req: &PageRequest) -> Option<i64> {
+        match self.pagination {
+            Pagination::Page => self.page_size,
+            _ => req.page_size.or(self.page_size),
+        }
+    }
+
+    // fetch a page of records, how to request the next page is decided by
+    // the pagination style
+    fn fetch_page(&mut self, req: PageRequest) -> Result<Page, FdwError> {
+        let (client, base) = match (&self.client, &self.remote_url) {
+            (Some(client), Some(base)) => (client, base),
+            _ => return Ok(Page::default()),
+        };
+        let url = self.page_url(base, &req)?;
+        let display_url = self.display_url(&url);
+
+        // make api call
+        let started = Instant::now();
+        let mut request = client.get(url);
+        if let Some((username, password)) = &self.basic_auth {
+            request = request.basic_auth(username, password.as_ref());
+        }
+        let resp = block_on_interruptible(&self.rt, request.send())?
+            .map_err(|err| request_error(&display_url, err))?;
+        stats::record_request(Self::FDW_NAME, started);
+        stats::inc_stats(
+            Self::FDW_NAME,
+            stats::Metric::BytesIn,
+            resp.content_length().unwrap_or(0) as i64,
+        );
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            // if it is 404 error, we should treat it as an empty
+            // result rather than a request error
+            self.metrics.record_request(started, 0);
+            return Ok(Page::default());
+        }
+
+        let resp = resp
+            .error_for_status()
+            .map_err(|err| request_error(&display_url, err))?;
+        let link = resp
+            .headers()
+            .get(header::LINK)
+            .and_then(|v| v.to_str().ok())
+            .and_then(NextPage::from_link_header);
+        let body = block_on_interruptible(&self.rt, resp.text())?
+            .map_err(|err| request_error(&display_url, err))?;
+        self.metrics.record_request(started, body.len());
+        self.metrics.record_page();
+
+        let body: JsonValue = serde_json::from_str(&body).map_err(JsonError::from)?;
+        let rows = self
+            .records(&body)
+            .into_iter()
+            .map(|record| self.record_to_row(record))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // save stats
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsIn, rows.len() as i64);
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsOut, rows.len() as i64);
+
+        let page = match self.pagination {
+            Pagination::None => Page::new(rows, None),
+            Pagination::Cursor => {
+                let has_more = self
+                    .has_more_path
+                    .as_ref()
+                    .and_then(|path| path.get(&body))
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(true);
+                let cursor = self
+                    .cursor_path
+                    .as_ref()
+                    .and_then(|path| path.get(&body))
+                    .and_then(|v| match v {
+                        JsonValue::String(s) if !s.is_empty() => Some(s.clone()),
+                        JsonValue::Number(n) => Some(n.to_string()),
+                        _ => None,
+                    })
+                    .filter(|_| has_more);
+                Page::new(rows, cursor.map(NextPage::Token))
+            }
+            Pagination::Offset | Pagination::Page => {
+                let offset = match req.next {
+                    Some(NextPage::Offset(offset)) => *offset,
+                    _ => 0,
+                };
+                let page_size = self.req_size(&req).unwrap_or_default();
+                Page::with_offset(rows, offset, page_size)
+            }
+            Pagination::Link => Page::new(rows, link),
+        };
+        Ok(page)
+    }
+
+    // get the records from response body, a single object is treated as one
+    // record and an array is treated as a list of records
+    fn records<'a>(&self, body: &'a JsonValue) -> Vec<&'a JsonValue> {
+        let values = self.records_path.select(body);
+        if let [JsonValue::Array(records)] = values[..] {
+            return records.iter().collect();
+        }
+        values.into_iter().filter(|v| !v.is_null()).collect()
+    }
+
+    fn record_to_row(&self, record: &JsonValue) -> Result<Row, FdwError> {
+        let mut row = Row::new();
+        for tgt_col in &self.tgt_cols {
+            let cell = match self.column_paths.get(&tgt_col.name) {
+                Some(path) => path
+                    .get(record)
+                    .map(|v| Cell::from_json(v, tgt_col))
+                    .transpose()?
+                    .flatten(),
+                // add the whole record to attrs column
+                None if tgt_col.name == "attrs" => Some(Cell::Json(JsonB(record.clone()))),
+                None => record
+                    .get(&tgt_col.name)
+                    .map(|v| Cell::from_json(v, tgt_col))
+                    .transpose()?
+                    .flatten(),
+            };
+
+            // fill the column with pushed down qual value if the record
+            // doesn't have it, so the row can pass local qual check
+            let cell = cell.or_else(|| {
+                self.params
+                    .iter()
+                    .find(|(col, _, _)| col == &tgt_col.name)
+                    .map(|(_, _, cell)| cell.clone())
+            });
+            row.push(&tgt_col.name, cell);
+        }
+        Ok(row)
+    }
+}
+
+#[derive(Error, Debug)]
+enum RestFdwError {
+    #[error("{0}")]
+    CreateRuntimeError(#[from] CreateRuntimeError),
+
+    #[error("{0}")]
+    OptionsError(#[from] OptionsError),
+
+    #[error("{0}")]
+    FdwError(#[from] FdwError),
+}
+
+impl From<RestFdwError> for ErrorReport {
+    fn from(value: RestFdwError) -> Self {
+        let err = match value {
+            RestFdwError::CreateRuntimeError(e) => FdwError::from(e),
+            RestFdwError::OptionsError(e) => FdwError::from(e),
+            RestFdwError::FdwError(e) => e,
+        };
+        stats::record_error(RestFdw::FDW_NAME, err.kind());
+        err.into()
+    }
+}
+
+impl ForeignDataWrapper<RestFdwError> for RestFdw {
+    fn new(options: &HashMap<String, String>) -> Result<Self, RestFdwError> {
+        let opts = RestFdwServerOptions::from_options(options)?;
+        let base_url = if opts.base_url.ends_with('/') {
+            opts.base_url
+        } else {
+            format!("{}/", opts.base_url)
+        };
+        let base_url = Url::parse(&base_url).map_err(|_| OptionsError::InvalidValue {
+            name: "base_url",
+            value: base_url.clone(),
+            ty: "URL",
+        })?;
+        let mut http_opts = HttpOptions::from_options(options)?;
+
+        // set up authentication
+        let api_key = || {
+            opts.api_key
+                .clone()
+                .ok_or(OptionsError::MissingOption("api_key"))
+        };
+        let mut headers = HeaderMap::new();
+        let mut basic_auth = None;
+        let mut auth_param = None;
+        match opts.auth_scheme.as_str() {
+            "none" => {}
+            "bearer" => {
+                let mut value =
+                    HeaderValue::from_str(&format!("Bearer {}", api_key()?)).map_err(|_| {
+                        OptionsError::InvalidValue {
+                            name: "api_key",
+                            value: "(not shown)".to_owned(),
+                            ty: "header value",
+                        }
+                    })?;
+                value.set_sensitive(true);
+                headers.insert(header::AUTHORIZATION, value);
+            }
+            "header" => {
+                let name = HeaderName::from_bytes(opts.auth_header.as_bytes()).map_err(|_| {
+                    OptionsError::InvalidValue {
+                        name: "auth_header",
+                        value: opts.auth_header.clone(),
+                        ty: "header name",
+                    }
+                })?;
+                let mut value =
+                    HeaderValue::from_str(&api_key()?).map_err(|_| OptionsError::InvalidValue {
+                        name: "api_key",
+                        value: "(not shown)".to_owned(),
+                        ty: "header value",
+                    })?;
+                value.set_sensitive(true);
+                headers.insert(name, value);
+            }
+            "basic" => {
+                let username = opts
+                    .username
+                    .clone()
+                    .ok_or(OptionsError::MissingOption("username"))?;
+                basic_auth = Some((username, opts.password.clone()));
+            }
+            "query" => {
+                http_opts.query_auth = Some((opts.auth_param.clone(), api_key()?));
+                auth_param = Some(opts.auth_param.clone());
+            }
+            _ => {
+                return Err(OptionsError::InvalidValue {
+                    name: "auth_scheme",
+                    value: opts.auth_scheme,
+                    ty: "auth scheme",
+                }
+                .into())
+            }
+        }
+
+        // set up pagination
+        let pagination = Pagination::parse(&opts.pagination)?;
+        if matches!(pagination, Pagination::Offset | Pagination::Page) && opts.page_size.is_none() {
+            return Err(OptionsError::MissingOption("page_size").into());
+        }
+        if pagination == Pagination::Cursor && opts.cursor_path.is_none() {
+            return Err(OptionsError::MissingOption("cursor_path").into());
+        }
+        if let Some(page_size) = opts.page_size {
+            if page_size <= 0 {
+                return Err(OptionsError::InvalidValue {
+                    name: "page_size",
+                    value: page_size.to_string(),
+                    ty: "positive integer",
+                }
+                .into());
+            }
+        }
+        let cursor_path = opts
+            .cursor_path
+            .as_deref()
+            .map(|path| parse_path("cursor_path", path))
+            .transpose()?;
+        let has_more_path = opts
+            .has_more_path
+            .as_deref()
+            .map(|path| parse_path("has_more_path", path))
+            .transpose()?;
+
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::CreateTimes, 1);
+
+        Ok(RestFdw {
+            rt: create_async_runtime()?,
+            base_url,
+            client: Some(http::create_client(headers, &http_opts)?),
+            basic_auth,
+            auth_param,
+            pagination,
+            page_size: opts.page_size,
+            page_size_param: opts.page_size_param,
+            cursor_param: opts.cursor_param,
+            cursor_path,
+            has_more_path,
+            offset_param: opts.offset_param,
+            page_param: opts.page_param,
+            remote_url: None,
+            records_path: JsonPath::default(),
+            column_paths: HashMap::new(),
+            params: Vec::new(),
+            tgt_cols: Vec::new(),
+            pager: Paginator::default(),
+            metrics: RemoteMetrics::default(),
+        })
+    }
+
+    fn begin_scan(
+        &mut self,
+        quals: &[Qual],
+        columns: &[Column],
+        _sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+    ) -> Result<(), RestFdwError> {
+        let opts = RestFdwTableOptions::from_options(options)?;
+        self.records_path = parse_path("records_path", &opts.records_path)?;
+        self.column_paths = match &opts.column_paths {
+            Some(mapping) => parse_mapping("column_paths", mapping)?
+                .into_iter()
+                .map(|(col, path)| Ok((col, parse_path("column_paths", &path)?)))
+                .collect::<Result<_, OptionsError>>()?,
+            None => HashMap::new(),
+        };
+
+        // push down the '=' quals on mapped columns as query parameters
+        let query_params = match &opts.query_params {
+            Some(mapping) => parse_mapping("query_params", mapping)?,
+            None => Vec::new(),
+        };
+        self.params = quals
+            .iter()
+            .filter(|qual| qual.operator == "=" && !qual.use_or)
+            .filter_map(|qual| {
+                let (_, param) = query_params.iter().find(|(col, _)| col == &qual.field)?;
+                match &qual.value {
+                    Value::Cell(cell) => Some((qual.field.clone(), param.clone(), cell.clone())),
+                    Value::Array(_) => None,
+                }
+            })
+            .collect();
+
+        self.remote_url = Some(self.build_url(&opts.path)?);
+        self.tgt_cols = columns.to_vec();
+        self.metrics = RemoteMetrics::default();
+
+        // pages are fetched lazily in iter_scan until the limit is reached
+        let pager = Paginator::new().with_limit(limit);
+        self.pager = match self.page_size {
+            Some(page_size) => pager.with_page_size(page_size),
+            None => pager,
+        };
+
+        Ok(())
+    }
+
+    fn explain(&self, _verbose: bool) -> Vec<(String, String)> {
+        let mut ret = Vec::new();
+        if let Some(url) = &self.remote_url {
+            ret.push(("Remote URL".to_string(), self.display_url(url)));
+        }
+        ret
+    }
+
+    fn remote_metrics(&self) -> Option<RemoteMetrics> {
+        Some(self.metrics.clone())
+    }
+
+    fn iter_scan(&mut self, row: &mut Row) -> Result<Option<()>, RestFdwError> {
+        let mut pager = std::mem::take(&mut self.pager);
+        let result = pager.next_row(row, |req| self.fetch_page(req));
+        self.pager = pager;
+        Ok(result?)
+    }
+
+    fn re_scan(&mut self) -> Result<(), RestFdwError> {
+        // fetch the pages again from the first page
+        self.pager.reset();
+        Ok(())
+    }
+
+    fn end_scan(&mut self) -> Result<(), RestFdwError> {
+        self.pager = Paginator::default();
+        Ok(())
+    }
+}
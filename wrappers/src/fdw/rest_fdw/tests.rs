@@ -0,0 +1,175 @@
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    fn rest_smoketest() {
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER rest_wrapper
+                         HANDLER rest_fdw_handler VALIDATOR rest_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            // responses are replayed from the cassette, the API URL is not accessed
+            let cassette = concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/fdw/rest_fdw/cassettes/users.json"
+            );
+            c.update(
+                &format!(
+                    r#"CREATE SERVER my_rest_server
+                         FOREIGN DATA WRAPPER rest_wrapper
+                         OPTIONS (
+                           base_url 'http://rest.invalid/api',
+                           auth_scheme 'bearer',
+                           api_key 'test_key',
+                           pagination 'cursor',
+                           cursor_path '$.meta.next_cursor',
+                           page_size '2',
+                           page_size_param 'limit',
+                           http_cassette '{}',
+                           http_cassette_mode 'replay'
+                         )"#,
+                    cassette
+                ),
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"
+                CREATE FOREIGN TABLE users (
+                  id bigint,
+                  name text,
+                  status text,
+                  city text,
+                  attrs jsonb
+                )
+                SERVER my_rest_server
+                OPTIONS (
+                    path 'users',
+                    records_path '$.data',
+                    column_paths 'city=$.address.city',
+                    query_params 'status=status'
+                  )
+             "#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            // all pages are fetched
+            let results = c
+                .select("SELECT * FROM users ORDER BY id", None, None)
+                .unwrap()
+                .filter_map(|r| {
+                    r.get_by_name::<i64, _>("id")
+                        .unwrap()
+                        .zip(r.get_by_name::<&str, _>("name").unwrap())
+                        .zip(r.get_by_name::<&str, _>("city").unwrap())
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                results,
+                vec![
+                    ((1, "Alice"), "Auckland"),
+                    ((2, "Bob"), "Berlin"),
+                    ((3, "Carol"), "Chicago")
+                ]
+            );
+
+            // qual is pushed down as query parameter
+            let results = c
+                .select(
+                    "SELECT attrs->>'name' AS name FROM users WHERE status = 'active' ORDER BY id",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("name").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec!["Alice", "Carol"]);
+        });
+    }
+
+    #[pg_test]
+    fn rest_query_auth() {
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER rest_wrapper
+                         HANDLER rest_fdw_handler VALIDATOR rest_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            // the API key is added to outgoing requests only, so the recorded
+            // requests in cassette don't have it
+            let cassette = concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/fdw/rest_fdw/cassettes/users.json"
+            );
+            c.update(
+                &format!(
+                    r#"CREATE SERVER my_rest_server
+                         FOREIGN DATA WRAPPER rest_wrapper
+                         OPTIONS (
+                           base_url 'http://rest.invalid/api',
+                           auth_scheme 'query',
+                           api_key 'secret_key',
+                           pagination 'cursor',
+                           cursor_path '$.meta.next_cursor',
+                           page_size '2',
+                           page_size_param 'limit',
+                           http_cassette '{}',
+                           http_cassette_mode 'replay'
+                         )"#,
+                    cassette
+                ),
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"
+                CREATE FOREIGN TABLE users (
+                  id bigint,
+                  name text
+                )
+                SERVER my_rest_server
+                OPTIONS (
+                    path 'users?api_key=other_key',
+                    records_path '$.data'
+                  )
+             "#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            let results = c
+                .select("SELECT id FROM users ORDER BY id", None, None)
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<i64, _>("id").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![1, 2, 3]);
+
+            // the API key in URL is not shown
+            let results = c
+                .select("EXPLAIN SELECT id FROM users", None, None)
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("QUERY PLAN").unwrap())
+                .collect::<Vec<_>>();
+            assert!(results
+                .iter()
+                .any(|r| r.contains("Remote URL: http://rest.invalid/api/users?api_key=***")));
+            assert!(!results
+                .iter()
+                .any(|r| r.contains("secret_key") || r.contains("other_key")));
+        });
+    }
+}
@@ -9,7 +9,7 @@ use parquet::arrow::async_reader::{
 use parquet::arrow::ProjectionMask;
 use pgrx::datum::datetime_support::to_timestamp;
 use pgrx::pg_sys;
-use pgrx::prelude::{Date, PgSqlErrorCode};
+use pgrx::prelude::Date;
 use std::cmp::min;
 use std::io::{Cursor, Error as IoError, ErrorKind, Result as IoResult, SeekFrom};
 use std::pin::Pin;
@@ -143,11 +143,14 @@ impl S3Parquet {
     const FDW_NAME: &str = "S3Fdw";
 
     // open batch stream from local buffer
-    pub(super) async fn open_local_stream(&mut self, buf: Vec<u8>) {
+    pub(super) async fn open_local_stream(&mut self, buf: Vec<u8>) -> Result<(), FdwError> {
         let cursor: Box<dyn AsyncFileReader> = Box::new(Cursor::new(buf));
-        let builder = ParquetRecordBatchStreamBuilder::new(cursor).await.unwrap();
-        let stream = builder.build().unwrap();
+        let stream = ParquetRecordBatchStreamBuilder::new(cursor)
+            .await
+            .and_then(|builder| builder.build())
+            .map_err(|err| FdwError::remote_query(format!("open parquet file failed: {}", err)))?;
         self.stream = Some(stream);
+        Ok(())
     }
 
     // open async record batch stream
@@ -160,7 +163,7 @@ impl S3Parquet {
         bucket: &str,
         object: &str,
         tgt_cols: &[Column],
-    ) {
+    ) -> Result<(), FdwError> {
         let handle = Handle::current();
         let rdr = S3ParquetReader::new(client, bucket, object);
 
@@ -176,97 +179,107 @@ impl S3Parquet {
             })
             .await;
 
-        let stream = task
-            .expect("create parquet batch stream builder failed")
-            .expect("create parquet batch stream builder failed")
-            .and_then(|builder| {
-                // get parquet file metadata
-                let file_metadata = builder.metadata().file_metadata();
-                let schema = file_metadata.schema_descr();
-                let cols = schema.columns();
+        let open_error =
+            |err: String| FdwError::remote_query(format!("open parquet file failed: {}", err));
+        let builder = task
+            .map_err(|err| open_error(err.to_string()))?
+            .map_err(|err| open_error(err.to_string()))?
+            .map_err(|err| open_error(err.to_string()))?;
 
-                // find target column indexes in parquest columns
-                let project_indexes = tgt_cols
-                    .iter()
-                    .map(|tgt_col| {
-                        cols.iter()
-                            .position(|col| col.name() == tgt_col.name)
-                            .unwrap_or_else(|| {
-                                panic!("column '{}' not found in parquet file", tgt_col.name)
-                            })
-                    })
-                    .collect::<Vec<usize>>();
+        // get parquet file metadata
+        let file_metadata = builder.metadata().file_metadata();
+        let schema = file_metadata.schema_descr();
+        let cols = schema.columns();
 
-                // set up projections for the builder
-                let mask = ProjectionMask::roots(schema, project_indexes);
-                builder.with_projection(mask).build()
+        // find target column indexes in parquest columns
+        let project_indexes = tgt_cols
+            .iter()
+            .map(|tgt_col| {
+                cols.iter()
+                    .position(|col| col.name() == tgt_col.name)
+                    .ok_or_else(|| {
+                        FdwError::not_found(format!(
+                            "column '{}' not found in parquet file",
+                            tgt_col.name
+                        ))
+                    })
             })
-            .map_err(to_io_error)
-            .unwrap();
+            .collect::<Result<Vec<usize>, FdwError>>()?;
+
+        // set up projections for the builder
+        let mask = ProjectionMask::roots(schema, project_indexes);
+        let stream = builder
+            .with_projection(mask)
+            .build()
+            .map_err(|err| open_error(err.to_string()))?;
 
         self.stream = Some(stream);
         self.batch = None;
         self.batch_idx = 0;
+        Ok(())
     }
 
     // refill record batch
-    pub(super) async fn refill(&mut self) -> Option<()> {
+    pub(super) async fn refill(&mut self) -> Result<Option<()>, FdwError> {
         // if there are still records in the batch
         if let Some(batch) = &self.batch {
             if self.batch_idx < batch.num_rows() {
-                return Some(());
+                return Ok(Some(()));
             }
         }
 
         // otherwise, read one moe batch
         if let Some(ref mut stream) = &mut self.stream {
-            match stream.try_next().await {
-                Ok(result) => {
-                    return result.map(|batch| {
-                        stats::inc_stats(
-                            Self::FDW_NAME,
-                            stats::Metric::RowsIn,
-                            batch.num_rows() as i64,
-                        );
-                        stats::inc_stats(
-                            Self::FDW_NAME,
-                            stats::Metric::BytesIn,
-                            batch.get_array_memory_size() as i64,
-                        );
+            let result = stream.try_next().await.map_err(|err| {
+                FdwError::remote_query(format!("read parquet record batch failed: {}", err))
+            })?;
+            return Ok(result.map(|batch| {
+                stats::inc_stats(
+                    Self::FDW_NAME,
+                    stats::Metric::RowsIn,
+                    batch.num_rows() as i64,
+                );
+                stats::inc_stats(
+                    Self::FDW_NAME,
+                    stats::Metric::BytesIn,
+                    batch.get_array_memory_size() as i64,
+                );
 
-                        self.batch = Some(batch);
-                        self.batch_idx = 0;
-                    })
-                }
-                Err(err) => {
-                    report_error(
-                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                        &format!("read parquet record batch failed: {}", err),
-                    );
-                    return None;
-                }
-            }
+                self.batch = Some(batch);
+                self.batch_idx = 0;
+            }));
         }
 
-        None
+        Ok(None)
     }
 
     // read one row from record batch
-    pub(super) fn read_into_row(&mut self, row: &mut Row, tgt_cols: &Vec<Column>) -> Option<()> {
+    pub(super) fn read_into_row(
+        &mut self,
+        row: &mut Row,
+        tgt_cols: &Vec<Column>,
+    ) -> Result<Option<()>, FdwError> {
         if let Some(batch) = &self.batch {
             for tgt_col in tgt_cols {
-                let col = batch
-                    .column_by_name(&tgt_col.name)
-                    .unwrap_or_else(|| panic!("column {} not found in parquet file", tgt_col.name));
+                let col = batch.column_by_name(&tgt_col.name).ok_or_else(|| {
+                    FdwError::not_found(format!(
+                        "column {} not found in parquet file",
+                        tgt_col.name
+                    ))
+                })?;
+                let type_mismatch = || {
+                    FdwError::type_conversion(format!(
+                        "column '{}' data type not match",
+                        tgt_col.name
+                    ))
+                };
 
                 macro_rules! col_to_cell {
                     ($array_type:ident, $cell_type:ident) => {{
                         let arr = col
                             .as_any()
                             .downcast_ref::<array::$array_type>()
-                            .unwrap_or_else(|| {
-                                panic!("column '{}' data type not match", tgt_col.name)
-                            });
+                            .ok_or_else(type_mismatch)?;
                         if arr.is_null(self.batch_idx) {
                             None
                         } else {
@@ -287,14 +300,13 @@ impl S3Parquet {
                         let arr = col
                             .as_any()
                             .downcast_ref::<array::Float64Array>()
-                            .unwrap_or_else(|| {
-                                panic!("column '{}' data type not match", tgt_col.name)
-                            });
+                            .ok_or_else(type_mismatch)?;
                         if arr.is_null(self.batch_idx) {
                             None
                         } else {
                             let value = arr.value(self.batch_idx);
-                            let num = pgrx::AnyNumeric::try_from(value).unwrap();
+                            let num =
+                                pgrx::AnyNumeric::try_from(value).map_err(|_| type_mismatch())?;
                             Some(Cell::Numeric(num))
                         }
                     }
@@ -302,9 +314,7 @@ impl S3Parquet {
                         let arr = col
                             .as_any()
                             .downcast_ref::<array::BinaryArray>()
-                            .unwrap_or_else(|| {
-                                panic!("column '{}' data type not match", tgt_col.name)
-                            });
+                            .ok_or_else(type_mismatch)?;
                         if arr.is_null(self.batch_idx) {
                             None
                         } else {
@@ -316,9 +326,7 @@ impl S3Parquet {
                         let arr = col
                             .as_any()
                             .downcast_ref::<array::Date64Array>()
-                            .unwrap_or_else(|| {
-                                panic!("column '{}' data type not match", tgt_col.name)
-                            });
+                            .ok_or_else(type_mismatch)?;
                         if arr.is_null(self.batch_idx) {
                             None
                         } else {
@@ -335,9 +343,7 @@ impl S3Parquet {
                         let arr = col
                             .as_any()
                             .downcast_ref::<array::TimestampNanosecondArray>()
-                            .unwrap_or_else(|| {
-                                panic!("column '{}' data type not match", tgt_col.name)
-                            });
+                            .ok_or_else(type_mismatch)?;
                         if arr.is_null(self.batch_idx) {
                             None
                         } else {
@@ -348,18 +354,17 @@ impl S3Parquet {
                         }
                     }
                     _ => {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("column '{}' data type not supported", tgt_col.name),
-                        );
-                        None
+                        return Err(FdwError::type_conversion(format!(
+                            "column '{}' data type not supported",
+                            tgt_col.name
+                        )));
                     }
                 };
                 row.push(&tgt_col.name, cell);
             }
             self.batch_idx += 1;
-            return Some(());
+            return Ok(Some(()));
         }
-        None
+        Ok(None)
     }
 }
@@ -2,9 +2,7 @@ use crate::stats;
 use async_compression::tokio::bufread::{BzDecoder, GzipDecoder, XzDecoder, ZlibDecoder};
 use aws_sdk_s3 as s3;
 use http::Uri;
-use pgrx::pg_sys;
 use pgrx::pg_sys::panic::ErrorReport;
-use pgrx::prelude::PgSqlErrorCode;
 use serde_json::{self, Value as JsonValue};
 use std::collections::{HashMap, VecDeque};
 use std::env;
@@ -14,6 +12,7 @@ use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader};
 
 use super::parquet::*;
 use supabase_wrappers::prelude::*;
+use thiserror::Error;
 
 // record parser for a S3 file
 enum Parser {
@@ -24,10 +23,21 @@ enum Parser {
 }
 
 #[wrappers_fdw(
-    version = "0.1.2",
+    version = "0.1.3",
     author = "Supabase",
     website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/s3_fdw",
-    error_type = "S3FdwError"
+    error_type = "S3FdwError",
+    options(
+        server(
+            aws_access_key_id(secret),
+            aws_secret_access_key(secret),
+            vault_access_key_id,
+            vault_secret_access_key,
+            aws_region,
+            is_mock(type = "bool"),
+        ),
+        table(uri(required), format(required), has_header(type = "bool"), compress)
+    )
 )]
 pub(crate) struct S3Fdw {
     rt: Runtime,
@@ -37,6 +47,9 @@ pub(crate) struct S3Fdw {
     tgt_cols: Vec<Column>,
     rows_out: i64,
 
+    // table options of current scan, used to open the object again on re-scan
+    scan_opts: HashMap<String, String>,
+
     // local string buffer for CSV and JSONL
     buf: String,
 }
@@ -53,9 +66,9 @@ impl S3Fdw {
     // Returns:
     //   Some - still have records to read
     //   None - no more records
-    fn refill(&mut self) -> Option<()> {
+    fn refill(&mut self) -> Result<Option<()>, S3FdwError> {
         if !self.buf.is_empty() {
-            return Some(());
+            return Ok(Some(()));
         }
 
         if let Some(ref mut rdr) = self.rdr {
@@ -63,21 +76,14 @@ impl S3Fdw {
             let mut total_lines = 0;
             let mut total_bytes = 0;
             loop {
-                match self.rt.block_on(rdr.read_line(&mut self.buf)) {
-                    Ok(num_bytes) => {
-                        total_lines += 1;
-                        total_bytes += num_bytes;
-                        if num_bytes == 0 || self.buf.len() > Self::BUF_SIZE {
-                            break;
-                        }
-                    }
-                    Err(err) => {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("fetch query result failed: {}", err),
-                        );
-                        return None;
-                    }
+                let num_bytes = block_on_interruptible(&self.rt, rdr.read_line(&mut self.buf))?
+                    .map_err(|err| {
+                        FdwError::remote_query(format!("fetch query result failed: {}", err))
+                    })?;
+                total_lines += 1;
+                total_bytes += num_bytes;
+                if num_bytes == 0 || self.buf.len() > Self::BUF_SIZE {
+                    break;
                 }
             }
 
@@ -86,7 +92,7 @@ impl S3Fdw {
         }
 
         if self.buf.is_empty() {
-            return None;
+            return Ok(None);
         }
 
         match &mut self.parser {
@@ -106,87 +112,97 @@ impl S3Fdw {
                     .collect::<Vec<&str>>()
                     .join(",");
                 let json_str = format!("{{ \"rows\": [{}] }}", s.trim_end_matches(','));
-                match serde_json::from_str::<JsonValue>(&json_str) {
-                    Ok(rows) => {
-                        *records =
-                            VecDeque::from(rows.get("rows").unwrap().as_array().unwrap().to_vec());
-                    }
-                    Err(err) => {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("parse json line file failed: {}", err),
-                        );
-                        return None;
-                    }
-                }
+                let rows = serde_json::from_str::<JsonValue>(&json_str).map_err(|err| {
+                    FdwError::type_conversion(format!("parse json line file failed: {}", err))
+                })?;
+                let rows = rows.get("rows").and_then(|v| v.as_array()).ok_or_else(|| {
+                    FdwError::type_conversion("parse json line file failed: invalid json lines")
+                })?;
+                *records = VecDeque::from(rows.to_vec());
             }
             _ => unreachable!(),
         }
 
-        Some(())
+        Ok(Some(()))
     }
 }
 
-enum S3FdwError {}
+#[derive(Error, Debug)]
+enum S3FdwError {
+    #[error("{0}")]
+    FdwError(#[from] FdwError),
+
+    #[error("{0}")]
+    OptionsError(#[from] OptionsError),
+}
 
 impl From<S3FdwError> for ErrorReport {
-    fn from(_value: S3FdwError) -> Self {
-        ErrorReport::new(PgSqlErrorCode::ERRCODE_FDW_ERROR, "", "")
+    fn from(value: S3FdwError) -> Self {
+        let err = match value {
+            S3FdwError::FdwError(e) => e,
+            S3FdwError::OptionsError(e) => FdwError::from(e),
+        };
+        stats::record_error(S3Fdw::FDW_NAME, err.kind());
+        err.into()
     }
 }
 
 impl ForeignDataWrapper<S3FdwError> for S3Fdw {
     fn new(options: &HashMap<String, String>) -> Result<Self, S3FdwError> {
-        let rt = tokio::runtime::Runtime::new().unwrap();
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|err| FdwError::from(CreateRuntimeError::from(err)))?;
         let mut ret = S3Fdw {
             rt,
             client: None,
             rdr: None,
             parser: Parser::JsonLine(VecDeque::new()),
             tgt_cols: Vec::new(),
+            scan_opts: HashMap::new(),
             rows_out: 0,
             buf: String::new(),
         };
 
-        // get is_mock flag
-        let is_mock: bool = options.get("is_mock") == Some(&"true".to_string());
+        let opts = S3FdwServerOptions::from_options(options)?;
+        let is_mock = opts.is_mock.unwrap_or(false);
 
         // get credentials
         let creds = if is_mock {
             // LocalStack uses hardcoded credentials
-            Some(("test".to_string(), "test".to_string()))
+            ("test".to_string(), "test".to_string())
         } else {
-            match options.get("vault_access_key_id") {
+            match opts.vault_access_key_id {
                 Some(vault_access_key_id) => {
                     // if using credentials stored in Vault
-                    require_option("vault_secret_access_key", options).and_then(
-                        |vault_secret_access_key| {
-                            get_vault_secret(vault_access_key_id)
-                                .zip(get_vault_secret(&vault_secret_access_key))
-                        },
+                    let vault_secret_access_key = opts
+                        .vault_secret_access_key
+                        .ok_or(OptionsError::MissingOption("vault_secret_access_key"))?;
+                    (
+                        VaultProvider
+                            .get_secret(&vault_access_key_id)
+                            .map_err(OptionsError::from)?,
+                        VaultProvider
+                            .get_secret(&vault_secret_access_key)
+                            .map_err(OptionsError::from)?,
                     )
                 }
                 None => {
-                    // if using credentials directly specified
-                    require_option("aws_access_key_id", options)
-                        .zip(require_option("aws_secret_access_key", options))
+                    // if using credentials directly specified or by secret references
+                    (
+                        opts.aws_access_key_id
+                            .ok_or(OptionsError::MissingOption("aws_access_key_id"))?,
+                        opts.aws_secret_access_key
+                            .ok_or(OptionsError::MissingOption("aws_secret_access_key"))?,
+                    )
                 }
             }
         };
-        if creds.is_none() {
-            return Ok(ret);
-        }
-        let creds = creds.unwrap();
 
         // get region
         let default_region = "us-east-1".to_string();
         let region = if is_mock {
             default_region
         } else {
-            options
-                .get("aws_region")
-                .map(|t| t.to_owned())
-                .unwrap_or(default_region)
+            opts.aws_region.unwrap_or(default_region)
         };
 
         // set AWS environment variables and create shared config from them
@@ -220,116 +236,106 @@ impl ForeignDataWrapper<S3FdwError> for S3Fdw {
         _limit: &Option<Limit>,
         options: &HashMap<String, String>,
     ) -> Result<(), S3FdwError> {
+        let opts = S3FdwTableOptions::from_options(options)?;
+
         // extract s3 bucket and object path from uri option
-        let (bucket, object) = if let Some(uri) = require_option("uri", options) {
-            match uri.parse::<Uri>() {
-                Ok(uri) => {
-                    if uri.scheme_str() != Option::Some("s3")
-                        || uri.host().is_none()
-                        || uri.path().is_empty()
-                    {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("invalid s3 uri: {}", uri),
-                        );
-                        return Ok(());
-                    }
-                    // exclude 1st "/" char in the path as s3 object path doesn't like it
-                    (uri.host().unwrap().to_owned(), uri.path()[1..].to_string())
-                }
-                Err(err) => {
-                    report_error(
-                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                        &format!("parse s3 uri failed: {}", err),
-                    );
-                    return Ok(());
+        let uri = opts
+            .uri
+            .parse::<Uri>()
+            .map_err(|err| OptionsError::InvalidValue {
+                name: "uri",
+                value: format!("{} ({})", opts.uri, err),
+                ty: "s3 uri",
+            })?;
+        let bucket = match uri.host() {
+            Some(host) if uri.scheme_str() == Some("s3") && !uri.path().is_empty() => {
+                host.to_owned()
+            }
+            _ => {
+                return Err(OptionsError::InvalidValue {
+                    name: "uri",
+                    value: uri.to_string(),
+                    ty: "s3 uri",
                 }
+                .into());
             }
-        } else {
-            return Ok(());
         };
+        // exclude 1st "/" char in the path as s3 object path doesn't like it
+        let object = uri.path()[1..].to_string();
 
-        let has_header: bool = options.get("has_header") == Some(&"true".to_string());
+        let has_header = opts.has_header.unwrap_or(false);
 
         self.tgt_cols = columns.to_vec();
+        self.scan_opts = options.clone();
+        self.rows_out = 0;
+        self.buf.clear();
 
         if let Some(client) = &self.client {
             // initialise parser according to format option
-            if let Some(format) = require_option("format", options) {
+            self.parser = match opts.format.as_str() {
                 // create dummy parser
-                match format.as_str() {
-                    "csv" => {
-                        self.parser = Parser::Csv(csv::Reader::from_reader(Cursor::new(vec![0])))
-                    }
-                    "jsonl" => self.parser = Parser::JsonLine(VecDeque::new()),
-                    "parquet" => self.parser = Parser::Parquet(S3Parquet::default()),
-                    _ => {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!(
-                                "invalid format option: {}, it can only be 'csv', 'jsonl' or 'parquet'",
-                                format
-                            ),
-                        );
-                        return Ok(());
+                "csv" => Parser::Csv(csv::Reader::from_reader(Cursor::new(vec![0]))),
+                "jsonl" => Parser::JsonLine(VecDeque::new()),
+                "parquet" => Parser::Parquet(S3Parquet::default()),
+                _ => {
+                    return Err(OptionsError::InvalidValue {
+                        name: "format",
+                        value: opts.format,
+                        ty: "format of 'csv', 'jsonl' or 'parquet'",
                     }
+                    .into());
                 }
-            } else {
-                return Ok(());
             };
 
-            let stream = match self
-                .rt
-                .block_on(client.get_object().bucket(&bucket).key(&object).send())
-            {
-                Ok(resp) => resp.body.into_async_read(),
-                Err(err) => {
-                    report_error(
-                        PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                        &format!("request s3 failed: {}", err),
-                    );
-                    return Ok(());
-                }
-            };
-
-            let mut boxed_stream: Pin<Box<dyn AsyncRead>> =
-                if let Some(compress) = options.get("compress") {
-                    let buf_rdr = BufReader::new(stream);
-                    match compress.as_str() {
-                        "bzip2" => Box::pin(BzDecoder::new(buf_rdr)),
-                        "gzip" => Box::pin(GzipDecoder::new(buf_rdr)),
-                        "xz" => Box::pin(XzDecoder::new(buf_rdr)),
-                        "zlib" => Box::pin(ZlibDecoder::new(buf_rdr)),
-                        _ => {
-                            report_error(
-                                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                                &format!("invalid compression option: {}", compress),
-                            );
-                            return Ok(());
+            let stream = block_on_interruptible(
+                &self.rt,
+                client.get_object().bucket(&bucket).key(&object).send(),
+            )?
+            .map_err(|err| FdwError::remote_query(format!("request s3 failed: {}", err)))?
+            .body
+            .into_async_read();
+
+            let mut boxed_stream: Pin<Box<dyn AsyncRead>> = if let Some(compress) = &opts.compress {
+                let buf_rdr = BufReader::new(stream);
+                match compress.as_str() {
+                    "bzip2" => Box::pin(BzDecoder::new(buf_rdr)),
+                    "gzip" => Box::pin(GzipDecoder::new(buf_rdr)),
+                    "xz" => Box::pin(XzDecoder::new(buf_rdr)),
+                    "zlib" => Box::pin(ZlibDecoder::new(buf_rdr)),
+                    _ => {
+                        return Err(OptionsError::InvalidValue {
+                            name: "compress",
+                            value: compress.to_owned(),
+                            ty: "compression of 'bzip2', 'gzip', 'xz' or 'zlib'",
                         }
+                        .into());
                     }
-                } else {
-                    Box::pin(stream)
-                };
+                }
+            } else {
+                Box::pin(stream)
+            };
 
             // deal with parquet file, read all its content to local buffer if it is
             // compressed, otherwise open async read stream for it
             if let Parser::Parquet(ref mut s3parquet) = &mut self.parser {
-                if options.get("compress").is_some() {
+                if opts.compress.is_some() {
                     // read all contents to local
                     let mut buf = Vec::new();
-                    self.rt
-                        .block_on(boxed_stream.read_to_end(&mut buf))
-                        .expect("read compressed parquet file failed");
-                    self.rt.block_on(s3parquet.open_local_stream(buf));
+                    block_on_interruptible(&self.rt, boxed_stream.read_to_end(&mut buf))?.map_err(
+                        |err| {
+                            FdwError::remote_query(format!(
+                                "read compressed parquet file failed: {}",
+                                err
+                            ))
+                        },
+                    )?;
+                    block_on_interruptible(&self.rt, s3parquet.open_local_stream(buf))??;
                 } else {
                     // open async read stream
-                    self.rt.block_on(s3parquet.open_async_stream(
-                        client,
-                        &bucket,
-                        &object,
-                        &self.tgt_cols,
-                    ));
+                    block_on_interruptible(
+                        &self.rt,
+                        s3parquet.open_async_stream(client, &bucket, &object, &self.tgt_cols),
+                    )??;
                 }
                 return Ok(());
             }
@@ -340,13 +346,9 @@ impl ForeignDataWrapper<S3FdwError> for S3Fdw {
             if let Parser::Csv(_) = self.parser {
                 if has_header {
                     let mut header = String::new();
-                    if let Err(err) = self.rt.block_on(rdr.read_line(&mut header)) {
-                        report_error(
-                            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                            &format!("fetch csv file failed: {}", err),
-                        );
-                        return Ok(());
-                    }
+                    block_on_interruptible(&self.rt, rdr.read_line(&mut header))?.map_err(
+                        |err| FdwError::remote_query(format!("fetch csv file failed: {}", err)),
+                    )?;
                 }
             }
 
@@ -359,10 +361,10 @@ impl ForeignDataWrapper<S3FdwError> for S3Fdw {
     fn iter_scan(&mut self, row: &mut Row) -> Result<Option<()>, S3FdwError> {
         // read parquet record
         if let Parser::Parquet(ref mut s3parquet) = &mut self.parser {
-            if self.rt.block_on(s3parquet.refill()).is_none() {
+            if block_on_interruptible(&self.rt, s3parquet.refill())??.is_none() {
                 return Ok(None);
             }
-            let ret = s3parquet.read_into_row(row, &self.tgt_cols);
+            let ret = s3parquet.read_into_row(row, &self.tgt_cols)?;
             if ret.is_some() {
                 self.rows_out += 1;
             } else {
@@ -373,7 +375,7 @@ impl ForeignDataWrapper<S3FdwError> for S3Fdw {
 
         // read csv or jsonl record
         loop {
-            if self.refill().is_none() {
+            if self.refill()?.is_none() {
                 break;
             }
 
@@ -381,28 +383,19 @@ impl ForeignDataWrapper<S3FdwError> for S3Fdw {
             match &mut self.parser {
                 Parser::Csv(rdr) => {
                     let mut record = csv::StringRecord::new();
-                    match rdr.read_record(&mut record) {
-                        Ok(result) => {
-                            if result {
-                                for col in &self.tgt_cols {
-                                    let cell =
-                                        record.get(col.num - 1).map(|s| Cell::String(s.to_owned()));
-                                    row.push(&col.name, cell);
-                                }
-                                self.rows_out += 1;
-                                return Ok(Some(()));
-                            } else {
-                                // no more records left in the local buffer, refill from remote
-                                self.buf.clear();
-                            }
-                        }
-                        Err(err) => {
-                            report_error(
-                                PgSqlErrorCode::ERRCODE_FDW_ERROR,
-                                &format!("read csv record failed: {}", err),
-                            );
-                            break;
+                    let result = rdr.read_record(&mut record).map_err(|err| {
+                        FdwError::type_conversion(format!("read csv record failed: {}", err))
+                    })?;
+                    if result {
+                        for col in &self.tgt_cols {
+                            let cell = record.get(col.num - 1).map(|s| Cell::String(s.to_owned()));
+                            row.push(&col.name, cell);
                         }
+                        self.rows_out += 1;
+                        return Ok(Some(()));
+                    } else {
+                        // no more records left in the local buffer, refill from remote
+                        self.buf.clear();
                     }
                 }
                 Parser::JsonLine(records) => {
@@ -450,24 +443,19 @@ impl ForeignDataWrapper<S3FdwError> for S3Fdw {
         Ok(None)
     }
 
+    fn re_scan(&mut self) -> Result<(), S3FdwError> {
+        // the object is read as a stream, so it is requested again from the
+        // beginning
+        let columns = std::mem::take(&mut self.tgt_cols);
+        let options = std::mem::take(&mut self.scan_opts);
+        self.end_scan()?;
+        self.begin_scan(&[], &columns, &[], &None, &options)
+    }
+
     fn end_scan(&mut self) -> Result<(), S3FdwError> {
         // release local resources
         self.rdr.take();
         self.parser = Parser::JsonLine(VecDeque::new());
         Ok(())
     }
-
-    fn validator(
-        options: Vec<Option<String>>,
-        catalog: Option<pg_sys::Oid>,
-    ) -> Result<(), S3FdwError> {
-        if let Some(oid) = catalog {
-            if oid == FOREIGN_TABLE_RELATION_ID {
-                check_options_contain(&options, "uri");
-                check_options_contain(&options, "format");
-            }
-        }
-
-        Ok(())
-    }
 }
@@ -190,6 +190,22 @@ mod tests {
 
             check_parquet_table("s3_test_table_parquet");
             check_parquet_table("s3_test_table_parquet_gz");
+
+            // the object is read as a stream, so a re-scan must open it again
+            // and return the same rows
+            let results = c
+                .select(
+                    "SELECT v.n, (SELECT count(*) FROM s3_test_table_csv WHERE v.n > 0) AS cnt
+                     FROM (VALUES (1), (2)) v(n) ORDER BY v.n",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<i64, _>("cnt").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results.len(), 2);
+            assert!(results[0] > 0);
+            assert_eq!(results[0], results[1]);
         });
     }
 }
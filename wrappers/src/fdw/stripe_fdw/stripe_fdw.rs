@@ -1,38 +1,37 @@
+use crate::http::{self, HttpOptions};
 use crate::stats;
 use pgrx::pg_sys::panic::ErrorReport;
-use pgrx::{datum::datetime_support::to_timestamp, pg_sys, prelude::PgSqlErrorCode, JsonB};
-use reqwest::{self, header, StatusCode, Url};
-use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
-use serde_json::{json, Map as JsonMap, Number, Value as JsonValue};
+use pgrx::JsonB;
+use reqwest::{header, StatusCode, Url};
+use reqwest_middleware::ClientWithMiddleware;
+use serde_json::{Map as JsonMap, Number, Value as JsonValue};
 use std::collections::HashMap;
+use std::time::Instant;
 
 use supabase_wrappers::prelude::*;
 use thiserror::Error;
 
-fn create_client(api_key: &str) -> ClientWithMiddleware {
+fn create_client(api_key: &str, opts: &HttpOptions) -> Result<ClientWithMiddleware, FdwError> {
     let mut headers = header::HeaderMap::new();
     let value = format!("Bearer {}", api_key);
-    let mut auth_value = header::HeaderValue::from_str(&value).unwrap();
+    let mut auth_value =
+        header::HeaderValue::from_str(&value).map_err(|_| OptionsError::InvalidValue {
+            name: "api_key",
+            value: "(not shown)".to_owned(),
+            ty: "header value",
+        })?;
     auth_value.set_sensitive(true);
     headers.insert(header::AUTHORIZATION, auth_value);
-    let client = reqwest::Client::builder()
-        .default_headers(headers)
-        .build()
-        .unwrap();
-    let retry_policy = ExponentialBackoff::builder().build_with_max_retries(3);
-    ClientBuilder::new(client)
-        .with(RetryTransientMiddleware::new_with_policy(retry_policy))
-        .build()
+    http::create_client(headers, opts)
 }
 
 fn body_to_rows(
     resp_body: &str,
-    normal_cols: Vec<(&str, &str)>,
+    normal_cols: Vec<&str>,
     tgt_cols: &[Column],
-) -> (Vec<Row>, Option<String>, Option<bool>) {
+) -> Result<(Vec<Row>, Option<String>, Option<bool>), FdwError> {
     let mut result = Vec::new();
-    let value: JsonValue = serde_json::from_str(resp_body).unwrap();
+    let value = parse_body(resp_body)?;
     let is_list = value
         .as_object()
         .and_then(|v| v.get("object"))
@@ -57,8 +56,15 @@ fn body_to_rows(
                 .as_object()
                 .and_then(|v| v.get(*bal_type))
                 .and_then(|v| v.as_array())
-                .map(|v| v[0].as_object().unwrap().clone())
-                .unwrap();
+                .and_then(|v| v.first())
+                .and_then(|v| v.as_object())
+                .cloned()
+                .ok_or_else(|| {
+                    FdwError::type_conversion(format!(
+                        "invalid response body: '{}' balance not found",
+                        bal_type
+                    ))
+                })?;
             obj.insert(
                 "balance_type".to_string(),
                 JsonValue::String(bal_type.to_string()),
@@ -71,14 +77,18 @@ fn body_to_rows(
         value
             .as_object()
             .map(|v| vec![JsonValue::Object(v.clone())])
-            .unwrap()
+            .ok_or_else(|| {
+                FdwError::type_conversion("invalid response body: expect a JSON object")
+            })?
     };
     let objs = if is_list {
         value
             .as_object()
             .and_then(|v| v.get("data"))
             .and_then(|v| v.as_array())
-            .unwrap()
+            .ok_or_else(|| {
+                FdwError::type_conversion("invalid response body: 'data' array not found")
+            })?
     } else {
         &single_wrapped
     };
@@ -87,28 +97,17 @@ fn body_to_rows(
     for obj in objs {
         let mut row = Row::new();
 
-        // extract normal columns
+        // extract normal columns, timestamps are Unix epoch in Stripe objects
         for tgt_col in tgt_cols {
-            if let Some((col_name, col_type)) = normal_cols.iter().find(|(c, _)| c == &tgt_col.name)
-            {
-                let cell = obj
-                    .as_object()
-                    .and_then(|v| v.get(*col_name))
-                    .and_then(|v| match *col_type {
-                        "bool" => v.as_bool().map(Cell::Bool),
-                        "i64" => v.as_i64().map(Cell::I64),
-                        "string" => v.as_str().map(|a| Cell::String(a.to_owned())),
-                        "timestamp" => v.as_i64().map(|a| {
-                            let ts = to_timestamp(a as f64);
-                            Cell::Timestamp(ts.to_utc())
-                        }),
-                        _ => None,
-                    });
-                row.push(col_name, cell);
+            if normal_cols.contains(&tgt_col.name.as_str()) {
+                let cell = match obj.as_object().and_then(|v| v.get(&tgt_col.name)) {
+                    Some(v) => Cell::from_json(v, tgt_col)?,
+                    None => None,
+                };
+                row.push(&tgt_col.name, cell);
             } else if &tgt_col.name == "attrs" {
                 // put all properties into 'attrs' JSON column
-                let attrs = serde_json::from_str(&obj.to_string()).unwrap();
-                row.push("attrs", Some(Cell::Json(JsonB(attrs))));
+                row.push("attrs", Some(Cell::Json(JsonB(obj.clone()))));
             }
         }
 
@@ -130,10 +129,10 @@ fn body_to_rows(
         .and_then(|v| v.get("has_more"))
         .and_then(|v| v.as_bool());
 
-    (result, cursor, has_more)
+    Ok((result, cursor, has_more))
 }
 
-fn row_to_body(row: &Row) -> JsonValue {
+fn row_to_body(row: &Row) -> Result<JsonValue, FdwError> {
     let mut map = JsonMap::new();
 
     for (col_name, cell) in row.iter() {
@@ -158,17 +157,16 @@ fn row_to_body(row: &Row) -> JsonValue {
                     }
                 }
                 _ => {
-                    report_error(
-                        PgSqlErrorCode::ERRCODE_FDW_INVALID_DATA_TYPE,
-                        &format!("field type {:?} not supported", cell),
-                    );
-                    return JsonValue::Null;
+                    return Err(FdwError::type_conversion(format!(
+                        "field type {:?} not supported",
+                        cell
+                    )));
                 }
             }
         }
     }
 
-    JsonValue::Object(map)
+    Ok(JsonValue::Object(map))
 }
 
 fn pushdown_quals(
@@ -222,63 +220,92 @@ fn pushdown_quals(
     }
 }
 
-// get stats metadata
-#[inline]
-fn get_stats_metadata() -> JsonB {
-    stats::get_metadata(StripeFdw::FDW_NAME).unwrap_or(JsonB(json!({
-        "request_cnt": 0i64,
-    })))
-}
-
-// save stats metadata
-#[inline]
-fn set_stats_metadata(stats_metadata: JsonB) {
-    stats::set_metadata(StripeFdw::FDW_NAME, Some(stats_metadata));
-}
-
-// increase stats metadata 'request_cnt' by 1
-#[inline]
-fn inc_stats_request_cnt(stats_metadata: &mut JsonB) {
-    if let Some(v) = stats_metadata.0.get_mut("request_cnt") {
-        *v = (v.as_i64().unwrap() + 1).into();
-    };
+// convert a failed request to error, the error kind is decided by response status
+fn request_error(err: impl Into<reqwest_middleware::Error>) -> FdwError {
+    let err = err.into();
+    let msg = format!("request failed: {}", err);
+    match &err {
+        reqwest_middleware::Error::Reqwest(e) => match e.status() {
+            Some(status) => FdwError::from_http_status(status.as_u16(), msg),
+            None => FdwError::connection_failed(msg),
+        },
+        reqwest_middleware::Error::Middleware(_) => FdwError::remote_query(msg),
+    }
 }
 
-macro_rules! report_request_error {
-    ($err:ident) => {{
-        report_error(
-            PgSqlErrorCode::ERRCODE_FDW_ERROR,
-            &format!("request failed: {}", $err),
-        );
-    }};
+// parse response body of a modify request
+fn parse_body(body: &str) -> Result<JsonValue, FdwError> {
+    serde_json::from_str(body)
+        .map_err(|err| FdwError::type_conversion(format!("invalid response body: {}", err)))
 }
 
 #[wrappers_fdw(
-    version = "0.1.7",
+    version = "0.1.8",
     author = "Supabase",
     website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/stripe_fdw",
-    error_type = "StripeFdwError"
+    error_type = "StripeFdwError",
+    options(
+        server(api_url, api_key(secret)),
+        table(object(required), rowid_column),
+        include(crate::http::HTTP_OPTION_SPECS)
+    )
 )]
 pub(crate) struct StripeFdw {
     rt: Runtime,
     base_url: Url,
     client: Option<ClientWithMiddleware>,
-    scan_result: Option<Vec<Row>>,
+    pager: Paginator,
+    quals: Vec<Qual>,
+    tgt_cols: Vec<Column>,
     obj: String,
     rowid_col: String,
+
+    // first page url and metrics for EXPLAIN
+    remote_url: Option<Url>,
+    metrics: RemoteMetrics,
 }
 
 impl StripeFdw {
     const FDW_NAME: &str = "StripeFdw";
 
+    // maximum page size limit for Stripe API
+    const PAGE_SIZE: i64 = 100;
+
+    // join a path to the base url, the path can be an object name from table
+    // options or a row id from the modified row
+    fn join_url(&self, path: &str) -> Result<Url, FdwError> {
+        self.base_url.join(path).map_err(|err| {
+            FdwError::type_conversion(format!("cannot build request URL from '{}': {}", path, err))
+        })
+    }
+
+    // url of an object by its id
+    fn object_url(&self, id: &Cell) -> Result<Url, FdwError> {
+        match id {
+            Cell::String(id) => {
+                let url = self.join_url(&format!("{}/", self.obj))?;
+                url.join(id).map_err(|err| {
+                    FdwError::type_conversion(format!(
+                        "cannot build request URL from '{}': {}",
+                        id, err
+                    ))
+                })
+            }
+            _ => Err(FdwError::type_conversion(format!(
+                "rowid column '{}' must be a text column",
+                self.rowid_col
+            ))),
+        }
+    }
+
     fn build_url(
         &self,
         obj: &str,
         quals: &[Qual],
         page_size: i64,
         cursor: &Option<String>,
-    ) -> Option<Url> {
-        let mut url = self.base_url.join(obj).unwrap();
+    ) -> Result<Url, FdwError> {
+        let mut url = self.join_url(obj)?;
 
         // pushdown quals other than id
         // ref: https://stripe.com/docs/api/[object]/list
@@ -307,16 +334,65 @@ impl StripeFdw {
             "transfers" => vec!["destination"],
             "checkout/sessions" => vec!["customer", "payment_intent", "subscription"],
             _ => {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_TABLE_NOT_FOUND,
-                    &format!("'{}' object is not implemented", obj),
-                );
-                return None;
+                return Err(FdwError::not_found(format!(
+                    "'{}' object is not implemented",
+                    obj
+                )));
             }
         };
         pushdown_quals(&mut url, obj, quals, fields, page_size, cursor);
 
-        Some(url)
+        Ok(url)
+    }
+
+    // fetch a page of the scanned object, the next page is requested by the
+    // id of last object in current page
+    fn fetch_page(&mut self, req: PageRequest) -> Result<Page, FdwError> {
+        let client = match &self.client {
+            Some(client) => client,
+            None => return Ok(Page::default()),
+        };
+        let cursor = match req.next {
+            Some(NextPage::Cursor(cursor)) => Some(cursor.clone()),
+            _ => None,
+        };
+        let page_size = req.page_size.unwrap_or(Self::PAGE_SIZE);
+        let url = self.build_url(&self.obj, &self.quals, page_size, &cursor)?;
+
+        // make api call
+        let started = Instant::now();
+        let resp =
+            block_on_interruptible(&self.rt, client.get(url).send())?.map_err(request_error)?;
+        stats::record_request(Self::FDW_NAME, started);
+        stats::inc_stats(
+            Self::FDW_NAME,
+            stats::Metric::BytesIn,
+            resp.content_length().unwrap_or(0) as i64,
+        );
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            // if it is 404 error, we should treat it as an empty
+            // result rather than a request error
+            self.metrics.record_request(started, 0);
+            return Ok(Page::default());
+        }
+
+        let resp = resp.error_for_status().map_err(request_error)?;
+        let body = block_on_interruptible(&self.rt, resp.text())?.map_err(request_error)?;
+        self.metrics.record_request(started, body.len());
+        self.metrics.record_page();
+        let (rows, starting_after, has_more) =
+            self.resp_to_rows(&self.obj, &body, &self.tgt_cols)?;
+
+        // save stats
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsIn, rows.len() as i64);
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsOut, rows.len() as i64);
+
+        let next = match has_more {
+            Some(true) => starting_after.map(NextPage::Cursor),
+            _ => None,
+        };
+        Ok(Page::new(rows, next))
     }
 
     // convert response body text to rows
@@ -325,305 +401,265 @@ impl StripeFdw {
         obj: &str,
         resp_body: &str,
         tgt_cols: &[Column],
-    ) -> (Vec<Row>, Option<String>, Option<bool>) {
-        match obj {
+    ) -> Result<(Vec<Row>, Option<String>, Option<bool>), FdwError> {
+        let ret = match obj {
             "accounts" => body_to_rows(
                 resp_body,
-                vec![
-                    ("id", "string"),
-                    ("business_type", "string"),
-                    ("country", "string"),
-                    ("email", "string"),
-                    ("type", "string"),
-                    ("created", "timestamp"),
-                ],
+                vec!["id", "business_type", "country", "email", "type", "created"],
                 tgt_cols,
             ),
             "balance" => body_to_rows(
                 resp_body,
-                vec![
-                    ("balance_type", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                ],
+                vec!["balance_type", "amount", "currency"],
                 tgt_cols,
             ),
             "balance_transactions" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("description", "string"),
-                    ("fee", "i64"),
-                    ("net", "i64"),
-                    ("status", "string"),
-                    ("type", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "amount",
+                    "currency",
+                    "description",
+                    "fee",
+                    "net",
+                    "status",
+                    "type",
+                    "created",
                 ],
                 tgt_cols,
             ),
             "charges" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("customer", "string"),
-                    ("description", "string"),
-                    ("invoice", "string"),
-                    ("payment_intent", "string"),
-                    ("status", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "amount",
+                    "currency",
+                    "customer",
+                    "description",
+                    "invoice",
+                    "payment_intent",
+                    "status",
+                    "created",
                 ],
                 tgt_cols,
             ),
             "customers" => body_to_rows(
                 resp_body,
-                vec![
-                    ("id", "string"),
-                    ("email", "string"),
-                    ("name", "string"),
-                    ("description", "string"),
-                    ("created", "timestamp"),
-                ],
+                vec!["id", "email", "name", "description", "created"],
                 tgt_cols,
             ),
             "disputes" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("charge", "string"),
-                    ("payment_intent", "string"),
-                    ("reason", "string"),
-                    ("status", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "amount",
+                    "currency",
+                    "charge",
+                    "payment_intent",
+                    "reason",
+                    "status",
+                    "created",
                 ],
                 tgt_cols,
             ),
             "events" => body_to_rows(
                 resp_body,
-                vec![
-                    ("id", "string"),
-                    ("type", "string"),
-                    ("api_version", "string"),
-                    ("created", "timestamp"),
-                ],
+                vec!["id", "type", "api_version", "created"],
                 tgt_cols,
             ),
             "files" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("filename", "string"),
-                    ("purpose", "string"),
-                    ("title", "string"),
-                    ("size", "i64"),
-                    ("type", "string"),
-                    ("url", "string"),
-                    ("created", "timestamp"),
-                    ("expires_at", "timestamp"),
+                    "id",
+                    "filename",
+                    "purpose",
+                    "title",
+                    "size",
+                    "type",
+                    "url",
+                    "created",
+                    "expires_at",
                 ],
                 tgt_cols,
             ),
             "file_links" => body_to_rows(
                 resp_body,
-                vec![
-                    ("id", "string"),
-                    ("file", "string"),
-                    ("url", "string"),
-                    ("created", "timestamp"),
-                    ("expired", "bool"),
-                    ("expires_at", "timestamp"),
-                ],
+                vec!["id", "file", "url", "created", "expired", "expires_at"],
                 tgt_cols,
             ),
             "invoices" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("customer", "string"),
-                    ("subscription", "string"),
-                    ("status", "string"),
-                    ("total", "i64"),
-                    ("currency", "string"),
-                    ("period_start", "timestamp"),
-                    ("period_end", "timestamp"),
+                    "id",
+                    "customer",
+                    "subscription",
+                    "status",
+                    "total",
+                    "currency",
+                    "period_start",
+                    "period_end",
                 ],
                 tgt_cols,
             ),
             "mandates" => body_to_rows(
                 resp_body,
-                vec![
-                    ("id", "string"),
-                    ("payment_method", "string"),
-                    ("status", "string"),
-                    ("type", "string"),
-                ],
+                vec!["id", "payment_method", "status", "type"],
                 tgt_cols,
             ),
             "payment_intents" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("customer", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("payment_method", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "customer",
+                    "amount",
+                    "currency",
+                    "payment_method",
+                    "created",
                 ],
                 tgt_cols,
             ),
             "payouts" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("arrival_date", "timestamp"),
-                    ("description", "string"),
-                    ("statement_descriptor", "string"),
-                    ("status", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "amount",
+                    "currency",
+                    "arrival_date",
+                    "description",
+                    "statement_descriptor",
+                    "status",
+                    "created",
                 ],
                 tgt_cols,
             ),
             "prices" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("active", "bool"),
-                    ("currency", "string"),
-                    ("product", "string"),
-                    ("unit_amount", "i64"),
-                    ("type", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "active",
+                    "currency",
+                    "product",
+                    "unit_amount",
+                    "type",
+                    "created",
                 ],
                 tgt_cols,
             ),
             "products" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("name", "string"),
-                    ("active", "bool"),
-                    ("default_price", "string"),
-                    ("description", "string"),
-                    ("created", "timestamp"),
-                    ("updated", "timestamp"),
+                    "id",
+                    "name",
+                    "active",
+                    "default_price",
+                    "description",
+                    "created",
+                    "updated",
                 ],
                 tgt_cols,
             ),
             "refunds" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("charge", "string"),
-                    ("payment_intent", "string"),
-                    ("reason", "string"),
-                    ("status", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "amount",
+                    "currency",
+                    "charge",
+                    "payment_intent",
+                    "reason",
+                    "status",
+                    "created",
                 ],
                 tgt_cols,
             ),
             "setup_attempts" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("application", "string"),
-                    ("customer", "string"),
-                    ("on_behalf_of", "string"),
-                    ("payment_method", "string"),
-                    ("setup_intent", "string"),
-                    ("status", "string"),
-                    ("usage", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "application",
+                    "customer",
+                    "on_behalf_of",
+                    "payment_method",
+                    "setup_intent",
+                    "status",
+                    "usage",
+                    "created",
                 ],
                 tgt_cols,
             ),
             "setup_intents" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("client_secret", "string"),
-                    ("customer", "string"),
-                    ("description", "string"),
-                    ("payment_method", "string"),
-                    ("status", "string"),
-                    ("usage", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "client_secret",
+                    "customer",
+                    "description",
+                    "payment_method",
+                    "status",
+                    "usage",
+                    "created",
                 ],
                 tgt_cols,
             ),
             "subscriptions" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("customer", "string"),
-                    ("currency", "string"),
-                    ("current_period_start", "timestamp"),
-                    ("current_period_end", "timestamp"),
+                    "id",
+                    "customer",
+                    "currency",
+                    "current_period_start",
+                    "current_period_end",
                 ],
                 tgt_cols,
             ),
             "tokens" => body_to_rows(
                 resp_body,
-                vec![
-                    ("id", "string"),
-                    ("type", "string"),
-                    ("client_ip", "string"),
-                    ("used", "bool"),
-                    ("created", "timestamp"),
-                ],
+                vec!["id", "type", "client_ip", "used", "created"],
                 tgt_cols,
             ),
             "topups" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("description", "string"),
-                    ("status", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "amount",
+                    "currency",
+                    "description",
+                    "status",
+                    "created",
                 ],
                 tgt_cols,
             ),
             "transfers" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("amount", "i64"),
-                    ("currency", "string"),
-                    ("description", "string"),
-                    ("destination", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "amount",
+                    "currency",
+                    "description",
+                    "destination",
+                    "created",
                 ],
                 tgt_cols,
             ),
             "checkout/sessions" => body_to_rows(
                 resp_body,
                 vec![
-                    ("id", "string"),
-                    ("customer", "string"),
-                    ("payment_intent", "string"),
-                    ("subscription", "string"),
-                    ("created", "timestamp"),
+                    "id",
+                    "customer",
+                    "payment_intent",
+                    "subscription",
+                    "created",
                 ],
                 tgt_cols,
             ),
             _ => {
-                report_error(
-                    PgSqlErrorCode::ERRCODE_FDW_TABLE_NOT_FOUND,
-                    &format!("'{}' object is not implemented", obj),
-                );
-                (Vec::new(), None, None)
+                return Err(FdwError::not_found(format!(
+                    "'{}' object is not implemented",
+                    obj
+                )));
             }
-        }
+        };
+        Ok(ret?)
     }
 }
 
@@ -631,21 +667,31 @@ impl StripeFdw {
 enum StripeFdwError {
     #[error("{0}")]
     CreateRuntimeError(#[from] CreateRuntimeError),
+
+    #[error("{0}")]
+    OptionsError(#[from] OptionsError),
+
+    #[error("{0}")]
+    FdwError(#[from] FdwError),
 }
 
 impl From<StripeFdwError> for ErrorReport {
     fn from(value: StripeFdwError) -> Self {
-        match value {
-            StripeFdwError::CreateRuntimeError(e) => e.into(),
-        }
+        let err = match value {
+            StripeFdwError::CreateRuntimeError(e) => FdwError::from(e),
+            StripeFdwError::OptionsError(e) => FdwError::from(e),
+            StripeFdwError::FdwError(e) => e,
+        };
+        stats::record_error(StripeFdw::FDW_NAME, err.kind());
+        err.into()
     }
 }
 
 impl ForeignDataWrapper<StripeFdwError> for StripeFdw {
     fn new(options: &HashMap<String, String>) -> Result<Self, StripeFdwError> {
-        let base_url = options
-            .get("api_url")
-            .map(|t| t.to_owned())
+        let opts = StripeFdwServerOptions::from_options(options)?;
+        let base_url = opts
+            .api_url
             // Ensure trailing slash is always present, otherwise /v1 will get obliterated when
             // joined with object
             .map(|s| {
@@ -656,25 +702,44 @@ impl ForeignDataWrapper<StripeFdwError> for StripeFdw {
                 }
             })
             .unwrap_or_else(|| "https://api.stripe.com/v1/".to_string());
-        let client = match options.get("api_key") {
-            Some(api_key) => Some(create_client(api_key)),
-            None => require_option("api_key_id", options)
-                .and_then(|key_id| get_vault_secret(&key_id))
-                .map(|api_key| create_client(&api_key)),
-        };
+        let base_url = Url::parse(&base_url).map_err(|_| OptionsError::InvalidValue {
+            name: "api_url",
+            value: base_url.clone(),
+            ty: "URL",
+        })?;
+        let api_key = opts.api_key.ok_or(OptionsError::MissingOption("api_key"))?;
+        let http_opts = HttpOptions::from_options(options)?;
 
         stats::inc_stats(Self::FDW_NAME, stats::Metric::CreateTimes, 1);
 
         Ok(StripeFdw {
             rt: create_async_runtime()?,
-            base_url: Url::parse(&base_url).unwrap(),
-            client,
-            scan_result: None,
+            base_url,
+            client: Some(create_client(&api_key, &http_opts)?),
+            pager: Paginator::default(),
+            quals: Vec::new(),
+            tgt_cols: Vec::new(),
             obj: String::default(),
             rowid_col: String::default(),
+            remote_url: None,
+            metrics: RemoteMetrics::default(),
         })
     }
 
+    fn get_rel_size(
+        &mut self,
+        quals: &[Qual],
+        _columns: &[Column],
+        _sorts: &[Sort],
+        _limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+    ) -> Result<(i64, i32), StripeFdwError> {
+        // prepare the first page url for EXPLAIN
+        self.obj = StripeFdwTableOptions::from_options(options)?.object;
+        self.remote_url = Some(self.build_url(&self.obj, quals, Self::PAGE_SIZE, &None)?);
+        Ok((0, 0))
+    }
+
     fn begin_scan(
         &mut self,
         quals: &[Qual],
@@ -683,272 +748,159 @@ impl ForeignDataWrapper<StripeFdwError> for StripeFdw {
         limit: &Option<Limit>,
         options: &HashMap<String, String>,
     ) -> Result<(), StripeFdwError> {
-        let obj = if let Some(name) = require_option("object", options) {
-            name
-        } else {
-            return Ok(());
-        };
-
-        if let Some(client) = &self.client {
-            let page_size = 100; // maximum page size limit for Stripe API
-            let page_cnt = if let Some(limit) = limit {
-                if limit.count == 0 {
-                    return Ok(());
-                }
-                (limit.offset + limit.count) / page_size + 1
-            } else {
-                // if no limit specified, fetch all records
-                i64::MAX
-            };
-            let mut page = 0;
-            let mut result = Vec::new();
-            let mut cursor: Option<String> = None;
-            let mut stats_metadata = get_stats_metadata();
-
-            while page < page_cnt {
-                // build url
-                let url = self.build_url(&obj, quals, page_size, &cursor);
-                if url.is_none() {
-                    return Ok(());
-                }
-                let url = url.unwrap();
-
-                // make api call
-                inc_stats_request_cnt(&mut stats_metadata);
-                match self.rt.block_on(client.get(url).send()) {
-                    Ok(resp) => {
-                        stats::inc_stats(
-                            Self::FDW_NAME,
-                            stats::Metric::BytesIn,
-                            resp.content_length().unwrap_or(0) as i64,
-                        );
-
-                        if resp.status() == StatusCode::NOT_FOUND {
-                            // if it is 404 error, we should treat it as an empty
-                            // result rather than a request error
-                            break;
-                        }
+        let obj = StripeFdwTableOptions::from_options(options)?.object;
+        self.remote_url = Some(self.build_url(&obj, quals, Self::PAGE_SIZE, &None)?);
+        self.obj = obj;
+        self.quals = quals.to_vec();
+        self.tgt_cols = columns.to_vec();
+        self.metrics = RemoteMetrics::default();
+
+        // pages are fetched lazily in iter_scan, if no limit specified, fetch
+        // all records
+        self.pager = Paginator::new()
+            .with_page_size(Self::PAGE_SIZE)
+            .with_limit(limit);
+        if limit.as_ref().map_or(false, |limit| limit.count == 0) {
+            self.pager = self.pager.with_max_rows(0);
+        }
 
-                        match resp.error_for_status() {
-                            Ok(resp) => {
-                                let body = self.rt.block_on(resp.text()).unwrap();
-                                let (rows, starting_after, has_more) =
-                                    self.resp_to_rows(&obj, &body, columns);
-                                if rows.is_empty() {
-                                    break;
-                                }
-                                result.extend(rows);
-                                match has_more {
-                                    Some(has_more) => {
-                                        if !has_more {
-                                            break;
-                                        }
-                                    }
-                                    None => break,
-                                }
-                                cursor = starting_after;
-                            }
-                            Err(err) => {
-                                report_request_error!(err);
-                                return Ok(());
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        report_request_error!(err);
-                        return Ok(());
-                    }
-                }
+        Ok(())
+    }
 
-                page += 1;
-            }
+    fn explain(&self, verbose: bool) -> Vec<(String, String)> {
+        let mut ret = Vec::new();
+        if let Some(url) = &self.remote_url {
+            ret.push(("Remote URL".to_string(), url.to_string()));
+        }
+        if verbose {
+            ret.push(("Remote Object".to_string(), self.obj.clone()));
+        }
+        ret
+    }
 
-            // save stats
-            stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsIn, result.len() as i64);
-            stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsOut, result.len() as i64);
-            set_stats_metadata(stats_metadata);
+    fn remote_metrics(&self) -> Option<RemoteMetrics> {
+        Some(self.metrics.clone())
+    }
 
-            self.scan_result = Some(result);
+    fn check_connection(&self) -> Result<Option<ServerInfo>, StripeFdwError> {
+        let mut info = ServerInfo::default();
+        if let Some(ref client) = self.client {
+            // getting the balance is cheap and needs a valid api key
+            let url = self.join_url("balance")?;
+            let resp = block_on_interruptible(&self.rt, client.get(url).send())?
+                .map_err(request_error)?
+                .error_for_status()
+                .map_err(request_error)?;
+            info.version = resp
+                .headers()
+                .get("stripe-version")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_owned());
         }
-
-        Ok(())
+        Ok(Some(info))
     }
 
     fn iter_scan(&mut self, row: &mut Row) -> Result<Option<()>, StripeFdwError> {
-        if let Some(ref mut result) = self.scan_result {
-            if !result.is_empty() {
-                return Ok(result
-                    .drain(0..1)
-                    .last()
-                    .map(|src_row| row.replace_with(src_row)));
-            }
-        }
-        Ok(None)
+        let mut pager = std::mem::take(&mut self.pager);
+        let result = pager.next_row(row, |req| self.fetch_page(req));
+        self.pager = pager;
+        Ok(result?)
+    }
+
+    fn re_scan(&mut self) -> Result<(), StripeFdwError> {
+        // fetch the pages again from the first page
+        self.pager.reset();
+        Ok(())
     }
 
     fn end_scan(&mut self) -> Result<(), StripeFdwError> {
-        self.scan_result.take();
+        self.pager = Paginator::default();
         Ok(())
     }
 
     fn begin_modify(&mut self, options: &HashMap<String, String>) -> Result<(), StripeFdwError> {
-        self.obj = require_option("object", options).unwrap_or_default();
-        self.rowid_col = require_option("rowid_column", options).unwrap_or_default();
+        let opts = StripeFdwTableOptions::from_options(options)?;
+        self.obj = opts.object;
+        self.rowid_col = opts
+            .rowid_column
+            .ok_or(OptionsError::MissingOption("rowid_column"))?;
         Ok(())
     }
 
     fn insert(&mut self, src: &Row) -> Result<(), StripeFdwError> {
-        if let Some(ref mut client) = self.client {
-            let url = self.base_url.join(&self.obj).unwrap();
-            let body = row_to_body(src);
-            if body.is_null() {
-                return Ok(());
-            }
-
-            let mut stats_metadata = get_stats_metadata();
+        if let Some(ref client) = self.client {
+            let url = self.join_url(&self.obj)?;
+            let body = row_to_body(src)?;
 
             // call Stripe API
-            inc_stats_request_cnt(&mut stats_metadata);
-            match self.rt.block_on(client.post(url).form(&body).send()) {
-                Ok(resp) => match resp.error_for_status() {
-                    Ok(resp) => {
-                        stats::inc_stats(
-                            Self::FDW_NAME,
-                            stats::Metric::BytesIn,
-                            resp.content_length().unwrap_or(0) as i64,
-                        );
-                        let body = self.rt.block_on(resp.text()).unwrap();
-                        let json: JsonValue = serde_json::from_str(&body).unwrap();
-                        if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
-                            report_info(&format!("inserted {} {}", self.obj, id));
-                        }
-                    }
-                    Err(err) => {
-                        report_request_error!(err);
-                        return Ok(());
-                    }
-                },
-                Err(err) => {
-                    report_request_error!(err);
-                    return Ok(());
-                }
+            let started = Instant::now();
+            let resp = block_on_interruptible(&self.rt, client.post(url).form(&body).send())?
+                .map_err(request_error)?
+                .error_for_status()
+                .map_err(request_error)?;
+            stats::record_request(Self::FDW_NAME, started);
+            stats::inc_stats(
+                Self::FDW_NAME,
+                stats::Metric::BytesIn,
+                resp.content_length().unwrap_or(0) as i64,
+            );
+            let body = block_on_interruptible(&self.rt, resp.text())?.map_err(request_error)?;
+            let json = parse_body(&body)?;
+            if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
+                report_info(&format!("inserted {} {}", self.obj, id));
             }
-
-            set_stats_metadata(stats_metadata);
         }
         Ok(())
     }
 
     fn update(&mut self, rowid: &Cell, new_row: &Row) -> Result<(), StripeFdwError> {
-        if let Some(ref mut client) = self.client {
-            let mut stats_metadata = get_stats_metadata();
-
-            match rowid {
-                Cell::String(rowid) => {
-                    let url = self
-                        .base_url
-                        .join(&format!("{}/", self.obj))
-                        .unwrap()
-                        .join(rowid)
-                        .unwrap();
-                    let body = row_to_body(new_row);
-                    if body.is_null() {
-                        return Ok(());
-                    }
+        if let Some(ref client) = self.client {
+            let url = self.object_url(rowid)?;
+            let body = row_to_body(new_row)?;
 
-                    // call Stripe API
-                    inc_stats_request_cnt(&mut stats_metadata);
-                    match self.rt.block_on(client.post(url).form(&body).send()) {
-                        Ok(resp) => match resp.error_for_status() {
-                            Ok(resp) => {
-                                stats::inc_stats(
-                                    Self::FDW_NAME,
-                                    stats::Metric::BytesIn,
-                                    resp.content_length().unwrap_or(0) as i64,
-                                );
-                                let body = self.rt.block_on(resp.text()).unwrap();
-                                let json: JsonValue = serde_json::from_str(&body).unwrap();
-                                if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
-                                    report_info(&format!("updated {} {}", self.obj, id));
-                                }
-                            }
-                            Err(err) => {
-                                report_request_error!(err);
-                                return Ok(());
-                            }
-                        },
-                        Err(err) => {
-                            report_request_error!(err);
-                            return Ok(());
-                        }
-                    }
-                }
-                _ => unreachable!(),
+            // call Stripe API
+            let started = Instant::now();
+            let resp = block_on_interruptible(&self.rt, client.post(url).form(&body).send())?
+                .map_err(request_error)?
+                .error_for_status()
+                .map_err(request_error)?;
+            stats::record_request(Self::FDW_NAME, started);
+            stats::inc_stats(
+                Self::FDW_NAME,
+                stats::Metric::BytesIn,
+                resp.content_length().unwrap_or(0) as i64,
+            );
+            let body = block_on_interruptible(&self.rt, resp.text())?.map_err(request_error)?;
+            let json = parse_body(&body)?;
+            if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
+                report_info(&format!("updated {} {}", self.obj, id));
             }
-
-            set_stats_metadata(stats_metadata);
         }
         Ok(())
     }
 
     fn delete(&mut self, rowid: &Cell) -> Result<(), StripeFdwError> {
-        if let Some(ref mut client) = self.client {
-            let mut stats_metadata = get_stats_metadata();
-
-            match rowid {
-                Cell::String(rowid) => {
-                    let url = self
-                        .base_url
-                        .join(&format!("{}/", self.obj))
-                        .unwrap()
-                        .join(rowid)
-                        .unwrap();
-
-                    // call Stripe API
-                    inc_stats_request_cnt(&mut stats_metadata);
-                    match self.rt.block_on(client.delete(url).send()) {
-                        Ok(resp) => match resp.error_for_status() {
-                            Ok(resp) => {
-                                stats::inc_stats(
-                                    Self::FDW_NAME,
-                                    stats::Metric::BytesIn,
-                                    resp.content_length().unwrap_or(0) as i64,
-                                );
-                                let body = self.rt.block_on(resp.text()).unwrap();
-                                let json: JsonValue = serde_json::from_str(&body).unwrap();
-                                if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
-                                    report_info(&format!("deleted {} {}", self.obj, id));
-                                }
-                            }
-                            Err(err) => {
-                                report_request_error!(err);
-                                return Ok(());
-                            }
-                        },
-                        Err(err) => {
-                            report_request_error!(err);
-                            return Ok(());
-                        }
-                    }
-                }
-                _ => unreachable!(),
-            }
+        if let Some(ref client) = self.client {
+            let url = self.object_url(rowid)?;
 
-            set_stats_metadata(stats_metadata);
-        }
-        Ok(())
-    }
-
-    fn validator(
-        options: Vec<Option<String>>,
-        catalog: Option<pg_sys::Oid>,
-    ) -> Result<(), StripeFdwError> {
-        if let Some(oid) = catalog {
-            if oid == FOREIGN_TABLE_RELATION_ID {
-                check_options_contain(&options, "object");
+            // call Stripe API
+            let started = Instant::now();
+            let resp = block_on_interruptible(&self.rt, client.delete(url).send())?
+                .map_err(request_error)?
+                .error_for_status()
+                .map_err(request_error)?;
+            stats::record_request(Self::FDW_NAME, started);
+            stats::inc_stats(
+                Self::FDW_NAME,
+                stats::Metric::BytesIn,
+                resp.content_length().unwrap_or(0) as i64,
+            );
+            let body = block_on_interruptible(&self.rt, resp.text())?.map_err(request_error)?;
+            let json = parse_body(&body)?;
+            if let Some(id) = json.get("id").and_then(|v| v.as_str()) {
+                report_info(&format!("deleted {} {}", self.obj, id));
             }
         }
-
         Ok(())
     }
 }
@@ -2,6 +2,7 @@
 #[pgrx::pg_schema]
 mod tests {
     use pgrx::prelude::*;
+    use supabase_wrappers::prelude::idle_instances;
 
     #[pg_test]
     fn stripe_smoketest() {
@@ -515,6 +516,19 @@ mod tests {
                 vec![(("available", 0), "usd"), (("pending", 0), "usd")]
             );
 
+            // a re-scan fetches the pages again from the first page
+            let results = c
+                .select(
+                    "SELECT v.n, (SELECT count(*) FROM stripe_balance WHERE v.n > 0)
+                     FROM (VALUES (1), (2)) v(n) ORDER BY v.n",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get::<i64>(2).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![2, 2]);
+
             let results = c
                 .select("SELECT * FROM stripe_balance_transactions", None, None)
                 .unwrap()
@@ -572,6 +586,37 @@ mod tests {
                 .collect::<Vec<_>>();
             assert_eq!(results, vec!["cus_MJiBgSUgeWFN0z"]);
 
+            let results = c
+                .select(
+                    "EXPLAIN SELECT * FROM stripe_customers WHERE email = 'test@test.com'",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("QUERY PLAN").unwrap())
+                .collect::<Vec<_>>();
+            assert!(results.iter().any(|r| r.contains(
+                "Remote URL: http://localhost:12111/v1/customers?email=test%40test.com&limit=100"
+            )));
+
+            // parameterized limit is pushed down in generic plan
+            c.update(
+                "SET LOCAL plan_cache_mode = force_generic_plan;
+                 PREPARE customers_limit(bigint) AS SELECT * FROM stripe_customers LIMIT $1",
+                None,
+                None,
+            )
+            .unwrap();
+            let results = c
+                .select("EXPLAIN EXECUTE customers_limit(1)", None, None)
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("QUERY PLAN").unwrap())
+                .collect::<Vec<_>>();
+            assert!(results.iter().any(|r| r.contains(
+                "limit = Some(Limit { count: 1, offset: 0, count_param: Some(Param { id: 1,"
+            )));
+            c.update("DEALLOCATE customers_limit", None, None).unwrap();
+
             let results = c
                 .select(
                     "SELECT attrs->>'id' as id FROM checkout_sessions",
@@ -954,4 +999,209 @@ mod tests {
             */
         });
     }
+
+    // create a Stripe server and return its oid, the instances are cached by
+    // server so the number of its idle instances can be checked
+    fn create_instance_test_server(c: &mut pgrx::spi::SpiClient) -> pg_sys::Oid {
+        c.update(
+            r#"CREATE FOREIGN DATA WRAPPER stripe_wrapper
+                     HANDLER stripe_fdw_handler VALIDATOR stripe_fdw_validator;
+               CREATE SERVER my_stripe_server
+                     FOREIGN DATA WRAPPER stripe_wrapper
+                     OPTIONS (
+                       api_url 'http://localhost:12111/v1',
+                       api_key 'sk_test_51LUmojFkiV6mfx3cpEzG9VaxhA86SA4DIj3b62RKHnRC0nhPp2JBbAmQ1izsX9RKD8rlzvw2xpY54AwZtXmWciif00Qi8J0w3O'
+                     );
+               CREATE FOREIGN TABLE stripe_balance (
+                 balance_type text,
+                 amount bigint
+               )
+               SERVER my_stripe_server
+               OPTIONS (object 'balance');
+               CREATE FOREIGN TABLE stripe_unknown (
+                 id text
+               )
+               SERVER my_stripe_server
+               OPTIONS (object 'unknown')"#,
+            None,
+            None,
+        )
+        .unwrap();
+        c.select(
+            "SELECT oid FROM pg_foreign_server WHERE srvname = 'my_stripe_server'",
+            None,
+            None,
+        )
+        .unwrap()
+        .first()
+        .get_one::<pg_sys::Oid>()
+        .unwrap()
+        .unwrap()
+    }
+
+    #[pg_test]
+    fn stripe_instance_reused() {
+        Spi::connect(|mut c| {
+            let server_id = create_instance_test_server(&mut c);
+
+            // the instance is returned to cache when the scan ends, and is
+            // reused by the next scan instead of creating a new one
+            c.select("SELECT * FROM stripe_balance", None, None)
+                .unwrap();
+            assert_eq!(idle_instances(server_id), 1);
+            c.select("SELECT * FROM stripe_balance", None, None)
+                .unwrap();
+            assert_eq!(idle_instances(server_id), 1);
+        });
+    }
+
+    #[pg_test]
+    fn stripe_instance_dropped_after_error() {
+        Spi::connect(|mut c| {
+            let server_id = create_instance_test_server(&mut c);
+
+            // the instance of a failed scan is not returned to cache
+            c.update(
+                "DO $$
+                 BEGIN
+                   PERFORM * FROM stripe_unknown;
+                 EXCEPTION WHEN others THEN
+                   NULL;
+                 END $$",
+                None,
+                None,
+            )
+            .unwrap();
+            assert_eq!(idle_instances(server_id), 0);
+        });
+    }
+
+    #[pg_test]
+    fn stripe_cassette_replay() {
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER stripe_wrapper
+                         HANDLER stripe_fdw_handler VALIDATOR stripe_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            // responses are replayed from the cassette, the API URL is not accessed
+            let cassette = concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/src/fdw/stripe_fdw/cassettes/balance.json"
+            );
+            c.update(
+                &format!(
+                    r#"CREATE SERVER my_stripe_server
+                         FOREIGN DATA WRAPPER stripe_wrapper
+                         OPTIONS (
+                           api_url 'http://stripe.invalid/v1',
+                           api_key 'sk_test_key',
+                           http_cassette '{}',
+                           http_cassette_mode 'replay'
+                         )"#,
+                    cassette
+                ),
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"
+                CREATE FOREIGN TABLE stripe_balance (
+                  balance_type text,
+                  amount bigint,
+                  currency text,
+                  attrs jsonb
+                )
+                SERVER my_stripe_server
+                OPTIONS (
+                    object 'balance'
+                  )
+             "#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            let results = c
+                .select(
+                    "SELECT * FROM stripe_balance ORDER BY balance_type",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| {
+                    r.get_by_name::<&str, _>("balance_type")
+                        .unwrap()
+                        .zip(r.get_by_name::<i64, _>("amount").unwrap())
+                        .zip(r.get_by_name::<&str, _>("currency").unwrap())
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                results,
+                vec![(("available", 2500), "usd"), (("pending", 300), "usd")]
+            );
+        });
+    }
+
+    #[pg_test(error = "canceling statement due to statement timeout")]
+    fn stripe_statement_timeout() {
+        // a server accepting connections but never responding, so the
+        // request can only be stopped by query cancel
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER stripe_wrapper
+                         HANDLER stripe_fdw_handler VALIDATOR stripe_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                &format!(
+                    r#"CREATE SERVER my_stripe_server
+                         FOREIGN DATA WRAPPER stripe_wrapper
+                         OPTIONS (
+                           api_url 'http://127.0.0.1:{}/v1',
+                           api_key 'sk_test_key'
+                         )"#,
+                    port
+                ),
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"
+                CREATE FOREIGN TABLE stripe_balance (
+                  balance_type text,
+                  amount bigint,
+                  currency text,
+                  attrs jsonb
+                )
+                SERVER my_stripe_server
+                OPTIONS (
+                    object 'balance'
+                  )
+             "#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            // statement timeout is only armed for top-level statements, arm it
+            // here as the whole test is run in one statement
+            unsafe {
+                pg_sys::enable_timeout_after(pg_sys::TimeoutId_STATEMENT_TIMEOUT, 500);
+            }
+            let _ = c.select("SELECT * FROM stripe_balance", None, None);
+        });
+
+        drop(listener);
+    }
 }
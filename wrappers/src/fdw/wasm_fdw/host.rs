@@ -0,0 +1,241 @@
+//! Host side of the WebAssembly wrapper interface defined in `wit/wrappers.wit`
+
+use pgrx::JsonB;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Method as HttpMethod,
+};
+use reqwest_middleware::ClientWithMiddleware;
+use std::str::FromStr;
+use std::time::Instant;
+
+use supabase_wrappers::prelude::*;
+
+wasmtime::component::bindgen!({
+    path: "src/fdw/wasm_fdw/wit",
+    world: "wrappers",
+});
+
+use supabase::wrappers::{http, types as wit, utils};
+
+// host state of a guest wrapper instance
+pub(super) struct HostState {
+    pub(super) rt: Runtime,
+    pub(super) client: ClientWithMiddleware,
+    pub(super) metrics: RemoteMetrics,
+    pub(super) limits: wasmtime::StoreLimits,
+}
+
+impl wit::Host for HostState {}
+
+impl http::Host for HostState {
+    fn send(&mut self, req: http::Request) -> wasmtime::Result<Result<http::Response, String>> {
+        let method = match req.method {
+            http::Method::Get => HttpMethod::GET,
+            http::Method::Post => HttpMethod::POST,
+            http::Method::Put => HttpMethod::PUT,
+            http::Method::Patch => HttpMethod::PATCH,
+            http::Method::Delete => HttpMethod::DELETE,
+        };
+        let mut headers = HeaderMap::new();
+        for (name, value) in &req.headers {
+            match (name.parse::<HeaderName>(), value.parse::<HeaderValue>()) {
+                (Ok(name), Ok(value)) => {
+                    headers.append(name, value);
+                }
+                _ => return Ok(Err(format!("invalid request header '{}'", name))),
+            }
+        }
+
+        let started = Instant::now();
+        let mut builder = self
+            .client
+            .request(method, req.url.as_str())
+            .headers(headers);
+        if !req.body.is_empty() {
+            builder = builder.body(req.body);
+        }
+        let result = block_on_interruptible(&self.rt, async {
+            let resp = builder.send().await?;
+            let url = resp.url().to_string();
+            let status_code = resp.status().as_u16();
+            let headers = resp
+                .headers()
+                .iter()
+                .map(|(name, value)| {
+                    let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+                    (name.to_string(), value)
+                })
+                .collect();
+            let body = resp.text().await?;
+            Ok::<_, reqwest_middleware::Error>(http::Response {
+                url,
+                status_code,
+                headers,
+                body,
+            })
+        });
+
+        // the query cancel is raised as error to stop the guest, the FDW
+        // will then let Postgres process the interrupt
+        let resp = match result {
+            Ok(Ok(resp)) => resp,
+            Ok(Err(err)) => return Ok(Err(format!("request failed: {}", err))),
+            Err(err) => return Err(err.into()),
+        };
+        self.metrics.record_request(started, resp.body.len());
+        crate::stats::inc_stats(
+            super::wasm_fdw::WasmFdw::FDW_NAME,
+            crate::stats::Metric::BytesIn,
+            resp.body.len() as i64,
+        );
+        Ok(Ok(resp))
+    }
+}
+
+impl utils::Host for HostState {
+    fn report_info(&mut self, msg: String) -> wasmtime::Result<()> {
+        report_info(&msg);
+        Ok(())
+    }
+
+    fn report_notice(&mut self, msg: String) -> wasmtime::Result<()> {
+        report_notice(&msg);
+        Ok(())
+    }
+
+    fn report_warning(&mut self, msg: String) -> wasmtime::Result<()> {
+        report_warning(&msg);
+        Ok(())
+    }
+
+    fn get_vault_secret(&mut self, secret_id: String) -> wasmtime::Result<Option<String>> {
+        Ok(get_vault_secret(&secret_id))
+    }
+}
+
+// convert options map to guest options, sorted by name
+pub(super) fn to_wit_options(
+    options: &std::collections::HashMap<String, String>,
+    excludes: &[&str],
+) -> Vec<(String, String)> {
+    let mut ret: Vec<(String, String)> = options
+        .iter()
+        .filter(|(name, _)| !excludes.contains(&name.as_str()))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    ret.sort();
+    ret
+}
+
+pub(super) fn to_wit_cell(cell: &Cell) -> wit::Cell {
+    match cell {
+        Cell::Bool(v) => wit::Cell::Bool(*v),
+        Cell::I8(v) => wit::Cell::I8(*v),
+        Cell::I16(v) => wit::Cell::I16(*v),
+        Cell::F32(v) => wit::Cell::F32(*v),
+        Cell::I32(v) => wit::Cell::I32(*v),
+        Cell::F64(v) => wit::Cell::F64(*v),
+        Cell::I64(v) => wit::Cell::I64(*v),
+        Cell::Numeric(v) => wit::Cell::Numeric(v.to_string().parse().unwrap_or(f64::NAN)),
+        Cell::String(v) => wit::Cell::String(v.clone()),
+        Cell::Date(_) => wit::Cell::Date(cell.to_string().trim_matches('\'').to_owned()),
+        Cell::Timestamp(_) => wit::Cell::Timestamp(cell.to_string().trim_matches('\'').to_owned()),
+        Cell::Json(v) => wit::Cell::Json(v.0.to_string()),
+    }
+}
+
+pub(super) fn from_wit_cell(cell: wit::Cell) -> Result<Cell, FdwError> {
+    let invalid = |ty: &str, value: &str| {
+        FdwError::type_conversion(format!(
+            "invalid {} value '{}' from wasm wrapper",
+            ty, value
+        ))
+    };
+    let cell = match cell {
+        wit::Cell::Bool(v) => Cell::Bool(v),
+        wit::Cell::I8(v) => Cell::I8(v),
+        wit::Cell::I16(v) => Cell::I16(v),
+        wit::Cell::F32(v) => Cell::F32(v),
+        wit::Cell::I32(v) => Cell::I32(v),
+        wit::Cell::F64(v) => Cell::F64(v),
+        wit::Cell::I64(v) => Cell::I64(v),
+        wit::Cell::Numeric(v) => Cell::Numeric(
+            pgrx::AnyNumeric::try_from(v).map_err(|_| invalid("numeric", &v.to_string()))?,
+        ),
+        wit::Cell::String(v) => Cell::String(v),
+        wit::Cell::Date(v) => {
+            Cell::Date(pgrx::Date::from_str(&v).map_err(|_| invalid("date", &v))?)
+        }
+        wit::Cell::Timestamp(v) => {
+            Cell::Timestamp(pgrx::Timestamp::from_str(&v).map_err(|_| invalid("timestamp", &v))?)
+        }
+        wit::Cell::Json(v) => Cell::Json(JsonB(
+            serde_json::from_str(&v).map_err(|_| invalid("json", &v))?,
+        )),
+    };
+    Ok(cell)
+}
+
+pub(super) fn to_wit_row(row: &Row) -> wit::Row {
+    wit::Row {
+        cols: row.cols.clone(),
+        cells: row
+            .cells
+            .iter()
+            .map(|cell| cell.as_ref().map(to_wit_cell))
+            .collect(),
+    }
+}
+
+// convert a guest row to a row of the target columns, missing columns are
+// set to null
+pub(super) fn from_wit_row(src: wit::Row, columns: &[Column]) -> Result<Row, FdwError> {
+    let mut cells: Vec<(String, Option<wit::Cell>)> = src.cols.into_iter().zip(src.cells).collect();
+    let mut row = Row::new();
+    for col in columns {
+        let cell = match cells.iter().position(|(name, _)| name == &col.name) {
+            Some(idx) => cells.swap_remove(idx).1.map(from_wit_cell).transpose()?,
+            None => None,
+        };
+        row.push(&col.name, cell);
+    }
+    Ok(row)
+}
+
+pub(super) fn to_wit_column(col: &Column) -> wit::Column {
+    wit::Column {
+        name: col.name.clone(),
+        num: col.num as u32,
+        type_oid: col.type_oid.as_u32(),
+    }
+}
+
+pub(super) fn to_wit_qual(qual: &Qual) -> wit::Qual {
+    wit::Qual {
+        field: qual.field.clone(),
+        operator: qual.operator.clone(),
+        value: match &qual.value {
+            Value::Cell(cell) => wit::Value::Cell(to_wit_cell(cell)),
+            Value::Array(cells) => wit::Value::Array(cells.iter().map(to_wit_cell).collect()),
+        },
+        use_or: qual.use_or,
+    }
+}
+
+pub(super) fn to_wit_sort(sort: &Sort) -> wit::Sort {
+    wit::Sort {
+        field: sort.field.clone(),
+        field_no: sort.field_no as u32,
+        reversed: sort.reversed,
+        nulls_first: sort.nulls_first,
+        collate: sort.collate.clone(),
+    }
+}
+
+pub(super) fn to_wit_limit(limit: &Limit) -> wit::Limit {
+    wit::Limit {
+        count: limit.count,
+        offset: limit.offset,
+    }
+}
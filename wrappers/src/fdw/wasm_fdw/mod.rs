@@ -0,0 +1,4 @@
+#![allow(clippy::module_inception)]
+mod host;
+mod tests;
+mod wasm_fdw;
@@ -0,0 +1,236 @@
+#[cfg(any(test, feature = "pg_test"))]
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+
+    // the test wrapper is built from wasm-wrappers/test_fdw, see its README
+    const TEST_PACKAGE: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../wasm-wrappers/test_fdw/target/test_fdw.wasm"
+    );
+
+    fn create_test_table(c: &mut pgrx::spi::SpiClient, table_options: &str) {
+        c.update(
+            r#"CREATE FOREIGN DATA WRAPPER wasm_wrapper
+                     HANDLER wasm_fdw_handler VALIDATOR wasm_fdw_validator"#,
+            None,
+            None,
+        )
+        .unwrap();
+        c.update(
+            &format!(
+                r#"CREATE SERVER my_wasm_server
+                     FOREIGN DATA WRAPPER wasm_wrapper
+                     OPTIONS (
+                       fdw_package_path '{}',
+                       rows '3'
+                     )"#,
+                TEST_PACKAGE
+            ),
+            None,
+            None,
+        )
+        .unwrap();
+        c.update(
+            &format!(
+                r#"CREATE FOREIGN TABLE wasm_table (
+                    id bigint,
+                    name text,
+                    attrs jsonb
+                  )
+                  SERVER my_wasm_server
+                  OPTIONS (
+                    rowid_column 'id'{}
+                  )"#,
+                table_options
+            ),
+            None,
+            None,
+        )
+        .unwrap();
+    }
+
+    #[pg_test]
+    fn wasm_scan() {
+        Spi::connect(|mut c| {
+            create_test_table(&mut c, ", prefix 'user'");
+
+            let results = c
+                .select("SELECT * FROM wasm_table ORDER BY id", None, None)
+                .unwrap()
+                .filter_map(|r| {
+                    r.get_by_name::<i64, _>("id")
+                        .unwrap()
+                        .zip(r.get_by_name::<&str, _>("name").unwrap())
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![(1, "user1"), (2, "user2"), (3, "user3")]);
+
+            let results = c
+                .select(
+                    "SELECT attrs->>'id' AS id FROM wasm_table WHERE id = 2",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<&str, _>("id").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec!["2"]);
+
+            // a re-scan is passed to the guest, which rewinds its rows
+            let results = c
+                .select(
+                    "SELECT v.n, (SELECT count(*) FROM wasm_table WHERE v.n > 0)
+                     FROM (VALUES (1), (2)) v(n) ORDER BY v.n",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get::<i64>(2).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![3, 3]);
+        });
+    }
+
+    #[pg_test]
+    fn wasm_modify() {
+        Spi::connect(|mut c| {
+            create_test_table(&mut c, "");
+            c.update(
+                "INSERT INTO wasm_table (id, name) VALUES (4, 'user4')",
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                "UPDATE wasm_table SET name = 'new name' WHERE id = 2",
+                None,
+                None,
+            )
+            .unwrap();
+            c.update("DELETE FROM wasm_table WHERE id = 3", None, None)
+                .unwrap();
+        });
+    }
+
+    #[pg_test(error = "row 1 already exists")]
+    fn wasm_guest_error() {
+        Spi::connect(|mut c| {
+            create_test_table(&mut c, "");
+            c.update(
+                "INSERT INTO wasm_table (id, name) VALUES (1, 'user1')",
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[pg_test(error = "canceling statement due to statement timeout")]
+    fn wasm_statement_timeout() {
+        Spi::connect(|mut c| {
+            create_test_table(&mut c, ", spin 'true'");
+
+            // statement timeout is only armed for top-level statements, arm it
+            // here as the whole test is run in one statement
+            unsafe {
+                pg_sys::enable_timeout_after(pg_sys::TimeoutId_STATEMENT_TIMEOUT, 500);
+            }
+            let _ = c.select("SELECT * FROM wasm_table", None, None);
+        });
+    }
+
+    #[pg_test(error = "cannot allocate 256 MB")]
+    fn wasm_memory_limit() {
+        Spi::connect(|mut c| {
+            create_test_table(&mut c, ", alloc_mb '256'");
+            let _ = c.select("SELECT * FROM wasm_table", None, None);
+        });
+    }
+
+    #[pg_test(error = "only superuser can set option \"fdw_package_path\"")]
+    fn wasm_package_path_by_non_superuser() {
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER wasm_wrapper
+                         HANDLER wasm_fdw_handler VALIDATOR wasm_fdw_validator;
+                   CREATE ROLE wrappers_test_user;
+                   GRANT USAGE ON FOREIGN DATA WRAPPER wasm_wrapper TO wrappers_test_user;
+                   SET ROLE wrappers_test_user"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"CREATE SERVER my_wasm_server
+                         FOREIGN DATA WRAPPER wasm_wrapper
+                         OPTIONS (
+                           fdw_package_path '/tmp/my_fdw.wasm'
+                         )"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[pg_test(error = "required option \"fdw_package_path\" is not specified")]
+    fn wasm_missing_package() {
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER wasm_wrapper
+                         HANDLER wasm_fdw_handler VALIDATOR wasm_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"CREATE SERVER my_wasm_server
+                         FOREIGN DATA WRAPPER wasm_wrapper
+                         OPTIONS (
+                           api_url 'http://localhost:8096'
+                         )"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[pg_test(
+        error = "invalid value \"(not shown)\" for option \"fdw_package_bytes\", expected a hex encoded bytea"
+    )]
+    fn wasm_invalid_package_bytes() {
+        Spi::connect(|mut c| {
+            c.update(
+                r#"CREATE FOREIGN DATA WRAPPER wasm_wrapper
+                         HANDLER wasm_fdw_handler VALIDATOR wasm_fdw_validator"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"CREATE SERVER my_wasm_server
+                         FOREIGN DATA WRAPPER wasm_wrapper
+                         OPTIONS (
+                           fdw_package_bytes '\x0061736d0'
+                         )"#,
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"CREATE FOREIGN TABLE wasm_table (
+                    id bigint
+                  )
+                  SERVER my_wasm_server"#,
+                None,
+                None,
+            )
+            .unwrap();
+
+            // the package is loaded when the wrapper is created
+            c.select("SELECT * FROM wasm_table", None, None).unwrap();
+        });
+    }
+}
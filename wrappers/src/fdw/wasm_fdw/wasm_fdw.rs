@@ -0,0 +1,352 @@
+use crate::http::{self, HttpOptions};
+use crate::stats;
+use pgrx::pg_sys::panic::ErrorReport;
+use pgrx::pg_sys::{self, Oid};
+use reqwest::header::HeaderMap;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use wasmtime::component::{Component, Linker};
+use wasmtime::{Config, Engine, Store, StoreLimitsBuilder, UpdateDeadline};
+
+use supabase_wrappers::prelude::*;
+use thiserror::Error;
+
+use super::host::{self, HostState, Wrappers};
+
+// the engine is shared by all wasm wrappers in current backend
+static ENGINE: OnceLock<Engine> = OnceLock::new();
+
+// interval of the engine epoch, a running guest checks for query cancel when
+// the epoch is changed
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+// max linear memory a guest wrapper instance can grow to
+const MAX_MEMORY_SIZE: usize = 128 * 1024 * 1024;
+
+// a package is identified by the file path with its modified time, or by its
+// content, so a changed package is compiled again
+#[derive(PartialEq, Eq, Hash)]
+enum PackageKey {
+    Path(PathBuf, Option<SystemTime>),
+    Bytes(Vec<u8>),
+}
+
+thread_local! {
+    // compiled components in current backend
+    static COMPONENTS: RefCell<HashMap<PackageKey, Component>> = RefCell::new(HashMap::new());
+}
+
+// the package file is read by the database server process, so only superuser
+// can set its path
+const PACKAGE_PATH: OptionSpec = OptionSpec {
+    name: "fdw_package_path",
+    level: OptionLevel::Server,
+    ty: OptionType::String,
+    default: None,
+    required: false,
+    secret: false,
+    superuser: true,
+};
+
+const PACKAGE_BYTES: OptionSpec = OptionSpec {
+    name: "fdw_package_bytes",
+    level: OptionLevel::Server,
+    ty: OptionType::String,
+    default: None,
+    required: false,
+    secret: false,
+    superuser: false,
+};
+
+fn engine() -> Result<&'static Engine, FdwError> {
+    if let Some(engine) = ENGINE.get() {
+        return Ok(engine);
+    }
+    let mut config = Config::new();
+    config.wasm_component_model(true);
+    config.epoch_interruption(true);
+    let engine = Engine::new(&config).map_err(wasm_error)?;
+    Ok(ENGINE.get_or_init(|| {
+        // advance the epoch in background, so a running guest can be stopped
+        // by query cancel
+        let ticker = engine.clone();
+        std::thread::spawn(move || loop {
+            std::thread::sleep(EPOCH_TICK);
+            ticker.increment_epoch();
+        });
+        engine
+    }))
+}
+
+// convert a wasm runtime error to FDW error, the query cancel raised by host
+// functions is kept as is
+fn wasm_error(err: wasmtime::Error) -> FdwError {
+    match err.downcast::<FdwError>() {
+        Ok(err) => err,
+        Err(err) => FdwError::remote_query(format!("wasm wrapper failed: {:#}", err)),
+    }
+}
+
+// convert an error returned by the guest wrapper
+fn guest_error(msg: String) -> FdwError {
+    FdwError::remote_query(msg)
+}
+
+// decode package bytes in hex, e.g. the text output of a bytea `\x0061736d...`
+fn decode_hex(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim();
+    let value = value.strip_prefix("\\x").unwrap_or(value);
+    if value.len() % 2 != 0 {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+// load the wasm component from local path or hex encoded bytes, the path takes
+// precedence if both are set. Compiled component is cached so it is only
+// compiled once in a backend, until the package is changed.
+fn load_component(options: &HashMap<String, String>) -> Result<Component, FdwError> {
+    let path: Option<String> = get_option(&PACKAGE_PATH, options)?;
+    let key = match path {
+        Some(path) => {
+            let path = PathBuf::from(path);
+            let modified = std::fs::metadata(&path)
+                .and_then(|meta| meta.modified())
+                .ok();
+            PackageKey::Path(path, modified)
+        }
+        None => {
+            let bytes: String = get_option(&PACKAGE_BYTES, options)?
+                .ok_or(OptionsError::MissingOption(PACKAGE_PATH.name))?;
+            let bytes = decode_hex(&bytes).ok_or(OptionsError::InvalidValue {
+                name: PACKAGE_BYTES.name,
+                value: "(not shown)".to_owned(),
+                ty: "hex encoded bytea",
+            })?;
+            PackageKey::Bytes(bytes)
+        }
+    };
+
+    if let Some(component) = COMPONENTS.with(|c| c.borrow().get(&key).cloned()) {
+        return Ok(component);
+    }
+
+    let engine = engine()?;
+    let component = match &key {
+        PackageKey::Path(path, _) => Component::from_file(engine, path).map_err(|err| {
+            FdwError::not_found(format!("cannot load wasm package '{}'", path.display()))
+                .with_detail(format!("{:#}", err))
+        })?,
+        PackageKey::Bytes(bytes) => Component::from_binary(engine, bytes).map_err(wasm_error)?,
+    };
+    COMPONENTS.with(|c| c.borrow_mut().insert(key, component.clone()));
+    Ok(component)
+}
+
+#[wrappers_fdw(
+    version = "0.1.0",
+    author = "Supabase",
+    website = "https://github.com/supabase/wrappers/tree/main/wrappers/src/fdw/wasm_fdw",
+    error_type = "WasmFdwError"
+)]
+pub(crate) struct WasmFdw {
+    store: Store<HostState>,
+    bindings: Wrappers,
+    tgt_cols: Vec<Column>,
+}
+
+impl WasmFdw {
+    pub(super) const FDW_NAME: &str = "WasmFdw";
+    const EXCLUDED_OPTIONS: &[&str] = &["fdw_package_path", "fdw_package_bytes"];
+}
+
+#[derive(Error, Debug)]
+enum WasmFdwError {
+    #[error("{0}")]
+    CreateRuntimeError(#[from] CreateRuntimeError),
+
+    #[error("{0}")]
+    OptionsError(#[from] OptionsError),
+
+    #[error("{0}")]
+    FdwError(#[from] FdwError),
+}
+
+impl From<WasmFdwError> for ErrorReport {
+    fn from(value: WasmFdwError) -> Self {
+        let err = match value {
+            WasmFdwError::CreateRuntimeError(e) => FdwError::from(e),
+            WasmFdwError::OptionsError(e) => FdwError::from(e),
+            WasmFdwError::FdwError(e) => e,
+        };
+        stats::record_error(WasmFdw::FDW_NAME, err.kind());
+        err.into()
+    }
+}
+
+// call a guest function, both the runtime error and the error returned by
+// guest are converted to FDW error
+macro_rules! call_guest {
+    ($self:ident, $func:ident $(, $arg:expr)*) => {
+        $self
+            .bindings
+            .supabase_wrappers_routines()
+            .$func(&mut $self.store $(, $arg)*)
+            .map_err(wasm_error)?
+            .map_err(guest_error)
+    };
+}
+
+impl ForeignDataWrapper<WasmFdwError> for WasmFdw {
+    fn new(options: &HashMap<String, String>) -> Result<Self, WasmFdwError> {
+        let component = load_component(options)?;
+        let http_opts = HttpOptions::from_options(options)?;
+        let state = HostState {
+            rt: create_async_runtime()?,
+            client: http::create_client(HeaderMap::new(), &http_opts)?,
+            metrics: RemoteMetrics::default(),
+            limits: StoreLimitsBuilder::new()
+                .memory_size(MAX_MEMORY_SIZE)
+                .build(),
+        };
+
+        let engine = engine()?;
+        let mut linker = Linker::new(engine);
+        Wrappers::add_to_linker(&mut linker, |state: &mut HostState| state).map_err(wasm_error)?;
+        let mut store = Store::new(engine, state);
+        store.limiter(|state| &mut state.limits);
+
+        // check for query cancel at each epoch tick, the cancel error stops
+        // the guest and then Postgres processes the interrupt
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_callback(|_| {
+            if interrupt_pending() {
+                return Err(FdwError::Cancelled.into());
+            }
+            Ok(UpdateDeadline::Continue(1))
+        });
+        let (bindings, _) =
+            Wrappers::instantiate(&mut store, &component, &linker).map_err(wasm_error)?;
+
+        stats::inc_stats(Self::FDW_NAME, stats::Metric::CreateTimes, 1);
+
+        let mut fdw = WasmFdw {
+            store,
+            bindings,
+            tgt_cols: Vec::new(),
+        };
+        let opts = host::to_wit_options(options, Self::EXCLUDED_OPTIONS);
+        call_guest!(fdw, call_init, &opts)?;
+        Ok(fdw)
+    }
+
+    fn begin_scan(
+        &mut self,
+        quals: &[Qual],
+        columns: &[Column],
+        sorts: &[Sort],
+        limit: &Option<Limit>,
+        options: &HashMap<String, String>,
+    ) -> Result<(), WasmFdwError> {
+        self.tgt_cols = columns.to_vec();
+        self.store.data_mut().metrics = RemoteMetrics::default();
+
+        let quals: Vec<_> = quals.iter().map(host::to_wit_qual).collect();
+        let columns: Vec<_> = columns.iter().map(host::to_wit_column).collect();
+        let sorts: Vec<_> = sorts.iter().map(host::to_wit_sort).collect();
+        let limit = limit.as_ref().map(host::to_wit_limit);
+        let opts = host::to_wit_options(options, &[]);
+        call_guest!(
+            self,
+            call_begin_scan,
+            &quals,
+            &columns,
+            &sorts,
+            limit,
+            &opts
+        )?;
+        Ok(())
+    }
+
+    fn remote_metrics(&self) -> Option<RemoteMetrics> {
+        Some(self.store.data().metrics.clone())
+    }
+
+    fn iter_scan(&mut self, row: &mut Row) -> Result<Option<()>, WasmFdwError> {
+        match call_guest!(self, call_iter_scan)? {
+            Some(src) => {
+                row.replace_with(host::from_wit_row(src, &self.tgt_cols)?);
+                stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsIn, 1);
+                stats::inc_stats(Self::FDW_NAME, stats::Metric::RowsOut, 1);
+                Ok(Some(()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn re_scan(&mut self) -> Result<(), WasmFdwError> {
+        call_guest!(self, call_re_scan)?;
+        Ok(())
+    }
+
+    fn end_scan(&mut self) -> Result<(), WasmFdwError> {
+        call_guest!(self, call_end_scan)?;
+        Ok(())
+    }
+
+    fn begin_modify(&mut self, options: &HashMap<String, String>) -> Result<(), WasmFdwError> {
+        let opts = host::to_wit_options(options, &[]);
+        call_guest!(self, call_begin_modify, &opts)?;
+        Ok(())
+    }
+
+    fn insert(&mut self, src: &Row) -> Result<(), WasmFdwError> {
+        let row = host::to_wit_row(src);
+        call_guest!(self, call_insert, &row)?;
+        Ok(())
+    }
+
+    fn update(&mut self, rowid: &Cell, new_row: &Row) -> Result<(), WasmFdwError> {
+        let rowid = host::to_wit_cell(rowid);
+        let row = host::to_wit_row(new_row);
+        call_guest!(self, call_update, &rowid, &row)?;
+        Ok(())
+    }
+
+    fn delete(&mut self, rowid: &Cell) -> Result<(), WasmFdwError> {
+        let rowid = host::to_wit_cell(rowid);
+        call_guest!(self, call_delete, &rowid)?;
+        Ok(())
+    }
+
+    fn end_modify(&mut self) -> Result<(), WasmFdwError> {
+        call_guest!(self, call_end_modify)?;
+        Ok(())
+    }
+
+    fn validator(options: Vec<Option<String>>, catalog: Option<Oid>) -> Result<(), WasmFdwError> {
+        // the wasm package is required on server, other options are validated
+        // by the guest wrapper when it is created
+        if catalog == Some(FOREIGN_SERVER_RELATION_ID) {
+            let has_option = |name: &str| {
+                options
+                    .iter()
+                    .flatten()
+                    .any(|opt| opt.split_once('=').map_or(opt.as_str(), |(k, _)| k) == name)
+            };
+            if !has_option(PACKAGE_PATH.name) && !has_option(PACKAGE_BYTES.name) {
+                return Err(OptionsError::MissingOption(PACKAGE_PATH.name).into());
+            }
+            if has_option(PACKAGE_PATH.name) && !unsafe { pg_sys::superuser() } {
+                return Err(OptionsError::SuperuserOnly(PACKAGE_PATH.name).into());
+            }
+        }
+        Ok(())
+    }
+}
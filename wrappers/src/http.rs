@@ -0,0 +1,427 @@
+//! Shared HTTP client for the FDWs accessing REST APIs
+//!
+//! The client retries transient failures with exponential backoff, respects
+//! the `Retry-After` header of `429 Too Many Requests` and `503 Service
+//! Unavailable` responses, and limits the request rate. Its timeouts, proxies,
+//! TLS certificates and user agent can also be set, and its requests can be
+//! recorded to and replayed from a cassette file for testing. They are
+//! configured by the server options in [`HTTP_OPTION_SPECS`], which are
+//! accepted by the FDWs including them in `wrappers_fdw` options.
+
+mod cassette;
+
+use async_trait::async_trait;
+use cassette::{CassetteMiddleware, CassetteMode};
+use reqwest::{
+    header::{self, HeaderMap},
+    Certificate, Identity, Proxy, Request, Response,
+};
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, Middleware, Next};
+use reqwest_retry::Retryable;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use task_local_extensions::Extensions;
+
+use supabase_wrappers::prelude::*;
+
+// longest delay before retrying a request, a longer `Retry-After` delay is
+// not waited for and the response is returned
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Server options of the shared HTTP client
+pub(crate) const HTTP_OPTION_SPECS: &[OptionSpec] = &[
+    OptionSpec {
+        name: "max_retries",
+        level: OptionLevel::Server,
+        ty: OptionType::Usize,
+        default: Some("3"),
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "retry_base_ms",
+        level: OptionLevel::Server,
+        ty: OptionType::Usize,
+        default: Some("100"),
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "requests_per_second",
+        level: OptionLevel::Server,
+        ty: OptionType::F64,
+        default: None,
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "respect_retry_after",
+        level: OptionLevel::Server,
+        ty: OptionType::Bool,
+        default: Some("true"),
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "timeout_ms",
+        level: OptionLevel::Server,
+        ty: OptionType::Usize,
+        default: None,
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "connect_timeout_ms",
+        level: OptionLevel::Server,
+        ty: OptionType::Usize,
+        default: None,
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "http_proxy",
+        level: OptionLevel::Server,
+        ty: OptionType::String,
+        default: None,
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "https_proxy",
+        level: OptionLevel::Server,
+        ty: OptionType::String,
+        default: None,
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "ca_cert",
+        level: OptionLevel::Server,
+        ty: OptionType::String,
+        default: None,
+        required: false,
+        secret: true,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "client_cert",
+        level: OptionLevel::Server,
+        ty: OptionType::String,
+        default: None,
+        required: false,
+        secret: true,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "client_key",
+        level: OptionLevel::Server,
+        ty: OptionType::String,
+        default: None,
+        required: false,
+        secret: true,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "user_agent",
+        level: OptionLevel::Server,
+        ty: OptionType::String,
+        default: None,
+        required: false,
+        secret: false,
+        superuser: false,
+    },
+    OptionSpec {
+        name: "http_cassette",
+        level: OptionLevel::Server,
+        ty: OptionType::String,
+        default: None,
+        required: false,
+        secret: false,
+        superuser: true,
+    },
+    OptionSpec {
+        name: "http_cassette_mode",
+        level: OptionLevel::Server,
+        ty: OptionType::String,
+        default: Some("replay"),
+        required: false,
+        secret: false,
+        superuser: true,
+    },
+];
+
+fn option_spec(name: &str) -> &'static OptionSpec {
+    HTTP_OPTION_SPECS
+        .iter()
+        .find(|spec| spec.name == name)
+        .unwrap()
+}
+
+/// Typed options of the shared HTTP client
+#[derive(Debug, Clone)]
+pub(crate) struct HttpOptions {
+    pub max_retries: usize,
+    pub retry_base_ms: usize,
+    pub requests_per_second: Option<f64>,
+    pub respect_retry_after: bool,
+    pub timeout_ms: Option<usize>,
+    pub connect_timeout_ms: Option<usize>,
+    pub http_proxy: Option<String>,
+    pub https_proxy: Option<String>,
+    pub ca_cert: Option<String>,
+    pub client_cert: Option<String>,
+    pub client_key: Option<String>,
+    pub user_agent: Option<String>,
+    pub http_cassette: Option<String>,
+    pub http_cassette_mode: CassetteMode,
+    /// API key query parameter as (name, key) added to each request, it is
+    /// not an option but set by the FDW
+    pub query_auth: Option<(String, String)>,
+}
+
+impl FromOptions for HttpOptions {
+    fn from_options(options: &HashMap<String, String>) -> Result<Self, OptionsError> {
+        let requests_per_second = get_option::<f64>(option_spec("requests_per_second"), options)?;
+        if let Some(rps) = requests_per_second {
+            if rps <= 0.0 {
+                return Err(OptionsError::InvalidValue {
+                    name: "requests_per_second",
+                    value: rps.to_string(),
+                    ty: "positive number",
+                });
+            }
+        }
+        Ok(Self {
+            max_retries: require_typed_option(option_spec("max_retries"), options)?,
+            retry_base_ms: require_typed_option(option_spec("retry_base_ms"), options)?,
+            requests_per_second,
+            respect_retry_after: require_typed_option(option_spec("respect_retry_after"), options)?,
+            timeout_ms: get_option(option_spec("timeout_ms"), options)?,
+            connect_timeout_ms: get_option(option_spec("connect_timeout_ms"), options)?,
+            http_proxy: get_option(option_spec("http_proxy"), options)?,
+            https_proxy: get_option(option_spec("https_proxy"), options)?,
+            ca_cert: get_option(option_spec("ca_cert"), options)?,
+            client_cert: get_option(option_spec("client_cert"), options)?,
+            client_key: get_option(option_spec("client_key"), options)?,
+            user_agent: get_option(option_spec("user_agent"), options)?,
+            http_cassette: get_option(option_spec("http_cassette"), options)?,
+            http_cassette_mode: CassetteMode::parse(&require_typed_option::<String>(
+                option_spec("http_cassette_mode"),
+                options,
+            )?)?,
+            query_auth: None,
+        })
+    }
+}
+
+// retry transient failures, the delay is from the `Retry-After` header or
+// exponential backoff
+struct RetryMiddleware {
+    max_retries: usize,
+    retry_base: Duration,
+    respect_retry_after: bool,
+}
+
+impl RetryMiddleware {
+    fn retry_delay(
+        &self,
+        result: &reqwest_middleware::Result<Response>,
+        n_retries: usize,
+    ) -> Option<Duration> {
+        if self.respect_retry_after {
+            let retry_after = result
+                .as_ref()
+                .ok()
+                .and_then(|resp| resp.headers().get(header::RETRY_AFTER))
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok());
+            if let Some(secs) = retry_after {
+                let delay = Duration::from_secs(secs);
+                return (delay <= MAX_RETRY_DELAY).then_some(delay);
+            }
+        }
+        let backoff = self.retry_base.saturating_mul(1u32 << n_retries.min(16));
+        Some(backoff.min(MAX_RETRY_DELAY))
+    }
+}
+
+#[async_trait]
+impl Middleware for RetryMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let mut n_retries = 0;
+        loop {
+            // request with a streaming body cannot be retried
+            let duplicate = match req.try_clone() {
+                Some(duplicate) => duplicate,
+                None => return next.run(req, extensions).await,
+            };
+            let result = next.clone().run(duplicate, extensions).await;
+            if n_retries >= self.max_retries {
+                return result;
+            }
+            let delay = match Retryable::from_reqwest_response(&result) {
+                Some(Retryable::Transient) => self.retry_delay(&result, n_retries),
+                _ => None,
+            };
+            match delay {
+                Some(delay) => {
+                    tokio::time::sleep(delay).await;
+                    n_retries += 1;
+                }
+                None => return result,
+            }
+        }
+    }
+}
+
+// add the API key to the query of each request, it is inside the cassette so
+// the key is not recorded
+struct QueryAuthMiddleware {
+    param: String,
+    key: String,
+}
+
+#[async_trait]
+impl Middleware for QueryAuthMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        req.url_mut()
+            .query_pairs_mut()
+            .append_pair(&self.param, &self.key);
+        next.run(req, extensions).await
+    }
+}
+
+// limit request rate by spacing requests at a fixed interval
+struct RateLimitMiddleware {
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+#[async_trait]
+impl Middleware for RateLimitMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let wait = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = (*next_slot).max(now);
+            *next_slot = slot + self.interval;
+            slot - now
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        next.run(req, extensions).await
+    }
+}
+
+// certificates and keys are not shown in errors, as they can be long or secret
+fn invalid_pem(name: &'static str, ty: &'static str) -> OptionsError {
+    OptionsError::InvalidValue {
+        name,
+        value: "(not shown)".to_owned(),
+        ty,
+    }
+}
+
+fn invalid_proxy(name: &'static str, value: &str) -> OptionsError {
+    OptionsError::InvalidValue {
+        name,
+        value: value.to_owned(),
+        ty: "proxy URL",
+    }
+}
+
+/// Create a HTTP client with default headers, such as the authorization header
+pub(crate) fn create_client(
+    headers: HeaderMap,
+    opts: &HttpOptions,
+) -> Result<ClientWithMiddleware, FdwError> {
+    let mut builder = reqwest::Client::builder().default_headers(headers);
+    if let Some(timeout_ms) = opts.timeout_ms {
+        builder = builder.timeout(Duration::from_millis(timeout_ms as u64));
+    }
+    if let Some(connect_timeout_ms) = opts.connect_timeout_ms {
+        builder = builder.connect_timeout(Duration::from_millis(connect_timeout_ms as u64));
+    }
+    if let Some(http_proxy) = &opts.http_proxy {
+        let proxy = Proxy::http(http_proxy).map_err(|_| invalid_proxy("http_proxy", http_proxy))?;
+        builder = builder.proxy(proxy);
+    }
+    if let Some(https_proxy) = &opts.https_proxy {
+        let proxy =
+            Proxy::https(https_proxy).map_err(|_| invalid_proxy("https_proxy", https_proxy))?;
+        builder = builder.proxy(proxy);
+    }
+    if let Some(ca_cert) = &opts.ca_cert {
+        let cert = Certificate::from_pem(ca_cert.as_bytes())
+            .map_err(|_| invalid_pem("ca_cert", "PEM certificate"))?;
+        builder = builder.add_root_certificate(cert);
+    }
+    match (&opts.client_cert, &opts.client_key) {
+        (Some(client_cert), Some(client_key)) => {
+            let identity = Identity::from_pkcs8_pem(client_cert.as_bytes(), client_key.as_bytes())
+                .map_err(|_| invalid_pem("client_key", "PEM PKCS#8 private key"))?;
+            builder = builder.identity(identity);
+        }
+        (Some(_), None) => return Err(OptionsError::MissingOption("client_key").into()),
+        (None, Some(_)) => return Err(OptionsError::MissingOption("client_cert").into()),
+        (None, None) => {}
+    }
+    if let Some(user_agent) = &opts.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    let client = builder.build().map_err(|err| {
+        FdwError::connection_failed(format!("create HTTP client failed: {}", err))
+    })?;
+
+    // the cassette is outermost, so the final response after retries is
+    // recorded and a replayed response is not retried
+    let mut builder = ClientBuilder::new(client);
+    if let Some(path) = &opts.http_cassette {
+        builder = builder.with(CassetteMiddleware::new(path, opts.http_cassette_mode)?);
+    }
+    if let Some((param, key)) = &opts.query_auth {
+        builder = builder.with(QueryAuthMiddleware {
+            param: param.clone(),
+            key: key.clone(),
+        });
+    }
+
+    // the rate limit is applied to each retry as well
+    builder = builder.with(RetryMiddleware {
+        max_retries: opts.max_retries,
+        retry_base: Duration::from_millis(opts.retry_base_ms as u64),
+        respect_retry_after: opts.respect_retry_after,
+    });
+    if let Some(rps) = opts.requests_per_second {
+        builder = builder.with(RateLimitMiddleware {
+            interval: Duration::from_secs_f64(1.0 / rps),
+            next_slot: Mutex::new(Instant::now()),
+        });
+    }
+    Ok(builder.build())
+}
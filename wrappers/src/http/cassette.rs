@@ -0,0 +1,226 @@
+//! Record and replay HTTP interactions
+//!
+//! A cassette is a JSON file of HTTP interactions, each of which is a request
+//! and the response to it. In `record` mode, requests are sent to the remote
+//! server and the responses are saved to the cassette. In `replay` mode, no
+//! request is sent and the responses are read from the cassette, so a FDW can
+//! be tested without accessing the remote server.
+//!
+//! Only the request method, URL and body are recorded, the request headers
+//! are not, so the authorization header is not saved to the cassette.
+
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use task_local_extensions::Extensions;
+
+use supabase_wrappers::prelude::*;
+
+// interactions recorded in current backend, keyed by cassette path
+static RECORDINGS: Mutex<Option<HashMap<PathBuf, Cassette>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CassetteMode {
+    Record,
+    Replay,
+}
+
+impl CassetteMode {
+    pub(crate) fn parse(mode: &str) -> Result<Self, OptionsError> {
+        match mode {
+            "record" => Ok(Self::Record),
+            "replay" => Ok(Self::Replay),
+            _ => Err(OptionsError::InvalidValue {
+                name: "http_cassette_mode",
+                value: mode.to_owned(),
+                ty: "'record' or 'replay'",
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct RecordedRequest {
+    method: String,
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+}
+
+impl RecordedRequest {
+    fn new(req: &Request) -> Self {
+        Self {
+            method: req.method().to_string(),
+            url: req.url().to_string(),
+            body: req
+                .body()
+                .and_then(|body| body.as_bytes())
+                .map(|body| String::from_utf8_lossy(body).into_owned()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    #[serde(default)]
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Interaction {
+    request: RecordedRequest,
+    response: RecordedResponse,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Cassette {
+    interactions: Vec<Interaction>,
+}
+
+fn cassette_error(msg: String) -> reqwest_middleware::Error {
+    reqwest_middleware::Error::middleware(io::Error::new(io::ErrorKind::Other, msg))
+}
+
+fn build_response(resp: &RecordedResponse) -> reqwest_middleware::Result<Response> {
+    let mut builder = ::http::Response::builder().status(resp.status);
+    for (name, value) in &resp.headers {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    builder
+        .body(resp.body.clone())
+        .map(Response::from)
+        .map_err(|err| cassette_error(format!("invalid recorded response: {}", err)))
+}
+
+// save an interaction to the cassette, the cassette recorded by previous
+// backends is overwritten by the first interaction recorded in this backend
+fn save_interaction(path: &Path, interaction: Interaction) -> io::Result<()> {
+    let mut recordings = RECORDINGS.lock().unwrap();
+    let cassette = recordings
+        .get_or_insert_with(HashMap::new)
+        .entry(path.to_path_buf())
+        .or_default();
+    if cassette.interactions.contains(&interaction) {
+        return Ok(());
+    }
+    cassette.interactions.push(interaction);
+    let content = serde_json::to_string_pretty(cassette)?;
+    fs::write(path, content)
+}
+
+pub(super) struct CassetteMiddleware {
+    path: PathBuf,
+    mode: CassetteMode,
+
+    // interactions to replay and whether each of them has been replayed
+    replay: Mutex<Vec<(Interaction, bool)>>,
+}
+
+impl CassetteMiddleware {
+    pub(super) fn new(path: &str, mode: CassetteMode) -> Result<Self, OptionsError> {
+        let replay = match mode {
+            CassetteMode::Record => Vec::new(),
+            CassetteMode::Replay => {
+                let cassette = fs::read_to_string(path)
+                    .ok()
+                    .and_then(|content| serde_json::from_str::<Cassette>(&content).ok())
+                    .ok_or_else(|| OptionsError::InvalidValue {
+                        name: "http_cassette",
+                        value: path.to_owned(),
+                        ty: "cassette file",
+                    })?;
+                cassette
+                    .interactions
+                    .into_iter()
+                    .map(|interaction| (interaction, false))
+                    .collect()
+            }
+        };
+        Ok(Self {
+            path: PathBuf::from(path),
+            mode,
+            replay: Mutex::new(replay),
+        })
+    }
+
+    // find the first matching interaction not replayed yet, or the last
+    // matching one if all of them have been replayed
+    fn replay(&self, req: &RecordedRequest) -> reqwest_middleware::Result<Response> {
+        let mut replay = self.replay.lock().unwrap();
+        let mut matched = replay
+            .iter_mut()
+            .filter(|(interaction, _)| &interaction.request == req)
+            .collect::<Vec<_>>();
+        let found = match matched.iter().position(|(_, replayed)| !replayed) {
+            Some(idx) => matched.swap_remove(idx),
+            None => matched.pop().ok_or_else(|| {
+                cassette_error(format!(
+                    "no recorded response for {} {} in cassette {}",
+                    req.method,
+                    req.url,
+                    self.path.display()
+                ))
+            })?,
+        };
+        found.1 = true;
+        build_response(&found.0.response)
+    }
+
+    async fn record(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let request = RecordedRequest::new(&req);
+        let resp = next.run(req, extensions).await?;
+        let status = resp.status().as_u16();
+        let headers = resp
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (name.to_string(), value.to_owned()))
+            })
+            .collect();
+        let body = resp.text().await?;
+        let response = RecordedResponse {
+            status,
+            headers,
+            body,
+        };
+        let resp = build_response(&response)?;
+        save_interaction(&self.path, Interaction { request, response }).map_err(|err| {
+            cassette_error(format!(
+                "save cassette {} failed: {}",
+                self.path.display(),
+                err
+            ))
+        })?;
+        Ok(resp)
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for CassetteMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        match self.mode {
+            CassetteMode::Record => self.record(req, extensions, next).await,
+            CassetteMode::Replay => self.replay(&RecordedRequest::new(&req)),
+        }
+    }
+}
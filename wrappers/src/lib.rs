@@ -5,8 +5,29 @@ pg_module_magic!();
 extension_sql_file!("../sql/bootstrap.sql", bootstrap);
 extension_sql_file!("../sql/finalize.sql", finalize);
 
+mod cache;
 mod fdw;
+#[cfg(any(
+    feature = "stripe_fdw",
+    feature = "firebase_fdw",
+    feature = "airtable_fdw",
+    feature = "logflare_fdw",
+    feature = "rest_fdw",
+    feature = "graphql_fdw",
+    feature = "wasm_fdw"
+))]
+mod http;
+mod remote;
 mod stats;
+mod sync;
+#[cfg(all(any(test, feature = "pg_test"), feature = "rest_fdw"))]
+mod tests;
+
+#[pg_guard]
+#[allow(non_snake_case)]
+pub extern "C" fn _PG_init() {
+    supabase_wrappers::init();
+}
 
 #[cfg(test)]
 pub mod pg_test {
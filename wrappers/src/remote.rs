@@ -0,0 +1,61 @@
+use pgrx::prelude::*;
+use pgrx::JsonB;
+use supabase_wrappers::server;
+
+#[pg_schema]
+mod wrappers {
+    use super::*;
+
+    /// Run a query on a foreign server and return the result rows
+    ///
+    /// The query is written in the remote source's own language and run with
+    /// the server's connection and credentials, without defining any foreign
+    /// table. Each row is returned as a JSON object keyed by column name,
+    /// instead of `setof record`, because the result columns are only known
+    /// after the query is run on the remote side and pgrx cannot return rows
+    /// of an anonymous record type. Use `jsonb_to_record` to get typed columns.
+    #[pg_extern]
+    fn remote_query(server_name: &str, sql: &str) -> SetOfIterator<'static, JsonB> {
+        let rows = server::remote_query(server_name, sql);
+        SetOfIterator::new(rows.into_iter().map(|row| JsonB(row.to_json())))
+    }
+
+    /// Run a statement on a foreign server without returning any rows
+    #[pg_extern]
+    fn remote_exec(server_name: &str, sql: &str) {
+        server::remote_exec(server_name, sql);
+    }
+
+    /// Check the connection to a foreign server
+    ///
+    /// A cheap authenticated request is made to the remote server. If the
+    /// check is failed, its error is returned instead of being raised.
+    #[pg_extern]
+    fn check_server(
+        server_name: &str,
+    ) -> TableIterator<
+        'static,
+        (
+            name!(status, String),
+            name!(latency_ms, Option<f64>),
+            name!(version, Option<String>),
+            name!(error, Option<String>),
+            name!(detail, Option<String>),
+            name!(hint, Option<String>),
+        ),
+    > {
+        let check = server::check_server(server_name);
+        let (error, detail, hint) = match check.error {
+            Some(err) => (Some(err.message), err.detail, err.hint),
+            None => (None, None, None),
+        };
+        TableIterator::new(vec![(
+            check.status.as_str().to_owned(),
+            check.latency.map(|d| d.as_secs_f64() * 1000.0),
+            check.version,
+            error,
+            detail,
+            hint,
+        )])
+    }
+}
@@ -1,9 +1,20 @@
 use pgrx::{prelude::*, JsonB};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::ffi::c_void;
 use std::fmt;
+use std::time::Instant;
+use supabase_wrappers::prelude::{current_foreign_table, report_warning, run_in_subtransaction};
 
 // fdw stats table name
 const FDW_STATS_TABLE: &str = "wrappers_fdw_stats";
 
+// upper bounds of remote request latency histogram buckets, in milliseconds,
+// the extra last bucket counts the requests slower than all the bounds
+pub(crate) const LATENCY_BUCKETS_MS: [f64; 11] = [
+    5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+
 // metric list
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
@@ -13,6 +24,7 @@ pub(crate) enum Metric {
     RowsOut,
     BytesIn,
     BytesOut,
+    Requests,
 }
 
 impl fmt::Display for Metric {
@@ -23,58 +35,288 @@ impl fmt::Display for Metric {
             Metric::RowsOut => write!(f, "rows_out"),
             Metric::BytesIn => write!(f, "bytes_in"),
             Metric::BytesOut => write!(f, "bytes_out"),
+            Metric::Requests => write!(f, "requests"),
         }
     }
 }
 
-// get stats table full qualified name
-fn get_stats_table() -> String {
+// changes to be added to a stats row
+struct StatsDelta {
+    create_times: i64,
+    rows_in: i64,
+    rows_out: i64,
+    bytes_in: i64,
+    bytes_out: i64,
+    requests: i64,
+    errors: BTreeMap<&'static str, i64>,
+    latency_sum_ms: f64,
+    latency_buckets: Vec<i64>,
+}
+
+impl Default for StatsDelta {
+    fn default() -> Self {
+        Self {
+            create_times: 0,
+            rows_in: 0,
+            rows_out: 0,
+            bytes_in: 0,
+            bytes_out: 0,
+            requests: 0,
+            errors: BTreeMap::new(),
+            latency_sum_ms: 0.0,
+            latency_buckets: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+        }
+    }
+}
+
+impl StatsDelta {
+    fn inc(&mut self, metric: Metric, inc: i64) {
+        let counter = match metric {
+            Metric::CreateTimes => &mut self.create_times,
+            Metric::RowsIn => &mut self.rows_in,
+            Metric::RowsOut => &mut self.rows_out,
+            Metric::BytesIn => &mut self.bytes_in,
+            Metric::BytesOut => &mut self.bytes_out,
+            Metric::Requests => &mut self.requests,
+        };
+        *counter += inc;
+    }
+
+    fn observe_latency(&mut self, latency_ms: f64) {
+        let idx = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| latency_ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.latency_buckets[idx] += 1;
+        self.latency_sum_ms += latency_ms;
+    }
+
+    // error kinds are plain identifiers, so no escaping is needed
+    fn errors_json(&self) -> String {
+        let items = self
+            .errors
+            .iter()
+            .map(|(kind, cnt)| format!("\"{}\": {}", kind, cnt))
+            .collect::<Vec<String>>()
+            .join(", ");
+        format!("{{{}}}", items)
+    }
+}
+
+// stats key, which is the fdw, server and table names
+type StatsKey = (String, String, String);
+
+thread_local! {
+    // stats accumulated in current backend but not saved to stats table yet
+    static PENDING_STATS: RefCell<BTreeMap<StatsKey, StatsDelta>> = RefCell::new(BTreeMap::new());
+
+    static XACT_CALLBACK_REGISTERED: Cell<bool> = Cell::new(false);
+}
+
+// find stats table full qualified name
+fn find_stats_table() -> Option<String> {
     let sql = format!(
         "select b.nspname || '.{}'
          from pg_catalog.pg_extension a join pg_namespace b on a.extnamespace = b.oid
          where a.extname = 'wrappers'",
         FDW_STATS_TABLE
     );
-    Spi::get_one(&sql)
-        .unwrap()
+    Spi::get_one(&sql).unwrap()
+}
+
+// get stats table full qualified name
+fn get_stats_table() -> String {
+    find_stats_table()
         .unwrap_or_else(|| panic!("cannot find fdw stats table '{}'", FDW_STATS_TABLE))
 }
 
-// increase stats value
-#[allow(dead_code)]
-pub(crate) fn inc_stats(fdw_name: &str, metric: Metric, inc: i64) {
+// get the server and table names stats are kept for, they are empty if no
+// foreign table is accessed yet
+fn stats_key() -> (String, String) {
+    current_foreign_table()
+        .map(|t| (t.server.clone(), t.table.clone()))
+        .unwrap_or_default()
+}
+
+// save pending stats before transaction commits
+//
+// Saving stats must not fail the user's commit, so it is done in a
+// subtransaction and its error is reported as a warning. It is skipped in a
+// repeatable read or serializable transaction, in which the stats row update
+// can fail for concurrent updates, the stats are then saved by a later
+// transaction.
+#[pg_guard]
+extern "C" fn stats_xact_callback(event: pg_sys::XactEvent, _arg: *mut c_void) {
+    if event != pg_sys::XactEvent_XACT_EVENT_PRE_COMMIT {
+        return;
+    }
+    // same as IsolationUsesXactSnapshot()
+    if unsafe { pg_sys::XactIsoLevel >= pg_sys::XACT_REPEATABLE_READ as i32 } {
+        return;
+    }
+    if let Err(err) = unsafe { run_in_subtransaction(flush_stats) } {
+        report_warning(&format!("save fdw stats failed: {}", err));
+    }
+}
+
+// accumulate changes to the stats of current foreign table in memory, they
+// are saved when the transaction commits
+fn add_stats(fdw_name: &str, f: impl FnOnce(&mut StatsDelta)) {
+    if !XACT_CALLBACK_REGISTERED.with(|r| r.replace(true)) {
+        unsafe {
+            pg_sys::RegisterXactCallback(Some(stats_xact_callback), std::ptr::null_mut());
+        }
+    }
+
+    let (server_name, table_name) = stats_key();
+    PENDING_STATS.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        f(pending
+            .entry((fdw_name.to_owned(), server_name, table_name))
+            .or_default())
+    });
+}
+
+// save pending stats to stats table
+//
+// Stats table cannot be written on a standby or in a read-only transaction,
+// in that case the stats are kept in memory and saved by a later transaction.
+// Stats collected in an aborted transaction are also kept and saved later.
+pub(crate) fn flush_stats() {
+    if PENDING_STATS.with(|pending| pending.borrow().is_empty()) {
+        return;
+    }
+    if unsafe { pg_sys::RecoveryInProgress() || pg_sys::XactReadOnly } {
+        return;
+    }
+
+    let pending = PENDING_STATS.with(|pending| std::mem::take(&mut *pending.borrow_mut()));
+
+    // the extension may be dropped, discard the stats then
+    let stats_table = match find_stats_table() {
+        Some(stats_table) => stats_table,
+        None => return,
+    };
+
+    for ((fdw_name, server_name, table_name), delta) in pending {
+        save_stats(&stats_table, &fdw_name, &server_name, &table_name, &delta);
+    }
+}
+
+// add changes to a stats row
+fn save_stats(
+    stats_table: &str,
+    fdw_name: &str,
+    server_name: &str,
+    table_name: &str,
+    delta: &StatsDelta,
+) {
     let sql = format!(
-        "insert into {} as s (fdw_name, {}) values($1, $2)
-         on conflict(fdw_name)
+        "insert into {} as s (
+            fdw_name, server_name, table_name, create_times, rows_in, rows_out,
+            bytes_in, bytes_out, requests, errors, latency_sum_ms, latency_buckets
+         )
+         values($1, $2, $3, $4, $5, $6, $7, $8, $9, $10::jsonb, $11, $12)
+         on conflict(fdw_name, server_name, table_name)
          do update set
-            {} = coalesce(s.{}, 0) + excluded.{},
+            create_times = coalesce(s.create_times, 0) + excluded.create_times,
+            rows_in = coalesce(s.rows_in, 0) + excluded.rows_in,
+            rows_out = coalesce(s.rows_out, 0) + excluded.rows_out,
+            bytes_in = coalesce(s.bytes_in, 0) + excluded.bytes_in,
+            bytes_out = coalesce(s.bytes_out, 0) + excluded.bytes_out,
+            requests = coalesce(s.requests, 0) + excluded.requests,
+            errors = (
+                select coalesce(jsonb_object_agg(key, cnt), '{{}}'::jsonb)
+                from (
+                    select key, sum(value::bigint) as cnt
+                    from (
+                        select * from jsonb_each_text(coalesce(s.errors, '{{}}'::jsonb))
+                        union all
+                        select * from jsonb_each_text(excluded.errors)
+                    ) e
+                    group by key
+                ) t
+            ),
+            latency_sum_ms = coalesce(s.latency_sum_ms, 0) + excluded.latency_sum_ms,
+            latency_buckets = (
+                select array_agg(coalesce(a, 0) + coalesce(b, 0) order by n)
+                from unnest(s.latency_buckets, excluded.latency_buckets)
+                    with ordinality as u(a, b, n)
+            ),
             updated_at = timezone('utc'::text, now())",
-        get_stats_table(),
-        metric,
-        metric,
-        metric,
-        metric
+        stats_table,
     );
+    let int8 = PgBuiltInOids::INT8OID.oid();
     Spi::run_with_args(
         &sql,
         Some(vec![
             (PgBuiltInOids::TEXTOID.oid(), fdw_name.into_datum()),
-            (PgBuiltInOids::INT8OID.oid(), inc.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), server_name.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), table_name.into_datum()),
+            (int8, delta.create_times.into_datum()),
+            (int8, delta.rows_in.into_datum()),
+            (int8, delta.rows_out.into_datum()),
+            (int8, delta.bytes_in.into_datum()),
+            (int8, delta.bytes_out.into_datum()),
+            (int8, delta.requests.into_datum()),
+            (
+                PgBuiltInOids::TEXTOID.oid(),
+                delta.errors_json().into_datum(),
+            ),
+            (
+                PgBuiltInOids::FLOAT8OID.oid(),
+                delta.latency_sum_ms.into_datum(),
+            ),
+            (
+                PgBuiltInOids::INT8ARRAYOID.oid(),
+                delta.latency_buckets.clone().into_datum(),
+            ),
         ]),
     )
     .unwrap();
 }
 
+// increase stats value
+#[allow(dead_code)]
+pub(crate) fn inc_stats(fdw_name: &str, metric: Metric, inc: i64) {
+    add_stats(fdw_name, |delta| delta.inc(metric, inc));
+}
+
+// record a finished remote request started at `started`, it increases the
+// request count and adds the request latency to histogram
+#[allow(dead_code)]
+pub(crate) fn record_request(fdw_name: &str, started: Instant) {
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    add_stats(fdw_name, |delta| {
+        delta.inc(Metric::Requests, 1);
+        delta.observe_latency(latency_ms);
+    });
+}
+
+// record an error returned by FDW, `kind` is from `FdwError::kind()`
+#[allow(dead_code)]
+pub(crate) fn record_error(fdw_name: &str, kind: &'static str) {
+    add_stats(fdw_name, |delta| {
+        *delta.errors.entry(kind).or_default() += 1
+    });
+}
+
 // get metadata
 #[allow(dead_code)]
 pub(crate) fn get_metadata(fdw_name: &str) -> Option<JsonB> {
     let sql = format!(
-        "select metadata from {} where fdw_name = $1",
+        "select metadata from {}
+         where fdw_name = $1 and server_name = $2 and table_name = $3",
         get_stats_table()
     );
+    let (server_name, table_name) = stats_key();
     Spi::get_one_with_args(
         &sql,
-        vec![(PgBuiltInOids::TEXTOID.oid(), fdw_name.into_datum())],
+        vec![
+            (PgBuiltInOids::TEXTOID.oid(), fdw_name.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), server_name.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), table_name.into_datum()),
+        ],
     )
     .unwrap()
 }
@@ -83,19 +325,374 @@ pub(crate) fn get_metadata(fdw_name: &str) -> Option<JsonB> {
 #[allow(dead_code)]
 pub(crate) fn set_metadata(fdw_name: &str, metadata: Option<JsonB>) {
     let sql = format!(
-        "insert into {} as s (fdw_name, metadata) values($1, $2)
-         on conflict(fdw_name)
+        "insert into {} as s (fdw_name, server_name, table_name, metadata)
+         values($1, $2, $3, $4)
+         on conflict(fdw_name, server_name, table_name)
          do update set
-            metadata = $2,
+            metadata = $4,
             updated_at = timezone('utc'::text, now())",
         get_stats_table()
     );
+    let (server_name, table_name) = stats_key();
     Spi::run_with_args(
         &sql,
         Some(vec![
             (PgBuiltInOids::TEXTOID.oid(), fdw_name.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), server_name.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), table_name.into_datum()),
             (PgBuiltInOids::JSONBOID.oid(), metadata.into_datum()),
         ]),
     )
     .unwrap();
 }
+
+// estimate a latency percentile from histogram buckets, by linear
+// interpolation within the bucket the percentile falls in
+pub(crate) fn latency_percentile(buckets: &[i64], q: f64) -> Option<f64> {
+    let total: i64 = buckets.iter().sum();
+    if total == 0 {
+        return None;
+    }
+    let rank = q * total as f64;
+    let mut cum = 0;
+    for (idx, cnt) in buckets.iter().enumerate() {
+        if *cnt > 0 && (cum + cnt) as f64 >= rank {
+            let lower = if idx == 0 {
+                0.0
+            } else {
+                LATENCY_BUCKETS_MS[idx - 1]
+            };
+
+            // the last bucket has no upper bound, so use its lower bound
+            let upper = match LATENCY_BUCKETS_MS.get(idx) {
+                Some(upper) => *upper,
+                None => return Some(lower),
+            };
+            return Some(lower + (upper - lower) * (rank - cum as f64) / *cnt as f64);
+        }
+        cum += cnt;
+    }
+    None
+}
+
+#[pg_extern]
+fn wrappers_stats() -> TableIterator<
+    'static,
+    (
+        name!(fdw_name, String),
+        name!(server_name, String),
+        name!(table_name, String),
+        name!(requests, i64),
+        name!(errors, i64),
+        name!(errors_by_kind, JsonB),
+        name!(latency_avg_ms, Option<f64>),
+        name!(latency_p50_ms, Option<f64>),
+        name!(latency_p90_ms, Option<f64>),
+        name!(latency_p99_ms, Option<f64>),
+        name!(rows_in, i64),
+        name!(rows_out, i64),
+        name!(bytes_in, i64),
+        name!(updated_at, TimestampWithTimeZone),
+    ),
+> {
+    // make stats collected in current backend visible
+    flush_stats();
+
+    let sql = format!(
+        "select fdw_name, server_name, table_name, requests,
+                coalesce(errors, '{{}}'::jsonb) as errors_by_kind,
+                (select sum(value::bigint) from jsonb_each_text(errors))::bigint as errors,
+                latency_sum_ms, latency_buckets, rows_in, rows_out, bytes_in,
+                updated_at
+         from {}
+         order by fdw_name, server_name, table_name",
+        get_stats_table()
+    );
+    let rows = Spi::connect(|client| {
+        client
+            .select(&sql, None, None)
+            .unwrap()
+            .map(|row| {
+                let get_i64 = |name: &str| row.get_by_name::<i64, _>(name).unwrap().unwrap_or(0);
+                let requests = get_i64("requests");
+                let errors = get_i64("errors");
+                let errors_by_kind = row
+                    .get_by_name::<JsonB, _>("errors_by_kind")
+                    .unwrap()
+                    .unwrap();
+                let latency_sum_ms = row
+                    .get_by_name::<f64, _>("latency_sum_ms")
+                    .unwrap()
+                    .unwrap_or(0.0);
+                let buckets = row
+                    .get_by_name::<Vec<Option<i64>>, _>("latency_buckets")
+                    .unwrap()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|v| v.unwrap_or(0))
+                    .collect::<Vec<i64>>();
+                let observed: i64 = buckets.iter().sum();
+                let latency_avg_ms = if observed > 0 {
+                    Some(latency_sum_ms / observed as f64)
+                } else {
+                    None
+                };
+                (
+                    row.get_by_name::<String, _>("fdw_name").unwrap().unwrap(),
+                    row.get_by_name::<String, _>("server_name")
+                        .unwrap()
+                        .unwrap(),
+                    row.get_by_name::<String, _>("table_name").unwrap().unwrap(),
+                    requests,
+                    errors,
+                    errors_by_kind,
+                    latency_avg_ms,
+                    latency_percentile(&buckets, 0.5),
+                    latency_percentile(&buckets, 0.9),
+                    latency_percentile(&buckets, 0.99),
+                    get_i64("rows_in"),
+                    get_i64("rows_out"),
+                    get_i64("bytes_in"),
+                    row.get_by_name::<TimestampWithTimeZone, _>("updated_at")
+                        .unwrap()
+                        .unwrap(),
+                )
+            })
+            .collect::<Vec<_>>()
+    });
+    TableIterator::new(rows.into_iter())
+}
+
+#[pg_extern]
+fn wrappers_stats_reset(
+    fdw_name: default!(Option<&str>, "NULL"),
+    server_name: default!(Option<&str>, "NULL"),
+    table_name: default!(Option<&str>, "NULL"),
+) -> i64 {
+    // discard the matching stats not saved yet as well
+    let matched = |filter: Option<&str>, name: &str| filter.map(|f| f == name).unwrap_or(true);
+    PENDING_STATS.with(|pending| {
+        pending.borrow_mut().retain(|(fdw, server, table), _| {
+            !(matched(fdw_name, fdw) && matched(server_name, server) && matched(table_name, table))
+        })
+    });
+
+    let sql = format!(
+        "with deleted as (
+            delete from {}
+            where ($1 is null or fdw_name = $1)
+              and ($2 is null or server_name = $2)
+              and ($3 is null or table_name = $3)
+            returning 1
+         )
+         select count(*) from deleted",
+        get_stats_table()
+    );
+    Spi::get_one_with_args(
+        &sql,
+        vec![
+            (PgBuiltInOids::TEXTOID.oid(), fdw_name.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), server_name.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), table_name.into_datum()),
+        ],
+    )
+    .unwrap()
+    .unwrap_or(0)
+}
+
+// a stats row to be rendered as metrics
+struct MetricsRow {
+    labels: String,
+    counters: Vec<(Metric, i64)>,
+    errors: Vec<(String, i64)>,
+    latency_sum_ms: f64,
+    latency_buckets: Vec<i64>,
+}
+
+// escape an OpenMetrics label value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn metric_help(metric: Metric) -> &'static str {
+    match metric {
+        Metric::CreateTimes => "Number of times the FDW instance has been created.",
+        Metric::RowsIn => "Rows input from origin.",
+        Metric::RowsOut => "Rows output to Postgres.",
+        Metric::BytesIn => "Bytes input from origin.",
+        Metric::BytesOut => "Bytes output to Postgres.",
+        Metric::Requests => "Requests made to origin.",
+    }
+}
+
+#[pg_extern]
+fn wrappers_metrics() -> String {
+    // make stats collected in current backend visible
+    flush_stats();
+
+    let sql = format!(
+        "select fdw_name, server_name, table_name, create_times, rows_in,
+                rows_out, bytes_in, bytes_out, requests, latency_sum_ms,
+                latency_buckets
+         from {}
+         order by fdw_name, server_name, table_name",
+        get_stats_table()
+    );
+    let errors_sql = format!(
+        "select e.key, e.value::bigint as cnt
+         from {}, jsonb_each_text(coalesce(errors, '{{}}'::jsonb)) e
+         where fdw_name = $1 and server_name = $2 and table_name = $3
+         order by e.key",
+        get_stats_table()
+    );
+    let rows = Spi::connect(|client| {
+        let rows = client
+            .select(&sql, None, None)
+            .unwrap()
+            .map(|row| {
+                let get_i64 = |name: &str| row.get_by_name::<i64, _>(name).unwrap().unwrap_or(0);
+                let get_str = |name: &str| row.get_by_name::<String, _>(name).unwrap().unwrap();
+                let key = (
+                    get_str("fdw_name"),
+                    get_str("server_name"),
+                    get_str("table_name"),
+                );
+                let counters = [
+                    Metric::CreateTimes,
+                    Metric::RowsIn,
+                    Metric::RowsOut,
+                    Metric::BytesIn,
+                    Metric::BytesOut,
+                    Metric::Requests,
+                ]
+                .into_iter()
+                .map(|metric| (metric, get_i64(&metric.to_string())))
+                .collect::<Vec<_>>();
+                let mut latency_buckets = row
+                    .get_by_name::<Vec<Option<i64>>, _>("latency_buckets")
+                    .unwrap()
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|v| v.unwrap_or(0))
+                    .collect::<Vec<i64>>();
+                latency_buckets.resize(LATENCY_BUCKETS_MS.len() + 1, 0);
+                let latency_sum_ms = row
+                    .get_by_name::<f64, _>("latency_sum_ms")
+                    .unwrap()
+                    .unwrap_or(0.0);
+                (key, counters, latency_sum_ms, latency_buckets)
+            })
+            .collect::<Vec<_>>();
+
+        rows.into_iter()
+            .map(|(key, counters, latency_sum_ms, latency_buckets)| {
+                let errors = client
+                    .select(
+                        &errors_sql,
+                        None,
+                        Some(vec![
+                            (PgBuiltInOids::TEXTOID.oid(), key.0.as_str().into_datum()),
+                            (PgBuiltInOids::TEXTOID.oid(), key.1.as_str().into_datum()),
+                            (PgBuiltInOids::TEXTOID.oid(), key.2.as_str().into_datum()),
+                        ]),
+                    )
+                    .unwrap()
+                    .map(|row| {
+                        (
+                            row.get_by_name::<String, _>("key").unwrap().unwrap(),
+                            row.get_by_name::<i64, _>("cnt").unwrap().unwrap_or(0),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                MetricsRow {
+                    labels: format!(
+                        "fdw=\"{}\",server=\"{}\",table=\"{}\"",
+                        escape_label(&key.0),
+                        escape_label(&key.1),
+                        escape_label(&key.2)
+                    ),
+                    counters,
+                    errors,
+                    latency_sum_ms,
+                    latency_buckets,
+                }
+            })
+            .collect::<Vec<_>>()
+    });
+
+    // samples of the same metric family must be grouped together
+    let mut out = String::new();
+    for (idx, metric) in [
+        Metric::CreateTimes,
+        Metric::RowsIn,
+        Metric::RowsOut,
+        Metric::BytesIn,
+        Metric::BytesOut,
+        Metric::Requests,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let family = format!("wrappers_{}", metric);
+        out.push_str(&format!("# TYPE {} counter\n", family));
+        out.push_str(&format!("# HELP {} {}\n", family, metric_help(metric)));
+        for row in &rows {
+            out.push_str(&format!(
+                "{}_total{{{}}} {}\n",
+                family, row.labels, row.counters[idx].1
+            ));
+        }
+    }
+
+    out.push_str("# TYPE wrappers_errors counter\n");
+    out.push_str("# HELP wrappers_errors Errors by kind.\n");
+    for row in &rows {
+        for (kind, cnt) in &row.errors {
+            out.push_str(&format!(
+                "wrappers_errors_total{{{},kind=\"{}\"}} {}\n",
+                row.labels,
+                escape_label(kind),
+                cnt
+            ));
+        }
+    }
+
+    let family = "wrappers_request_latency_seconds";
+    out.push_str(&format!("# TYPE {} histogram\n", family));
+    out.push_str(&format!("# UNIT {} seconds\n", family));
+    out.push_str(&format!(
+        "# HELP {} Latency of requests made to origin.\n",
+        family
+    ));
+    for row in &rows {
+        let mut cum = 0;
+        for (bound, cnt) in LATENCY_BUCKETS_MS.iter().zip(&row.latency_buckets) {
+            cum += cnt;
+            out.push_str(&format!(
+                "{}_bucket{{{},le=\"{:?}\"}} {}\n",
+                family,
+                row.labels,
+                bound / 1000.0,
+                cum
+            ));
+        }
+        let count: i64 = row.latency_buckets.iter().sum();
+        out.push_str(&format!(
+            "{}_bucket{{{},le=\"+Inf\"}} {}\n",
+            family, row.labels, count
+        ));
+        out.push_str(&format!(
+            "{}_sum{{{}}} {:?}\n",
+            family,
+            row.labels,
+            row.latency_sum_ms / 1000.0
+        ));
+        out.push_str(&format!("{}_count{{{}}} {}\n", family, row.labels, count));
+    }
+
+    out.push_str("# EOF\n");
+    out
+}
@@ -0,0 +1,290 @@
+use pgrx::prelude::*;
+use pgrx::PgRelation;
+use supabase_wrappers::prelude::run_in_subtransaction;
+
+// sync state and history table names
+const SYNC_STATE_TABLE: &str = "wrappers_sync_state";
+const SYNC_HISTORY_TABLE: &str = "wrappers_sync_history";
+
+// get full qualified name of a table created by this extension
+fn get_extension_table(table: &str) -> String {
+    let sql = format!(
+        "select b.nspname || '.{}'
+         from pg_catalog.pg_extension a join pg_namespace b on a.extnamespace = b.oid
+         where a.extname = 'wrappers'",
+        table
+    );
+    Spi::get_one::<String>(&sql)
+        .unwrap()
+        .unwrap_or_else(|| panic!("cannot find table '{}'", table))
+}
+
+// get quoted schema qualified name of a table, it is also the key of the sync
+// state so it is the same regardless of search path
+fn qualified_name(table_id: pg_sys::Oid) -> String {
+    Spi::get_one_with_args::<String>(
+        "select format('%I.%I', n.nspname, c.relname)
+         from pg_class c join pg_namespace n on n.oid = c.relnamespace
+         where c.oid = $1",
+        vec![(PgBuiltInOids::OIDOID.oid(), table_id.into_datum())],
+    )
+    .unwrap()
+    .unwrap()
+}
+
+// get the column rows are upserted by, which is the foreign table's
+// `rowid_column` option or the target table's single column primary key
+fn get_rowid_column(ftable_id: pg_sys::Oid, target_id: pg_sys::Oid) -> Option<String> {
+    Spi::get_one_with_args::<String>(
+        "select option_value
+         from pg_foreign_table ft, pg_options_to_table(ft.ftoptions)
+         where ft.ftrelid = $1 and option_name = 'rowid_column'
+         union all
+         select a.attname::text
+         from pg_index i
+         join pg_attribute a on a.attrelid = i.indrelid and a.attnum = i.indkey[0]
+         where i.indrelid = $2 and i.indisprimary and i.indnatts = 1
+         limit 1",
+        vec![
+            (PgBuiltInOids::OIDOID.oid(), ftable_id.into_datum()),
+            (PgBuiltInOids::OIDOID.oid(), target_id.into_datum()),
+        ],
+    )
+    .unwrap()
+}
+
+// copy the foreign table rows whose cursor column is greater than last value
+// to the target table, returns number of rows synced and the new last value
+fn sync_rows(
+    ftable_id: pg_sys::Oid,
+    ftable: &str,
+    target_id: pg_sys::Oid,
+    target: &str,
+    cursor_column: &str,
+    last_value: Option<&str>,
+) -> (i64, Option<String>) {
+    let rowid_column = get_rowid_column(ftable_id, target_id).unwrap_or_else(|| {
+        panic!(
+            "option 'rowid_column' is required on {} as {} has no single column primary key",
+            ftable, target
+        )
+    });
+
+    // columns exist in both foreign table and target table, with the
+    // cursor column type
+    let (columns, cursor_type) = Spi::connect(|client| {
+        let columns = client
+            .select(
+                "select a.attname::text as name, quote_ident(a.attname) as ident,
+                        format_type(a.atttypid, a.atttypmod) as type_name
+                 from pg_attribute a
+                 join pg_attribute b on b.attrelid = $2 and b.attname = a.attname
+                   and b.attnum > 0 and not b.attisdropped
+                 where a.attrelid = $1 and a.attnum > 0 and not a.attisdropped
+                 order by a.attnum",
+                None,
+                Some(vec![
+                    (PgBuiltInOids::OIDOID.oid(), ftable_id.into_datum()),
+                    (PgBuiltInOids::OIDOID.oid(), target_id.into_datum()),
+                ]),
+            )
+            .unwrap()
+            .map(|row| {
+                (
+                    row.get_by_name::<String, _>("name").unwrap().unwrap(),
+                    row.get_by_name::<String, _>("ident").unwrap().unwrap(),
+                    row.get_by_name::<String, _>("type_name").unwrap().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let cursor_type = columns
+            .iter()
+            .find(|(name, _, _)| name == cursor_column)
+            .map(|(_, _, type_name)| type_name.clone())
+            .unwrap_or_else(|| {
+                panic!(
+                    "cursor column '{}' must exist in both {} and {}",
+                    cursor_column, ftable, target
+                )
+            });
+        if !columns.iter().any(|(name, _, _)| name == &rowid_column) {
+            panic!(
+                "rowid column '{}' must exist in both {} and {}",
+                rowid_column, ftable, target
+            );
+        }
+        (columns, cursor_type)
+    });
+
+    let quote_ident = |name: &str| {
+        columns
+            .iter()
+            .find(|(col, _, _)| col == name)
+            .map(|(_, ident, _)| ident.clone())
+            .unwrap()
+    };
+    let cursor = quote_ident(cursor_column);
+    let rowid = quote_ident(&rowid_column);
+    let col_list = columns
+        .iter()
+        .map(|(_, ident, _)| ident.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    let updates = columns
+        .iter()
+        .filter(|(name, _, _)| name != &rowid_column)
+        .map(|(_, ident, _)| format!("{} = excluded.{}", ident, ident))
+        .collect::<Vec<_>>();
+    let on_conflict = if updates.is_empty() {
+        "do nothing".to_owned()
+    } else {
+        format!("do update set {}", updates.join(", "))
+    };
+
+    // the cursor condition is pushed down to the foreign table scan as a qual
+    let (cond, args) = match last_value {
+        Some(last_value) => (
+            format!("where {} > $1::{}", cursor, cursor_type),
+            Some(vec![(
+                PgBuiltInOids::TEXTOID.oid(),
+                last_value.into_datum(),
+            )]),
+        ),
+        None => (String::new(), None),
+    };
+    let sql = format!(
+        "with src as (
+            select {cols} from {ftable} {cond}
+         ), ins as (
+            insert into {target} ({cols})
+            select {cols} from src
+            on conflict ({rowid}) {on_conflict}
+            returning 1
+         )
+         select (select count(*) from ins) as rows_synced,
+                (select max({cursor})::text from src) as last_value",
+        cols = col_list,
+        ftable = ftable,
+        cond = cond,
+        target = target,
+        rowid = rowid,
+        on_conflict = on_conflict,
+        cursor = cursor,
+    );
+    let (rows_synced, new_value) = Spi::connect(|mut client| {
+        let row = client.update(&sql, None, args).unwrap().first();
+        (
+            row.get_by_name::<i64, _>("rows_synced")
+                .unwrap()
+                .unwrap_or(0),
+            row.get_by_name::<String, _>("last_value").unwrap(),
+        )
+    });
+    let new_value = new_value.or_else(|| last_value.map(|v| v.to_owned()));
+
+    let sql = format!(
+        "insert into {} (foreign_table, target_table, cursor_column, last_value, last_synced_at)
+         values ($1, $2, $3, $4, timezone('utc'::text, now()))
+         on conflict (foreign_table, target_table)
+         do update set cursor_column = excluded.cursor_column,
+                       last_value = excluded.last_value,
+                       last_synced_at = excluded.last_synced_at",
+        get_extension_table(SYNC_STATE_TABLE)
+    );
+    Spi::run_with_args(
+        &sql,
+        Some(vec![
+            (PgBuiltInOids::TEXTOID.oid(), ftable.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), target.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), cursor_column.into_datum()),
+            (PgBuiltInOids::TEXTOID.oid(), new_value.clone().into_datum()),
+        ]),
+    )
+    .unwrap();
+
+    (rows_synced, new_value)
+}
+
+#[pg_schema]
+mod wrappers {
+    use super::*;
+
+    /// Incrementally copy new rows of a foreign table into a local table
+    ///
+    /// The rows whose `cursor_column` is greater than the max value synced last
+    /// time are upserted into the target table by the foreign table's
+    /// `rowid_column`, or the target table's primary key. Each sync is recorded
+    /// in the sync history table, a failed sync is rolled back and its error is
+    /// recorded and returned.
+    #[pg_extern]
+    fn sync(
+        foreign_table: PgRelation,
+        target: PgRelation,
+        cursor_column: &str,
+    ) -> TableIterator<
+        'static,
+        (
+            name!(rows_synced, i64),
+            name!(last_value, Option<String>),
+            name!(error, Option<String>),
+        ),
+    > {
+        let (ftable_id, target_id) = (foreign_table.oid(), target.oid());
+        let (ftable, target) = (qualified_name(ftable_id), qualified_name(target_id));
+        let started_at = Spi::get_one::<TimestampWithTimeZone>("select clock_timestamp()")
+            .unwrap()
+            .unwrap();
+
+        let last_value = Spi::get_one_with_args::<String>(
+            &format!(
+                "select last_value from {}
+                 where foreign_table = $1 and target_table = $2",
+                get_extension_table(SYNC_STATE_TABLE)
+            ),
+            vec![
+                (PgBuiltInOids::TEXTOID.oid(), ftable.as_str().into_datum()),
+                (PgBuiltInOids::TEXTOID.oid(), target.as_str().into_datum()),
+            ],
+        )
+        .unwrap();
+
+        let result = unsafe {
+            run_in_subtransaction(|| {
+                sync_rows(
+                    ftable_id,
+                    &ftable,
+                    target_id,
+                    &target,
+                    cursor_column,
+                    last_value.as_deref(),
+                )
+            })
+        };
+        let (rows_synced, new_value, error) = match result {
+            Ok((rows_synced, new_value)) => (rows_synced, new_value, None),
+            Err(err) => (0, last_value.clone(), Some(err.to_string())),
+        };
+
+        let sql = format!(
+            "insert into {} (foreign_table, target_table, from_value, to_value,
+                             rows_synced, error, started_at)
+             values ($1, $2, $3, $4, $5, $6, $7)",
+            get_extension_table(SYNC_HISTORY_TABLE)
+        );
+        Spi::run_with_args(
+            &sql,
+            Some(vec![
+                (PgBuiltInOids::TEXTOID.oid(), ftable.as_str().into_datum()),
+                (PgBuiltInOids::TEXTOID.oid(), target.as_str().into_datum()),
+                (PgBuiltInOids::TEXTOID.oid(), last_value.into_datum()),
+                (PgBuiltInOids::TEXTOID.oid(), new_value.clone().into_datum()),
+                (PgBuiltInOids::INT8OID.oid(), rows_synced.into_datum()),
+                (PgBuiltInOids::TEXTOID.oid(), error.clone().into_datum()),
+                (PgBuiltInOids::TIMESTAMPTZOID.oid(), started_at.into_datum()),
+            ]),
+        )
+        .unwrap();
+
+        TableIterator::new(vec![(rows_synced, new_value, error)].into_iter())
+    }
+}
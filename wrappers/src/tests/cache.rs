@@ -0,0 +1,86 @@
+#[pgrx::pg_schema]
+mod tests {
+    use crate::tests::{create_users_table, select_user_ids};
+    use pgrx::prelude::*;
+
+    // result cache status shown in `EXPLAIN ANALYZE` of a `users` scan
+    fn cache_status(c: &pgrx::spi::SpiClient) -> Option<String> {
+        c.select("EXPLAIN ANALYZE SELECT * FROM users", None, None)
+            .unwrap()
+            .filter_map(|r| r.get_by_name::<&str, _>("QUERY PLAN").unwrap())
+            .find_map(|r| {
+                r.trim()
+                    .strip_prefix("Result Cache: ")
+                    .map(|s| s.to_owned())
+            })
+    }
+
+    #[pg_test]
+    fn cache_hit() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "cache_ttl '60'");
+
+            // scan result is cached after the first scan
+            assert_eq!(cache_status(&c).as_deref(), Some("miss"));
+            assert_eq!(cache_status(&c).as_deref(), Some("hit"));
+            assert_eq!(select_user_ids(&c), vec![1, 2, 3]);
+        });
+    }
+
+    #[pg_test]
+    fn cache_off() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            assert_eq!(cache_status(&c), None);
+
+            // rows more than cache_max_rows are not cached
+            c.update(
+                "ALTER FOREIGN TABLE users OPTIONS (ADD cache_ttl '60', ADD cache_max_rows '2')",
+                None,
+                None,
+            )
+            .unwrap();
+            assert_eq!(cache_status(&c).as_deref(), Some("miss"));
+            assert_eq!(cache_status(&c).as_deref(), Some("miss"));
+        });
+    }
+
+    #[pg_test]
+    fn cache_invalidate() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "cache_ttl '60'");
+            assert_eq!(cache_status(&c).as_deref(), Some("miss"));
+
+            c.update(
+                "SELECT wrappers_cache_invalidate('public.users')",
+                None,
+                None,
+            )
+            .unwrap();
+            assert_eq!(cache_status(&c).as_deref(), Some("miss"));
+
+            c.update(
+                "SELECT wrappers_cache_invalidate_server('my_rest_server')",
+                None,
+                None,
+            )
+            .unwrap();
+            assert_eq!(cache_status(&c).as_deref(), Some("miss"));
+
+            c.update("SELECT wrappers_cache_invalidate()", None, None)
+                .unwrap();
+            assert_eq!(cache_status(&c).as_deref(), Some("miss"));
+            assert_eq!(cache_status(&c).as_deref(), Some("hit"));
+        });
+    }
+
+    #[pg_test(error = "only superuser can invalidate result cache of all foreign tables")]
+    fn cache_invalidate_all_by_non_superuser() {
+        Spi::run(
+            "CREATE ROLE cache_test_user;
+             SET ROLE cache_test_user;
+             SELECT wrappers_cache_invalidate()",
+        )
+        .unwrap();
+    }
+}
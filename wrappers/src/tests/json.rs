@@ -0,0 +1,171 @@
+#[pgrx::pg_schema]
+mod tests {
+    use pgrx::prelude::*;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use std::str::FromStr;
+    use supabase_wrappers::prelude::*;
+
+    fn column(name: &str, type_oid: pg_sys::Oid) -> Column {
+        Column {
+            name: name.to_owned(),
+            num: 1,
+            type_oid,
+        }
+    }
+
+    #[pg_test]
+    fn json_path_parse() {
+        let same_paths = [
+            ("$.data.items", "data.items"),
+            ("$.data.items", "$['data']['items']"),
+            ("$.data[*].name", "data.*.name"),
+        ];
+        for (a, b) in same_paths {
+            assert_eq!(
+                JsonPath::from_str(a).unwrap(),
+                JsonPath::from_str(b).unwrap()
+            );
+        }
+
+        for path in ["$.", "$..a", "$[0", "$[x]"] {
+            assert!(
+                JsonPath::from_str(path).is_err(),
+                "{} should be invalid",
+                path
+            );
+        }
+    }
+
+    #[pg_test]
+    fn json_path_select() {
+        let value = json!({
+            "data": [
+                {"name": "Alice", "address": {"city": "Auckland"}},
+                {"name": "Bob", "address": {"city": "Berlin"}}
+            ],
+            "meta": {"next": null}
+        });
+        let select = |path: &str| JsonPath::from_str(path).unwrap().select(&value);
+
+        assert_eq!(select("$"), vec![&value]);
+        assert_eq!(select("$.data[1].name"), vec![&json!("Bob")]);
+        assert_eq!(
+            select("$.data[*].address.city"),
+            vec![&json!("Auckland"), &json!("Berlin")]
+        );
+        assert_eq!(select("$.meta.*"), vec![&json!(null)]);
+        assert!(select("$.data[2]").is_empty());
+        assert!(select("$.nothing.here").is_empty());
+        assert_eq!(
+            JsonPath::from_str("data[*].name").unwrap().get(&value),
+            Some(&json!("Alice"))
+        );
+    }
+
+    #[pg_test]
+    fn json_cell_round_trip() {
+        let cases = [
+            (json!(true), pg_sys::BOOLOID),
+            (json!(42), pg_sys::INT2OID),
+            (json!(42), pg_sys::INT4OID),
+            (json!(9007199254740993i64), pg_sys::INT8OID),
+            (json!(1.5), pg_sys::FLOAT8OID),
+            (json!("hello"), pg_sys::TEXTOID),
+            (json!("2023-08-01"), pg_sys::DATEOID),
+            (json!("2023-08-01T12:34:56"), pg_sys::TIMESTAMPOID),
+            (json!({"a": [1, 2]}), pg_sys::JSONBOID),
+        ];
+        for (value, type_oid) in cases {
+            let cell = Cell::from_json(&value, &column("col", type_oid))
+                .unwrap()
+                .unwrap();
+            let expected = match type_oid {
+                pg_sys::TIMESTAMPOID => json!("2023-08-01 12:34:56"),
+                _ => value,
+            };
+            assert_eq!(cell.to_json(), expected);
+        }
+
+        // numeric keeps the precision of the number's text
+        let cell = Cell::from_json(
+            &serde_json::from_str("12345678901234567").unwrap(),
+            &column("col", pg_sys::NUMERICOID),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            cell.to_json(),
+            serde_json::from_str::<serde_json::Value>("12345678901234567").unwrap()
+        );
+        let cell = Cell::from_json(&json!("0.1"), &column("col", pg_sys::NUMERICOID))
+            .unwrap()
+            .unwrap();
+        assert_eq!(cell.to_json(), json!(0.1));
+
+        // number is Unix epoch for timestamp
+        let cell = Cell::from_json(&json!(0), &column("col", pg_sys::TIMESTAMPOID))
+            .unwrap()
+            .unwrap();
+        assert_eq!(cell.to_json(), json!("1970-01-01 00:00:00"));
+
+        assert!(
+            Cell::from_json(&json!(null), &column("col", pg_sys::INT4OID))
+                .unwrap()
+                .is_none()
+        );
+        assert!(Cell::from_json(&json!(70000), &column("col", pg_sys::INT2OID)).is_err());
+        assert!(Cell::from_json(&json!("abc"), &column("col", pg_sys::INT8OID)).is_err());
+    }
+
+    #[pg_test]
+    fn json_row_round_trip() {
+        let columns = vec![
+            column("id", pg_sys::INT8OID),
+            column("name", pg_sys::TEXTOID),
+            column("missing", pg_sys::TEXTOID),
+        ];
+        let value = json!({"id": 1, "name": "Alice", "extra": true});
+        let row = Row::from_json(&value, &columns).unwrap();
+        assert_eq!(
+            row.to_json(),
+            json!({"id": 1, "name": "Alice", "missing": null})
+        );
+        assert!(Row::from_json(&json!([1]), &columns).is_err());
+    }
+
+    #[derive(Serialize, Deserialize, FromRow, IntoRow, Debug, PartialEq)]
+    struct Customer {
+        id: String,
+        email: Option<String>,
+        created: i64,
+    }
+
+    #[pg_test]
+    fn json_from_row_into_row() {
+        let columns = vec![
+            column("id", pg_sys::TEXTOID),
+            column("email", pg_sys::TEXTOID),
+            column("created", pg_sys::TIMESTAMPOID),
+        ];
+        let customer = Customer {
+            id: "cus_1".to_owned(),
+            email: None,
+            created: 1690893296,
+        };
+
+        // timestamp column is converted from and to Unix epoch
+        let row = Customer {
+            id: "cus_1".to_owned(),
+            email: None,
+            created: 1690893296,
+        }
+        .into_row(&columns)
+        .unwrap();
+        assert_eq!(
+            row.to_json(),
+            json!({"id": "cus_1", "email": null, "created": "2023-08-01 12:34:56"})
+        );
+        assert_eq!(Customer::from_row(&row).unwrap(), customer);
+    }
+}
@@ -0,0 +1,26 @@
+#[pgrx::pg_schema]
+mod tests {
+    use crate::tests::{create_users_table, select_user_ids};
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    fn metrics_exposition() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            assert_eq!(select_user_ids(&c), vec![1, 2, 3]);
+
+            let metrics = c
+                .select("SELECT wrappers_metrics() AS metrics", None, None)
+                .unwrap()
+                .first()
+                .get_one::<String>()
+                .unwrap()
+                .unwrap();
+            assert!(metrics.contains("# TYPE wrappers_requests counter"));
+            assert!(metrics.contains("server=\"my_rest_server\""));
+            assert!(metrics.contains("table=\"public.users\""));
+            assert!(metrics.contains("wrappers_request_latency_seconds_bucket{"));
+            assert!(metrics.ends_with("# EOF\n"));
+        });
+    }
+}
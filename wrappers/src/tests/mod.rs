@@ -0,0 +1,79 @@
+// tests of the framework features, the REST wrapper replaying responses from
+// a cassette is used as the foreign server so no remote service is needed
+use pgrx::spi::SpiClient;
+
+mod cache;
+mod json;
+mod metrics;
+mod options;
+mod remote;
+mod rescan;
+mod secret;
+mod stats;
+mod sync;
+
+// create a REST foreign server and a `users` foreign table on it
+pub(crate) fn create_users_table(c: &mut SpiClient, table_options: &str) {
+    c.update(
+        r#"CREATE FOREIGN DATA WRAPPER rest_wrapper
+                 HANDLER rest_fdw_handler VALIDATOR rest_fdw_validator"#,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let cassette = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/fdw/rest_fdw/cassettes/users.json"
+    );
+    c.update(
+        &format!(
+            r#"CREATE SERVER my_rest_server
+                 FOREIGN DATA WRAPPER rest_wrapper
+                 OPTIONS (
+                   base_url 'http://rest.invalid/api',
+                   auth_scheme 'bearer',
+                   api_key 'test_key',
+                   pagination 'cursor',
+                   cursor_path '$.meta.next_cursor',
+                   page_size '2',
+                   page_size_param 'limit',
+                   http_cassette '{}',
+                   http_cassette_mode 'replay'
+                 )"#,
+            cassette
+        ),
+        None,
+        None,
+    )
+    .unwrap();
+
+    let mut options = vec!["path 'users'", "records_path '$.data'"];
+    if !table_options.is_empty() {
+        options.push(table_options);
+    }
+    c.update(
+        &format!(
+            r#"CREATE FOREIGN TABLE users (
+                 id bigint,
+                 name text,
+                 status text,
+                 attrs jsonb
+               )
+               SERVER my_rest_server
+               OPTIONS ({})"#,
+            options.join(", ")
+        ),
+        None,
+        None,
+    )
+    .unwrap();
+}
+
+// ids of all the rows in `users` foreign table
+pub(crate) fn select_user_ids(c: &SpiClient) -> Vec<i64> {
+    c.select("SELECT id FROM users ORDER BY id", None, None)
+        .unwrap()
+        .filter_map(|r| r.get_by_name::<i64, _>("id").unwrap())
+        .collect()
+}
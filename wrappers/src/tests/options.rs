@@ -0,0 +1,90 @@
+#[pgrx::pg_schema]
+mod tests {
+    use crate::tests::{create_users_table, select_user_ids};
+    use pgrx::prelude::*;
+
+    #[pg_test(error = "invalid option \"api_key\" for user mapping")]
+    fn user_mapping_rejects_server_option() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            c.update(
+                r#"CREATE USER MAPPING FOR CURRENT_USER SERVER my_rest_server
+                     OPTIONS (api_key 'other_key')"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[pg_test]
+    fn scan_with_user_mapping() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            c.update(
+                "CREATE USER MAPPING FOR CURRENT_USER SERVER my_rest_server",
+                None,
+                None,
+            )
+            .unwrap();
+
+            // server options are still used when user mapping exists
+            assert_eq!(select_user_ids(&c), vec![1, 2, 3]);
+        });
+    }
+
+    #[pg_test(error = "invalid option \"http_cassette\" for user mapping")]
+    fn user_mapping_rejects_cassette() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            c.update(
+                r#"CREATE USER MAPPING FOR CURRENT_USER SERVER my_rest_server
+                     OPTIONS (http_cassette '/tmp/cassette.json')"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[pg_test(error = "only superuser can set option \"http_cassette\"")]
+    fn cassette_set_by_non_superuser() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            c.update(
+                "CREATE ROLE wrappers_test_user;
+                 GRANT USAGE ON FOREIGN DATA WRAPPER rest_wrapper TO wrappers_test_user;
+                 ALTER SERVER my_rest_server OWNER TO wrappers_test_user;
+                 SET ROLE wrappers_test_user",
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                "ALTER SERVER my_rest_server OPTIONS (SET http_cassette '/tmp/cassette.json')",
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[pg_test(error = "only superuser can set option \"http_cassette\"")]
+    fn cassette_owned_by_non_superuser() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+
+            // the cassette set by superuser is not used after the server is
+            // owned by a non-superuser
+            c.update(
+                "CREATE ROLE wrappers_test_user;
+                 GRANT USAGE ON FOREIGN DATA WRAPPER rest_wrapper TO wrappers_test_user;
+                 ALTER SERVER my_rest_server OWNER TO wrappers_test_user",
+                None,
+                None,
+            )
+            .unwrap();
+            select_user_ids(&c);
+        });
+    }
+}
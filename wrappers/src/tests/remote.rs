@@ -0,0 +1,77 @@
+#[pgrx::pg_schema]
+mod tests {
+    use crate::tests::create_users_table;
+    use pgrx::prelude::*;
+
+    // let a non-superuser use the server, with or without its own user mapping
+    fn set_role_with_usage(c: &mut pgrx::spi::SpiClient, user_mapping: bool) {
+        c.update(
+            "CREATE ROLE wrappers_test_user;
+             GRANT USAGE ON FOREIGN DATA WRAPPER rest_wrapper TO wrappers_test_user;
+             GRANT USAGE ON FOREIGN SERVER my_rest_server TO wrappers_test_user;
+             GRANT USAGE ON SCHEMA wrappers TO wrappers_test_user",
+            None,
+            None,
+        )
+        .unwrap();
+        if user_mapping {
+            c.update(
+                "CREATE USER MAPPING FOR wrappers_test_user SERVER my_rest_server",
+                None,
+                None,
+            )
+            .unwrap();
+        }
+        c.update("SET ROLE wrappers_test_user", None, None).unwrap();
+    }
+
+    #[pg_test(
+        error = "must be owner of foreign server my_rest_server or have a user mapping on it to run remote query"
+    )]
+    fn remote_query_without_user_mapping() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            set_role_with_usage(&mut c, false);
+            c.select(
+                "SELECT * FROM wrappers.remote_query('my_rest_server', 'select * from users')",
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[pg_test(
+        error = "must be owner of foreign server my_rest_server or have a user mapping on it to run remote statement"
+    )]
+    fn remote_exec_without_user_mapping() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            set_role_with_usage(&mut c, false);
+            c.update(
+                "SELECT wrappers.remote_exec('my_rest_server', 'delete from users')",
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    // the privilege check is passed, then the statement is rejected by the
+    // REST wrapper
+    #[pg_test(
+        error = "remote statement is not supported by the foreign data wrapper of server \"my_rest_server\""
+    )]
+    fn remote_exec_with_user_mapping() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            set_role_with_usage(&mut c, true);
+            c.update(
+                "SELECT wrappers.remote_exec('my_rest_server', 'delete from users')",
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+}
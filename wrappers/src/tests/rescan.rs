@@ -0,0 +1,47 @@
+#[pgrx::pg_schema]
+mod tests {
+    use crate::tests::create_users_table;
+    use pgrx::prelude::*;
+
+    #[pg_test]
+    fn rescan_without_params() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+
+            // the inner scan is re-scanned for each outer row with the same
+            // parameters, so it must return all the rows again each time
+            let results = c
+                .select(
+                    "SELECT v.n, (SELECT count(*) FROM users WHERE v.n > 0) AS cnt
+                     FROM (VALUES (1), (2), (3)) v(n) ORDER BY v.n",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<i64, _>("cnt").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![3, 3, 3]);
+        });
+    }
+
+    #[pg_test]
+    fn rescan_with_changed_params() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+
+            // the inner scan is restarted with the new parameter value for
+            // each outer row
+            let results = c
+                .select(
+                    "SELECT v.name, (SELECT count(*) FROM users t WHERE t.name = v.name) AS cnt
+                     FROM (VALUES ('Alice'), ('Bob'), ('Dave')) v(name) ORDER BY v.name",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<i64, _>("cnt").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![1, 1, 0]);
+        });
+    }
+}
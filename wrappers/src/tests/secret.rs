@@ -0,0 +1,118 @@
+#[pgrx::pg_schema]
+mod tests {
+    use crate::tests::{create_users_table, select_user_ids};
+    use pgrx::prelude::*;
+    use std::collections::HashMap;
+    use supabase_wrappers::prelude::get_secret_option;
+
+    fn get_api_key(opt_name: &str, value: &str) -> Option<String> {
+        let options = HashMap::from([(opt_name.to_owned(), value.to_owned())]);
+        get_secret_option("api_key", &options).unwrap()
+    }
+
+    #[pg_test]
+    fn secret_env_provider() {
+        std::env::set_var("WRAPPERS_TEST_API_KEY", "env_key");
+        assert_eq!(
+            get_api_key("api_key_secret", "env:WRAPPERS_TEST_API_KEY"),
+            Some("env_key".to_owned())
+        );
+    }
+
+    #[pg_test]
+    fn secret_file_provider() {
+        // trailing newlines are removed
+        let path = std::env::temp_dir().join("wrappers_test_api_key");
+        std::fs::write(&path, "file_key\n").unwrap();
+        assert_eq!(
+            get_api_key("api_key_secret", &format!("file:{}", path.display())),
+            Some("file_key".to_owned())
+        );
+    }
+
+    #[pg_test]
+    fn secret_guc_provider() {
+        Spi::run("SET my_app.api_key = 'guc_key'").unwrap();
+        assert_eq!(
+            get_api_key("api_key_secret", "guc:my_app.api_key"),
+            Some("guc_key".to_owned())
+        );
+    }
+
+    #[pg_test]
+    fn secret_vault_provider() {
+        // a stand-in for the view created by Vault extension
+        Spi::run(
+            "CREATE SCHEMA vault;
+             CREATE TABLE vault.decrypted_secrets (key_id uuid, decrypted_secret text);
+             INSERT INTO vault.decrypted_secrets
+               VALUES ('5bb6e5c8-5b2c-4bd1-9d30-4f0c1b0d3a1e', 'vault_key')",
+        )
+        .unwrap();
+        let key_id = "5bb6e5c8-5b2c-4bd1-9d30-4f0c1b0d3a1e";
+        assert_eq!(
+            get_api_key("api_key_secret", &format!("vault:{}", key_id)),
+            Some("vault_key".to_owned())
+        );
+
+        // Vault secret id can also be specified in `_id` option
+        assert_eq!(
+            get_api_key("api_key_id", key_id),
+            Some("vault_key".to_owned())
+        );
+    }
+
+    #[pg_test(error = "permission denied to use secret provider \"env\", must be superuser")]
+    fn secret_permission_denied_in_validator() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            c.update(
+                "CREATE ROLE wrappers_test_user;
+                 GRANT USAGE ON FOREIGN DATA WRAPPER rest_wrapper TO wrappers_test_user;
+                 SET ROLE wrappers_test_user",
+                None,
+                None,
+            )
+            .unwrap();
+            c.update(
+                r#"CREATE SERVER my_other_server
+                     FOREIGN DATA WRAPPER rest_wrapper
+                     OPTIONS (
+                       base_url 'http://rest.invalid/api',
+                       api_key_secret 'env:WRAPPERS_TEST_API_KEY'
+                     )"#,
+                None,
+                None,
+            )
+            .unwrap();
+        });
+    }
+
+    #[pg_test(error = "permission denied to use secret provider \"env\", must be superuser")]
+    fn secret_permission_denied_on_resolve() {
+        std::env::set_var("WRAPPERS_TEST_API_KEY", "env_key");
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            c.update(
+                "ALTER SERVER my_rest_server
+                   OPTIONS (DROP api_key, ADD api_key_secret 'env:WRAPPERS_TEST_API_KEY')",
+                None,
+                None,
+            )
+            .unwrap();
+
+            // the secret is resolved when the server owner is superuser
+            assert_eq!(select_user_ids(&c), vec![1, 2, 3]);
+
+            // but not after the server is owned by a non-superuser
+            c.update(
+                "CREATE ROLE wrappers_test_user;
+                 ALTER SERVER my_rest_server OWNER TO wrappers_test_user",
+                None,
+                None,
+            )
+            .unwrap();
+            select_user_ids(&c);
+        });
+    }
+}
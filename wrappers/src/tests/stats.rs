@@ -0,0 +1,56 @@
+#[pgrx::pg_schema]
+mod tests {
+    use crate::tests::{create_users_table, select_user_ids};
+    use pgrx::prelude::*;
+
+    // request and row counts of `users` foreign table
+    fn users_stats(c: &pgrx::spi::SpiClient) -> Vec<(String, i64, i64)> {
+        c.select(
+            "SELECT server_name, requests, rows_in FROM wrappers_stats()
+             WHERE table_name LIKE '%.users'",
+            None,
+            None,
+        )
+        .unwrap()
+        .filter_map(|r| {
+            let server_name = r.get_by_name::<String, _>("server_name").unwrap()?;
+            let requests = r.get_by_name::<i64, _>("requests").unwrap()?;
+            let rows_in = r.get_by_name::<i64, _>("rows_in").unwrap()?;
+            Some((server_name, requests, rows_in))
+        })
+        .collect()
+    }
+
+    #[pg_test]
+    fn stats_per_table() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            assert_eq!(select_user_ids(&c), vec![1, 2, 3]);
+
+            // 3 rows are fetched in 2 pages
+            assert_eq!(users_stats(&c), vec![("my_rest_server".to_owned(), 2, 3)]);
+        });
+    }
+
+    #[pg_test]
+    fn stats_reset() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            assert_eq!(select_user_ids(&c), vec![1, 2, 3]);
+            assert_eq!(users_stats(&c).len(), 1);
+
+            let deleted = c
+                .select(
+                    "SELECT wrappers_stats_reset(server_name => 'my_rest_server')",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_one::<i64>()
+                .unwrap();
+            assert!(deleted.unwrap_or(0) > 0);
+            assert!(users_stats(&c).is_empty());
+        });
+    }
+}
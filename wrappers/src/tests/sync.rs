@@ -0,0 +1,104 @@
+#[pgrx::pg_schema]
+mod tests {
+    use crate::tests::create_users_table;
+    use pgrx::prelude::*;
+
+    // sync `users` foreign table to a target table, returns rows synced, the
+    // new last value and the error
+    fn sync_users(
+        c: &mut pgrx::spi::SpiClient,
+        target: &str,
+    ) -> (Option<i64>, Option<String>, Option<String>) {
+        c.update(
+            &format!("SELECT * FROM wrappers.sync('users', '{}', 'id')", target),
+            None,
+            None,
+        )
+        .unwrap()
+        .first()
+        .get_three::<i64, String, String>()
+        .unwrap()
+    }
+
+    #[pg_test]
+    fn sync_incremental() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            c.update(
+                "CREATE TABLE users_copy (id bigint PRIMARY KEY, name text)",
+                None,
+                None,
+            )
+            .unwrap();
+
+            // the first sync copies all rows, rows are upserted by the
+            // target table's primary key
+            assert_eq!(
+                sync_users(&mut c, "users_copy"),
+                (Some(3), Some("3".to_owned()), None)
+            );
+            let ids = c
+                .select("SELECT id FROM users_copy ORDER BY id", None, None)
+                .unwrap()
+                .filter_map(|r| r.get_by_name::<i64, _>("id").unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(ids, vec![1, 2, 3]);
+
+            // no new rows since last sync
+            assert_eq!(
+                sync_users(&mut c, "users_copy"),
+                (Some(0), Some("3".to_owned()), None)
+            );
+
+            // state is kept by schema qualified names
+            let state = c
+                .select(
+                    "SELECT foreign_table, target_table, last_value FROM wrappers_sync_state",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .first()
+                .get_three::<String, String, String>()
+                .unwrap();
+            assert_eq!(
+                state,
+                (
+                    Some("public.users".to_owned()),
+                    Some("public.users_copy".to_owned()),
+                    Some("3".to_owned())
+                )
+            );
+        });
+    }
+
+    #[pg_test]
+    fn sync_error_in_history() {
+        Spi::connect(|mut c| {
+            create_users_table(&mut c, "");
+            c.update("CREATE TABLE users_nopk (id bigint, name text)", None, None)
+                .unwrap();
+
+            // failed sync is rolled back and its error is returned
+            let (rows_synced, last_value, error) = sync_users(&mut c, "users_nopk");
+            assert_eq!(rows_synced, Some(0));
+            assert_eq!(last_value, None);
+            assert!(error.unwrap().contains("option 'rowid_column' is required"));
+
+            let results = c
+                .select(
+                    "SELECT target_table, error IS NULL AS ok FROM wrappers_sync_history ORDER BY id",
+                    None,
+                    None,
+                )
+                .unwrap()
+                .filter_map(|r| {
+                    r.get_by_name::<&str, _>("target_table")
+                        .unwrap()
+                        .zip(r.get_by_name::<bool, _>("ok").unwrap())
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(results, vec![("public.users_nopk", false)]);
+        });
+    }
+}